@@ -0,0 +1,14 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/flashvm.proto");
+        // A bundled protoc, so building the grpc feature needs no system protobuf compiler
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this host");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/flashvm.proto"], &["proto"])
+            .expect("failed to compile proto/flashvm.proto");
+    }
+}
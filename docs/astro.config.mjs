@@ -32,6 +32,9 @@ export default defineConfig({
             { label: 'Python API', link: '/api' },
             { label: 'Artifacts', link: '/usage/artifacts' },
             { label: 'Embedded Image & Storage', link: '/usage/image' },
+            { label: 'Command line', link: '/usage/cli' },
+            { label: 'Daemon', link: '/usage/daemon' },
+            { label: 'Jupyter', link: '/usage/jupyter' },
             { label: 'Troubleshooting', link: '/troubleshooting' }
           ]
         },
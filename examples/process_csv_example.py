@@ -47,6 +47,6 @@ else:
         if content is None:
             print(f"{name}: not inlined (increase max_bytes_inline).")
             continue
-        (out_dir / name).write_bytes(content)
+        (out_dir / name).write_bytes(bytes(content))
         print(f"Saved {name} ({a['size_bytes']} bytes)")
     print(f"Local copies in: {out_dir.resolve()}")
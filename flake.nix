@@ -91,6 +91,22 @@
           echo "✅ Vendored OCI layout into $dst"
         '';
 
+        # One layout for x86_64 and aarch64 wheels: each image under python-basic-<arch>, which
+        # flashvm prefers on a host of that arch. Building the other system's layout needs a
+        # remote builder or binfmt emulation.
+        vendorMultiarchScript = pkgs.writeShellScript "vendor-oci-multiarch" ''
+          set -euo pipefail
+          dst="flashvm/data/oci"
+          if [ -e "$dst" ]; then
+            chmod -R u+w "$dst" 2>/dev/null || true
+            rm -rf "$dst"
+          fi
+          mkdir -p "$dst"
+          ${pkgs.skopeo}/bin/skopeo --insecure-policy copy oci:${self.packages.x86_64-linux.oci-layout}/oci:python-basic oci:"$dst":python-basic-amd64
+          ${pkgs.skopeo}/bin/skopeo --insecure-policy copy oci:${self.packages.aarch64-linux.oci-layout}/oci:python-basic oci:"$dst":python-basic-arm64
+          echo "✅ Vendored amd64 and arm64 OCI layouts into $dst"
+        '';
+
         vendorDockerScript = pkgs.writeShellScript "vendor-oci-from-dockerfile" ''
           set -euo pipefail
           dst="flashvm/data/oci"
@@ -203,6 +219,7 @@ PY
       in {
         vendor-oci        = { type = "app"; program = "${vendorScript}"; };
         vendor-oci-docker = { type = "app"; program = "${vendorDockerScript}"; };
+        vendor-oci-multiarch = { type = "app"; program = "${vendorMultiarchScript}"; };
         build             = { type = "app"; program = "${buildScript}"; };
         develop           = { type = "app"; program = "${developScript}"; };
         publish           = { type = "app"; program = "${publishScript}"; };
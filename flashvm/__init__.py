@@ -1,4 +1,10 @@
+import atexit
+
 from ._core import *  # noqa: F403
+from ._core import drain_pool as _drain_pool
 
-__all__ = [name for name in dir() if not name.startswith("_")]
+__all__ = [name for name in dir() if not name.startswith("_") and name != "atexit"]
 __version__ = "0.1.1"
+
+# Warm-pool VMs are real krunvm VMs; don't leave them behind when the interpreter exits
+atexit.register(_drain_pool)
@@ -0,0 +1,186 @@
+"""Runs one notebook cell inside the microVM for flashvm.kernel.
+
+Not imported on the host: the kernel sends this file's source to the guest, which runs it in
+a namespace of its own (so cells can't clobber it) and calls ``_flashvm_cell(source)``.
+Each cell is a fresh interpreter, so the session state travels between cells through
+/work: imports, functions and classes are replayed from their source, and other variables
+are pickled.
+"""
+
+import ast
+import base64
+import io
+import itertools
+import json
+import linecache
+import os
+import pickle
+import sys
+import traceback
+import types
+
+_STATE_IN = "/work/in/.flashvm_kernel/state.pkl"
+_OUT_DIR = "/work/out/.flashvm_kernel"
+_DEFINITIONS = (ast.Import, ast.ImportFrom, ast.FunctionDef, ast.AsyncFunctionDef, ast.ClassDef)
+_REPR_METHODS = (
+    ("text/html", "_repr_html_"),
+    ("text/markdown", "_repr_markdown_"),
+    ("text/latex", "_repr_latex_"),
+    ("image/svg+xml", "_repr_svg_"),
+    ("image/png", "_repr_png_"),
+    ("image/jpeg", "_repr_jpeg_"),
+    ("application/json", "_repr_json_"),
+)
+
+
+def _mime_bundle(obj):
+    bundle = {"text/plain": repr(obj)}
+    for mime, method in _REPR_METHODS:
+        fn = getattr(obj, method, None)
+        if not callable(fn) or isinstance(obj, type):
+            continue
+        try:
+            data = fn()
+        except Exception:
+            continue
+        if isinstance(data, tuple):
+            data = data[0]
+        if data is None:
+            continue
+        if isinstance(data, bytes):
+            data = base64.b64encode(data).decode("ascii")
+        bundle[mime] = data
+    return bundle
+
+
+def _figures():
+    """PNG bundles for open matplotlib figures, which are then closed."""
+    plt = sys.modules.get("matplotlib.pyplot")
+    if plt is None:
+        return []
+    bundles = []
+    for num in plt.get_fignums():
+        fig = plt.figure(num)
+        buf = io.BytesIO()
+        fig.savefig(buf, format="png", bbox_inches="tight")
+        bundles.append({"image/png": base64.b64encode(buf.getvalue()).decode("ascii"), "text/plain": repr(fig)})
+    plt.close("all")
+    return bundles
+
+
+def _definition_source(node, lines):
+    start = min([d.lineno for d in getattr(node, "decorator_list", [])] + [node.lineno])
+    return "\n".join(lines[start - 1 : node.end_lineno])
+
+
+def _definition_key(node):
+    if isinstance(node, (ast.Import, ast.ImportFrom)):
+        return "import:" + ",".join(alias.asname or alias.name for alias in node.names)
+    return "def:" + node.name
+
+
+def _bound(node, ns):
+    if isinstance(node, (ast.Import, ast.ImportFrom)):
+        return all((alias.asname or alias.name).split(".")[0] in ns for alias in node.names)
+    return node.name in ns
+
+
+def _load_state(ns):
+    try:
+        with open(_STATE_IN, "rb") as f:
+            state = pickle.load(f)
+    except FileNotFoundError:
+        return {"definitions": [], "modules": {}, "names": {}}
+    for _key, source in state["definitions"]:
+        try:
+            exec(compile(source, "<previous cell>", "exec"), ns)
+        except Exception as e:
+            sys.stderr.write("flashvm: could not restore %s: %s\n" % (source.splitlines()[0], e))
+    for name, module in state["modules"].items():
+        try:
+            ns[name] = __import__(module, fromlist=["_"])
+        except ImportError:
+            pass
+    for name, blob in state["names"].items():
+        try:
+            ns[name] = pickle.loads(blob)
+        except Exception as e:
+            sys.stderr.write("flashvm: could not restore %r: %s\n" % (name, e))
+    return state
+
+
+def _save_state(ns, state, skip):
+    names, modules, dropped = {}, {}, []
+    for name, value in list(ns.items()):
+        if name.startswith("__") or name in skip:
+            continue
+        if isinstance(value, types.ModuleType):
+            modules[name] = value.__name__
+            continue
+        if isinstance(value, (types.FunctionType, type)) and any(
+            key == "def:" + name for key, _ in state["definitions"]
+        ):
+            continue
+        try:
+            names[name] = pickle.dumps(value, protocol=pickle.HIGHEST_PROTOCOL)
+        except Exception:
+            dropped.append(name)
+    state = {"definitions": state["definitions"], "modules": modules, "names": names}
+    with open(os.path.join(_OUT_DIR, "state.pkl"), "wb") as f:
+        pickle.dump(state, f, protocol=pickle.HIGHEST_PROTOCOL)
+    return dropped
+
+
+def _flashvm_cell(source):
+    os.makedirs(_OUT_DIR, exist_ok=True)
+    ns = sys.modules["__main__"].__dict__
+    helpers = {name for name in ns if not name.startswith("__")}
+    state = _load_state(ns)
+    report = {"result": None, "displays": [], "error": None, "dropped": []}
+
+    def display(*objs):
+        for obj in objs:
+            report["displays"].append(_mime_bundle(obj))
+
+    ns["display"] = display
+    helpers.add("display")
+
+    # So tracebacks can show the cell's lines
+    linecache.cache["<cell>"] = (len(source), None, source.splitlines(True), "<cell>")
+    nodes = []
+    try:
+        tree = ast.parse(source, "<cell>")
+        nodes = list(tree.body)
+        last = None
+        if tree.body and isinstance(tree.body[-1], ast.Expr):
+            last = ast.Expression(tree.body.pop().value)
+        exec(compile(tree, "<cell>", "exec"), ns)
+        if last is not None:
+            value = eval(compile(last, "<cell>", "eval"), ns)
+            if value is not None:
+                ns["_"] = value
+                report["result"] = _mime_bundle(value)
+    except BaseException as e:
+        te = traceback.TracebackException.from_exception(e)
+        # Start at the cell's own frames, not this driver's
+        te.stack = traceback.StackSummary.from_list(
+            list(itertools.dropwhile(lambda frame: frame.filename != "<cell>", te.stack))
+        )
+        tb = list(te.format())
+        report["error"] = {"ename": type(e).__name__, "evalue": str(e), "traceback": tb}
+
+    # Definitions are replayed in later cells, the latest one per name. A failed cell
+    # still keeps those that ran before the failure.
+    lines = source.splitlines()
+    definitions = dict(state["definitions"])
+    for node in nodes:
+        if isinstance(node, _DEFINITIONS) and _bound(node, ns):
+            key = _definition_key(node)
+            definitions.pop(key, None)
+            definitions[key] = _definition_source(node, lines)
+    state["definitions"] = list(definitions.items())
+
+    report["displays"].extend(_figures())
+    report["dropped"] = _save_state(ns, state, helpers)
+    with open(os.path.join(_OUT_DIR, "cell.json"), "w") as f:
+        json.dump(report, f)
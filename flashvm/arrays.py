@@ -0,0 +1,93 @@
+"""NumPy arrays in and out of runs as ``.npy`` files instead of pickled text.
+
+``stage_arrays`` writes arrays straight into memory-mapped ``.npy`` files and returns the
+``files_in`` entries that stage them under ``/work/in``. Code in the guest opens them with
+``numpy.load(path, mmap_mode='r')`` and saves its results with ``numpy.save`` under
+``/work/out``. ``load_arrays`` turns those artifacts back into arrays that are read-only views
+of the result's bytes, without copying them.
+"""
+
+import io
+import os
+import re
+import tempfile
+
+from . import _core
+
+_NAME = re.compile(r'^[A-Za-z0-9_.-]+$')
+
+
+def stage_arrays(arrays, directory=None):
+    """Write ``{name: array}`` to ``<directory>/<name>.npy`` and return ``files_in`` entries
+    for ``/work/in/<name>.npy``.
+
+    ``directory`` defaults to a new temporary directory, which the caller removes once the run
+    is over. Arrays of Python objects are rejected, since they would need pickle.
+    """
+    import numpy as np
+
+    directory = directory or tempfile.mkdtemp(prefix='flashvm-arrays-')
+    files_in = []
+    for name, array in arrays.items():
+        if not _NAME.match(name) or name in ('.', '..'):
+            raise _core.FlashVMValidationError(f'invalid array name {name!r}: use letters, digits, _, - and .')
+        array = np.asanyarray(array)
+        if array.dtype.hasobject:
+            raise _core.FlashVMValidationError(f'array {name!r} holds Python objects; only plain dtypes can be mapped')
+        path = os.path.join(directory, f'{name}.npy')
+        mapped = np.lib.format.open_memmap(path, mode='w+', dtype=array.dtype, shape=array.shape)
+        mapped[...] = array
+        mapped.flush()
+        del mapped
+        files_in.append((path, f'{name}.npy'))
+    return files_in
+
+
+def load_arrays(result):
+    """The ``.npy`` artifacts of a ``run`` result, as ``{name: array}``.
+
+    ``out/stats/mean.npy`` is named ``stats/mean``. Artifacts returned inline become read-only
+    views of their ``content``. Larger ones are memory-mapped from ``host_path`` while it still
+    exists (e.g. with ``keep_vm`` or a cached result), and raise ``FlashVMValidationError``
+    after that; pass a larger ``max_bytes_inline`` to get them inline.
+    """
+    import numpy as np
+
+    arrays = {}
+    for artifact in result['artifacts']:
+        guest_path = artifact['guest_path']
+        if not guest_path.endswith('.npy'):
+            continue
+        name = guest_path[len('out/'):] if guest_path.startswith('out/') else guest_path
+        name = name[: -len('.npy')]
+        content = artifact.get('content')
+        if content is not None:
+            arrays[name] = _view(np, bytes(content))
+        elif os.path.exists(artifact['host_path']):
+            arrays[name] = np.load(artifact['host_path'], mmap_mode='r')
+        else:
+            raise _core.FlashVMValidationError(
+                f'{guest_path} ({artifact["size_bytes"]} bytes) was not returned inline; '
+                'raise max_bytes_inline to load it'
+            )
+    return arrays
+
+
+def _view(np, content):
+    """An array over the bytes of a ``.npy`` file, sharing their memory."""
+    f = io.BytesIO(content)
+    version = np.lib.format.read_magic(f)
+    if version == (1, 0):
+        shape, fortran_order, dtype = np.lib.format.read_array_header_1_0(f)
+    elif version == (2, 0):
+        shape, fortran_order, dtype = np.lib.format.read_array_header_2_0(f)
+    else:
+        f.seek(0)
+        return np.load(f)
+    if dtype.hasobject:
+        raise _core.FlashVMValidationError('array holds Python objects, which would need pickle to load')
+    count = 1
+    for dim in shape:
+        count *= dim
+    array = np.frombuffer(content, dtype=dtype, count=count, offset=f.tell())
+    return array.reshape(shape, order='F' if fortran_order else 'C')
@@ -0,0 +1,102 @@
+"""Apache Arrow tables in and out of runs as IPC files.
+
+``stage_tables`` writes tables (or record batches, or pandas DataFrames) as Arrow IPC files and
+returns the ``files_in`` entries that stage them under ``/work/in``. Code in the guest opens
+them with ``pyarrow.ipc.open_file(pyarrow.memory_map(path))`` or ``pyarrow.feather.read_table``
+and writes its results the same way under ``/work/out``. ``load_tables`` opens the ``*.arrow``
+and ``*.feather`` artifacts of a result without copying their buffers.
+"""
+
+import os
+import re
+import tempfile
+
+from . import _core
+
+_NAME = re.compile(r'^[A-Za-z0-9_.-]+$')
+SUFFIXES = ('.arrow', '.feather')
+
+
+def stage_tables(tables, directory=None):
+    """Write ``{name: table}`` to ``<directory>/<name>.arrow`` and return ``files_in`` entries
+    for ``/work/in/<name>.arrow``.
+
+    ``directory`` defaults to a new temporary directory, which the caller removes once the run
+    is over.
+    """
+    import pyarrow as pa
+
+    directory = directory or tempfile.mkdtemp(prefix='flashvm-arrow-')
+    files_in = []
+    for name, table in tables.items():
+        if not _NAME.match(name) or name in ('.', '..'):
+            raise _core.FlashVMValidationError(f'invalid table name {name!r}: use letters, digits, _, - and .')
+        table = _as_table(pa, table)
+        path = os.path.join(directory, f'{name}.arrow')
+        with pa.OSFile(path, 'wb') as sink, pa.ipc.new_file(sink, table.schema) as writer:
+            writer.write_table(table)
+        files_in.append((path, f'{name}.arrow'))
+    return files_in
+
+
+def load_tables(result):
+    """The Arrow artifacts of a ``run`` result, as ``{name: pyarrow.Table}``.
+
+    ``out/stats/daily.arrow`` is named ``stats/daily``. Artifacts returned inline are read in
+    place from their ``content``. Larger ones are memory-mapped from ``host_path`` while it
+    still exists (e.g. with ``keep_vm`` or a cached result), and raise
+    ``FlashVMValidationError`` after that; pass a larger ``max_bytes_inline`` to get them inline.
+    """
+    import pyarrow as pa
+
+    tables = {}
+    for artifact in result['artifacts']:
+        guest_path = artifact['guest_path']
+        suffix = next((s for s in SUFFIXES if guest_path.endswith(s)), None)
+        if suffix is None:
+            continue
+        name = guest_path[len('out/'):] if guest_path.startswith('out/') else guest_path
+        name = name[: -len(suffix)]
+        content = artifact.get('content')
+        if content is not None:
+            source = pa.BufferReader(pa.py_buffer(bytes(content)))
+        elif os.path.exists(artifact['host_path']):
+            source = pa.memory_map(artifact['host_path'], 'r')
+        else:
+            raise _core.FlashVMValidationError(
+                f'{guest_path} ({artifact["size_bytes"]} bytes) was not returned inline; '
+                'raise max_bytes_inline to load it'
+            )
+        tables[name] = _read(pa, source, guest_path)
+    return tables
+
+
+def _as_table(pa, table):
+    if isinstance(table, pa.Table):
+        return table
+    if isinstance(table, pa.RecordBatch):
+        return pa.Table.from_batches([table])
+    if hasattr(table, 'to_arrow'):
+        return table.to_arrow()
+    # pandas DataFrames, and anything else pyarrow knows how to convert
+    return pa.Table.from_pandas(table, preserve_index=False) if hasattr(table, 'columns') else pa.table(table)
+
+
+def _read(pa, source, guest_path):
+    """An IPC file or stream; Feather v2 is an IPC file, v1 goes through ``pyarrow.feather``."""
+    try:
+        return pa.ipc.open_file(source).read_all()
+    except pa.ArrowInvalid:
+        pass
+    source.seek(0)
+    try:
+        return pa.ipc.open_stream(source).read_all()
+    except pa.ArrowInvalid:
+        pass
+    source.seek(0)
+    try:
+        import pyarrow.feather
+
+        return pyarrow.feather.read_table(source)
+    except (pa.ArrowInvalid, OSError) as e:
+        raise _core.FlashVMValidationError(f'{guest_path} is not an Arrow IPC or Feather file: {e}') from e
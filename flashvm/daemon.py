@@ -0,0 +1,195 @@
+"""Client for the ``flashvmd`` daemon.
+
+The daemon owns the warm VM pool and image cache, so short-lived processes that run code
+through it share warm VMs instead of each booting their own. ``DaemonClient.over_ssh`` runs
+code on another host instead, so a machine without KVM can use a server for sandboxing.
+"""
+
+import base64
+import itertools
+import json
+import os
+import shlex
+import socket
+import subprocess
+
+from . import _core
+
+_ERRORS = {
+    'IMAGE_RESOLUTION': _core.FlashVMImageError,
+    'VM_CONFIGURATION': _core.FlashVMConfigError,
+    'EXECUTION': _core.FlashVMExecutionError,
+    'TIMEOUT': _core.FlashVMTimeout,
+    'PULL_TIMEOUT': _core.FlashVMPullTimeout,
+    'GUEST_UNRESPONSIVE': _core.FlashVMGuestUnresponsive,
+    'MISSING_DEPENDENCY': _core.FlashVMMissingDependency,
+    'CACHE': _core.FlashVMCacheError,
+    'VALIDATION': _core.FlashVMValidationError,
+    'OUT_OF_MEMORY': _core.FlashVMOutOfMemory,
+    'DISK_FULL': _core.FlashVMDiskFull,
+    'OVERLOADED': _core.FlashVMOverloaded,
+    'POLICY_VIOLATION': _core.FlashVMPolicyViolation,
+    'RATE_LIMITED': _core.FlashVMRateLimited,
+    'KILLED': _core.FlashVMKilled,
+}
+
+
+def default_socket_path():
+    """The socket flashvmd listens on by default."""
+    if os.environ.get('FLASHVM_DAEMON_SOCKET'):
+        return os.environ['FLASHVM_DAEMON_SOCKET']
+    runtime_dir = os.environ.get('XDG_RUNTIME_DIR')
+    if runtime_dir:
+        return os.path.join(runtime_dir, 'flashvm.sock')
+    return f'/tmp/flashvm-{os.getuid()}.sock'
+
+
+class DaemonClient:
+    """One connection to flashvmd; requests on it are answered in order."""
+
+    def __init__(self, path=None, timeout=None):
+        self.path = path or default_socket_path()
+        self._ids = itertools.count(1)
+        self._process = None
+        self._sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        self._sock.settimeout(timeout)
+        try:
+            self._sock.connect(self.path)
+        except OSError as e:
+            self._sock.close()
+            raise _core.FlashVMError(f'cannot connect to flashvmd at {self.path}: {e}') from e
+        self._file = self._sock.makefile('rw', encoding='utf-8')
+
+    @classmethod
+    def over_ssh(cls, host, command='flashvmd --stdio', ssh=('ssh',)):
+        """Run code on ``host``, through a ``flashvmd --stdio`` started there over SSH.
+
+        Input files are sent with each run and artifacts are written to the local ``out_dir``,
+        so nothing needs to be shared between the hosts. The daemon, and its warm pool, lasts
+        as long as the client.
+        """
+        argv = [*ssh, host, *shlex.split(command)]
+        try:
+            process = subprocess.Popen(
+                argv, stdin=subprocess.PIPE, stdout=subprocess.PIPE, encoding='utf-8', bufsize=1
+            )
+        except OSError as e:
+            raise _core.FlashVMError(f'cannot start {argv[0]}: {e}') from e
+        client = cls.__new__(cls)
+        client.path = None
+        client._ids = itertools.count(1)
+        client._process = process
+        client._sock = None
+        client._file = _Pipes(process.stdout, process.stdin)
+        return client
+
+    def call(self, method, **params):
+        """Send one JSON-RPC request and return its result, raising flashvm exceptions on errors."""
+        request_id = next(self._ids)
+        request = {'jsonrpc': '2.0', 'id': request_id, 'method': method, 'params': params}
+        self._file.write(json.dumps(request) + '\n')
+        self._file.flush()
+        line = self._file.readline()
+        if not line:
+            raise _core.FlashVMError('flashvmd closed the connection')
+        response = json.loads(line)
+        error = response.get('error')
+        if error is None:
+            return response['result']
+        data = error.get('data') or {}
+        exc = _ERRORS.get(data.get('code'), _core.FlashVMError)(error['message'])
+        exc.code = data.get('code')
+        exc.image = data.get('image')
+        exc.command = data.get('command')
+        exc.stderr = data.get('stderr')
+        exc.retry_after = data.get('retry_after')
+        exc.result = data.get('result')
+        raise exc
+
+    def ping(self):
+        return self.call('ping')
+
+    def run(self, code, profile=None, config=None, expect=None, out_dir=None, files_in=None):
+        """Run ``code`` in a pooled VM.
+
+        ``config`` takes the ``run(config_file=...)`` keys. ``files_in`` is a list of
+        ``(host_path, guest_path)``, as for ``run``. Files matching ``expect`` are written to
+        ``out_dir``; without it, each artifact has its ``content`` as bytes.
+        """
+        params = {'code': code}
+        if profile is not None:
+            params['profile'] = profile
+        if config is not None:
+            params['config'] = config
+        if files_in:
+            params['files'] = [
+                {'guest_path': guest, 'content': base64.b64encode(_read(host)).decode('ascii')}
+                for host, guest in files_in
+            ]
+        if expect:
+            params['expect'] = list(expect)
+        # A remote daemon can't write to this host, so its artifacts come back inline
+        if out_dir is not None and self._process is None:
+            params['out_dir'] = os.path.abspath(out_dir)
+        result = self.call('run', **params)
+        for artifact in result['artifacts']:
+            if 'content' not in artifact:
+                continue
+            artifact['content'] = base64.b64decode(artifact['content'])
+            if out_dir is not None:
+                guest_path = artifact['guest_path']
+                rel = guest_path[len('out/'):] if guest_path.startswith('out/') else guest_path
+                root = os.path.abspath(out_dir)
+                path = os.path.abspath(os.path.join(root, rel))
+                if not path.startswith(root + os.sep):
+                    raise _core.FlashVMValidationError(f'artifact {guest_path} is outside out_dir')
+                os.makedirs(os.path.dirname(path), exist_ok=True)
+                with open(path, 'wb') as f:
+                    f.write(artifact.pop('content'))
+                artifact['path'] = path
+        return result
+
+    def list_images(self):
+        return self.call('list_images')
+
+    def drain_pool(self):
+        return self.call('drain_pool')
+
+    def close(self):
+        self._file.close()
+        if self._sock is not None:
+            self._sock.close()
+        if self._process is not None:
+            self._process.wait()
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+
+class _Pipes:
+    """A child's stdout and stdin as one line-oriented file."""
+
+    def __init__(self, reader, writer):
+        self._reader = reader
+        self._writer = writer
+
+    def readline(self):
+        return self._reader.readline()
+
+    def write(self, text):
+        self._writer.write(text)
+
+    def flush(self):
+        self._writer.flush()
+
+    def close(self):
+        self._writer.close()
+        self._reader.close()
+
+
+def _read(path):
+    with open(path, 'rb') as f:
+        return f.read()
@@ -0,0 +1,86 @@
+"""pandas DataFrames in and out of a run, staged as Parquet.
+
+``run_dataframe`` writes each input DataFrame to ``/work/in/<name>.parquet``, wraps the code so
+the guest finds them already loaded as variables of the same name, saves the declared outputs
+to ``/work/out/<name>.parquet`` once the code is done, and reads them back on the host. The
+image needs pandas and a Parquet engine (``pyarrow`` or ``fastparquet``).
+"""
+
+import io
+import keyword
+import shutil
+import tempfile
+
+from . import _core
+
+_WRAPPER = """\
+import pandas as _pd
+{loads}
+exec(compile({code!r}, '<code>', 'exec'))
+import os as _os
+_os.makedirs('/work/out', exist_ok=True)
+for _name in {outputs!r}:
+    if _name not in globals():
+        raise SystemExit(f'run_dataframe: output {{_name!r}} was never assigned')
+    if not isinstance(globals()[_name], _pd.DataFrame):
+        raise SystemExit(f'run_dataframe: output {{_name!r}} is a {{type(globals()[_name]).__name__}}, not a DataFrame')
+    globals()[_name].to_parquet(f'/work/out/{{_name}}.parquet')
+"""
+
+
+def run_dataframe(image, code, inputs=None, outputs=(), **options):
+    """Run ``code`` in ``image`` with ``inputs`` (``{name: DataFrame}``) loaded as variables and
+    return the ``run`` result with the ``outputs`` variables as DataFrames under
+    ``"dataframes"``.
+
+    ``options`` are passed on to ``run``. ``max_bytes_inline`` defaults to 256 MiB so outputs
+    come back inline. A non-zero exit raises ``FlashVMExecutionError`` with the guest's
+    ``stderr``.
+    """
+    import pandas as pd
+
+    inputs = dict(inputs or {})
+    outputs = list(outputs)
+    for name in [*inputs, *outputs]:
+        if not name.isidentifier() or keyword.iskeyword(name) or name.startswith('_'):
+            raise _core.FlashVMValidationError(f'invalid DataFrame name {name!r}: use a Python identifier without a leading _')
+
+    directory = tempfile.mkdtemp(prefix='flashvm-dataframes-')
+    try:
+        files_in = list(options.pop('files_in', None) or [])
+        for name, df in inputs.items():
+            path = f'{directory}/{name}.parquet'
+            df.to_parquet(path)
+            files_in.append((path, f'{name}.parquet'))
+        loads = '\n'.join(f"{name} = _pd.read_parquet('/work/in/{name}.parquet')" for name in inputs)
+        expect = list(options.pop('expect', None) or []) + [f'out/{name}.parquet' for name in outputs]
+        options.setdefault('max_bytes_inline', 256 << 20)
+        result = _core.run(
+            _WRAPPER.format(loads=loads, code=code, outputs=outputs),
+            image=image,
+            files_in=files_in,
+            expect=expect,
+            **options,
+        )
+    finally:
+        shutil.rmtree(directory, ignore_errors=True)
+
+    if result['exit_code'] != 0:
+        lines = result['stderr'].strip().splitlines()
+        exc = _core.FlashVMExecutionError(
+            f"code exited with {result['exit_code']}" + (f': {lines[-1]}' if lines else '')
+        )
+        exc.stderr = result['stderr']
+        raise exc
+    artifacts = {a['guest_path']: a for a in result['artifacts']}
+    dataframes = {}
+    for name in outputs:
+        artifact = artifacts.get(f'out/{name}.parquet')
+        if artifact is None or artifact.get('content') is None:
+            size = artifact['size_bytes'] if artifact else 0
+            raise _core.FlashVMValidationError(
+                f'output {name!r} ({size} bytes) was not returned inline; raise max_bytes_inline to load it'
+            )
+        dataframes[name] = pd.read_parquet(io.BytesIO(bytes(artifact['content'])))
+    result['dataframes'] = dataframes
+    return result
@@ -0,0 +1,269 @@
+"""Jupyter kernel whose cells run in flashvm microVMs.
+
+Each notebook kernel is a flashvm session: every cell runs in a warm-pool VM, the
+notebook's variables are carried from cell to cell, rich reprs and matplotlib figures are
+shown inline, and files the cell writes under /work/out are copied next to the notebook.
+
+Install the kernel spec with ``python -m flashvm.kernel install`` (needs ``ipykernel``).
+"""
+
+import argparse
+import base64
+import json
+import os
+import sys
+import tempfile
+import threading
+
+import flashvm
+
+try:
+    from ipykernel.kernelbase import Kernel
+except ImportError:
+    # Session works without ipykernel; install() and main() say what's missing
+    Kernel = object
+
+_GUEST_DRIVER = os.path.join(os.path.dirname(__file__), "_kernel_guest.py")
+_STATE_GUEST_PATH = ".flashvm_kernel/state.pkl"
+_KERNEL_OUT = "out/.flashvm_kernel/"
+# Artifacts come back inline up to this size; larger ones are copied from the workspace
+_MAX_INLINE = 256 * 1024 * 1024
+_IMAGE_TYPES = {".png": "image/png", ".jpg": "image/jpeg", ".jpeg": "image/jpeg", ".gif": "image/gif"}
+
+
+def cell_code(source):
+    """Code that runs ``source`` as a notebook cell in the guest."""
+    with open(_GUEST_DRIVER, encoding="utf-8") as f:
+        driver = f.read()
+    return (
+        "_flashvm = {'__name__': '_flashvm'}\n"
+        f"exec(compile({driver!r}, '<flashvm kernel>', 'exec'), _flashvm)\n"
+        f"_flashvm['_flashvm_cell']({source!r})\n"
+    )
+
+
+class Session:
+    """One notebook's state: the pickled namespace carried from cell to cell.
+
+    ``profile`` and ``config_file`` pick the run settings, as for ``flashvm.run``; they
+    default to ``FLASHVM_KERNEL_PROFILE`` and ``FLASHVM_KERNEL_CONFIG``. Files cells write
+    under /work/out are copied to ``out_dir`` (``FLASHVM_KERNEL_OUT``, else ``flashvm_out``).
+    """
+
+    def __init__(self, profile=None, config_file=None, out_dir=None):
+        self.profile = profile or os.environ.get("FLASHVM_KERNEL_PROFILE") or None
+        self.config_file = config_file or os.environ.get("FLASHVM_KERNEL_CONFIG") or None
+        self.out_dir = out_dir or os.environ.get("FLASHVM_KERNEL_OUT") or "flashvm_out"
+        self._state = None
+        self._tmp = tempfile.TemporaryDirectory(prefix="flashvm-kernel-")
+
+    def run_cell(self, source):
+        """Run one cell and return a dict with ``stdout``, ``stderr``, ``exit_code``,
+        ``report`` (result, displays, error and dropped names; None when the cell never
+        finished) and ``files`` (``(guest_path, host_path, size_bytes)`` of copied files).
+
+        On KeyboardInterrupt the cell's VM is killed before the interrupt propagates.
+        """
+        files_in = []
+        if self._state is not None:
+            state_path = os.path.join(self._tmp.name, "state.pkl")
+            with open(state_path, "wb") as f:
+                f.write(self._state)
+            files_in.append((state_path, _STATE_GUEST_PATH))
+        result = self._run_interruptibly(
+            cell_code(source),
+            files_in=files_in,
+            expect=["out/**/*"],
+            max_bytes_inline=_MAX_INLINE,
+            use_pool=True,
+            profile=self.profile,
+            config_file=self.config_file,
+        )
+
+        report, files = None, []
+        for artifact in result["artifacts"]:
+            guest_path = artifact["guest_path"]
+            data = artifact.get("content")
+            if data is not None:
+                data = bytes(data)
+            else:
+                with open(artifact["host_path"], "rb") as f:
+                    data = f.read()
+            if guest_path == _KERNEL_OUT + "cell.json":
+                report = json.loads(data)
+            elif guest_path == _KERNEL_OUT + "state.pkl":
+                self._state = data
+            elif not guest_path.startswith(_KERNEL_OUT):
+                dest = os.path.join(self.out_dir, guest_path[len("out/") :])
+                os.makedirs(os.path.dirname(dest), exist_ok=True)
+                with open(dest, "wb") as f:
+                    f.write(data)
+                files.append((guest_path, dest, artifact["size_bytes"]))
+        return {
+            "stdout": result["stdout"],
+            "stderr": result["stderr"],
+            "exit_code": result["exit_code"],
+            "report": report,
+            "files": files,
+        }
+
+    def _run_interruptibly(self, code, **kwargs):
+        # run() holds no Python frames while the VM runs, so wait on a thread that can be
+        # interrupted and kill the VM ourselves
+        box = {}
+
+        def target():
+            try:
+                box["result"] = flashvm.run(code, **kwargs)
+            except BaseException as e:
+                box["error"] = e
+
+        thread = threading.Thread(target=target, daemon=True)
+        thread.start()
+        try:
+            while thread.is_alive():
+                thread.join(0.1)
+        except KeyboardInterrupt:
+            for run in flashvm.list_runs():
+                if run["owner_pid"] == os.getpid():
+                    flashvm.kill(run["run_id"])
+            thread.join()
+            raise
+        if "error" in box:
+            raise box["error"]
+        return box["result"]
+
+
+class FlashVMKernel(Kernel):
+    implementation = "flashvm"
+    implementation_version = flashvm.__version__
+    language = "python"
+    language_version = "3"
+    language_info = {
+        "name": "python",
+        "mimetype": "text/x-python",
+        "file_extension": ".py",
+        "codemirror_mode": {"name": "ipython", "version": 3},
+        "pygments_lexer": "ipython3",
+    }
+    banner = "Python in flashvm microVMs: each cell runs in an isolated VM"
+
+    def __init__(self, **kwargs):
+        super().__init__(**kwargs)
+        self.session = Session()
+
+    def do_execute(self, code, silent, store_history=True, user_expressions=None, allow_stdin=False):
+        if not code.strip():
+            return self._ok()
+        try:
+            outcome = self.session.run_cell(code)
+        except KeyboardInterrupt:
+            return self._error("KeyboardInterrupt", "", [])
+        except flashvm.FlashVMError as e:
+            return self._error(type(e).__name__, str(e), [f"{type(e).__name__}: {e}"])
+
+        if not silent:
+            self._stream("stdout", outcome["stdout"])
+            self._stream("stderr", outcome["stderr"])
+        report = outcome["report"]
+        if report is None:
+            evalue = f"the cell did not finish (exit code {outcome['exit_code']})"
+            return self._error("FlashVMError", evalue, [f"FlashVMError: {evalue}"])
+        if report["dropped"] and not silent:
+            names = ", ".join(sorted(report["dropped"]))
+            self._stream("stderr", f"flashvm: not kept for the next cell (can't be pickled): {names}\n")
+        if not silent:
+            for bundle in report["displays"]:
+                self.send_response(self.iopub_socket, "display_data", {"data": bundle, "metadata": {}})
+            for guest_path, host_path, size in outcome["files"]:
+                self.send_response(self.iopub_socket, "display_data", {"data": _file_bundle(guest_path, host_path, size), "metadata": {}})
+            if report["result"] is not None:
+                content = {"execution_count": self.execution_count, "data": report["result"], "metadata": {}}
+                self.send_response(self.iopub_socket, "execute_result", content)
+        error = report["error"]
+        if error is not None:
+            return self._error(error["ename"], error["evalue"], error["traceback"])
+        return self._ok()
+
+    def _stream(self, name, text):
+        if text:
+            self.send_response(self.iopub_socket, "stream", {"name": name, "text": text})
+
+    def _ok(self):
+        return {"status": "ok", "execution_count": self.execution_count, "payload": [], "user_expressions": {}}
+
+    def _error(self, ename, evalue, traceback):
+        content = {"ename": ename, "evalue": evalue, "traceback": traceback}
+        self.send_response(self.iopub_socket, "error", content)
+        return {"status": "error", "execution_count": self.execution_count, **content}
+
+
+def _file_bundle(guest_path, host_path, size):
+    """Images inline; other files as a link to the copy next to the notebook."""
+    mime = _IMAGE_TYPES.get(os.path.splitext(host_path)[1].lower())
+    if mime is not None:
+        with open(host_path, "rb") as f:
+            return {mime: base64.b64encode(f.read()).decode("ascii"), "text/plain": f"/work/{guest_path}"}
+    if host_path.lower().endswith(".svg"):
+        with open(host_path, encoding="utf-8") as f:
+            return {"image/svg+xml": f.read(), "text/plain": f"/work/{guest_path}"}
+    return {
+        "text/markdown": f"[/work/{guest_path}]({host_path}) ({size} bytes)",
+        "text/plain": f"/work/{guest_path} -> {host_path} ({size} bytes)",
+    }
+
+
+def install(user=True, prefix=None, name="flashvm", display_name=None, profile=None, config_file=None):
+    """Install the kernel spec so Jupyter lists the kernel. ``profile`` and ``config_file``
+    become the kernel's run settings. Returns the spec's directory."""
+    try:
+        from jupyter_client.kernelspec import KernelSpecManager
+    except ImportError:
+        raise ImportError("flashvm.kernel needs ipykernel: pip install 'flashvm[jupyter]'") from None
+    env = {}
+    if profile:
+        env["FLASHVM_KERNEL_PROFILE"] = profile
+    if config_file:
+        env["FLASHVM_KERNEL_CONFIG"] = os.path.abspath(config_file)
+    spec = {
+        "argv": [sys.executable, "-m", "flashvm.kernel", "-f", "{connection_file}"],
+        "display_name": display_name or "Python (flashvm)",
+        "language": "python",
+        "interrupt_mode": "signal",
+        "env": env,
+    }
+    with tempfile.TemporaryDirectory() as spec_dir:
+        with open(os.path.join(spec_dir, "kernel.json"), "w", encoding="utf-8") as f:
+            json.dump(spec, f, indent=2)
+        return KernelSpecManager().install_kernel_spec(spec_dir, name, user=user, prefix=prefix)
+
+
+def main(argv=None):
+    argv = sys.argv[1:] if argv is None else argv
+    if argv[:1] == ["install"]:
+        parser = argparse.ArgumentParser(prog="python -m flashvm.kernel install")
+        parser.add_argument("--sys-prefix", action="store_true", help="install into sys.prefix instead of the user's directory")
+        parser.add_argument("--name", default="flashvm")
+        parser.add_argument("--display-name")
+        parser.add_argument("--profile", help="registered flashvm profile for the kernel's runs")
+        parser.add_argument("--config", help="run settings file for the kernel's runs")
+        args = parser.parse_args(argv[1:])
+        path = install(
+            user=not args.sys_prefix,
+            prefix=sys.prefix if args.sys_prefix else None,
+            name=args.name,
+            display_name=args.display_name,
+            profile=args.profile,
+            config_file=args.config,
+        )
+        print(f"Installed kernel spec {args.name} in {path}")
+        return
+    if Kernel is object:
+        sys.exit("flashvm.kernel needs ipykernel: pip install 'flashvm[jupyter]'")
+    from ipykernel.kernelapp import IPKernelApp
+
+    IPKernelApp.launch_instance(argv=argv, kernel_class=FlashVMKernel)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,100 @@
+"""Run a test suite with pytest in the guest and get its results back parsed.
+
+``run_pytest`` stages the suite under ``/work/in/tests``, runs pytest there with a JUnit XML
+report written to ``/work/out``, and adds the parsed report to the ``run`` result under
+``"tests"``. The image needs pytest; pass ``requirements=["pytest"]`` if it has none.
+"""
+
+import os
+import xml.etree.ElementTree as ET
+
+from . import _core
+
+REPORT = 'out/flashvm-junit.xml'
+
+_CODE = """\
+import sys
+import pytest
+sys.exit(pytest.main(['/work/in/tests', '--rootdir=/work/in/tests', '-p', 'no:cacheprovider',
+                      '--junitxml=/work/{report}'] + {args!r}))
+"""
+
+
+def run_pytest(tests, pytest_args=(), **options):
+    """Run pytest on ``tests`` (a file or directory on the host) and return the ``run`` result
+    with the parsed report under ``"tests"``.
+
+    ``pytest_args`` are appended to the pytest command line; ``options`` are passed on to
+    ``run``. Failing tests are reported, not raised. A run that leaves no report (pytest
+    missing, or the run died first) raises ``FlashVMExecutionError`` with the guest's
+    ``stderr``.
+    """
+    tests = os.path.abspath(tests)
+    if os.path.isdir(tests):
+        files_in = [
+            (os.path.join(dirpath, name), os.path.join('tests', os.path.relpath(dirpath, tests), name))
+            for dirpath, dirnames, filenames in os.walk(tests)
+            if '__pycache__' not in dirpath.split(os.sep)
+            for name in filenames
+        ]
+    elif os.path.isfile(tests):
+        files_in = [(tests, os.path.join('tests', os.path.basename(tests)))]
+    else:
+        raise _core.FlashVMValidationError(f'no test file or directory at {tests}')
+    files_in = [(host, os.path.normpath(guest)) for host, guest in files_in]
+    files_in += list(options.pop('files_in', None) or [])
+    expect = list(options.pop('expect', None) or []) + [REPORT]
+    options.setdefault('max_bytes_inline', 64 << 20)
+
+    result = _core.run(
+        _CODE.format(report=REPORT, args=[str(a) for a in pytest_args]),
+        files_in=files_in,
+        expect=expect,
+        **options,
+    )
+    report = next((a for a in result['artifacts'] if a['guest_path'] == REPORT), None)
+    if report is None or report.get('content') is None:
+        lines = result['stderr'].strip().splitlines()
+        exc = _core.FlashVMExecutionError(
+            f"pytest left no report (exit code {result['exit_code']})" + (f': {lines[-1]}' if lines else '')
+        )
+        exc.stderr = result['stderr']
+        raise exc
+    result['tests'] = parse_junit(bytes(report['content']))
+    return result
+
+
+def parse_junit(xml):
+    """Counts and per-test details from a JUnit XML report, as pytest writes it.
+
+    Returns ``{"passed", "failed", "errors", "skipped", "total", "cases"}``; each case has
+    ``name``, ``classname``, ``file``, ``line``, ``time``, ``outcome`` (``"passed"``,
+    ``"failed"``, ``"error"`` or ``"skipped"``), ``message`` and ``details``.
+    """
+    try:
+        root = ET.fromstring(xml)
+    except ET.ParseError as e:
+        raise _core.FlashVMValidationError(f'invalid JUnit XML report: {e}') from e
+    cases = []
+    for case in root.iter('testcase'):
+        outcome, message, details = 'passed', None, None
+        # A test that failed and then errored in teardown has both; the failure is the news
+        for tag, name in (('failure', 'failed'), ('error', 'error'), ('skipped', 'skipped')):
+            element = case.find(tag)
+            if element is not None:
+                outcome, message, details = name, element.get('message'), element.text
+                break
+        line = case.get('line')
+        cases.append({
+            'name': case.get('name'),
+            'classname': case.get('classname'),
+            'file': case.get('file'),
+            'line': int(line) if line and line.isdigit() else None,
+            'time': float(case.get('time') or 0),
+            'outcome': outcome,
+            'message': message,
+            'details': details,
+        })
+    counts = {key: sum(c['outcome'] == outcome for c in cases)
+              for key, outcome in (('passed', 'passed'), ('failed', 'failed'), ('errors', 'error'), ('skipped', 'skipped'))}
+    return {**counts, 'total': len(cases), 'cases': cases}
@@ -0,0 +1,124 @@
+use crate::config::{Rlimits, VMConfig};
+use crate::error::VMError;
+use crate::global_config;
+use crate::runs::TimeoutExtension;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+/// Where every run appends its audit record, or None when auditing is off. Starts from
+/// `FLASHVM_AUDIT_LOG`, else the global config's `audit_log`, and can be changed with
+/// `configure_audit`.
+fn log_path() -> &'static Mutex<Option<PathBuf>> {
+    static PATH: OnceLock<Mutex<Option<PathBuf>>> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::var("FLASHVM_AUDIT_LOG")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .or_else(|| global_config::global().audit_log.clone());
+        Mutex::new(path.map(PathBuf::from))
+    })
+}
+
+pub fn path() -> Option<PathBuf> {
+    log_path().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(feature = "python")]
+pub fn configure(path: Option<PathBuf>) {
+    *log_path().lock().unwrap_or_else(|e| e.into_inner()) = path;
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord {
+    /// When the run finished
+    pub ts: DateTime<Utc>,
+    pub run_id: String,
+    pub tenant: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    /// Image the run used, or the one it asked for when it failed before resolving it
+    pub image: Option<String>,
+    /// `sha256:` ID of the image in local storage, when the run got that far
+    pub image_digest: Option<String>,
+    pub code_sha256: String,
+    pub limits: Limits,
+    /// `extend_timeout` calls made while the run was running, on top of `limits.timeout_seconds`
+    pub timeout_extensions: Vec<TimeoutExtension>,
+    /// None when flashvm failed the run (see `error`)
+    pub exit_code: Option<i32>,
+    /// `FlashVMError.code` of a failed run
+    pub error: Option<&'static str>,
+    pub duration_ms: u64,
+    /// The calling process and its user
+    pub pid: u32,
+    pub uid: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Limits {
+    pub cpus: u32,
+    pub memory_mb: u32,
+    pub timeout_seconds: u64,
+    pub network: bool,
+    /// Outbound allowlist in force, when network is on
+    pub egress_restricted: bool,
+    pub rlimits: Rlimits,
+}
+
+impl Limits {
+    pub fn of(config: &VMConfig) -> Self {
+        Limits {
+            cpus: config.cpus,
+            memory_mb: config.memory_mb,
+            timeout_seconds: config.timeout.as_secs(),
+            network: config.network,
+            egress_restricted: config.egress.is_some(),
+            rlimits: config.rlimits,
+        }
+    }
+}
+
+/// The open audit log. Opened before a run starts, so a run that couldn't be audited
+/// never runs.
+pub struct AuditLog {
+    file: File,
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// The configured log, or None when auditing is off.
+    pub fn open() -> Result<Option<Self>, VMError> {
+        let Some(path) = path() else { return Ok(None) };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| VMError::VMConfiguration(format!("cannot open audit log {}: {}", path.display(), e)))?;
+        Ok(Some(AuditLog { file, path }))
+    }
+
+    /// Append `record` as one line, in a single write under an exclusive lock, so records
+    /// from concurrent processes never interleave.
+    pub fn append(mut self, record: &AuditRecord) -> Result<(), VMError> {
+        let mut line = serde_json::to_vec(record).map_err(|e| VMError::Execution(e.to_string()))?;
+        line.push(b'\n');
+        let fd = self.file.as_raw_fd();
+        // SAFETY: plain syscalls on a descriptor we own; the lock is released on close
+        if unsafe { libc::flock(fd, libc::LOCK_EX) } != 0 {
+            return Err(self.write_error(std::io::Error::last_os_error()));
+        }
+        self.file.write_all(&line).and_then(|_| self.file.sync_data()).map_err(|e| self.write_error(e))
+    }
+
+    fn write_error(&self, e: std::io::Error) -> VMError {
+        VMError::IO(std::io::Error::new(e.kind(), format!("cannot write audit log {}: {}", self.path.display(), e)))
+    }
+}
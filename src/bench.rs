@@ -0,0 +1,81 @@
+use crate::config::{PhaseTimings, VMConfig};
+use crate::error::VMError;
+use crate::vm_runner::VMRunner;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// What each bench run executes: enough to start the interpreter and print, so the numbers
+/// are flashvm's rather than the code's
+pub const SNIPPET: &str = "print(sum(range(1000)))";
+
+/// The VM backend runs go through. krunvm is the only one so far; reports name it so numbers
+/// from different hosts and setups can be compared
+pub const BACKEND: &str = "krunvm";
+
+type Phase = fn(&PhaseTimings) -> Duration;
+
+/// Phases in run order, as reported
+pub const PHASES: [(&str, Phase); 6] = [
+    ("resolve", |t| t.resolve),
+    ("stage", |t| t.stage),
+    ("create", |t| t.create),
+    ("boot", |t| t.boot),
+    ("exec", |t| t.exec),
+    ("collect", |t| t.collect),
+];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Percentiles {
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub backend: &'static str,
+    pub runs: usize,
+    pub use_pool: bool,
+    /// Per phase in `PHASES`
+    pub phases: BTreeMap<&'static str, Percentiles>,
+    /// Whole runs (`execution_time`)
+    pub total: Percentiles,
+}
+
+/// Run `SNIPPET` `runs` times, one after another, with `config` and report p50/p95 per phase.
+/// The result cache is bypassed; with `use_pool` every run after the first gets a warm VM.
+pub fn run(config: &VMConfig, runs: usize) -> Result<BenchReport, VMError> {
+    if runs == 0 {
+        return Err(VMError::VMConfiguration("bench needs at least one run".to_string()));
+    }
+    let mut config = config.clone();
+    config.result_cache = false;
+    let runner = VMRunner::new();
+    let mut results = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let result = runner.execute_python_code(SNIPPET, &config, Vec::new(), Vec::new())?;
+        if result.exit_code != 0 {
+            return Err(VMError::Execution(format!("bench snippet exited with {}", result.exit_code))
+                .with_stderr(&result.stderr));
+        }
+        results.push(result);
+    }
+    let percentiles = |durations: Vec<Duration>| {
+        let mut ms: Vec<f64> = durations.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        ms.sort_by(f64::total_cmp);
+        // Nearest rank
+        let at = |p: f64| ms[((p * ms.len() as f64).ceil() as usize).clamp(1, ms.len()) - 1];
+        Percentiles { p50_ms: at(0.50), p95_ms: at(0.95) }
+    };
+    let phases = PHASES
+        .iter()
+        .map(|(name, phase)| (*name, percentiles(results.iter().map(|r| phase(&r.timings)).collect())))
+        .collect();
+    Ok(BenchReport {
+        backend: BACKEND,
+        runs,
+        use_pool: config.use_pool,
+        phases,
+        total: percentiles(results.iter().map(|r| r.execution_time).collect()),
+    })
+}
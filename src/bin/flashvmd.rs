@@ -0,0 +1,7 @@
+// The python feature builds an extension module that expects to be loaded by an interpreter
+#[cfg(feature = "python")]
+compile_error!("build the flashvmd binary with `--no-default-features --features daemon`");
+
+fn main() -> std::process::ExitCode {
+    flashvm::daemon::main()
+}
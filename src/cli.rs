@@ -0,0 +1,358 @@
+use crate::bench;
+use crate::config::{FileOutput, PipRequirements, PullProgress};
+use crate::diagnostics;
+use crate::error::VMError;
+use crate::image_cache::ImageCache;
+use crate::image_resolver::ImageResolver;
+use crate::vm_runner::VMRunner;
+use crate::{global_config, logging, platform, profiles, settings, telemetry};
+use clap::{Args, Parser, Subcommand};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Exit status for flashvm's own failures, so they aren't mistaken for the code's exit status
+const EXIT_FLASHVM_ERROR: u8 = 125;
+
+/// Run Python in libkrun microVMs
+#[derive(Parser)]
+#[command(name = "flashvm", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a Python script in a fresh microVM and exit with its exit code
+    Run(RunArgs),
+    /// Build an image from a Containerfile, or layer pip packages onto a base image
+    Build(BuildArgs),
+    /// Pull images into containers-storage ahead of their first run
+    Pull {
+        #[arg(required = true)]
+        images: Vec<String>,
+        #[arg(long, default_value_t = 4)]
+        max_parallel: usize,
+    },
+    /// List the images flashvm built, imported or pulled
+    Images {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Remove least recently used derived images
+    Gc {
+        /// Remove images until the rest fit in this many MiB
+        #[arg(long)]
+        max_total_mb: Option<u64>,
+        /// Remove images unused for this many seconds
+        #[arg(long)]
+        max_age: Option<u64>,
+    },
+    /// Time runs of a standard snippet and print p50/p95 per phase
+    Bench {
+        #[arg(long, default_value_t = 10)]
+        runs: usize,
+        #[arg(long)]
+        image: Option<String>,
+        /// Reuse warm VMs between runs
+        #[arg(long)]
+        use_pool: bool,
+        #[arg(long)]
+        profile: Option<String>,
+        /// TOML, YAML or JSON file with run settings
+        #[arg(long)]
+        config: Option<PathBuf>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check that this host can run flashvm
+    Doctor {
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Script to run; `-` reads it from stdin
+    #[arg(required_unless_present = "code", conflicts_with = "code")]
+    script: Option<PathBuf>,
+    /// Code to run instead of a script
+    #[arg(short = 'c', long)]
+    code: Option<String>,
+    #[arg(long)]
+    image: Option<String>,
+    #[arg(long)]
+    cpus: Option<u32>,
+    #[arg(long)]
+    memory_mb: Option<u32>,
+    /// Timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+    #[arg(long)]
+    network: bool,
+    /// KEY=VALUE, repeatable
+    #[arg(short = 'e', long = "env", value_parser = key_value)]
+    env: Vec<(String, String)>,
+    /// pip requirement installed before the run (needs --network), repeatable
+    #[arg(short = 'r', long = "requirement")]
+    requirements: Vec<String>,
+    /// KEY=VALUE recorded in the audit log, repeatable
+    #[arg(long = "label", value_parser = key_value)]
+    labels: Vec<(String, String)>,
+    /// Reuse the stored result of an identical earlier run
+    #[arg(long)]
+    result_cache: bool,
+    /// Tenant from the global config's [tenants] to run for
+    #[arg(long)]
+    tenant: Option<String>,
+    #[arg(long)]
+    profile: Option<String>,
+    /// TOML, YAML or JSON file with run settings
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Glob of files under /work/out to copy back, repeatable
+    #[arg(long)]
+    expect: Vec<String>,
+    /// Where files matched by --expect are written
+    #[arg(long, default_value = ".")]
+    out: PathBuf,
+}
+
+#[derive(Args)]
+struct BuildArgs {
+    /// Containerfile or Dockerfile to build
+    #[arg(required_unless_present_any = ["pip", "requirements_file"])]
+    containerfile: Option<PathBuf>,
+    /// Build context (default: the Containerfile's directory)
+    #[arg(long)]
+    context: Option<PathBuf>,
+    #[arg(long)]
+    tag: Option<String>,
+    /// KEY=VALUE, repeatable
+    #[arg(long = "build-arg", value_parser = key_value)]
+    build_args: Vec<(String, String)>,
+    /// pip package to layer onto --base instead of building a Containerfile, repeatable
+    #[arg(long, conflicts_with = "containerfile")]
+    pip: Vec<String>,
+    /// requirements.txt to layer onto --base
+    #[arg(short = 'r', long, conflicts_with = "containerfile")]
+    requirements_file: Option<PathBuf>,
+    /// Base for --pip builds (default: the embedded image)
+    #[arg(long)]
+    base: Option<String>,
+}
+
+fn key_value(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))
+}
+
+/// Entry point of the `flashvm` binary.
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+    logging::init_from_env();
+    telemetry::init_from_env();
+    global_config::loaded();
+    settings::env_defaults();
+    let result = match cli.command {
+        Command::Run(args) => run(args),
+        Command::Build(args) => build(args),
+        Command::Pull { images, max_parallel } => pull(&images, max_parallel),
+        Command::Images { json } => images(json),
+        Command::Gc { max_total_mb, max_age } => gc(max_total_mb, max_age),
+        Command::Bench { runs, image, use_pool, profile, config, json } => {
+            bench(runs, image, use_pool, profile.as_deref(), config.as_deref(), json)
+        }
+        Command::Doctor { json } => doctor(json),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("flashvm: {}", e);
+        ExitCode::from(EXIT_FLASHVM_ERROR)
+    })
+}
+
+fn run(args: RunArgs) -> Result<ExitCode, VMError> {
+    let code = match (&args.code, &args.script) {
+        (Some(code), _) => code.clone(),
+        (None, Some(path)) if path == Path::new("-") => {
+            let mut code = String::new();
+            std::io::stdin().read_to_string(&mut code)?;
+            code
+        }
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|e| VMError::VMConfiguration(format!("cannot read {}: {}", path.display(), e)))?,
+        (None, None) => unreachable!("clap requires a script or --code"),
+    };
+    let mut config = profiles::base_config(args.profile.as_deref(), args.config.as_deref())?;
+    if args.image.is_some() {
+        config.image = args.image;
+    }
+    if args.tenant.is_some() {
+        config.tenant = args.tenant;
+    }
+    config.cpus = args.cpus.unwrap_or(config.cpus);
+    config.memory_mb = args.memory_mb.unwrap_or(config.memory_mb);
+    config.timeout = args.timeout.map(Duration::from_secs).unwrap_or(config.timeout);
+    config.network |= args.network;
+    config.result_cache |= args.result_cache;
+    config.env.extend(args.env);
+    config.labels.extend(args.labels);
+    if !args.requirements.is_empty() {
+        config.requirements = args.requirements;
+    }
+    // Artifacts are written to --out, so they all come back inline
+    config.max_bytes_inline = u64::MAX;
+
+    let expect = args.expect.into_iter().map(|pattern| FileOutput { pattern }).collect();
+    let result = VMRunner::new().execute_python_code(&code, &config, vec![], expect)?;
+    print!("{}", result.stdout);
+    eprint!("{}", result.stderr);
+    for artifact in &result.artifacts {
+        let rel = artifact.guest_path.strip_prefix("out/").unwrap_or(&artifact.guest_path);
+        let dest = args.out.join(rel);
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match &artifact.content {
+            Some(content) => std::fs::write(&dest, content)?,
+            None => std::fs::copy(&artifact.host_path, &dest).map(|_| ())?,
+        }
+    }
+    // Signals come back negative; shells report those as failures too
+    Ok(ExitCode::from(u8::try_from(result.exit_code).unwrap_or(1)))
+}
+
+fn build(args: BuildArgs) -> Result<ExitCode, VMError> {
+    let progress: Arc<dyn Fn(&str) + Send + Sync> = Arc::new(|line: &str| eprintln!("{}", line));
+    let resolver = ImageResolver::new().with_progress(Some(progress));
+    let image = match args.containerfile {
+        Some(path) => {
+            let build_args: HashMap<String, String> = args.build_args.into_iter().collect();
+            resolver.build_from_containerfile(&path, args.context.as_deref(), args.tag.as_deref(), &build_args, None)?
+        }
+        None => {
+            let reqs = PipRequirements {
+                packages: args.pip,
+                requirements_file: args.requirements_file,
+                constraints_file: None,
+                wheel_dir: None,
+                use_uv: false,
+                labels: vec![],
+            };
+            resolver.pip_install_into_image(args.base.as_deref(), &reqs, args.tag.as_deref(), None, None, None)?
+        }
+    };
+    println!("{}", image);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn pull(images: &[String], max_parallel: usize) -> Result<ExitCode, VMError> {
+    let report = |p: PullProgress| match p.event {
+        "done" => eprintln!("{}: done ({} layers, {} bytes)", p.image, p.layers, p.bytes),
+        "failed" => {}
+        event => eprintln!("{}: {}", p.image, event),
+    };
+    let results = VMRunner::new().pre_pull_images(images, max_parallel, &report);
+    let mut failed = false;
+    for (image, result) in images.iter().zip(results) {
+        if let Err(e) = result {
+            eprintln!("flashvm: {}: {}", image, e);
+            failed = true;
+        }
+    }
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+fn images(json: bool) -> Result<ExitCode, VMError> {
+    let images = ImageResolver::new().list_cached_images()?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&images).map_err(|e| VMError::Cache(e.to_string()))?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("{:<60} {:<14} {:>10}  LAST USED", "NAME", "KIND", "SIZE (MB)");
+    for img in images {
+        println!(
+            "{:<60} {:<14} {:>10}  {}",
+            img.name,
+            img.kind.as_str(),
+            img.size_bytes / (1024 * 1024),
+            img.last_used.format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn gc(max_total_mb: Option<u64>, max_age: Option<u64>) -> Result<ExitCode, VMError> {
+    for name in ImageCache::new().gc_derived(max_age.unwrap_or(0), max_total_mb.unwrap_or(0))? {
+        println!("{}", name);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn bench(
+    runs: usize,
+    image: Option<String>,
+    use_pool: bool,
+    profile: Option<&str>,
+    config_file: Option<&Path>,
+    json: bool,
+) -> Result<ExitCode, VMError> {
+    let mut config = profiles::base_config(profile, config_file)?;
+    if image.is_some() {
+        config.image = image;
+    }
+    config.use_pool |= use_pool;
+    let report = bench::run(&config, runs)?;
+    if json {
+        println!("{}", serde_json::json!(report));
+        return Ok(ExitCode::SUCCESS);
+    }
+    let pool = if report.use_pool { ", warm pool" } else { "" };
+    println!("{} runs on {}{}", report.runs, report.backend, pool);
+    println!("{:<8} {:>10} {:>10}", "phase", "p50 ms", "p95 ms");
+    let rows = bench::PHASES.iter().map(|(name, _)| (*name, report.phases[name])).chain([("total", report.total)]);
+    for (name, p) in rows {
+        println!("{:<8} {:>10.1} {:>10.1}", name, p.p50_ms, p.p95_ms);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn doctor(json: bool) -> Result<ExitCode, VMError> {
+    let checks = diagnostics::run_checks();
+    let ready = checks.iter().all(|c| c.ok || !diagnostics::is_required(c.name));
+    if json {
+        let checks: Vec<serde_json::Value> = checks
+            .iter()
+            .map(|c| {
+                serde_json::json!({
+                    "name": c.name,
+                    "ok": c.ok,
+                    "required": diagnostics::is_required(c.name),
+                    "detail": c.detail,
+                    "hint": c.hint,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::json!({ "ready": ready, "arch": platform::host_arch(), "checks": checks }));
+    } else {
+        println!("host {}/{}", std::env::consts::OS, platform::host_arch());
+        for c in &checks {
+            let status = match (c.ok, diagnostics::is_required(c.name)) {
+                (true, _) => "ok",
+                (false, true) => "FAIL",
+                (false, false) => "warn",
+            };
+            println!("{:<4} {}: {}", status, c.name, c.detail);
+            if let (false, Some(hint)) = (c.ok, &c.hint) {
+                println!("     {}", hint);
+            }
+        }
+    }
+    Ok(if ready { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
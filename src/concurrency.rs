@@ -0,0 +1,249 @@
+use crate::error::VMError;
+use crate::global_config;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Limits the scheduler admits runs under.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SchedulerConfig {
+    /// VMs running at once
+    pub max_vms: usize,
+    /// Sum of the running VMs' `memory_mb` (0 = no limit)
+    pub max_memory_mb: u64,
+    /// Runs waiting for admission; more are turned away (0 = no limit)
+    pub max_queue: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        let section = global_config::global().scheduler.clone().unwrap_or_default();
+        let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        Self {
+            max_vms: section.max_vms.unwrap_or(cpus).max(1),
+            max_memory_mb: section.max_memory_mb.unwrap_or_else(host_memory_mb),
+            max_queue: section.max_queue.unwrap_or(0),
+        }
+    }
+}
+
+/// Host RAM from `/proc/meminfo`, or 0 (no limit) when it can't be read.
+fn host_memory_mb() -> u64 {
+    let meminfo = fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    meminfo
+        .lines()
+        .find_map(|l| l.strip_prefix("MemTotal:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or(0, |kb| kb / 1024)
+}
+
+/// Admission control for VMs: a run starts only while the VM count and the memory committed
+/// to running VMs stay within `SchedulerConfig`. The rest wait in arrival order, each up to
+/// its own deadline, so a burst queues instead of overcommitting the host.
+pub struct Scheduler {
+    state: Mutex<SchedulerState>,
+    changed: Condvar,
+}
+
+struct SchedulerState {
+    config: SchedulerConfig,
+    running: usize,
+    memory_in_use_mb: u64,
+    /// Tickets of waiting runs, oldest first, with their memory and arrival time
+    queue: VecDeque<(u64, u64, Instant)>,
+    next_ticket: u64,
+    admitted: u64,
+    rejected: u64,
+    timed_out: u64,
+    wait_ms_total: u64,
+}
+
+impl SchedulerState {
+    fn fits(&self, memory_mb: u64) -> bool {
+        self.running < self.config.max_vms
+            && (self.config.max_memory_mb == 0 || self.memory_in_use_mb + memory_mb <= self.config.max_memory_mb)
+    }
+}
+
+/// Queue and usage counters, as `scheduler_stats()` reports them.
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulerStats {
+    #[serde(flatten)]
+    pub config: SchedulerConfig,
+    pub running: usize,
+    pub memory_in_use_mb: u64,
+    pub queued: usize,
+    pub queued_memory_mb: u64,
+    /// How long the oldest waiting run has waited
+    pub oldest_wait_ms: u64,
+    pub admitted_total: u64,
+    /// Turned away because the queue was full
+    pub rejected_total: u64,
+    /// Gave up waiting at their deadline
+    pub timed_out_total: u64,
+    /// Time admitted runs spent queued, summed
+    pub wait_ms_total: u64,
+}
+
+/// Held while a VM is running; gives its capacity back on drop.
+pub struct Admission<'a> {
+    scheduler: &'a Scheduler,
+    memory_mb: u64,
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self {
+            state: Mutex::new(SchedulerState {
+                config,
+                running: 0,
+                memory_in_use_mb: 0,
+                queue: VecDeque::new(),
+                next_ticket: 0,
+                admitted: 0,
+                rejected: 0,
+                timed_out: 0,
+                wait_ms_total: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait until a VM of `memory_mb` fits and every run queued before it has started, or
+    /// until `deadline`. Fails at once when the queue is full or the VM could never fit.
+    pub fn admit(&self, memory_mb: u32, deadline: Option<Instant>) -> Result<Admission<'_>, VMError> {
+        let memory_mb = u64::from(memory_mb);
+        let mut st = self.lock();
+        if st.config.max_memory_mb > 0 && memory_mb > st.config.max_memory_mb {
+            return Err(VMError::VMConfiguration(format!(
+                "memory_mb={} exceeds the scheduler's max_memory_mb={}",
+                memory_mb, st.config.max_memory_mb
+            )));
+        }
+        if st.queue.is_empty() && st.fits(memory_mb) {
+            return Ok(self.start(&mut st, memory_mb, Duration::ZERO));
+        }
+        if st.config.max_queue > 0 && st.queue.len() >= st.config.max_queue {
+            st.rejected += 1;
+            return Err(VMError::Overloaded(format!(
+                "{} runs already queued (max_queue={})",
+                st.queue.len(),
+                st.config.max_queue
+            )));
+        }
+        let ticket = st.next_ticket;
+        st.next_ticket += 1;
+        let queued_at = Instant::now();
+        st.queue.push_back((ticket, memory_mb, queued_at));
+        loop {
+            if st.queue.front().is_some_and(|&(t, _, _)| t == ticket) && st.fits(memory_mb) {
+                st.queue.pop_front();
+                // The next run in line may fit as well
+                self.changed.notify_all();
+                return Ok(self.start(&mut st, memory_mb, queued_at.elapsed()));
+            }
+            st = match deadline {
+                None => self.changed.wait(st).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        st.queue.retain(|&(t, _, _)| t != ticket);
+                        st.timed_out += 1;
+                        self.changed.notify_all();
+                        return Err(VMError::Overloaded(format!(
+                            "no capacity for a {} MiB VM within {:?} ({} running, {} MiB in use)",
+                            memory_mb,
+                            queued_at.elapsed(),
+                            st.running,
+                            st.memory_in_use_mb
+                        )));
+                    }
+                    self.changed.wait_timeout(st, deadline - now).unwrap_or_else(|e| e.into_inner()).0
+                }
+            };
+        }
+    }
+
+    fn start(&self, st: &mut SchedulerState, memory_mb: u64, waited: Duration) -> Admission<'_> {
+        st.running += 1;
+        st.memory_in_use_mb += memory_mb;
+        st.admitted += 1;
+        st.wait_ms_total += waited.as_millis() as u64;
+        Admission { scheduler: self, memory_mb }
+    }
+
+    pub fn config(&self) -> SchedulerConfig {
+        self.lock().config
+    }
+
+    pub fn configure(&self, config: SchedulerConfig) {
+        self.lock().config = SchedulerConfig { max_vms: config.max_vms.max(1), ..config };
+        self.changed.notify_all();
+    }
+
+    /// Whether a run arriving now would have to wait.
+    pub fn busy(&self, memory_mb: u32) -> bool {
+        let st = self.lock();
+        !st.queue.is_empty() || !st.fits(u64::from(memory_mb))
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        let st = self.lock();
+        SchedulerStats {
+            config: st.config,
+            running: st.running,
+            memory_in_use_mb: st.memory_in_use_mb,
+            queued: st.queue.len(),
+            queued_memory_mb: st.queue.iter().map(|&(_, m, _)| m).sum(),
+            oldest_wait_ms: st.queue.front().map_or(0, |&(_, _, at)| at.elapsed().as_millis() as u64),
+            admitted_total: st.admitted,
+            rejected_total: st.rejected,
+            timed_out_total: st.timed_out,
+            wait_ms_total: st.wait_ms_total,
+        }
+    }
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        let mut st = self.scheduler.lock();
+        st.running -= 1;
+        st.memory_in_use_mb -= self.memory_mb;
+        self.scheduler.changed.notify_all();
+    }
+}
+
+/// Process-wide scheduler; by default as many VMs as host CPUs, within the host's RAM.
+pub fn scheduler() -> &'static Scheduler {
+    static SCHEDULER: OnceLock<Scheduler> = OnceLock::new();
+    SCHEDULER.get_or_init(|| Scheduler::new(SchedulerConfig::default()))
+}
+
+/// Exclusive advisory `flock` on a file, for work that must not overlap across processes.
+/// Released when dropped.
+pub struct FileLock {
+    _file: fs::File,
+}
+
+impl FileLock {
+    /// Block until the lock on `path` (created if missing) is held.
+    pub fn acquire(path: &Path) -> Result<Self, VMError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+        // SAFETY: fd is valid for the lifetime of `file`; the lock is released when it closes
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self { _file: file })
+    }
+}
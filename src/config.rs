@@ -1,6 +1,19 @@
+use crate::coverage::CoverageReport;
+use crate::cprofile::ProfileReport;
+use crate::display::DisplayEntry;
+use crate::error::VMError;
+use crate::global_config;
+use crate::seccomp::{SeccompProfile, SeccompSetting};
+use crate::secrets::Secrets;
+use crate::settings;
+use memmap2::Mmap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Main configuration for running Python code in a microVM
@@ -22,15 +35,68 @@ pub struct VMConfig {
     pub network: bool,
     /// Ports (host:guest) when network=true
     pub ports: Vec<(u16, u16)>,
+    /// Permit host ports below 1024 in `ports`
+    pub allow_privileged_ports: bool,
     /// Extra Python args (e.g., -u)
     pub python_args: Vec<String>,
     /// Max size in bytes to inline artifacts
     pub max_bytes_inline: u64,
+    /// Keep the VM and workspace after the run (for debugging)
+    pub keep_vm: KeepVm,
+    /// Retries for `krunvm start` failures that happen before user code runs
+    pub retry_policy: RetryPolicy,
+    /// Reuse a warm, already-created VM for this image/shape when one is idle
+    pub use_pool: bool,
+    /// Resolve registry tags to `name@sha256:...` before pulling, so `image_used` is reproducible
+    pub pin_digest: bool,
+    /// Packages (pip requirement specifiers) installed into a throwaway venv before the run
+    pub requirements: Vec<String>,
+    /// Host directory of wheels to install `requirements` from, offline
+    pub wheel_dir: Option<PathBuf>,
+    /// Install `requirements` with `uv pip install` when the image has `uv`, else pip
+    pub use_uv: bool,
+    /// Mount the shared pip cache (see `CacheConfig::pip_cache`); needs `network`
+    pub pip_cache: bool,
+    /// Egress proxy exported to the guest (and to pip inside it)
+    pub proxy: Option<ProxyConfig>,
+    /// Outbound allowlist; None = unrestricted when network is on
+    pub egress: Option<EgressPorts>,
+    pub capture: CaptureMode,
+    /// Also build a merged, timestamped stdout+stderr view (implies JSON-lines capture)
+    pub merge_streams: bool,
+    /// Run user code as this (uid, gid) instead of the guest's root
+    pub run_as: Option<(u32, u32)>,
+    /// setrlimit limits for user code, applied by the guest runner before it starts
+    pub rlimits: Rlimits,
+    /// Syscalls denied to user code by a seccomp filter in the guest
+    pub seccomp: Option<SeccompProfile>,
+    /// Files under /run/secrets in the guest (tmpfs); never written to the workspace
+    pub secrets: Secrets,
+    /// Caller's key/value tags for the run, recorded in the audit log
+    pub labels: BTreeMap<String, String>,
+    /// Return a stored result for an identical earlier run instead of booting a VM
+    pub result_cache: bool,
+    /// Longest wait for the scheduler to admit the run; None = wait as long as it takes
+    pub queue_timeout: Option<Duration>,
+    /// Tenant the run works for: its images, caches and quotas (see `tenants`)
+    pub tenant: Option<String>,
+    /// Run user code under coverage.py (the image needs it) and summarize what it ran
+    pub coverage: bool,
+    /// Run user code under cProfile and summarize where the time went
+    pub cprofile: bool,
+    /// Capture figures and rich reprs the way a notebook would (see `display`)
+    pub display: bool,
+    /// Distributed-trace context exported to the guest's environment
+    pub trace: TraceContext,
+    /// IANA zone the guest's local time is in (`TZ` and /etc/localtime); UTC when unset
+    pub timezone: Option<String>,
+    /// `LANG`/`LC_ALL` for user code; the image's default when unset
+    pub locale: Option<String>,
 }
 
 impl Default for VMConfig {
     fn default() -> Self {
-        Self {
+        let mut config = Self {
             image: None,
             cpus: 1,
             memory_mb: 512,
@@ -39,12 +105,436 @@ impl Default for VMConfig {
             timeout: Duration::from_secs(30),
             network: false,
             ports: vec![],
+            allow_privileged_ports: false,
             python_args: vec!["-u".to_string()],
             max_bytes_inline: 1024 * 1024, // 1MB
+            keep_vm: KeepVm::Never,
+            retry_policy: RetryPolicy::default(),
+            use_pool: false,
+            pin_digest: false,
+            requirements: vec![],
+            wheel_dir: None,
+            use_uv: false,
+            pip_cache: false,
+            proxy: None,
+            egress: None,
+            capture: CaptureMode::Stdio,
+            merge_streams: false,
+            run_as: None,
+            rlimits: Rlimits::default(),
+            seccomp: None,
+            secrets: Secrets::default(),
+            labels: BTreeMap::new(),
+            result_cache: false,
+            queue_timeout: None,
+            tenant: None,
+            coverage: false,
+            cprofile: false,
+            display: false,
+            trace: TraceContext::default(),
+            timezone: None,
+            locale: None,
+        };
+        if let Some(run) = global_config::global().run.clone() {
+            if let Err(e) = run.apply(&mut config) {
+                log::warn!("Ignoring [run] in the global config: {}", e);
+            }
+        }
+        let env_defaults = settings::env_defaults();
+        if env_defaults.image.is_some() {
+            config.image = env_defaults.image.clone();
+        }
+        if let Some(timeout) = env_defaults.timeout {
+            config.timeout = timeout;
+        }
+        config
+    }
+}
+
+impl VMConfig {
+    /// Capture mode actually used in the guest (`merge_streams` needs output records)
+    pub fn effective_capture(&self) -> CaptureMode {
+        if self.merge_streams && self.capture == CaptureMode::Stdio {
+            CaptureMode::Both
+        } else {
+            self.capture
+        }
+    }
+}
+
+/// Sandbox policy as written in a config file (TOML, YAML or JSON); every key is optional
+/// and uses the name of the matching `run` argument. See `VMConfig::with_file`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VMConfigFile {
+    pub image: Option<String>,
+    pub cpus: Option<u32>,
+    pub memory_mb: Option<u32>,
+    pub env: Option<HashMap<String, String>>,
+    pub timeout_seconds: Option<u64>,
+    pub workdir: Option<String>,
+    pub python_args: Option<Vec<String>>,
+    pub network: Option<bool>,
+    pub ports: Option<Vec<(u16, u16)>>,
+    pub allow_privileged_ports: Option<bool>,
+    pub max_bytes_inline: Option<u64>,
+    pub keep_vm: Option<String>,
+    pub use_pool: Option<bool>,
+    pub pin_digest: Option<bool>,
+    pub requirements: Option<Vec<String>>,
+    pub wheel_dir: Option<PathBuf>,
+    pub use_uv: Option<bool>,
+    pub pip_cache: Option<bool>,
+    pub proxy: Option<ProxyFile>,
+    pub allow_egress_ports: Option<Vec<u16>>,
+    pub capture: Option<String>,
+    pub merge_streams: Option<bool>,
+    pub run_as: Option<(u32, u32)>,
+    pub rlimits: Option<Rlimits>,
+    pub seccomp: Option<SeccompSetting>,
+    pub labels: Option<BTreeMap<String, String>>,
+    pub result_cache: Option<bool>,
+    pub queue_timeout_seconds: Option<u64>,
+    pub tenant: Option<String>,
+    pub coverage: Option<bool>,
+    pub cprofile: Option<bool>,
+    pub display: Option<bool>,
+    pub trace: Option<TraceContext>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyFile {
+    pub http: Option<String>,
+    pub https: Option<String>,
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl VMConfigFile {
+    /// Parse `path` by its extension: `.toml`, `.yaml`/`.yml` or `.json`.
+    pub fn load(path: &Path) -> Result<Self, VMError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| VMError::VMConfiguration(format!("cannot read {}: {}", path.to_string_lossy(), e)))?;
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        let parsed = match ext.as_str() {
+            "toml" => toml::from_str(&text).map_err(|e| e.to_string()),
+            "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+            "json" => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => Err("unsupported extension; use .toml, .yaml, .yml or .json".to_string()),
+        };
+        parsed.map_err(|e| VMError::VMConfiguration(format!("invalid config file {}: {}", path.to_string_lossy(), e)))
+    }
+
+    /// Overwrite the fields of `config` this file sets.
+    pub fn apply(self, config: &mut VMConfig) -> Result<(), VMError> {
+        fn set<T>(target: &mut T, value: Option<T>) {
+            if let Some(v) = value {
+                *target = v;
+            }
+        }
+        if self.image.is_some() {
+            config.image = self.image;
+        }
+        set(&mut config.cpus, self.cpus);
+        set(&mut config.memory_mb, self.memory_mb);
+        set(&mut config.env, self.env);
+        set(&mut config.timeout, self.timeout_seconds.map(Duration::from_secs));
+        set(&mut config.workdir, self.workdir);
+        set(&mut config.python_args, self.python_args);
+        set(&mut config.network, self.network);
+        set(&mut config.ports, self.ports);
+        set(&mut config.allow_privileged_ports, self.allow_privileged_ports);
+        set(&mut config.max_bytes_inline, self.max_bytes_inline);
+        set(&mut config.keep_vm, self.keep_vm.as_deref().map(KeepVm::parse).transpose().map_err(VMError::VMConfiguration)?);
+        set(&mut config.use_pool, self.use_pool);
+        set(&mut config.pin_digest, self.pin_digest);
+        set(&mut config.requirements, self.requirements);
+        if self.wheel_dir.is_some() {
+            config.wheel_dir = self.wheel_dir;
+        }
+        set(&mut config.use_uv, self.use_uv);
+        set(&mut config.pip_cache, self.pip_cache);
+        if let Some(p) = self.proxy {
+            config.proxy = Some(ProxyConfig { http: p.http, https: p.https, no_proxy: p.no_proxy });
+        }
+        if let Some(ports) = self.allow_egress_ports {
+            config.egress = Some(EgressPorts::new(&ports).map_err(VMError::VMConfiguration)?);
+        }
+        set(&mut config.capture, self.capture.as_deref().map(CaptureMode::parse).transpose().map_err(VMError::VMConfiguration)?);
+        set(&mut config.merge_streams, self.merge_streams);
+        if self.run_as.is_some() {
+            config.run_as = self.run_as;
+        }
+        set(&mut config.rlimits, self.rlimits);
+        if let Some(setting) = self.seccomp {
+            config.seccomp = Some(setting.resolve().map_err(VMError::VMConfiguration)?);
+        }
+        if let Some(labels) = self.labels {
+            config.labels.extend(labels);
+        }
+        set(&mut config.result_cache, self.result_cache);
+        if let Some(seconds) = self.queue_timeout_seconds {
+            config.queue_timeout = Some(Duration::from_secs(seconds));
+        }
+        if self.tenant.is_some() {
+            config.tenant = self.tenant;
+        }
+        set(&mut config.coverage, self.coverage);
+        set(&mut config.cprofile, self.cprofile);
+        set(&mut config.display, self.display);
+        set(&mut config.trace, self.trace);
+        if self.timezone.is_some() {
+            config.timezone = self.timezone;
+        }
+        if self.locale.is_some() {
+            config.locale = self.locale;
+        }
+        Ok(())
+    }
+}
+
+impl VMConfig {
+    /// `self` with the settings of a config file laid over it.
+    pub fn with_file(mut self, path: &Path) -> Result<Self, VMError> {
+        VMConfigFile::load(path)?.apply(&mut self)?;
+        Ok(self)
+    }
+}
+
+/// stderr fragments meaning the guest can never start, so retrying is pointless.
+pub const PERMANENT_START_FAILURES: &[&str] = &[
+    "flashvm: egress policy",
+    "No such file or directory",
+    "not found",
+    "Permission denied",
+    "Invalid argument",
+];
+
+/// Backoff schedule for VM start attempts
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub backoff_multiplier: f64,
+    pub max_backoff: Duration,
+    /// A failed start whose stderr contains any of these is not retried
+    pub permanent_errors: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(150),
+            backoff_multiplier: 2.0,
+            max_backoff: Duration::from_secs(2),
+            permanent_errors: PERMANENT_START_FAILURES.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the attempt following `attempt` (1-based)
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        let factor = self.backoff_multiplier.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        let secs = (self.initial_backoff.as_secs_f64() * factor).min(self.max_backoff.as_secs_f64());
+        Duration::from_secs_f64(secs)
+    }
+
+    /// Whether a start that failed with `stderr` is worth another attempt
+    pub fn is_retriable(&self, stderr: &str) -> bool {
+        !self.permanent_errors.iter().any(|m| stderr.contains(m.as_str()))
+    }
+}
+
+/// When to skip deleting the VM and workspace after a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeepVm {
+    #[default]
+    Never,
+    /// Keep only when the run exits non-zero or times out
+    OnFailure,
+    Always,
+}
+
+impl KeepVm {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "never" => Ok(KeepVm::Never),
+            "on_failure" => Ok(KeepVm::OnFailure),
+            "always" => Ok(KeepVm::Always),
+            other => Err(format!(
+                "invalid keep_vm '{}': expected 'never', 'on_failure' or 'always'",
+                other
+            )),
+        }
+    }
+}
+
+/// HTTP(S) proxy settings for networked runs and image builds
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    pub http: Option<String>,
+    pub https: Option<String>,
+    /// Hosts/domains that bypass the proxy
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Variables in both spellings, since tools disagree on which case they read.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        let mut vars = vec![];
+        let no_proxy = (!self.no_proxy.is_empty()).then(|| self.no_proxy.join(","));
+        for (name, value) in [("HTTP_PROXY", &self.http), ("HTTPS_PROXY", &self.https), ("NO_PROXY", &no_proxy)] {
+            if let Some(v) = value {
+                vars.push((name.to_string(), v.clone()));
+                vars.push((name.to_lowercase(), v.clone()));
+            }
+        }
+        vars
+    }
+}
+
+/// Outbound TCP ports the guest may connect to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EgressPorts {
+    pub allowed_ports: Vec<u16>,
+}
+
+impl EgressPorts {
+    /// Only ports: krunvm's TSI networking connects from the VMM process, which Landlock
+    /// can filter by port but not by destination host.
+    pub fn new(ports: &[u16]) -> Result<Self, String> {
+        if ports.contains(&0) {
+            return Err("allow_egress_ports: 0 is not a valid port".to_string());
+        }
+        let mut allowed_ports = ports.to_vec();
+        allowed_ports.sort_unstable();
+        allowed_ports.dedup();
+        Ok(Self { allowed_ports })
+    }
+}
+
+/// Per-process limits for user code; each one set sets both the soft and the hard limit.
+/// `None` keeps the guest's default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rlimits {
+    /// Open file descriptors (RLIMIT_NOFILE)
+    pub nofile: Option<u64>,
+    /// Processes and threads of the user (RLIMIT_NPROC); root ignores it, so it needs `run_as`
+    pub nproc: Option<u64>,
+    /// Largest file the process may write, in bytes (RLIMIT_FSIZE)
+    pub fsize: Option<u64>,
+    /// Core dump size in bytes (RLIMIT_CORE); 0 disables core dumps
+    pub core: Option<u64>,
+}
+
+impl Rlimits {
+    /// Accepts the keys "nofile", "nproc", "fsize" and "core".
+    #[cfg(feature = "python")]
+    pub fn from_map(limits: &HashMap<String, u64>) -> Result<Self, String> {
+        let mut rlimits = Rlimits::default();
+        for (name, &value) in limits {
+            let slot = match name.as_str() {
+                "nofile" => &mut rlimits.nofile,
+                "nproc" => &mut rlimits.nproc,
+                "fsize" => &mut rlimits.fsize,
+                "core" => &mut rlimits.core,
+                other => {
+                    return Err(format!(
+                        "invalid rlimit '{}': expected 'nofile', 'nproc', 'fsize' or 'core'",
+                        other
+                    ))
+                }
+            };
+            *slot = Some(value);
+        }
+        Ok(rlimits)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Rlimits::default()
+    }
+}
+
+/// Where a run sits in the caller's distributed trace. The run's span joins `traceparent`'s
+/// trace, and the guest gets the context as `TRACEPARENT`/`TRACESTATE` (the OpenTelemetry
+/// environment carrier) and `FLASHVM_CORRELATION_ID`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraceContext {
+    /// W3C `traceparent`: `00-<32 hex trace id>-<16 hex parent id>-<2 hex flags>`
+    pub traceparent: Option<String>,
+    /// W3C `tracestate`, passed on as is
+    pub tracestate: Option<String>,
+    /// Any ID of the caller's, for logs that don't speak W3C trace context
+    pub correlation_id: Option<String>,
+}
+
+impl TraceContext {
+    /// Accepts the keys "traceparent", "tracestate" and "correlation_id".
+    #[cfg(feature = "python")]
+    pub fn from_map(values: &HashMap<String, String>) -> Result<Self, String> {
+        let mut trace = TraceContext::default();
+        for (name, value) in values {
+            let slot = match name.as_str() {
+                "traceparent" => &mut trace.traceparent,
+                "tracestate" => &mut trace.tracestate,
+                "correlation_id" => &mut trace.correlation_id,
+                other => {
+                    return Err(format!(
+                        "invalid trace key '{}': expected 'traceparent', 'tracestate' or 'correlation_id'",
+                        other
+                    ))
+                }
+            };
+            *slot = Some(value.clone());
+        }
+        Ok(trace)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(traceparent) = &self.traceparent {
+            if !is_traceparent(traceparent) {
+                return Err(format!(
+                    "invalid traceparent '{}': expected 00-<32 hex trace id>-<16 hex parent id>-<2 hex flags>",
+                    traceparent
+                ));
+            }
+        }
+        for (name, value, max) in [("tracestate", &self.tracestate, 512), ("correlation_id", &self.correlation_id, 256)] {
+            if let Some(value) = value {
+                if value.is_empty() || value.len() > max || !value.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+                    return Err(format!("{} must be 1 to {} printable ASCII characters", name, max));
+                }
+            }
         }
+        Ok(())
+    }
+
+    /// Environment the guest gets; the caller's `env` entries override these.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        [("TRACEPARENT", &self.traceparent), ("TRACESTATE", &self.tracestate), ("FLASHVM_CORRELATION_ID", &self.correlation_id)]
+            .into_iter()
+            .filter_map(|(name, value)| value.clone().map(|v| (name.to_string(), v)))
+            .collect()
     }
 }
 
+/// Version 00 layout, with the all-zero IDs the spec forbids rejected
+fn is_traceparent(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    let zero = |s: &str| s.bytes().all(|b| b == b'0');
+    matches!(parts.as_slice(), [version, trace_id, parent_id, flags]
+        if *version == "00" && hex(trace_id, 32) && hex(parent_id, 16) && hex(flags, 2) && !zero(trace_id) && !zero(parent_id))
+}
+
 /// Input files
 #[derive(Debug, Clone)]
 pub struct FileInput {
@@ -67,6 +557,80 @@ pub struct ExecutionResult {
     pub execution_time: Duration,
     pub artifacts: Vec<Artifact>,
     pub image_used: String,
+    /// Run ID (also embedded in the krunvm VM name)
+    pub run_id: String,
+    /// Set when the VM and workspace were left in place (see `KeepVm`)
+    pub kept_vm: Option<KeptVm>,
+    /// `krunvm start` attempts made (see `RetryPolicy`)
+    pub attempts: u32,
+    /// Ordered output records (`CaptureMode::JsonLines`/`Both` only)
+    pub output: Option<Vec<OutputChunk>>,
+    /// Both streams line by line in production order, each prefixed with `[+<ts>s <stream>]`
+    pub merged_output: Option<String>,
+    /// Served from the result cache instead of a fresh run
+    #[serde(default)]
+    pub cached: bool,
+    /// With `VMConfig::coverage`, when coverage.py produced a report
+    #[serde(default)]
+    pub coverage: Option<CoverageReport>,
+    /// With `VMConfig::cprofile`, when the code got to run
+    #[serde(default)]
+    pub cprofile: Option<ProfileReport>,
+    /// With `VMConfig::display`, the figures and rich reprs the code displayed, in order
+    #[serde(default)]
+    pub display: Vec<DisplayEntry>,
+    /// What the code wrote to `/work/out/flashvm_result.json`, parsed
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+    /// Peak RSS of the user process as the guest runner measured it, for sizing `memory_mb`
+    #[serde(default)]
+    pub peak_memory_bytes: Option<u64>,
+    /// Where `execution_time` went
+    #[serde(default)]
+    pub timings: PhaseTimings,
+}
+
+/// Wall-clock time of each phase of a run. Phases a run skipped (`create` for a pooled VM,
+/// everything after `resolve` for a cached result) are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    /// Resolving the image, and converting it for krunvm when a VM is created
+    pub resolve: Duration,
+    /// Staging inputs, wheels, the code and the guest runner's spec in the workspace
+    #[serde(default)]
+    pub stage: Duration,
+    /// `krunvm create`
+    pub create: Duration,
+    /// `krunvm start` until the guest runner is about to start user code, retries included
+    pub boot: Duration,
+    /// User code, until the VM is down
+    pub exec: Duration,
+    /// Reading the guest's status, output and artifacts
+    pub collect: Duration,
+}
+
+impl PhaseTimings {
+    /// As results report them: `{"resolve_ms": ..., ...}`, in whole milliseconds
+    #[cfg(any(feature = "python", feature = "daemon"))]
+    pub fn to_json(self) -> serde_json::Value {
+        let ms = |d: Duration| d.as_millis() as u64;
+        serde_json::json!({
+            "resolve_ms": ms(self.resolve),
+            "stage_ms": ms(self.stage),
+            "create_ms": ms(self.create),
+            "boot_ms": ms(self.boot),
+            "exec_ms": ms(self.exec),
+            "collect_ms": ms(self.collect),
+        })
+    }
+}
+
+/// VM and workspace preserved after a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeptVm {
+    pub vm_name: String,
+    /// Host directory mounted at /work in the guest
+    pub workspace: PathBuf,
 }
 
 /// Collected artifact
@@ -75,34 +639,251 @@ pub struct Artifact {
     pub guest_path: String,
     pub host_path: PathBuf,
     pub size_bytes: u64,
-    pub content: Option<Vec<u8>>,
+    /// Set for artifacts up to `max_bytes_inline`
+    pub content: Option<ArtifactContent>,
 }
 
-/// Capture mode (future use)
-#[derive(Debug, Clone)]
+/// Bytes of an inline artifact. Collected files are mapped rather than read, so only the pages a
+/// caller touches are ever read; the mapping outlives the workspace the file was in.
+#[derive(Clone)]
+pub enum ArtifactContent {
+    Owned(Vec<u8>),
+    Mapped(Arc<Mmap>),
+}
+
+impl ArtifactContent {
+    /// Map `path`, which nothing may write to or truncate again: a file in the workspace of a
+    /// VM that is gone, or in a result cache entry. Use `read` for anything else.
+    pub fn map(path: &Path) -> Result<Self, VMError> {
+        let file = fs::File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Ok(ArtifactContent::Owned(Vec::new()));
+        }
+        // SAFETY: callers only map files that are never modified again. Workspaces of finished
+        // runs are deleted or wiped for the next pooled run, and cache entries are write-once and
+        // only ever removed; both unlink the files, which keeps the mapped pages valid.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(ArtifactContent::Mapped(Arc::new(map)))
+    }
+
+    /// Copy `path` into memory, for files that may still change (e.g. in a kept VM's workspace).
+    pub fn read(path: &Path) -> Result<Self, VMError> {
+        Ok(ArtifactContent::Owned(fs::read(path)?))
+    }
+}
+
+impl Deref for ArtifactContent {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ArtifactContent::Owned(data) => data,
+            ArtifactContent::Mapped(map) => map,
+        }
+    }
+}
+
+impl AsRef<[u8]> for ArtifactContent {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for ArtifactContent {
+    fn from(data: Vec<u8>) -> Self {
+        ArtifactContent::Owned(data)
+    }
+}
+
+impl fmt::Debug for ArtifactContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ArtifactContent({} bytes)", self.len())
+    }
+}
+
+// Same shape as the `Vec<u8>` this used to be, so stored results stay readable
+impl Serialize for ArtifactContent {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for ArtifactContent {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<u8>::deserialize(deserializer).map(ArtifactContent::from)
+    }
+}
+
+/// How guest output is captured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum CaptureMode {
+    /// Raw console streams only
+    #[default]
     Stdio,
+    /// Guest runner records timestamped chunks; stdout/stderr are rebuilt from them
     JsonLines,
+    /// Records plus the raw console streams
     Both,
 }
 
+impl CaptureMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "stdio" => Ok(CaptureMode::Stdio),
+            "json_lines" => Ok(CaptureMode::JsonLines),
+            "both" => Ok(CaptureMode::Both),
+            other => Err(format!(
+                "invalid capture '{}': expected 'stdio', 'json_lines' or 'both'",
+                other
+            )),
+        }
+    }
+
+    /// Value of `capture` in the guest spec
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaptureMode::Stdio => "stdio",
+            CaptureMode::JsonLines => "json_lines",
+            CaptureMode::Both => "both",
+        }
+    }
+}
+
+/// One chunk of guest output, as recorded by the guest runner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputChunk {
+    /// "stdout" or "stderr"
+    pub stream: String,
+    /// Seconds since the user process started
+    pub ts: f64,
+    pub chunk: String,
+}
+
+/// Progress of one image in `VMRunner::pre_pull_images`
+#[cfg(any(feature = "python", feature = "cli"))]
+#[derive(Debug, Clone)]
+pub struct PullProgress {
+    pub image: String,
+    /// "started", "layer", "done" or "failed"
+    pub event: &'static str,
+    /// Layers copied so far (0 for images that were already local)
+    pub layers: u32,
+    /// Size of the image in storage; only known once it is done
+    pub bytes: u64,
+    /// Only reported by the Python API; the CLI prints the error `pre_pull_images` returns
+    #[cfg_attr(not(feature = "python"), allow(dead_code))]
+    pub error: Option<String>,
+}
+
+/// What `pip_install_into_image` installs: packages and/or a requirements file, optionally
+/// under a constraints file and from a local wheel directory (all copied into the build
+/// container).
+#[cfg(any(feature = "python", feature = "cli"))]
+#[derive(Debug, Clone, Default)]
+pub struct PipRequirements {
+    pub packages: Vec<String>,
+    pub requirements_file: Option<PathBuf>,
+    pub constraints_file: Option<PathBuf>,
+    /// Install only from these wheels (`--no-index --find-links`); required in offline mode
+    pub wheel_dir: Option<PathBuf>,
+    /// Install with `uv pip install` when the base image has `uv`, else pip
+    pub use_uv: bool,
+    /// Extra labels set on the committed image
+    pub labels: Vec<(String, String)>,
+}
+
+#[cfg(any(feature = "python", feature = "cli"))]
+impl PipRequirements {
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.requirements_file.is_none()
+    }
+}
+
+/// What `conda_install_into_image` installs: an environment file (`environment.yml`)
+/// and/or packages, from `channels` (conda-forge when empty).
+#[cfg(feature = "python")]
+#[derive(Debug, Clone, Default)]
+pub struct CondaSpec {
+    pub packages: Vec<String>,
+    pub env_file: Option<PathBuf>,
+    pub channels: Vec<String>,
+}
+
+#[cfg(feature = "python")]
+impl CondaSpec {
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.env_file.is_none()
+    }
+}
+
+/// Credentials for pushing to a registry. `Debug` never prints the password.
+#[cfg(feature = "python")]
+#[derive(Clone)]
+pub enum RegistryAuth {
+    Credentials { username: String, password: String },
+    /// A containers-auth.json / docker config.json
+    AuthFile(PathBuf),
+}
+
+#[cfg(feature = "python")]
+impl std::fmt::Debug for RegistryAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryAuth::Credentials { username, .. } => write!(f, "Credentials({}, <redacted>)", username),
+            RegistryAuth::AuthFile(p) => write!(f, "AuthFile({:?})", p),
+        }
+    }
+}
+
 /// Local cache/config
 #[derive(Debug, Clone)]
 pub struct CacheConfig {
+    /// Manifest, pip cache, run records and lock files; starts from `FLASHVM_CACHE_DIR`, the
+    /// global config, then `$XDG_CACHE_HOME/flashvm` or `~/.cache/flashvm`
     pub cache_dir: String,
+    /// Cached images unused for longer than this are evicted (0 = no limit)
     pub cache_ttl_seconds: u64,
+    /// Evict least recently used images beyond this total (0 = no limit)
     pub max_cache_size_mb: u64,
+    /// Run an eviction pass before importing or building an image
+    pub evict_before_import: bool,
+    /// Repository flashvm's images live under, `<namespace>:<tag>`; starts from
+    /// `FLASHVM_IMAGE_NAMESPACE`
+    pub image_namespace: String,
+    /// Share a pip/uv download cache (under `cache_dir`) between builds
+    pub pip_cache: bool,
+    /// Trim the pip cache's oldest files beyond this total (0 = no limit)
+    pub pip_cache_max_mb: u64,
+    /// Stored run results older than this are discarded (0 = no limit)
+    pub result_ttl_seconds: u64,
+}
+
+/// `$XDG_CACHE_HOME/flashvm`, else `~/.cache/flashvm`. Relative `XDG_CACHE_HOME` values are
+/// ignored, as the XDG spec requires.
+fn default_cache_dir() -> String {
+    match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) if Path::new(&dir).is_absolute() => format!("{}/flashvm", dir.trim_end_matches('/')),
+        _ => format!("{}/.cache/flashvm", std::env::var("HOME").unwrap_or("/tmp".to_string())),
+    }
 }
 
 impl Default for CacheConfig {
     fn default() -> Self {
+        let global = global_config::global();
+        let cache = global.cache.clone().unwrap_or_default();
         Self {
-            cache_dir: format!(
-                "{}/.cache/flashvm",
-                std::env::var("HOME").unwrap_or("/tmp".to_string())
-            ),
-            cache_ttl_seconds: 24 * 3600,
-            max_cache_size_mb: 1024,
+            cache_dir: settings::env_defaults().cache_dir.clone().unwrap_or_else(default_cache_dir),
+            cache_ttl_seconds: cache.ttl_seconds.unwrap_or(24 * 3600),
+            max_cache_size_mb: cache.max_size_mb.unwrap_or(1024),
+            evict_before_import: cache.evict_before_import.unwrap_or(false),
+            image_namespace: std::env::var("FLASHVM_IMAGE_NAMESPACE")
+                .ok()
+                .or_else(|| global.image_namespace.clone())
+                .filter(|ns| !ns.trim().is_empty())
+                .unwrap_or_else(|| "localhost/flashvm".to_string()),
+            pip_cache: cache.pip_cache.unwrap_or(true),
+            pip_cache_max_mb: cache.pip_cache_max_mb.unwrap_or(2048),
+            result_ttl_seconds: cache.result_ttl_seconds.unwrap_or(24 * 3600),
         }
     }
 }
@@ -0,0 +1,83 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// coverage.py data file, written by user code in the guest and returned as an artifact
+pub const DATA_FILE: &str = ".coverage";
+
+/// `coverage json` output, written by the guest runner (under /work/scripts)
+pub const REPORT_FILE: &str = "coverage.json";
+
+/// Where the run's own code is in the guest, as coverage.py names it (absolute, or relative to
+/// the workdir)
+const MAIN_PATHS: &[&str] = &["/work/scripts/main.py", "scripts/main.py"];
+
+/// Statement coverage of a run, summarized from coverage.py's JSON report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub percent_covered: f64,
+    pub covered_lines: u64,
+    pub num_statements: u64,
+    /// Per measured file; the run's code is `main.py`
+    pub files: BTreeMap<String, FileCoverage>,
+    /// The data file among the artifacts, for `coverage combine`/`report` on the host
+    pub data_file: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileCoverage {
+    pub percent_covered: f64,
+    pub covered_lines: u64,
+    pub num_statements: u64,
+    pub missing_lines: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct JsonReport {
+    files: BTreeMap<String, JsonFile>,
+    totals: JsonSummary,
+}
+
+#[derive(Deserialize)]
+struct JsonFile {
+    summary: JsonSummary,
+    #[serde(default)]
+    missing_lines: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct JsonSummary {
+    covered_lines: u64,
+    num_statements: u64,
+    percent_covered: f64,
+}
+
+/// The runner's coverage report, or None when it wrote none (coverage.py failed, or the guest
+/// went down first) or wrote one in a format this host doesn't know.
+pub fn read_report(scripts_dir: &Path) -> Option<CoverageReport> {
+    let data = fs::read(scripts_dir.join(REPORT_FILE)).ok()?;
+    let report: JsonReport = serde_json::from_slice(&data).map_err(|e| debug!("Ignoring coverage report: {}", e)).ok()?;
+    let files = report
+        .files
+        .into_iter()
+        .map(|(path, file)| {
+            let name = if MAIN_PATHS.contains(&path.as_str()) { "main.py".to_string() } else { path };
+            let summary = FileCoverage {
+                percent_covered: file.summary.percent_covered,
+                covered_lines: file.summary.covered_lines,
+                num_statements: file.summary.num_statements,
+                missing_lines: file.missing_lines,
+            };
+            (name, summary)
+        })
+        .collect();
+    Some(CoverageReport {
+        percent_covered: report.totals.percent_covered,
+        covered_lines: report.totals.covered_lines,
+        num_statements: report.totals.num_statements,
+        files,
+        data_file: format!("out/{}", DATA_FILE),
+    })
+}
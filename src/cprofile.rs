@@ -0,0 +1,60 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// pstats dump of the run, written in the guest and returned as an artifact
+pub const STATS_FILE: &str = "profile.pstats";
+
+/// Top functions from the dump, written by the guest runner (under /work/scripts)
+pub const REPORT_FILE: &str = "profile.json";
+
+/// Functions kept in the summary, by cumulative time
+pub const TOP_N: usize = 25;
+
+/// Where cProfile ran the user code, as the guest runner reports it
+const MAIN_PATH: &str = "/work/scripts/main.py";
+
+/// cProfile summary of a run: the functions that took the most cumulative time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileReport {
+    /// Seconds spent in profiled code
+    pub total_time: f64,
+    pub top: Vec<ProfileEntry>,
+    /// The pstats dump among the artifacts, for `pstats`/snakeviz on the host
+    pub stats_file: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileEntry {
+    /// `main.py` for the run's code, `~` for built-ins
+    pub file: String,
+    pub line: u32,
+    pub function: String,
+    pub calls: u64,
+    /// Calls that weren't recursive
+    pub primitive_calls: u64,
+    /// Seconds in the function itself
+    pub total_time: f64,
+    /// Seconds in the function and everything it called
+    pub cumulative_time: f64,
+}
+
+#[derive(Deserialize)]
+struct JsonReport {
+    total_time: f64,
+    top: Vec<ProfileEntry>,
+}
+
+/// The runner's summary, or None when it wrote none (the code never got to run, or the guest
+/// went down first).
+pub fn read_report(scripts_dir: &Path) -> Option<ProfileReport> {
+    let data = fs::read(scripts_dir.join(REPORT_FILE)).ok()?;
+    let report: JsonReport = serde_json::from_slice(&data).map_err(|e| debug!("Ignoring profile report: {}", e)).ok()?;
+    let top = report
+        .top
+        .into_iter()
+        .map(|entry| ProfileEntry { file: if entry.file == MAIN_PATH { "main.py".to_string() } else { entry.file }, ..entry })
+        .collect();
+    Some(ProfileReport { total_time: report.total_time, top, stats_file: format!("out/{}", STATS_FILE) })
+}
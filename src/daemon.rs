@@ -0,0 +1,422 @@
+use crate::config::{FileOutput, VMConfig, VMConfigFile};
+use crate::error::VMError;
+use crate::image_resolver::ImageResolver;
+use crate::pool;
+use crate::rate_limit;
+use crate::vm_runner::VMRunner;
+use crate::{concurrency, global_config, logging, profiles, settings, telemetry, tenants};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::Parser;
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// flashvm failures; `data.code` is the `VMError` code
+const FLASHVM_ERROR: i64 = -32000;
+
+/// How often the accept loop checks for SIGINT/SIGTERM
+const ACCEPT_POLL: Duration = Duration::from_millis(200);
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// flashvm daemon: owns the warm VM pool and image cache and runs code for clients over a
+/// Unix socket (newline-delimited JSON-RPC 2.0)
+#[derive(Parser)]
+#[command(name = "flashvmd", version)]
+struct Args {
+    /// Socket path (default: $FLASHVM_DAEMON_SOCKET, $XDG_RUNTIME_DIR/flashvm.sock or
+    /// /tmp/flashvm-<uid>.sock)
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    /// Serve one client on stdin/stdout instead, and exit when it disconnects; for
+    /// `ssh host flashvmd --stdio`
+    #[arg(long, conflicts_with = "socket")]
+    stdio: bool,
+    /// Also serve the gRPC API on this address, e.g. 127.0.0.1:50051 (unauthenticated)
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "ADDR")]
+    grpc: Option<std::net::SocketAddr>,
+    /// Also serve the HTTP API on this address, e.g. 127.0.0.1:8080
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "ADDR")]
+    http: Option<std::net::SocketAddr>,
+}
+
+/// `FLASHVM_DAEMON_SOCKET`, else `$XDG_RUNTIME_DIR/flashvm.sock`, else `/tmp/flashvm-<uid>.sock`
+pub fn default_socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("FLASHVM_DAEMON_SOCKET").filter(|p| !p.is_empty()) {
+        return PathBuf::from(path);
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("flashvm.sock"),
+        // SAFETY: getuid has no preconditions
+        None => PathBuf::from(format!("/tmp/flashvm-{}.sock", unsafe { libc::getuid() })),
+    }
+}
+
+/// Entry point of the `flashvmd` binary.
+pub fn main() -> ExitCode {
+    let args = Args::parse();
+    logging::init_from_env();
+    telemetry::init_from_env();
+    global_config::loaded();
+    settings::env_defaults();
+    if args.stdio {
+        return serve_stdio();
+    }
+    let path = args.socket.clone().unwrap_or_else(default_socket_path);
+    match serve(&path, &args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("flashvmd: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// One client over stdin/stdout; stdout carries responses only, so logs go to stderr.
+fn serve_stdio() -> ExitCode {
+    let served = handle_connection(std::io::stdin().lock(), std::io::stdout().lock());
+    let drained = pool::vm_pool().drain();
+    info!("flashvmd: client disconnected; removed {} pooled VMs", drained);
+    match served {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("flashvmd: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+extern "C" fn request_stop(_signal: libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+/// Accept clients until SIGINT/SIGTERM, one thread per connection, then drain the pool.
+fn serve(path: &Path, args: &Args) -> Result<(), VMError> {
+    let listener = bind(path)?;
+    #[cfg(feature = "grpc")]
+    let grpc = match args.grpc {
+        Some(addr) => {
+            let server = crate::grpc::start(addr, &STOP).inspect_err(|_| {
+                let _ = fs::remove_file(path);
+            })?;
+            eprintln!("flashvmd: serving gRPC on {}", addr);
+            Some(server)
+        }
+        None => None,
+    };
+    #[cfg(feature = "http")]
+    let http = match args.http {
+        Some(addr) => {
+            let server = crate::http::start(addr, &STOP).inspect_err(|_| {
+                STOP.store(true, Ordering::SeqCst);
+                let _ = fs::remove_file(path);
+            })?;
+            eprintln!("flashvmd: serving HTTP on {}", addr);
+            Some(server)
+        }
+        None => None,
+    };
+    #[cfg(not(any(feature = "grpc", feature = "http")))]
+    let _ = args;
+    // SAFETY: the handler only stores to an atomic
+    unsafe {
+        libc::signal(libc::SIGINT, request_stop as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, request_stop as *const () as libc::sighandler_t);
+    }
+    eprintln!("flashvmd: listening on {}", path.display());
+    while !STOP.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                std::thread::spawn(move || {
+                    let served = stream
+                        .set_nonblocking(false)
+                        .and_then(|()| stream.try_clone())
+                        .and_then(|writer| handle_connection(BufReader::new(stream), writer));
+                    if let Err(e) = served {
+                        warn!("flashvmd: connection closed: {}", e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(ACCEPT_POLL),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => warn!("flashvmd: accept failed: {}", e),
+        }
+    }
+    let _ = fs::remove_file(path);
+    #[cfg(feature = "grpc")]
+    if let Some(server) = grpc {
+        let _ = server.join();
+    }
+    #[cfg(feature = "http")]
+    if let Some(server) = http {
+        let _ = server.join();
+    }
+    let drained = pool::vm_pool().drain();
+    info!("flashvmd: stopped; removed {} pooled VMs", drained);
+    Ok(())
+}
+
+/// Bind `path` for the current user only, replacing a stale socket left by a dead daemon.
+fn bind(path: &Path) -> Result<UnixListener, VMError> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(VMError::VMConfiguration(format!("a daemon is already listening on {}", path.display())));
+        }
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+fn handle_connection(reader: impl BufRead, mut writer: impl Write) -> std::io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => respond(request),
+            Err(e) => error_response(Value::Null, PARSE_ERROR, &e.to_string(), None),
+        };
+        writeln!(writer, "{}", response)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn respond(request: Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return error_response(id, INVALID_REQUEST, "expected a JSON-RPC 2.0 request object with a method", None);
+    };
+    let params = request.get("params").cloned().unwrap_or(json!({}));
+    let result = match method {
+        "ping" => Ok(json!({ "version": env!("CARGO_PKG_VERSION") })),
+        "run" => match serde_json::from_value::<RunParams>(params) {
+            Ok(params) => run(params),
+            Err(e) => return error_response(id, INVALID_PARAMS, &e.to_string(), None),
+        },
+        "list_images" => ImageResolver::new()
+            .list_cached_images()
+            .and_then(|images| serde_json::to_value(images).map_err(|e| VMError::Cache(e.to_string()))),
+        "drain_pool" => Ok(json!(pool::vm_pool().drain())),
+        "scheduler_stats" => match params.get("tenant").and_then(Value::as_str) {
+            Some(name) => tenants::get(name).map(|t| json!(t.stats())),
+            None => Ok(json!(concurrency::scheduler().stats())),
+        },
+        other => return error_response(id, METHOD_NOT_FOUND, &format!("unknown method '{}'", other), None),
+    };
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error_response(id, FLASHVM_ERROR, &e.to_string(), Some(error_data(&e))),
+    }
+}
+
+/// `{code, image, command}`, `stderr` and `retry_after` of `e`, as the Python exceptions carry them
+pub(crate) fn error_data(e: &VMError) -> Value {
+    let context = e.context().cloned().unwrap_or_default();
+    json!({
+        "code": e.code(),
+        "image": context.image,
+        "command": context.command,
+        "stderr": context.stderr,
+        "retry_after": e.retry_after().map(|d| d.as_secs_f64()),
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RunParams {
+    code: String,
+    profile: Option<String>,
+    /// `run` settings, with the config-file keys
+    config: Option<VMConfigFile>,
+    /// Staged under /work/in
+    #[serde(default)]
+    files: Vec<InputFile>,
+    #[serde(default)]
+    expect: Vec<String>,
+    /// Directory matched artifacts are copied to; without it they come back inline
+    out_dir: Option<PathBuf>,
+}
+
+/// A file sent along with a run
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct InputFile {
+    guest_path: String,
+    /// Base64
+    content: String,
+}
+
+/// `(guest_path, content)` of each file, decoded.
+pub(crate) fn decode_inputs(files: Vec<InputFile>) -> Result<Vec<(String, Vec<u8>)>, VMError> {
+    files
+        .into_iter()
+        .map(|file| {
+            let content = BASE64
+                .decode(&file.content)
+                .map_err(|e| VMError::Validation(format!("content of {} is not base64: {}", file.guest_path, e)))?;
+            Ok((file.guest_path, content))
+        })
+        .collect()
+}
+
+fn run(params: RunParams) -> Result<Value, VMError> {
+    // The socket is the owner's only, so its clients have no API keys
+    rate_limit::enforce_api_key(None)?;
+    let config = run_config(params.profile.as_deref(), params.config)?;
+    let (_staging, files) = stage_inputs(decode_inputs(params.files)?)?;
+    let expect = params.expect.into_iter().map(|pattern| FileOutput { pattern }).collect();
+    let result = VMRunner::new().execute_python_code(&params.code, &config, files, expect)?;
+
+    let mut artifacts = Vec::new();
+    for artifact in &result.artifacts {
+        let Some(out_dir) = &params.out_dir else {
+            let content = match &artifact.content {
+                Some(content) => BASE64.encode(content),
+                None => BASE64.encode(fs::read(&artifact.host_path)?),
+            };
+            artifacts.push(json!({
+                "guest_path": artifact.guest_path,
+                "content": content,
+                "size_bytes": artifact.size_bytes,
+            }));
+            continue;
+        };
+        let rel = artifact.guest_path.strip_prefix("out/").unwrap_or(&artifact.guest_path);
+        let dest = out_dir.join(rel);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match &artifact.content {
+            Some(content) => fs::write(&dest, content)?,
+            None => fs::copy(&artifact.host_path, &dest).map(|_| ())?,
+        }
+        artifacts.push(json!({
+            "guest_path": artifact.guest_path,
+            "path": dest.to_string_lossy(),
+            "size_bytes": artifact.size_bytes,
+        }));
+    }
+    Ok(json!({
+        "stdout": result.stdout,
+        "stderr": result.stderr,
+        "exit_code": result.exit_code,
+        "execution_time_ms": result.execution_time.as_millis() as u64,
+        "timings": result.timings.to_json(),
+        "image_used": result.image_used,
+        "run_id": result.run_id,
+        "attempts": result.attempts,
+        "cached": result.cached,
+        "peak_memory_bytes": result.peak_memory_bytes,
+        "coverage": result.coverage,
+        "cprofile": result.cprofile,
+        "display": result.display,
+        "value": result.value,
+        "artifacts": artifacts,
+    }))
+}
+
+/// Settings for a client's run, layered like `run`: defaults, the profile, then the client's
+/// settings. Runs use the warm pool unless the settings turn it off, and artifacts always come
+/// back inline, since clients get them from the daemon rather than from its filesystem.
+pub(crate) fn run_config(profile: Option<&str>, settings: Option<VMConfigFile>) -> Result<VMConfig, VMError> {
+    let mut config = profiles::base_config(profile, None)?;
+    config.use_pool = true;
+    if let Some(settings) = settings {
+        settings.apply(&mut config)?;
+    }
+    config.max_bytes_inline = u64::MAX;
+    Ok(config)
+}
+
+/// Write uploaded `(guest_path, content)` files to a staging directory, under numbered names;
+/// the runner checks each guest path.
+pub(crate) fn stage_inputs(
+    files: impl IntoIterator<Item = (String, Vec<u8>)>,
+) -> Result<(tempfile::TempDir, Vec<crate::config::FileInput>), VMError> {
+    let staging = tempfile::TempDir::new()?;
+    let mut inputs = Vec::new();
+    for (i, (guest_path, content)) in files.into_iter().enumerate() {
+        let host_path = staging.path().join(i.to_string());
+        fs::write(&host_path, content)?;
+        inputs.push(crate::config::FileInput { host_path, guest_path });
+    }
+    Ok((staging, inputs))
+}
+
+/// The client's run ID when it is a free UUID, else a fresh one.
+#[cfg(any(feature = "grpc", feature = "http"))]
+pub(crate) fn claim_run_id(requested: Option<String>) -> Result<String, VMError> {
+    let Some(run_id) = requested.filter(|id| !id.is_empty()) else {
+        return Ok(crate::runs::RunRegistry::new_run_id());
+    };
+    let run_id = uuid::Uuid::parse_str(&run_id)
+        .map_err(|_| VMError::Validation(format!("run_id must be a UUID: {}", run_id)))?
+        .to_string();
+    if crate::runs::RunRegistry::new().get(&run_id)?.is_some() {
+        return Err(VMError::Validation(format!("run_id {} is already in use", run_id)));
+    }
+    Ok(run_id)
+}
+
+/// Kills a run whose client went away (cancelled the call, disconnected or hit its deadline)
+/// unless `finished` was set first.
+#[cfg(any(feature = "grpc", feature = "http"))]
+pub(crate) struct KillOnDrop {
+    pub run_id: String,
+    pub finished: std::sync::Arc<AtomicBool>,
+}
+
+#[cfg(any(feature = "grpc", feature = "http"))]
+impl KillOnDrop {
+    pub fn new(run_id: &str) -> Self {
+        KillOnDrop { run_id: run_id.to_string(), finished: Default::default() }
+    }
+}
+
+#[cfg(any(feature = "grpc", feature = "http"))]
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        if self.finished.load(Ordering::SeqCst) {
+            return;
+        }
+        let run_id = self.run_id.clone();
+        // Killing waits on krunvm; keep that off the async runtime
+        std::thread::spawn(move || {
+            if let Err(e) = crate::runs::RunRegistry::new().kill(&run_id) {
+                warn!("[run {}] Failed to kill abandoned run: {}", run_id, e);
+            }
+        });
+    }
+}
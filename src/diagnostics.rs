@@ -0,0 +1,245 @@
+use crate::image_cache;
+#[cfg(target_os = "macos")]
+use crate::platform;
+use crate::storage_tool::StorageTool;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// One environment check with a remediation hint when it fails.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, ok: true, detail: detail.into(), hint: None }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name, ok: false, detail: detail.into(), hint: Some(hint.into()) }
+    }
+}
+
+/// Run every host check; none of them short-circuit so the report is complete.
+pub fn run_checks() -> Vec<Check> {
+    let mut checks = vec![
+        check_tool(
+            "krunvm",
+            &["--version"],
+            "install krunvm (https://github.com/containers/krunvm) and make sure it is on PATH",
+        ),
+        check_tool(
+            "buildah",
+            &["--version"],
+            if StorageTool::current() == StorageTool::Podman {
+                "install buildah: krunvm needs it to create VMs (podman is used for image management meanwhile)"
+            } else {
+                "install buildah from your distribution's packages"
+            },
+        ),
+        check_tool(
+            "skopeo",
+            &["--version"],
+            "optional: install skopeo for faster image imports (buildah is used as a fallback)",
+        ),
+    ];
+    checks.extend(hypervisor_checks());
+    checks.extend([check_storage_driver(), check_cache_dir()]);
+    checks
+}
+
+/// What krunvm needs from the host to boot VMs rootless
+#[cfg(target_os = "linux")]
+fn hypervisor_checks() -> Vec<Check> {
+    vec![check_kvm(), check_userns(), check_subids()]
+}
+
+/// libkrun uses Hypervisor.framework on macOS, and nothing runs in a user namespace there
+#[cfg(target_os = "macos")]
+fn hypervisor_checks() -> Vec<Check> {
+    vec![check_hvf()]
+}
+
+/// Whether a failed check blocks execution (the rest only degrade it).
+pub fn is_required(name: &str) -> bool {
+    !matches!(name, "skopeo" | "storage_driver" | "subuid")
+}
+
+fn check_tool(name: &'static str, args: &[&str], hint: &str) -> Check {
+    match Command::new(name).args(args).output() {
+        Ok(out) if out.status.success() => {
+            let version = String::from_utf8_lossy(&out.stdout).lines().next().unwrap_or("").trim().to_string();
+            Check::pass(name, version)
+        }
+        Ok(out) => Check::fail(
+            name,
+            format!("`{} {}` exited with {}", name, args.join(" "), out.status),
+            hint,
+        ),
+        Err(e) => Check::fail(name, format!("not found: {}", e), hint),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_kvm() -> Check {
+    let kvm = Path::new("/dev/kvm");
+    if !kvm.exists() {
+        return Check::fail(
+            "kvm",
+            "/dev/kvm does not exist",
+            "enable virtualization in firmware and load kvm_intel/kvm_amd (nested virtualization is needed inside VMs)",
+        );
+    }
+    // SAFETY: access(2) on a static NUL-terminated path
+    let rw = unsafe { libc::access(c"/dev/kvm".as_ptr(), libc::R_OK | libc::W_OK) } == 0;
+    if rw {
+        return Check::pass("kvm", "/dev/kvm is readable and writable");
+    }
+    let group = kvm_group_name().unwrap_or_else(|| "kvm".to_string());
+    Check::fail(
+        "kvm",
+        format!("no read/write access to /dev/kvm (owned by group {})", group),
+        format!("add yourself to the group with `sudo usermod -aG {} $USER` and log in again", group),
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn kvm_group_name() -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let gid = fs::metadata("/dev/kvm").ok()?.gid();
+    let groups = fs::read_to_string("/etc/group").ok()?;
+    groups.lines().find_map(|line| {
+        let mut parts = line.split(':');
+        let name = parts.next()?;
+        let id = parts.nth(1)?.parse::<u32>().ok()?;
+        (id == gid).then(|| name.to_string())
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn check_userns() -> Check {
+    let max = fs::read_to_string("/proc/sys/user/max_user_namespaces")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok());
+    if max == Some(0) {
+        return Check::fail(
+            "user_namespaces",
+            "user.max_user_namespaces is 0",
+            "run `sudo sysctl -w user.max_user_namespaces=15000`",
+        );
+    }
+    // Debian/Ubuntu-specific knob; absent elsewhere
+    if let Ok(v) = fs::read_to_string("/proc/sys/kernel/unprivileged_userns_clone") {
+        if v.trim() == "0" {
+            return Check::fail(
+                "user_namespaces",
+                "kernel.unprivileged_userns_clone is 0",
+                "run `sudo sysctl -w kernel.unprivileged_userns_clone=1`",
+            );
+        }
+    }
+    Check::pass(
+        "user_namespaces",
+        match max {
+            Some(n) => format!("max_user_namespaces={}", n),
+            None => "enabled".to_string(),
+        },
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn check_subids() -> Check {
+    let user = current_user();
+    let has_entry = |path: &str| {
+        fs::read_to_string(path)
+            .map(|s| s.lines().any(|l| l.split(':').next() == Some(user.as_str())))
+            .unwrap_or(false)
+    };
+    // Root doesn't need subordinate ids for `buildah unshare`
+    // SAFETY: geteuid never fails
+    if unsafe { libc::geteuid() } == 0 || (has_entry("/etc/subuid") && has_entry("/etc/subgid")) {
+        Check::pass("subuid", format!("subordinate ids configured for {}", user))
+    } else {
+        Check::fail(
+            "subuid",
+            format!("no /etc/subuid or /etc/subgid entry for {}", user),
+            format!("run `sudo usermod --add-subuids 100000-165535 --add-subgids 100000-165535 {}`", user),
+        )
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn current_user() -> String {
+    // SAFETY: getpwuid returns a pointer into static storage or NULL
+    unsafe {
+        let pw = libc::getpwuid(libc::geteuid());
+        if !pw.is_null() {
+            return std::ffi::CStr::from_ptr((*pw).pw_name).to_string_lossy().into_owned();
+        }
+    }
+    std::env::var("USER").unwrap_or_default()
+}
+
+#[cfg(target_os = "macos")]
+fn check_hvf() -> Check {
+    if platform::hvf_supported() {
+        Check::pass("hvf", "Hypervisor.framework is available")
+    } else {
+        Check::fail(
+            "hvf",
+            "sysctl kern.hv_support is not 1",
+            "run on Apple silicon with macOS 11 or later; inside a VM, Hypervisor.framework needs nested virtualization",
+        )
+    }
+}
+
+fn check_storage_driver() -> Check {
+    let tool = StorageTool::current().binary();
+    let out = match Command::new(tool).arg("info").output() {
+        Ok(out) if out.status.success() => out,
+        _ => {
+            return Check::fail(
+                "storage_driver",
+                format!("could not run `{} info`", tool),
+                format!("check that {} works for your user (`{} info`)", tool, tool),
+            )
+        }
+    };
+    // buildah and podman spell the key differently
+    let driver = serde_json::from_slice::<serde_json::Value>(&out.stdout)
+        .ok()
+        .and_then(|v| {
+            let store = &v["store"];
+            store["GraphDriverName"].as_str().or(store["graphDriverName"].as_str()).map(str::to_string)
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    if driver == "vfs" {
+        Check::fail(
+            "storage_driver",
+            "vfs",
+            "vfs copies every layer; install fuse-overlayfs or use a kernel with rootless overlay for faster image setup",
+        )
+    } else {
+        Check::pass("storage_driver", driver)
+    }
+}
+
+fn check_cache_dir() -> Check {
+    let dir = image_cache::cache_config().cache_dir;
+    let probe = Path::new(&dir).join(format!(".doctor-{}", std::process::id()));
+    let result = fs::create_dir_all(&dir).and_then(|_| fs::write(&probe, b"ok"));
+    let _ = fs::remove_file(&probe);
+    match result {
+        Ok(()) => Check::pass("cache_dir", format!("{} is writable", dir)),
+        Err(e) => Check::fail(
+            "cache_dir",
+            format!("{} is not writable: {}", dir, e),
+            "fix the directory permissions, or point FLASHVM_CACHE_DIR or configure_cache(cache_dir=...) at a writable location",
+        ),
+    }
+}
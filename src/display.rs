@@ -0,0 +1,57 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Where the guest runner's display hook writes each output (under /work/out)
+pub const DIR: &str = "_display";
+
+/// The hook's list of outputs, in display order (under `DIR`)
+pub const INDEX_FILE: &str = "index.json";
+
+/// The outputs themselves, as artifacts; they are named `<n>.<ext>`, the index is not
+pub const PATTERN: &str = "_display/[0-9]*";
+
+/// A figure or rich repr the run's code displayed (`display(obj)`, `plt.show()`, or a bare
+/// expression on the last line, as in a notebook cell)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayEntry {
+    /// MIME type of the repr Jupyter would prefer (`text/html`, `image/png`, ...; figures are
+    /// `image/png`), or `text/plain` when the object had none
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The output among the artifacts
+    pub path: String,
+    /// `repr()` of the object, cut to 1000 characters, for frontends that can't render `kind`
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    file: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// The hook's outputs, or none when the code displayed nothing (or the index is unreadable).
+/// Entries whose file didn't make it are left out.
+pub fn read_index(output_dir: &Path) -> Vec<DisplayEntry> {
+    let dir = output_dir.join(DIR);
+    let Ok(data) = fs::read(dir.join(INDEX_FILE)) else {
+        return Vec::new();
+    };
+    let entries: Vec<IndexEntry> = match serde_json::from_slice(&data) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Ignoring display index: {}", e);
+            return Vec::new();
+        }
+    };
+    entries
+        .into_iter()
+        .filter(|entry| !entry.file.contains('/') && dir.join(&entry.file).is_file())
+        .map(|entry| DisplayEntry { kind: entry.kind, path: format!("out/{}/{}", DIR, entry.file), text: entry.text })
+        .collect()
+}
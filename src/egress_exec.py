@@ -0,0 +1,59 @@
+"""Exec a command with outbound TCP limited to a set of ports, via Landlock.
+
+Usage (inside `buildah unshare`): python3 -c <this> '<json ports>' cmd [args...]
+
+krunvm's TSI networking performs guest connects from the VMM process itself, so
+restricting that process restricts the guest. Needs Landlock ABI >= 4 (Linux 6.7).
+"""
+import ctypes
+import json
+import os
+import struct
+import sys
+
+# Same numbers on every architecture (added after the syscall table unification)
+SYS_LANDLOCK_CREATE_RULESET = 444
+SYS_LANDLOCK_ADD_RULE = 445
+SYS_LANDLOCK_RESTRICT_SELF = 446
+LANDLOCK_CREATE_RULESET_VERSION = 1
+LANDLOCK_ACCESS_NET_CONNECT_TCP = 1 << 1
+LANDLOCK_RULE_NET_PORT = 2
+
+libc = ctypes.CDLL(None, use_errno=True)
+libc.syscall.restype = ctypes.c_long
+
+
+def fail(msg):
+    sys.stderr.write("flashvm: egress policy: %s\n" % msg)
+    sys.exit(126)
+
+
+def main():
+    ports = json.loads(sys.argv[1])
+    argv = sys.argv[2:]
+
+    abi = libc.syscall(SYS_LANDLOCK_CREATE_RULESET, None, ctypes.c_size_t(0),
+                       ctypes.c_uint32(LANDLOCK_CREATE_RULESET_VERSION))
+    if abi < 4:
+        fail("kernel lacks Landlock network support (needs Linux 6.7+)")
+
+    # struct landlock_ruleset_attr { u64 handled_access_fs; u64 handled_access_net; }
+    attr = ctypes.create_string_buffer(struct.pack("=QQ", 0, LANDLOCK_ACCESS_NET_CONNECT_TCP), 16)
+    fd = libc.syscall(SYS_LANDLOCK_CREATE_RULESET, attr, ctypes.c_size_t(16), ctypes.c_uint32(0))
+    if fd < 0:
+        fail("landlock_create_ruleset: %s" % os.strerror(ctypes.get_errno()))
+
+    for port in ports:
+        # struct landlock_net_port_attr { u64 allowed_access; u64 port; }
+        rule = ctypes.create_string_buffer(struct.pack("=QQ", LANDLOCK_ACCESS_NET_CONNECT_TCP, port), 16)
+        if libc.syscall(SYS_LANDLOCK_ADD_RULE, ctypes.c_int(fd), ctypes.c_int(LANDLOCK_RULE_NET_PORT),
+                        rule, ctypes.c_uint32(0)) != 0:
+            fail("landlock_add_rule(%d): %s" % (port, os.strerror(ctypes.get_errno())))
+
+    if libc.syscall(SYS_LANDLOCK_RESTRICT_SELF, ctypes.c_int(fd), ctypes.c_uint32(0)) != 0:
+        fail("landlock_restrict_self: %s" % os.strerror(ctypes.get_errno()))
+    os.close(fd)
+    os.execvp(argv[0], argv)
+
+
+main()
@@ -1,6 +1,9 @@
-use pyo3::exceptions::PyRuntimeError;
+use crate::config::ExecutionResult;
 use std::fmt;
 
+/// Keep at most this much of a failing command's stderr on the error
+const STDERR_EXCERPT_BYTES: usize = 2000;
+
 #[derive(Debug)]
 pub enum VMError {
     ImageResolution(String),
@@ -8,8 +11,132 @@ pub enum VMError {
     Execution(String),
     IO(std::io::Error),
     Timeout(String),
+    /// An image pull made no progress for the pull timeout
+    PullTimeout(String),
+    /// The guest stopped sending heartbeats before the run's timeout: it hung or crashed
+    GuestUnresponsive(String),
     MissingDependency(String),
     Cache(String),
+    /// Caller input rejected as unsafe or malformed
+    Validation(String),
+    /// Guest process killed by the OOM killer
+    OutOfMemory(String),
+    /// The guest's /work filled up
+    DiskFull(String),
+    /// Not admitted by the scheduler: its queue was full or the run's queue deadline passed
+    Overloaded(String),
+    /// The run asked for more than the sandbox policy allows
+    PolicyViolation(String),
+    /// Killed with `kill` before its VM started
+    Killed(String),
+    /// The caller's rate limit is used up; a run is available again after the duration
+    RateLimited(String, std::time::Duration),
+    /// Any of the above plus what was being done when it failed
+    WithContext { source: Box<VMError>, context: ErrorContext },
+}
+
+/// Details attached to an error for automated callers.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorContext {
+    pub image: Option<String>,
+    pub command: Option<String>,
+    pub stderr: Option<String>,
+    /// What the run produced before it failed, when it got as far as running
+    pub result: Option<Box<ExecutionResult>>,
+}
+
+impl VMError {
+    /// Stable, machine-readable identifier of the failure type.
+    pub fn code(&self) -> &'static str {
+        match self {
+            VMError::ImageResolution(_) => "IMAGE_RESOLUTION",
+            VMError::VMConfiguration(_) => "VM_CONFIGURATION",
+            VMError::Execution(_) => "EXECUTION",
+            VMError::IO(_) => "IO",
+            VMError::Timeout(_) => "TIMEOUT",
+            VMError::PullTimeout(_) => "PULL_TIMEOUT",
+            VMError::GuestUnresponsive(_) => "GUEST_UNRESPONSIVE",
+            VMError::MissingDependency(_) => "MISSING_DEPENDENCY",
+            VMError::Cache(_) => "CACHE",
+            VMError::Validation(_) => "VALIDATION",
+            VMError::OutOfMemory(_) => "OUT_OF_MEMORY",
+            VMError::DiskFull(_) => "DISK_FULL",
+            VMError::Overloaded(_) => "OVERLOADED",
+            VMError::PolicyViolation(_) => "POLICY_VIOLATION",
+            VMError::Killed(_) => "KILLED",
+            VMError::RateLimited(..) => "RATE_LIMITED",
+            VMError::WithContext { source, .. } => source.code(),
+        }
+    }
+
+    /// How long a rate-limited caller should wait before trying again.
+    #[cfg(any(feature = "python", feature = "daemon"))]
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            VMError::RateLimited(_, retry_after) => Some(*retry_after),
+            VMError::WithContext { source, .. } => source.retry_after(),
+            _ => None,
+        }
+    }
+
+    #[cfg(any(feature = "python", feature = "daemon"))]
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            VMError::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    fn map_context(self, f: impl FnOnce(&mut ErrorContext)) -> Self {
+        match self {
+            VMError::WithContext { source, mut context } => {
+                f(&mut context);
+                VMError::WithContext { source, context }
+            }
+            other => {
+                let mut context = ErrorContext::default();
+                f(&mut context);
+                VMError::WithContext { source: Box::new(other), context }
+            }
+        }
+    }
+
+    /// Record the image being handled; an image already recorded closer to the failure wins.
+    pub fn with_image(self, image: &str) -> Self {
+        self.map_context(|c| {
+            c.image.get_or_insert_with(|| image.to_string());
+        })
+    }
+
+    /// Record what the guest printed to stderr before failing.
+    pub fn with_stderr(self, stderr: &str) -> Self {
+        self.map_context(|c| c.stderr = Some(stderr_excerpt(stderr)))
+    }
+
+    /// Keep the partial result of a run that failed after its code ran.
+    pub fn with_result(self, result: ExecutionResult) -> Self {
+        self.map_context(|c| c.result = Some(Box::new(result)))
+    }
+
+    /// Record the command that failed and the tail of its stderr.
+    pub fn with_command(self, argv: &[&str], stderr: &str) -> Self {
+        self.map_context(|c| {
+            c.command = Some(argv.join(" "));
+            c.stderr = Some(stderr_excerpt(stderr));
+        })
+    }
+}
+
+fn stderr_excerpt(stderr: &str) -> String {
+    let trimmed = stderr.trim();
+    if trimmed.len() <= STDERR_EXCERPT_BYTES {
+        return trimmed.to_string();
+    }
+    let mut start = trimmed.len() - STDERR_EXCERPT_BYTES;
+    while !trimmed.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("...{}", &trimmed[start..])
 }
 
 impl fmt::Display for VMError {
@@ -20,8 +147,20 @@ impl fmt::Display for VMError {
             VMError::Execution(msg) => write!(f, "Execution error: {}", msg),
             VMError::IO(err) => write!(f, "I/O error: {}", err),
             VMError::Timeout(msg) => write!(f, "Timeout: {}", msg),
+            VMError::PullTimeout(msg) => write!(f, "Pull timeout: {}", msg),
+            VMError::GuestUnresponsive(msg) => write!(f, "Guest unresponsive: {}", msg),
             VMError::MissingDependency(dep) => write!(f, "Missing dependency: {}", dep),
             VMError::Cache(msg) => write!(f, "Cache error: {}", msg),
+            VMError::Validation(msg) => write!(f, "Validation error: {}", msg),
+            VMError::OutOfMemory(msg) => write!(f, "Out of memory: {}", msg),
+            VMError::DiskFull(msg) => write!(f, "Disk full: {}", msg),
+            VMError::Overloaded(msg) => write!(f, "Overloaded: {}", msg),
+            VMError::PolicyViolation(msg) => write!(f, "Policy violation: {}", msg),
+            VMError::Killed(msg) => write!(f, "Killed: {}", msg),
+            VMError::RateLimited(msg, retry_after) => {
+                write!(f, "Rate limited: {}; retry after {:.1}s", msg, retry_after.as_secs_f64())
+            }
+            VMError::WithContext { source, .. } => write!(f, "{}", source),
         }
     }
 }
@@ -36,4 +175,104 @@ impl From<anyhow::Error> for VMError {
     fn from(err: anyhow::Error) -> Self { VMError::Execution(err.to_string()) }
 }
 
-pub type PyVMError = PyRuntimeError;
+#[cfg(feature = "python")]
+pub use python::{config_error, register_exceptions, to_py_err};
+
+/// `VMError`s as Python exceptions
+#[cfg(feature = "python")]
+mod python {
+    use super::VMError;
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyRuntimeError;
+    use pyo3::prelude::*;
+
+    create_exception!(flashvm, FlashVMError, PyRuntimeError, "Base class for flashvm errors.");
+    create_exception!(flashvm, FlashVMImageError, FlashVMError, "The image could not be resolved or imported.");
+    create_exception!(flashvm, FlashVMConfigError, FlashVMError, "Invalid VM or run configuration.");
+    create_exception!(flashvm, FlashVMExecutionError, FlashVMError, "The VM could not be driven to completion.");
+    create_exception!(flashvm, FlashVMTimeout, FlashVMError, "The operation exceeded its time limit.");
+    create_exception!(flashvm, FlashVMPullTimeout, FlashVMTimeout, "An image pull stopped making progress.");
+    create_exception!(flashvm, FlashVMGuestUnresponsive, FlashVMTimeout, "The guest stopped sending heartbeats: it hung or crashed.");
+    create_exception!(flashvm, FlashVMMissingDependency, FlashVMError, "A required host tool is missing.");
+    create_exception!(flashvm, FlashVMCacheError, FlashVMError, "The image cache could not be read or updated.");
+    create_exception!(flashvm, FlashVMValidationError, FlashVMError, "An argument was rejected as unsafe or malformed.");
+    create_exception!(flashvm, FlashVMOutOfMemory, FlashVMError, "The guest ran out of memory.");
+    create_exception!(flashvm, FlashVMDiskFull, FlashVMError, "The guest workspace ran out of space.");
+    create_exception!(flashvm, FlashVMPolicyViolation, FlashVMConfigError, "The run asked for more than the sandbox policy allows.");
+    create_exception!(flashvm, FlashVMOverloaded, FlashVMError, "The scheduler turned the run away: its queue was full or the run waited past its queue deadline.");
+    create_exception!(flashvm, FlashVMKilled, FlashVMError, "The run was killed with `kill` before its VM started.");
+    create_exception!(flashvm, FlashVMRateLimited, FlashVMError, "The caller ran out of its rate limit; `retry_after` says how many seconds to wait.");
+
+    /// Convert a `VMError` into the matching Python exception, prefixed with what was being done.
+    /// The exception carries `code`, `image`, `command`, `stderr`, `retry_after` and `result`
+    /// attributes.
+    pub fn to_py_err(prefix: &str, err: VMError) -> PyErr {
+        let msg = format!("{}: {}", prefix, err);
+        attach_context(new_err_for(&err, msg), &err)
+    }
+
+    /// Argument validation failure raised before any VM work, as `FlashVMConfigError`.
+    pub fn config_error(msg: impl Into<String>) -> PyErr {
+        let msg = msg.into();
+        attach_context(FlashVMConfigError::new_err(msg.clone()), &VMError::VMConfiguration(msg))
+    }
+
+    fn new_err_for(err: &VMError, msg: String) -> PyErr {
+        match err {
+            VMError::ImageResolution(_) => FlashVMImageError::new_err(msg),
+            VMError::VMConfiguration(_) => FlashVMConfigError::new_err(msg),
+            VMError::Execution(_) => FlashVMExecutionError::new_err(msg),
+            VMError::IO(_) => FlashVMError::new_err(msg),
+            VMError::Timeout(_) => FlashVMTimeout::new_err(msg),
+            VMError::PullTimeout(_) => FlashVMPullTimeout::new_err(msg),
+            VMError::GuestUnresponsive(_) => FlashVMGuestUnresponsive::new_err(msg),
+            VMError::MissingDependency(_) => FlashVMMissingDependency::new_err(msg),
+            VMError::Cache(_) => FlashVMCacheError::new_err(msg),
+            VMError::Validation(_) => FlashVMValidationError::new_err(msg),
+            VMError::OutOfMemory(_) => FlashVMOutOfMemory::new_err(msg),
+            VMError::DiskFull(_) => FlashVMDiskFull::new_err(msg),
+            VMError::Overloaded(_) => FlashVMOverloaded::new_err(msg),
+            VMError::PolicyViolation(_) => FlashVMPolicyViolation::new_err(msg),
+            VMError::Killed(_) => FlashVMKilled::new_err(msg),
+            VMError::RateLimited(..) => FlashVMRateLimited::new_err(msg),
+            VMError::WithContext { source, .. } => new_err_for(source, msg),
+        }
+    }
+
+    fn attach_context(py_err: PyErr, err: &VMError) -> PyErr {
+        Python::with_gil(|py| {
+            let value = py_err.value_bound(py);
+            let context = err.context().cloned().unwrap_or_default();
+            // Attribute errors here would only hide the original failure
+            let _ = value.setattr("code", err.code());
+            let _ = value.setattr("image", context.image);
+            let _ = value.setattr("command", context.command);
+            let _ = value.setattr("stderr", context.stderr);
+            let _ = value.setattr("retry_after", err.retry_after().map(|d| d.as_secs_f64()));
+            let result = context.result.and_then(|r| crate::python::execution_result_to_py(py, *r).ok());
+            let _ = value.setattr("result", result);
+        });
+        py_err
+    }
+
+    pub fn register_exceptions(m: &Bound<'_, PyModule>) -> PyResult<()> {
+        let py = m.py();
+        m.add("FlashVMError", py.get_type_bound::<FlashVMError>())?;
+        m.add("FlashVMImageError", py.get_type_bound::<FlashVMImageError>())?;
+        m.add("FlashVMConfigError", py.get_type_bound::<FlashVMConfigError>())?;
+        m.add("FlashVMExecutionError", py.get_type_bound::<FlashVMExecutionError>())?;
+        m.add("FlashVMTimeout", py.get_type_bound::<FlashVMTimeout>())?;
+        m.add("FlashVMPullTimeout", py.get_type_bound::<FlashVMPullTimeout>())?;
+        m.add("FlashVMGuestUnresponsive", py.get_type_bound::<FlashVMGuestUnresponsive>())?;
+        m.add("FlashVMMissingDependency", py.get_type_bound::<FlashVMMissingDependency>())?;
+        m.add("FlashVMCacheError", py.get_type_bound::<FlashVMCacheError>())?;
+        m.add("FlashVMValidationError", py.get_type_bound::<FlashVMValidationError>())?;
+        m.add("FlashVMOutOfMemory", py.get_type_bound::<FlashVMOutOfMemory>())?;
+        m.add("FlashVMDiskFull", py.get_type_bound::<FlashVMDiskFull>())?;
+        m.add("FlashVMOverloaded", py.get_type_bound::<FlashVMOverloaded>())?;
+        m.add("FlashVMPolicyViolation", py.get_type_bound::<FlashVMPolicyViolation>())?;
+        m.add("FlashVMRateLimited", py.get_type_bound::<FlashVMRateLimited>())?;
+        m.add("FlashVMKilled", py.get_type_bound::<FlashVMKilled>())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use crate::config::VMConfigFile;
+use crate::policy::Policy;
+use crate::rate_limit::RateLimit;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Host-wide defaults from `/etc/flashvm/config.toml` and the user's
+/// `~/.config/flashvm/config.toml` (or `FLASHVM_CONFIG`), the user file winning key by key.
+/// Environment variables and API calls override both.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlobalConfig {
+    pub storage_tool: Option<String>,
+    pub cache_dir: Option<String>,
+    pub image_namespace: Option<String>,
+    pub offline: Option<bool>,
+    /// Import the embedded image in the background when the Python module loads
+    pub preimport: Option<bool>,
+    /// Seconds an image pull may go without progress before it is killed
+    pub pull_timeout_seconds: Option<u64>,
+    /// Seconds a running guest may go without a heartbeat before the run is killed; 0 turns it off
+    pub heartbeat_timeout_seconds: Option<u64>,
+    /// JSONL file every run appends its audit record to
+    pub audit_log: Option<String>,
+    pub cache: Option<CacheSection>,
+    pub pool: Option<PoolSection>,
+    pub scheduler: Option<SchedulerSection>,
+    /// Limits on what runs may ask for
+    pub policy: Option<Policy>,
+    /// Runs each tenant, and each daemon API key, may start per minute
+    pub rate_limit: Option<RateLimit>,
+    /// Defaults for every run, in the `run(config_file=...)` format
+    pub run: Option<VMConfigFile>,
+    /// Extra named profiles for `run(profile=...)`
+    pub profiles: Option<BTreeMap<String, VMConfigFile>>,
+    /// Tenants for `run(tenant=...)`, as for `register_tenant`
+    pub tenants: Option<BTreeMap<String, TenantSection>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheSection {
+    pub ttl_seconds: Option<u64>,
+    pub max_size_mb: Option<u64>,
+    pub evict_before_import: Option<bool>,
+    pub pip_cache: Option<bool>,
+    pub pip_cache_max_mb: Option<u64>,
+    pub result_ttl_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolSection {
+    pub max_idle_per_key: Option<usize>,
+    pub max_uses: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchedulerSection {
+    pub max_vms: Option<usize>,
+    pub max_memory_mb: Option<u64>,
+    pub max_queue: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantSection {
+    pub image_namespace: Option<String>,
+    pub cache_dir: Option<String>,
+    pub max_vms: Option<usize>,
+    pub max_memory_mb: Option<u64>,
+    pub max_queue: Option<usize>,
+    /// Budget of the tenant's image cache, enforced by eviction before each import
+    pub max_storage_mb: Option<u64>,
+    /// Applies to the tenant's runs on top of the host's policy
+    pub policy: Option<Policy>,
+    /// Replaces the host's `[rate_limit]` for the tenant
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// What was loaded: the merged settings, the files they came from and the files that
+/// were skipped because they didn't parse.
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub config: GlobalConfig,
+    pub files: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+pub fn loaded() -> &'static Loaded {
+    static LOADED: OnceLock<Loaded> = OnceLock::new();
+    LOADED.get_or_init(load)
+}
+
+pub fn global() -> &'static GlobalConfig {
+    &loaded().config
+}
+
+/// System file first, so the user file's keys win.
+fn candidate_files() -> Vec<PathBuf> {
+    let user = match std::env::var("FLASHVM_CONFIG").ok().filter(|p| !p.trim().is_empty()) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let config_home = std::env::var("XDG_CONFIG_HOME")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".config"));
+            config_home.join("flashvm").join("config.toml")
+        }
+    };
+    vec![PathBuf::from("/etc/flashvm/config.toml"), user]
+}
+
+fn load() -> Loaded {
+    let mut loaded = Loaded::default();
+    let mut merged = Value::Object(Default::default());
+    for path in candidate_files() {
+        let Ok(text) = std::fs::read_to_string(&path) else { continue };
+        let parsed = toml::from_str::<Value>(&text).map_err(|e| e.to_string()).and_then(|value| {
+            // Check the file on its own so an error names the file it is in
+            serde_json::from_value::<GlobalConfig>(value.clone()).map(|_| value).map_err(|e| e.to_string())
+        });
+        match parsed {
+            Ok(value) => {
+                merge(&mut merged, value);
+                loaded.files.push(path);
+            }
+            Err(e) => {
+                let msg = format!("ignoring {}: {}", path.to_string_lossy(), e);
+                // Import must not fail, and logging may not be set up yet
+                eprintln!("flashvm: {}", msg);
+                loaded.errors.push(msg);
+            }
+        }
+    }
+    loaded.config = serde_json::from_value(merged).unwrap_or_default();
+    loaded
+}
+
+/// Deep-merge `overlay` into `base`; tables merge key by key, anything else is replaced.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
@@ -0,0 +1,304 @@
+use crate::config::{CaptureMode, ExecutionResult, FileOutput, VMConfig, VMConfigFile};
+use crate::daemon::{self, KillOnDrop};
+use crate::error::VMError;
+use crate::image_resolver::{BuildProgress, ImageResolver};
+use crate::rate_limit;
+use crate::runs::RunRegistry;
+use crate::telemetry;
+use crate::vm_runner::{OutputListener, VMRunner};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{TcpListenerStream, UnboundedReceiverStream};
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+mod proto {
+    tonic::include_proto!("flashvm.v1");
+}
+
+use proto::flash_vm_server::{FlashVm, FlashVmServer};
+use proto::{build_event, stream_event};
+use proto::{
+    Artifact, BuildEvent, BuildImageRequest, CancelRequest, CancelResponse, ExecuteRequest, ExecuteResponse,
+    ExtendTimeoutRequest, ExtendTimeoutResponse, OutputChunk, PauseRequest, PauseResponse, PhaseTimings,
+    ResumeRequest, ResumeResponse, StreamEvent,
+};
+
+/// Largest request accepted, input files included
+const MAX_REQUEST_BYTES: usize = 64 * 1024 * 1024;
+
+/// How often the server checks whether the daemon is stopping
+const STOP_POLL: Duration = Duration::from_millis(200);
+
+/// Bind `addr` and serve the gRPC API on a thread of its own until `stop` is set.
+pub fn start(addr: SocketAddr, stop: &'static AtomicBool) -> Result<std::thread::JoinHandle<()>, VMError> {
+    let listener = std::net::TcpListener::bind(addr)
+        .map_err(|e| VMError::VMConfiguration(format!("cannot listen on {}: {}", addr, e)))?;
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    Ok(std::thread::spawn(move || {
+        let served = runtime.block_on(async move {
+            let incoming = TcpListenerStream::new(tokio::net::TcpListener::from_std(listener)?);
+            let service = FlashVmServer::new(Service).max_decoding_message_size(MAX_REQUEST_BYTES);
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, async {
+                    while !stop.load(Ordering::SeqCst) {
+                        tokio::time::sleep(STOP_POLL).await;
+                    }
+                })
+                .await
+                .map_err(|e| VMError::Execution(e.to_string()))
+        });
+        if let Err(e) = served {
+            eprintln!("flashvmd: gRPC server on {} stopped: {}", addr, e);
+        }
+    }))
+}
+
+struct Service;
+
+/// Streams events until dropped, killing the run if it hasn't finished by then.
+struct RunEvents {
+    events: UnboundedReceiverStream<Result<StreamEvent, Status>>,
+    _kill: KillOnDrop,
+}
+
+impl Stream for RunEvents {
+    type Item = Result<StreamEvent, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+#[tonic::async_trait]
+impl FlashVm for Service {
+    async fn execute(&self, request: Request<ExecuteRequest>) -> Result<Response<ExecuteResponse>, Status> {
+        let span = request_span(&request);
+        // The gRPC API is unauthenticated, so its callers share one bucket
+        rate_limit::enforce_api_key(None).map_err(status)?;
+        let mut request = request.into_inner();
+        let run_id = daemon::claim_run_id(request.run_id.take()).map_err(status)?;
+        let kill = KillOnDrop::new(&run_id);
+        let runner = VMRunner::new().with_run_id(Some(run_id));
+        let response = tokio::task::spawn_blocking(move || span.in_scope(|| execute(runner, request, false)))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        kill.finished.store(true, Ordering::SeqCst);
+        response.map(Response::new).map_err(status)
+    }
+
+    type StreamStream = RunEvents;
+
+    async fn stream(&self, request: Request<ExecuteRequest>) -> Result<Response<RunEvents>, Status> {
+        let span = request_span(&request);
+        rate_limit::enforce_api_key(None).map_err(status)?;
+        let mut request = request.into_inner();
+        let run_id = daemon::claim_run_id(request.run_id.take()).map_err(status)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let _ = tx.send(Ok(StreamEvent { event: Some(stream_event::Event::RunId(run_id.clone())) }));
+
+        let output = tx.clone();
+        let listener: OutputListener = Arc::new(move |chunk| {
+            let chunk = OutputChunk { stream: chunk.stream.clone(), ts: chunk.ts, chunk: chunk.chunk.clone() };
+            let _ = output.send(Ok(StreamEvent { event: Some(stream_event::Event::Output(chunk)) }));
+        });
+        let runner = VMRunner::new().with_run_id(Some(run_id.clone())).with_output_listener(Some(listener));
+        let kill = KillOnDrop::new(&run_id);
+        let done = kill.finished.clone();
+        tokio::task::spawn_blocking(move || {
+            let event = span
+                .in_scope(|| execute(runner, request, true))
+                .map(|result| StreamEvent { event: Some(stream_event::Event::Result(result)) })
+                .map_err(status);
+            done.store(true, Ordering::SeqCst);
+            let _ = tx.send(event);
+        });
+        Ok(Response::new(RunEvents {
+            events: UnboundedReceiverStream::new(rx),
+            _kill: kill,
+        }))
+    }
+
+    async fn cancel(&self, request: Request<CancelRequest>) -> Result<Response<CancelResponse>, Status> {
+        let run_id = request.into_inner().run_id;
+        let cancelled = tokio::task::spawn_blocking(move || RunRegistry::new().kill(&run_id))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?;
+        Ok(Response::new(CancelResponse { cancelled }))
+    }
+
+    async fn pause(&self, request: Request<PauseRequest>) -> Result<Response<PauseResponse>, Status> {
+        let run_id = request.into_inner().run_id;
+        let paused = tokio::task::spawn_blocking(move || RunRegistry::new().pause(&run_id))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?;
+        Ok(Response::new(PauseResponse { paused }))
+    }
+
+    async fn resume(&self, request: Request<ResumeRequest>) -> Result<Response<ResumeResponse>, Status> {
+        let run_id = request.into_inner().run_id;
+        let resumed = tokio::task::spawn_blocking(move || RunRegistry::new().resume(&run_id))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?;
+        Ok(Response::new(ResumeResponse { resumed }))
+    }
+
+    async fn extend_timeout(
+        &self,
+        request: Request<ExtendTimeoutRequest>,
+    ) -> Result<Response<ExtendTimeoutResponse>, Status> {
+        let ExtendTimeoutRequest { run_id, seconds } = request.into_inner();
+        if seconds == 0 {
+            return Err(status(VMError::VMConfiguration("seconds must be at least 1".to_string())));
+        }
+        let extended = tokio::task::spawn_blocking(move || RunRegistry::new().extend_timeout(&run_id, seconds))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?;
+        Ok(Response::new(ExtendTimeoutResponse { extended }))
+    }
+
+    type BuildImageStream = UnboundedReceiverStream<Result<BuildEvent, Status>>;
+
+    async fn build_image(&self, request: Request<BuildImageRequest>) -> Result<Response<Self::BuildImageStream>, Status> {
+        let request = request.into_inner();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let log = tx.clone();
+        let progress: BuildProgress = Arc::new(move |line: &str| {
+            let _ = log.send(Ok(BuildEvent { event: Some(build_event::Event::Log(line.to_string())) }));
+        });
+        tokio::task::spawn_blocking(move || {
+            let event = build_image(request, progress)
+                .map(|image| BuildEvent { event: Some(build_event::Event::Image(image)) })
+                .map_err(status);
+            let _ = tx.send(event);
+        });
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
+}
+
+/// Joins the caller's trace when the request carries `traceparent` metadata.
+fn request_span<T>(request: &Request<T>) -> tracing::Span {
+    let metadata = |name: &str| request.metadata().get(name).and_then(|v| v.to_str().ok());
+    telemetry::request_span(metadata("traceparent"), metadata("tracestate"))
+}
+
+/// Settings layered like `run`: defaults, profile, `config_json`, then the explicit fields.
+fn run_config(request: &mut ExecuteRequest) -> Result<VMConfig, VMError> {
+    let settings = match &request.config_json {
+        Some(json) => Some(
+            serde_json::from_str::<VMConfigFile>(json)
+                .map_err(|e| VMError::VMConfiguration(format!("invalid config_json: {}", e)))?,
+        ),
+        None => None,
+    };
+    let mut config = daemon::run_config(request.profile.as_deref(), settings)?;
+    if request.image.is_some() {
+        config.image = request.image.take();
+    }
+    config.cpus = request.cpus.unwrap_or(config.cpus);
+    config.memory_mb = request.memory_mb.unwrap_or(config.memory_mb);
+    config.timeout = request.timeout_seconds.map(Duration::from_secs).unwrap_or(config.timeout);
+    config.network = request.network.unwrap_or(config.network);
+    config.env.extend(std::mem::take(&mut request.env));
+    Ok(config)
+}
+
+fn execute(runner: VMRunner, mut request: ExecuteRequest, stream: bool) -> Result<ExecuteResponse, VMError> {
+    let mut config = run_config(&mut request)?;
+    // Live output comes from the guest's output records
+    if stream && config.effective_capture() == CaptureMode::Stdio {
+        config.capture = CaptureMode::JsonLines;
+    }
+    let (_staging, files) = daemon::stage_inputs(request.files.into_iter().map(|f| (f.guest_path, f.content)))?;
+    let expect = request.expect.into_iter().map(|pattern| FileOutput { pattern }).collect();
+    let result = runner.execute_python_code(&request.code, &config, files, expect)?;
+    response(result)
+}
+
+fn response(result: ExecutionResult) -> Result<ExecuteResponse, VMError> {
+    let mut artifacts = Vec::new();
+    for artifact in result.artifacts {
+        let content = match artifact.content {
+            Some(content) => content.to_vec(),
+            None => std::fs::read(&artifact.host_path)?,
+        };
+        artifacts.push(Artifact { guest_path: artifact.guest_path, content, size_bytes: artifact.size_bytes });
+    }
+    Ok(ExecuteResponse {
+        stdout: result.stdout,
+        stderr: result.stderr,
+        exit_code: result.exit_code,
+        execution_time_ms: result.execution_time.as_millis() as u64,
+        image_used: result.image_used,
+        run_id: result.run_id,
+        attempts: result.attempts,
+        artifacts,
+        cached: result.cached,
+        peak_memory_bytes: result.peak_memory_bytes,
+        timings: Some(PhaseTimings {
+            resolve_ms: result.timings.resolve.as_millis() as u64,
+            stage_ms: result.timings.stage.as_millis() as u64,
+            create_ms: result.timings.create.as_millis() as u64,
+            boot_ms: result.timings.boot.as_millis() as u64,
+            exec_ms: result.timings.exec.as_millis() as u64,
+            collect_ms: result.timings.collect.as_millis() as u64,
+        }),
+    })
+}
+
+fn build_image(request: BuildImageRequest, progress: BuildProgress) -> Result<String, VMError> {
+    let workdir = TempDir::new()?;
+    let containerfile = workdir.path().join("Containerfile");
+    std::fs::write(&containerfile, &request.containerfile)?;
+    let context = workdir.path().join("context");
+    std::fs::create_dir(&context)?;
+    let context = request.context_dir.as_deref().map(std::path::Path::new).unwrap_or(&context);
+    ImageResolver::new().with_progress(Some(progress)).build_from_containerfile(
+        &containerfile,
+        Some(context),
+        request.tag.as_deref(),
+        &request.build_args,
+        None,
+    )
+}
+
+/// gRPC status for `e`; the `flashvm-code` metadata carries `VMError::code`, and
+/// `retry-after` the seconds a rate-limited caller should wait.
+fn status(e: VMError) -> Status {
+    let mut status = Status::new(grpc_code(&e), e.to_string());
+    if let Ok(code) = e.code().parse() {
+        status.metadata_mut().insert("flashvm-code", code);
+    }
+    if let Some(Ok(seconds)) = e.retry_after().map(|d| d.as_secs_f64().ceil().to_string().parse()) {
+        status.metadata_mut().insert("retry-after", seconds);
+    }
+    status
+}
+
+fn grpc_code(e: &VMError) -> tonic::Code {
+    match e {
+        VMError::VMConfiguration(_) | VMError::Validation(_) => tonic::Code::InvalidArgument,
+        VMError::Timeout(_) | VMError::PullTimeout(_) => tonic::Code::DeadlineExceeded,
+        VMError::GuestUnresponsive(_) => tonic::Code::Aborted,
+        VMError::MissingDependency(_) => tonic::Code::FailedPrecondition,
+        VMError::OutOfMemory(_) | VMError::DiskFull(_) => tonic::Code::ResourceExhausted,
+        VMError::Overloaded(_) => tonic::Code::Unavailable,
+        VMError::RateLimited(..) => tonic::Code::ResourceExhausted,
+        VMError::PolicyViolation(_) => tonic::Code::PermissionDenied,
+        VMError::Killed(_) => tonic::Code::Cancelled,
+        VMError::WithContext { source, .. } => grpc_code(source),
+        _ => tonic::Code::Internal,
+    }
+}
@@ -0,0 +1,669 @@
+#!/usr/bin/env python3
+"""FlashVM guest runner.
+
+Shipped verbatim into /work/scripts/run.py; everything run-specific comes from
+the spec file the host writes next to it, so no values are interpolated into code.
+
+Spec and status are frames of the host's control channel (src/guest_channel.rs): a
+4-byte big-endian length, then a MessagePack map {version, kind, body}. The guest
+can't be assumed to have a msgpack package, so the codec below covers the types the
+two sides exchange: nil, bools, ints, floats, str, bin, arrays and maps.
+"""
+import codecs
+import errno
+import json
+import os
+import resource
+import selectors
+import shutil
+import signal
+import socket
+import struct
+import subprocess
+import sys
+import threading
+import time
+
+SPEC_PATH = "/work/scripts/spec.msgpack"
+# Runs user code under cProfile; unlike `python -m cProfile`, exceptions and exit codes get through
+CPROFILE_MAIN = "/work/scripts/cprofile_main.py"
+CPROFILE_SOURCE = """\
+import cProfile, sys
+stats_file, sys.argv = sys.argv[1], sys.argv[2:]
+with open(sys.argv[0], "rb") as f:
+    code = compile(f.read(), sys.argv[0], "exec")
+profiler = cProfile.Profile()
+try:
+    profiler.runctx(code, {"__name__": "__main__", "__file__": sys.argv[0], "__builtins__": __builtins__}, None)
+finally:
+    profiler.dump_stats(stats_file)
+"""
+# Runs user code with a notebook-style display hook: display(obj), plt.show() and a bare
+# expression on the last line write the object's richest repr to the display directory
+DISPLAY_MAIN = "/work/scripts/display_main.py"
+DISPLAY_SOURCE = """\
+import ast, base64, builtins, importlib.abc, importlib.util, io, json, os, sys
+display_dir, sys.argv = sys.argv[1], sys.argv[2:]
+os.environ.setdefault("MPLBACKEND", "Agg")
+entries = []
+# Figures displayed since pyplot last closed them, which show() doesn't repeat
+shown = set()
+# In Jupyter's order of preference: an object's entry is the first of these it has
+REPRS = (
+    ("text/html", "_repr_html_", "html"), ("text/markdown", "_repr_markdown_", "md"),
+    ("text/latex", "_repr_latex_", "tex"), ("image/svg+xml", "_repr_svg_", "svg"),
+    ("image/png", "_repr_png_", "png"), ("image/jpeg", "_repr_jpeg_", "jpg"),
+    ("application/json", "_repr_json_", "json"),
+)
+
+def rich_repr(obj):
+    if hasattr(obj, "savefig") and type(obj).__module__.startswith("matplotlib"):
+        shown.add(id(obj))
+        buf = io.BytesIO()
+        obj.savefig(buf, format="png", bbox_inches="tight")
+        return "image/png", buf.getvalue(), "png"
+    for kind, method, ext in REPRS:
+        fn = None if isinstance(obj, type) else getattr(obj, method, None)
+        try:
+            data = fn() if callable(fn) else None
+        except Exception:
+            continue
+        if isinstance(data, tuple):
+            data = data[0]
+        if data is None:
+            continue
+        if kind == "application/json" and not isinstance(data, (str, bytes)):
+            data = json.dumps(data)
+        elif kind.startswith("image/") and ext != "svg" and isinstance(data, str):
+            data = base64.b64decode(data)
+        return kind, data, ext
+    return "text/plain", None, "txt"
+
+def display(*objs):
+    for obj in objs:
+        text = repr(obj)
+        kind, data, ext = rich_repr(obj)
+        name = "%d.%s" % (len(entries), ext)
+        data = text if data is None else data
+        os.makedirs(display_dir, exist_ok=True)
+        with open(os.path.join(display_dir, name), "wb") as f:
+            f.write(data if isinstance(data, bytes) else data.encode("utf-8"))
+        entries.append({"type": kind, "file": name, "text": text[:1000]})
+        with open(os.path.join(display_dir, "index.json"), "w", encoding="utf-8") as f:
+            json.dump(entries, f)
+
+def show_figures(*args, **kwargs):
+    plt = sys.modules["matplotlib.pyplot"]
+    for num in plt.get_fignums():
+        if id(plt.figure(num)) not in shown:
+            display(plt.figure(num))
+    plt.close("all")
+    shown.clear()
+
+class PyplotHook(importlib.abc.MetaPathFinder):
+    def find_spec(self, name, path, target=None):
+        if name != "matplotlib.pyplot":
+            return None
+        sys.meta_path.remove(self)
+        spec = importlib.util.find_spec(name)
+        exec_module = spec.loader.exec_module
+        def patched(module):
+            exec_module(module)
+            module.show = show_figures
+        spec.loader.exec_module = patched
+        return spec
+
+builtins.display = display
+sys.meta_path.insert(0, PyplotHook())
+with open(sys.argv[0], "rb") as f:
+    tree = ast.parse(f.read(), sys.argv[0])
+last = tree.body.pop() if tree.body and isinstance(tree.body[-1], ast.Expr) else None
+namespace = {"__name__": "__main__", "__file__": sys.argv[0], "__builtins__": builtins}
+try:
+    exec(compile(tree, sys.argv[0], "exec"), namespace)
+    if last is not None:
+        value = eval(compile(ast.Expression(last.value), sys.argv[0], "eval"), namespace)
+        if value is not None:
+            display(value)
+finally:
+    # Figures left open are shown, as a notebook's inline backend would
+    if "matplotlib.pyplot" in sys.modules:
+        show_figures()
+"""
+PROTOCOL_VERSION = 1
+VENV_DIR = "/work/.venv"
+CHUNK_SIZE = 65536
+# Writable by user code when it runs unprivileged
+USER_DIRS = ("/work/in", "/work/out", "/work/tmp")
+SECRETS_DIR = "/run/secrets"
+RLIMITS = {
+    "nofile": resource.RLIMIT_NOFILE,
+    "nproc": resource.RLIMIT_NPROC,
+    "fsize": resource.RLIMIT_FSIZE,
+    "core": resource.RLIMIT_CORE,
+}
+PR_SET_NO_NEW_PRIVS = 38
+PR_SET_SECCOMP = 22
+SECCOMP_MODE_FILTER = 2
+SECCOMP_RET_ALLOW = 0x7FFF0000
+SECCOMP_RET_ERRNO = 0x00050000
+# Classic BPF opcodes: BPF_LD|BPF_W|BPF_ABS, BPF_JMP|BPF_JEQ|BPF_K, BPF_ALU|BPF_AND|BPF_K, BPF_RET|BPF_K
+BPF_LD_W_ABS = 0x20
+BPF_JEQ_K = 0x15
+BPF_AND_K = 0x54
+BPF_RET_K = 0x06
+
+
+class ProtocolError(ValueError):
+    pass
+
+
+# Fixed-size MessagePack types: marker -> struct format
+_FIXED = {
+    0xCA: ">f", 0xCB: ">d",
+    0xCC: ">B", 0xCD: ">H", 0xCE: ">I", 0xCF: ">Q",
+    0xD0: ">b", 0xD1: ">h", 0xD2: ">i", 0xD3: ">q",
+}
+# Sized MessagePack types: marker -> (length format, kind)
+_SIZED = {
+    0xC4: (">B", "bin"), 0xC5: (">H", "bin"), 0xC6: (">I", "bin"),
+    0xD9: (">B", "str"), 0xDA: (">H", "str"), 0xDB: (">I", "str"),
+    0xDC: (">H", "array"), 0xDD: (">I", "array"),
+    0xDE: (">H", "map"), 0xDF: (">I", "map"),
+}
+
+
+def pack(obj):
+    out = bytearray()
+    _pack(obj, out)
+    return bytes(out)
+
+
+def _pack_size(out, n, fix_marker, fix_limit, markers):
+    """Write the header of a sized type: its fix form when n fits, else the smallest of
+    markers (8-, 16- and 32-bit lengths; None where the type has no such form)."""
+    if fix_marker is not None and n < fix_limit:
+        out.append(fix_marker | n)
+        return
+    for marker, fmt, limit in zip(markers, (">B", ">H", ">I"), (1 << 8, 1 << 16, 1 << 32)):
+        if marker is not None and n < limit:
+            out.append(marker)
+            out += struct.pack(fmt, n)
+            return
+    raise ProtocolError("value too large for MessagePack: %d" % n)
+
+
+def _pack(obj, out):
+    if obj is None:
+        out.append(0xC0)
+    elif obj is False or obj is True:
+        out.append(0xC3 if obj else 0xC2)
+    elif isinstance(obj, int):
+        if 0 <= obj < 0x80 or -32 <= obj < 0:
+            out.append(obj & 0xFF)
+        elif 0 <= obj < 1 << 64:
+            out.append(0xCF)
+            out += struct.pack(">Q", obj)
+        elif -(1 << 63) <= obj < 0:
+            out.append(0xD3)
+            out += struct.pack(">q", obj)
+        else:
+            raise ProtocolError("integer out of range: %d" % obj)
+    elif isinstance(obj, float):
+        out.append(0xCB)
+        out += struct.pack(">d", obj)
+    elif isinstance(obj, str):
+        data = obj.encode("utf-8")
+        _pack_size(out, len(data), 0xA0, 32, (0xD9, 0xDA, 0xDB))
+        out += data
+    elif isinstance(obj, (bytes, bytearray)):
+        _pack_size(out, len(obj), None, 0, (0xC4, 0xC5, 0xC6))
+        out += obj
+    elif isinstance(obj, (list, tuple)):
+        _pack_size(out, len(obj), 0x90, 16, (None, 0xDC, 0xDD))
+        for item in obj:
+            _pack(item, out)
+    elif isinstance(obj, dict):
+        _pack_size(out, len(obj), 0x80, 16, (None, 0xDE, 0xDF))
+        for key, value in obj.items():
+            _pack(key, out)
+            _pack(value, out)
+    else:
+        raise ProtocolError("can't encode %s as MessagePack" % type(obj).__name__)
+
+
+def unpack(data):
+    try:
+        obj, end = _unpack(data, 0)
+    except (IndexError, struct.error):
+        raise ProtocolError("truncated MessagePack value")
+    if end != len(data):
+        raise ProtocolError("%d trailing bytes after MessagePack value" % (len(data) - end))
+    return obj
+
+
+def _unpack(data, i):
+    marker = data[i]
+    i += 1
+    if marker <= 0x7F:
+        return marker, i
+    if marker >= 0xE0:
+        return marker - 0x100, i
+    if marker <= 0x8F:
+        return _unpack_items(data, i, marker & 0x0F, "map")
+    if marker <= 0x9F:
+        return _unpack_items(data, i, marker & 0x0F, "array")
+    if marker <= 0xBF:
+        return _unpack_items(data, i, marker & 0x1F, "str")
+    if marker in (0xC0, 0xC2, 0xC3):
+        return {0xC0: None, 0xC2: False, 0xC3: True}[marker], i
+    if marker in _FIXED:
+        fmt = _FIXED[marker]
+        return struct.unpack_from(fmt, data, i)[0], i + struct.calcsize(fmt)
+    if marker in _SIZED:
+        fmt, kind = _SIZED[marker]
+        n = struct.unpack_from(fmt, data, i)[0]
+        return _unpack_items(data, i + struct.calcsize(fmt), n, kind)
+    raise ProtocolError("unsupported MessagePack marker 0x%02x" % marker)
+
+
+def _unpack_items(data, i, n, kind):
+    if kind in ("str", "bin"):
+        if i + n > len(data):
+            raise IndexError(i + n)
+        raw = bytes(data[i:i + n])
+        return (raw.decode("utf-8") if kind == "str" else raw), i + n
+    if kind == "array":
+        items = []
+        for _ in range(n):
+            item, i = _unpack(data, i)
+            items.append(item)
+        return items, i
+    result = {}
+    for _ in range(n):
+        key, i = _unpack(data, i)
+        result[key], i = _unpack(data, i)
+    return result, i
+
+
+def read_frame(path, kind):
+    """The body of the control-channel frame at path, which must be of this kind and version."""
+    with open(path, "rb") as f:
+        data = f.read()
+    if len(data) < 4 or struct.unpack(">I", data[:4])[0] != len(data) - 4:
+        raise ProtocolError("%s: truncated frame" % path)
+    frame = unpack(data[4:])
+    if not isinstance(frame, dict) or frame.get("version") != PROTOCOL_VERSION or frame.get("kind") != kind:
+        got = (frame.get("version"), frame.get("kind")) if isinstance(frame, dict) else None
+        raise ProtocolError("%s: expected a version %d %r frame, got %r" % (path, PROTOCOL_VERSION, kind, got))
+    return frame.get("body")
+
+
+def write_frame(path, kind, body):
+    payload = pack({"version": PROTOCOL_VERSION, "kind": kind, "body": body})
+    with open(path, "wb") as f:
+        f.write(struct.pack(">I", len(payload)) + payload)
+
+
+def install_requirements(spec, env):
+    """Install spec["requirements"] into a throwaway venv; returns (python, exit code)."""
+    subprocess.run([sys.executable, "-m", "venv", "--system-site-packages", VENV_DIR], check=True)
+    python = os.path.join(VENV_DIR, "bin", "python")
+    uv = shutil.which("uv") if spec.get("use_uv") else None
+    if uv:
+        cmd = [uv, "pip", "install", "--python", python, "--no-config", "-q"]
+    else:
+        cmd = [python, "-m", "pip", "install", "--disable-pip-version-check", "--no-input", "-q"]
+    if spec.get("find_links"):
+        cmd += ["--no-index", "--find-links", spec["find_links"]]
+    res = subprocess.run(cmd + list(spec["requirements"]), env=env)
+    if res.returncode != 0:
+        sys.stderr.write("flashvm: failed to install requirements\n")
+    return python, res.returncode
+
+
+def run_captured(cmd, env, capture_path, passthrough, **popen_kwargs):
+    """Run cmd, recording every output chunk as a JSON line {stream, ts, chunk}.
+
+    ts is seconds since the process started (monotonic). With passthrough the
+    chunks are also copied to this runner's own stdout/stderr.
+    """
+    start = time.monotonic()
+    proc = subprocess.Popen(cmd, env=env, stdout=subprocess.PIPE, stderr=subprocess.PIPE, **popen_kwargs)
+    sel = selectors.DefaultSelector()
+    for name, pipe, console in (("stdout", proc.stdout, sys.stdout), ("stderr", proc.stderr, sys.stderr)):
+        decoder = codecs.getincrementaldecoder("utf-8")("replace")
+        sel.register(pipe, selectors.EVENT_READ, (name, console, decoder))
+
+    with open(capture_path, "w", encoding="utf-8") as out:
+        while sel.get_map():
+            for key, _ in sel.select():
+                name, console, decoder = key.data
+                data = os.read(key.fd, CHUNK_SIZE)
+                chunk = decoder.decode(data, final=not data)
+                if not data:
+                    sel.unregister(key.fileobj)
+                if chunk:
+                    record = {"stream": name, "ts": round(time.monotonic() - start, 6), "chunk": chunk}
+                    out.write(json.dumps(record) + "\n")
+                    out.flush()
+                if passthrough and data:
+                    console.buffer.write(data)
+                    console.flush()
+    return wait_with_usage(proc)
+
+
+def wait_with_usage(proc):
+    """Reap proc with wait4 to get its own resource usage; returns (returncode, peak RSS in bytes).
+
+    The peak covers proc and the children it waited for, not the runner or pip before it.
+    """
+    while True:
+        try:
+            _, status, usage = os.wait4(proc.pid, 0)
+            break
+        except InterruptedError:
+            continue
+    proc.returncode = -os.WTERMSIG(status) if os.WIFSIGNALED(status) else os.WEXITSTATUS(status)
+    # ru_maxrss is in KiB on Linux
+    return proc.returncode, usage.ru_maxrss * 1024
+
+
+def chown_tree(root, uid, gid):
+    for dirpath, dirnames, filenames in os.walk(root):
+        os.lchown(dirpath, uid, gid)
+        for name in dirnames + filenames:
+            os.lchown(os.path.join(dirpath, name), uid, gid)
+
+
+def ensure_account(uid, gid):
+    """Give uid/gid passwd/group entries so tools that look the user up keep working."""
+    try:
+        with open("/etc/group", "a+", encoding="utf-8") as f:
+            f.seek(0)
+            if not any(line.split(":")[2:3] == [str(gid)] for line in f):
+                f.write("flashvm:x:%d:\n" % gid)
+        with open("/etc/passwd", "a+", encoding="utf-8") as f:
+            f.seek(0)
+            if not any(line.split(":")[2:3] == [str(uid)] for line in f):
+                f.write("flashvm:x:%d:%d::/work/tmp:/sbin/nologin\n" % (uid, gid))
+    except OSError:
+        pass
+
+
+def seccomp_installer(spec):
+    """Build the filter now; return a function that installs it (in the child, last).
+
+    Classic BPF over seccomp_data: calls under another ABI, the denied syscalls and (when
+    asked) socket(AF_PACKET, ...) / socket(..., SOCK_RAW) fail with EPERM; the rest run.
+    """
+    import ctypes  # only needed here; keeps the runner importable in images without libffi
+
+    def stmt(code, k):
+        return (code, 0, 0, k)
+
+    def jeq(k, jt, jf):
+        return (BPF_JEQ_K, jt, jf, k)
+
+    deny = stmt(BPF_RET_K, SECCOMP_RET_ERRNO | errno.EPERM)
+    allow = stmt(BPF_RET_K, SECCOMP_RET_ALLOW)
+    prog = [stmt(BPF_LD_W_ABS, 4), jeq(spec["arch"], 1, 0), deny, stmt(BPF_LD_W_ABS, 0)]
+    for nr in spec["deny"]:
+        prog += [jeq(nr, 0, 1), deny]
+    if spec.get("socket") is not None:
+        # args[0] (domain) and args[1] (type) low words; both guest ABIs are little-endian
+        prog += [
+            jeq(spec["socket"], 0, 6),
+            stmt(BPF_LD_W_ABS, 16), jeq(socket.AF_PACKET, 3, 0),
+            stmt(BPF_LD_W_ABS, 24), stmt(BPF_AND_K, 0xF), jeq(socket.SOCK_RAW, 0, 1),
+            deny,
+        ]
+    prog.append(allow)
+
+    class SockFilter(ctypes.Structure):
+        _fields_ = [("code", ctypes.c_ushort), ("jt", ctypes.c_ubyte), ("jf", ctypes.c_ubyte), ("k", ctypes.c_uint)]
+
+    class SockFprog(ctypes.Structure):
+        _fields_ = [("len", ctypes.c_ushort), ("filter", ctypes.POINTER(SockFilter))]
+
+    filters = (SockFilter * len(prog))(*[SockFilter(*ins) for ins in prog])
+    fprog = SockFprog(len(prog), filters)
+    libc = ctypes.CDLL(None, use_errno=True)
+
+    def install():
+        # fprog/filters stay referenced by this closure until exec
+        if libc.prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 or \
+                libc.prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, ctypes.byref(fprog), 0, 0) != 0:
+            err = ctypes.get_errno()
+            raise OSError(err, os.strerror(err))
+
+    return install
+
+
+def child_setup(rlimits, run_as, seccomp):
+    """preexec_fn for user code: set the limits while still root, so they may go above
+    the guest's defaults, then drop to run_as (Popen's user= would drop first), then
+    install the seccomp filter so none of the above is filtered."""
+    limits = [(RLIMITS[name], value) for name, value in rlimits.items()]
+    install_filter = seccomp_installer(seccomp) if seccomp else None
+
+    def preexec():
+        step = "rlimits"
+        try:
+            for res, value in limits:
+                resource.setrlimit(res, (value, value))
+            if run_as:
+                step = "run_as"
+                uid, gid = run_as
+                os.setgroups([])
+                os.setgid(gid)
+                os.setuid(uid)
+            if install_filter:
+                step = "seccomp"
+                install_filter()
+        except (OSError, ValueError) as e:
+            os.write(2, ("flashvm: %s: %s\n" % (step, e)).encode())
+            os._exit(126)
+
+    return preexec
+
+
+def install_secrets(src, owner):
+    """Move secret files from the host-backed mount into a private tmpfs.
+
+    Fails closed: if the tmpfs can't be mounted the secrets are not exposed at all.
+    """
+    names = sorted(os.listdir(src))
+    if not names:
+        return
+    os.makedirs(SECRETS_DIR, exist_ok=True)
+    res = subprocess.run(["mount", "-t", "tmpfs", "-o", "mode=0700,size=1m,nosuid,nodev,noexec", "tmpfs", SECRETS_DIR],
+                         stderr=subprocess.PIPE)
+    if res.returncode != 0:
+        raise OSError(res.stderr.decode(errors="replace").strip() or "mount tmpfs failed")
+    for name in names:
+        path = os.path.join(src, name)
+        with open(path, "rb") as f:
+            data = f.read()
+        os.unlink(path)
+        dest = os.path.join(SECRETS_DIR, name)
+        fd = os.open(dest, os.O_WRONLY | os.O_CREAT | os.O_TRUNC, 0o400)
+        with os.fdopen(fd, "wb") as f:
+            f.write(data)
+        if owner:
+            os.chown(dest, *owner)
+    if owner:
+        os.chown(SECRETS_DIR, *owner)
+
+
+def install_timezone(tz, env):
+    """Make tz["name"] the guest's local time: /etc/localtime for whatever reads it, and TZ.
+
+    The image's tz database is preferred; without one TZ points at the host's copy of the zone.
+    A TZ from the caller's env is left alone.
+    """
+    zoneinfo = os.path.join("/usr/share/zoneinfo", tz["name"])
+    source = zoneinfo if os.path.isfile(zoneinfo) else tz.get("zoneinfo")
+    if source is None:
+        env.setdefault("TZ", tz["name"])
+        return
+    try:
+        shutil.copyfile(source, "/etc/localtime.flashvm")
+        os.replace("/etc/localtime.flashvm", "/etc/localtime")
+    except OSError:
+        pass
+    env.setdefault("TZ", tz["name"] if source == zoneinfo else ":" + source)
+
+
+def start_heartbeat(heartbeat):
+    """Rewrite heartbeat["path"] every interval from a daemon thread, so the host can tell a
+    guest that is still up from one that hung or crashed. It beats for as long as this process
+    runs, whatever user code is doing.
+    """
+    def beat():
+        while True:
+            try:
+                with open(heartbeat["path"], "w") as f:
+                    f.write("%.3f\n" % time.time())
+            except OSError:
+                pass
+            time.sleep(heartbeat["interval_seconds"])
+
+    threading.Thread(target=beat, name="flashvm-heartbeat", daemon=True).start()
+
+
+def oom_kill_count():
+    """Kernel-wide OOM kill counter, or None when /proc/vmstat doesn't expose it."""
+    try:
+        with open("/proc/vmstat", encoding="ascii") as f:
+            for line in f:
+                if line.startswith("oom_kill "):
+                    return int(line.split()[1])
+    except (OSError, ValueError):
+        pass
+    return None
+
+
+def summarize_profile(cprofile):
+    """Write the top functions of the pstats dump, by cumulative time, as JSON."""
+    import pstats
+
+    stats = pstats.Stats(cprofile["stats_file"])
+    rows = sorted(stats.stats.items(), key=lambda item: item[1][3], reverse=True)
+    top = [
+        {
+            "file": file, "line": line, "function": function, "calls": calls, "primitive_calls": primitive,
+            "total_time": round(total, 6), "cumulative_time": round(cumulative, 6),
+        }
+        for (file, line, function), (primitive, calls, total, cumulative, _) in rows[:cprofile["top"]]
+    ]
+    with open(cprofile["report"], "w", encoding="utf-8") as f:
+        json.dump({"total_time": round(stats.total_tt, 6), "top": top}, f)
+
+
+def main():
+    try:
+        spec = read_frame(SPEC_PATH, "spec")
+    except (OSError, ProtocolError) as e:
+        sys.stderr.write("flashvm: control channel: %s\n" % e)
+        return 126
+    if spec.get("heartbeat"):
+        start_heartbeat(spec["heartbeat"])
+
+    env = dict(os.environ)
+    env.update({str(k): str(v) for k, v in spec.get("env", {}).items()})
+    env["FLASHVM_RUN_ID"] = spec["run_id"]
+    if spec.get("timezone"):
+        install_timezone(spec["timezone"], env)
+
+    # Tells the host user code is about to run (start failures past this point are not retried)
+    open(spec["started_marker"], "w").close()
+
+    run_as = spec.get("run_as")
+    if spec.get("secrets_in"):
+        try:
+            install_secrets(spec["secrets_in"], run_as)
+        except OSError as e:
+            sys.stderr.write("flashvm: secrets: %s\n" % e)
+            return 126
+
+    python = sys.executable
+    if spec.get("requirements"):
+        python, rc = install_requirements(spec, env)
+        if rc != 0:
+            return rc
+
+    target = [spec["main"]]
+    if spec.get("display"):
+        with open(DISPLAY_MAIN, "w", encoding="utf-8") as f:
+            f.write(DISPLAY_SOURCE)
+        target = [DISPLAY_MAIN, spec["display"]] + target
+    cprofile = spec.get("cprofile")
+    if cprofile:
+        with open(CPROFILE_MAIN, "w", encoding="utf-8") as f:
+            f.write(CPROFILE_SOURCE)
+        target = [CPROFILE_MAIN, cprofile["stats_file"]] + target
+    cmd = [python] + list(spec.get("python_args", [])) + target
+    coverage = spec.get("coverage")
+    if coverage:
+        if subprocess.run([python, "-c", "import coverage"], env=env, stderr=subprocess.DEVNULL).returncode != 0:
+            sys.stderr.write("flashvm: coverage: coverage.py is not installed; add it to the image or requirements\n")
+            return 126
+        # COVERAGE_FILE rather than --data-file, which older coverage.py releases lack
+        env["COVERAGE_FILE"] = coverage["data_file"]
+        cmd = [python] + list(spec.get("python_args", [])) + ["-m", "coverage", "run", "--omit", CPROFILE_MAIN + "," + DISPLAY_MAIN] + target
+    popen_kwargs = {}
+    if run_as:
+        uid, gid = run_as
+        ensure_account(uid, gid)
+        for d in USER_DIRS:
+            chown_tree(d, uid, gid)
+        env["HOME"] = "/work/tmp"
+        popen_kwargs = {"user": uid, "group": gid, "extra_groups": []}
+    rlimits = {k: v for k, v in (spec.get("rlimits") or {}).items() if v is not None}
+    if rlimits or spec.get("seccomp"):
+        popen_kwargs = {"preexec_fn": child_setup(rlimits, run_as, spec.get("seccomp"))}
+
+    capture = spec.get("capture", "stdio")
+    oom_before = oom_kill_count()
+    try:
+        if capture != "stdio":
+            rc, peak_rss = run_captured(cmd, env, spec["capture_path"], passthrough=capture == "both", **popen_kwargs)
+        else:
+            rc, peak_rss = wait_with_usage(subprocess.Popen(cmd, env=env, **popen_kwargs))
+    finally:
+        if run_as:
+            # Hand /work back to guest root (the host user) so the host can clean it up
+            for d in USER_DIRS:
+                chown_tree(d, 0, 0)
+    oom_after = oom_kill_count()
+    if coverage and os.path.exists(coverage["data_file"]):
+        subprocess.run([python, "-m", "coverage", "json", "-o", coverage["report"]], env=env, stdout=subprocess.DEVNULL)
+    if cprofile and os.path.exists(cprofile["stats_file"]):
+        try:
+            summarize_profile(cprofile)
+        except (OSError, ValueError, EOFError) as e:
+            sys.stderr.write("flashvm: cprofile: %s\n" % e)
+
+    # Prefer the kernel's counter; a bare SIGKILL is the best guess without it
+    if oom_before is not None and oom_after is not None:
+        oom = oom_after > oom_before
+    else:
+        oom = rc == -signal.SIGKILL
+    status = {"returncode": rc, "oom": oom, "peak_rss_bytes": peak_rss}
+    try:
+        st = os.statvfs("/work")
+        status["work_free_bytes"] = st.f_bavail * st.f_frsize
+        status["work_total_bytes"] = st.f_blocks * st.f_frsize
+    except OSError:
+        pass
+    try:
+        write_frame(spec["status_path"], "status", status)
+    except OSError:
+        # /work may be the thing that is full; the host falls back to its own checks
+        pass
+    return rc
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,154 @@
+use crate::config::Rlimits;
+use crate::error::VMError;
+use crate::seccomp;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use log::debug;
+
+/// Version of the host ↔ guest runner contract. Bumped whenever a field changes meaning or a
+/// new one becomes required; `guest/runner.py` has the same number and refuses specs of any
+/// other, and the host ignores statuses of any other.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Written by the host before boot (under /work/scripts)
+pub const SPEC_FILE: &str = "spec.msgpack";
+
+/// Written by the guest runner after user code exits (under /work/scripts)
+pub const STATUS_FILE: &str = "status.msgpack";
+
+/// Touched by the guest runner every `HEARTBEAT_INTERVAL` while it is up (under /work/scripts)
+pub const HEARTBEAT_FILE: &str = "heartbeat";
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Frames larger than this are not a status the runner wrote
+const MAX_FRAME_BYTES: usize = 1024 * 1024;
+
+/// Both directions are a file handshake on the `/work` share: the host writes the spec before
+/// boot, the runner writes its status once user code exits. Each file holds one frame: a 4-byte
+/// big-endian length, then this as a MessagePack map.
+#[derive(Serialize, Deserialize)]
+struct Frame<T> {
+    version: u32,
+    kind: String,
+    body: T,
+}
+
+/// Host → guest: everything the runner needs to start user code
+#[derive(Serialize)]
+pub struct GuestSpec<'a> {
+    pub env: &'a HashMap<String, String>,
+    pub python_args: &'a [String],
+    pub main: String,
+    pub started_marker: String,
+    pub run_id: &'a str,
+    pub requirements: &'a [String],
+    pub find_links: Option<&'static str>,
+    pub use_uv: bool,
+    pub capture: &'static str,
+    pub capture_path: String,
+    pub status_path: String,
+    pub run_as: Option<(u32, u32)>,
+    pub rlimits: Option<Rlimits>,
+    pub seccomp: Option<seccomp::GuestFilter>,
+    pub secrets_in: Option<&'static str>,
+    /// coverage.py data file and JSON report, when user code runs under coverage
+    pub coverage: Option<GuestCoverage>,
+    /// pstats dump and summary, when user code runs under cProfile
+    pub cprofile: Option<GuestProfile>,
+    /// Directory the display hook writes outputs and their index to, when it is installed
+    pub display: Option<String>,
+    /// Zone for user code's local time, when one was asked for
+    pub timezone: Option<GuestTimezone>,
+    /// Where to send heartbeats, when the host watches for them
+    pub heartbeat: Option<GuestHeartbeat>,
+}
+
+#[derive(Serialize)]
+pub struct GuestHeartbeat {
+    pub path: String,
+    pub interval_seconds: f64,
+}
+
+#[derive(Serialize)]
+pub struct GuestTimezone {
+    pub name: String,
+    /// The host's TZif file for the zone, for images without a tz database
+    pub zoneinfo: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GuestCoverage {
+    pub data_file: String,
+    pub report: String,
+}
+
+#[derive(Serialize)]
+pub struct GuestProfile {
+    pub stats_file: String,
+    pub report: String,
+    /// Functions kept in the summary
+    pub top: usize,
+}
+
+/// Guest → host: what the runner saw of the user process
+#[derive(Debug, Default, Deserialize)]
+pub struct GuestStatus {
+    #[serde(default)]
+    pub oom: bool,
+    /// statvfs of /work after the run, as seen by the guest
+    #[serde(default)]
+    pub work_free_bytes: Option<u64>,
+    #[serde(default)]
+    pub work_total_bytes: Option<u64>,
+    /// Peak RSS of the user process and the children it waited for
+    #[serde(default)]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+fn encode<T: Serialize>(kind: &str, body: &T) -> Result<Vec<u8>, VMError> {
+    let frame = Frame { version: PROTOCOL_VERSION, kind: kind.to_string(), body };
+    let payload = rmp_serde::to_vec_named(&frame).map_err(|e| VMError::Execution(e.to_string()))?;
+    let mut out = Vec::with_capacity(4 + payload.len());
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(&payload);
+    Ok(out)
+}
+
+fn decode<T: DeserializeOwned>(kind: &str, data: &[u8]) -> Result<T, String> {
+    let (len, payload) = data.split_first_chunk::<4>().ok_or("truncated frame header")?;
+    let len = u32::from_be_bytes(*len) as usize;
+    if len > MAX_FRAME_BYTES || payload.len() != len {
+        return Err(format!("frame of {} bytes announces {}", payload.len(), len));
+    }
+    #[derive(Deserialize)]
+    struct Header {
+        version: u32,
+        kind: String,
+    }
+    let header: Header = rmp_serde::from_slice(payload).map_err(|e| e.to_string())?;
+    if header.version != PROTOCOL_VERSION {
+        return Err(format!("version {} (expected {})", header.version, PROTOCOL_VERSION));
+    }
+    if header.kind != kind {
+        return Err(format!("a '{}' frame where '{}' was expected", header.kind, kind));
+    }
+    let frame: Frame<T> = rmp_serde::from_slice(payload).map_err(|e| e.to_string())?;
+    Ok(frame.body)
+}
+
+pub fn write_spec(scripts_dir: &Path, spec: &GuestSpec) -> Result<(), VMError> {
+    fs::write(scripts_dir.join(SPEC_FILE), encode("spec", spec)?)?;
+    Ok(())
+}
+
+/// The runner's status, or None when it wrote none (the guest went down with it, or /work was
+/// full) or wrote one this host can't read.
+pub fn read_status(scripts_dir: &Path) -> Option<GuestStatus> {
+    let data = fs::read(scripts_dir.join(STATUS_FILE)).ok()?;
+    decode("status", &data).map_err(|e| debug!("Ignoring guest status: {}", e)).ok()
+}
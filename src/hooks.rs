@@ -0,0 +1,88 @@
+use crate::config::{ExecutionResult, VMConfig};
+use crate::error::VMError;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// What a hook is told about the run it is called for. Only hooks registered from
+/// Python read it.
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub struct RunContext<'a> {
+    pub run_id: &'a str,
+    pub code: &'a str,
+}
+
+/// Custom logic around every run of this process: logging, quota checks, artifact
+/// post-processing. Both methods default to doing nothing.
+pub trait RunHook: Send + Sync {
+    /// Called before anything is resolved or staged, and before the config is validated and
+    /// checked against the sandbox policy. May change `config`; Err vetoes the run.
+    fn before_run(&self, _ctx: &RunContext, _config: &mut VMConfig) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called once outputs and artifacts are collected (or the result came from the result
+    /// cache). May change `result`; Err fails the run.
+    fn after_run(&self, _ctx: &RunContext, _config: &VMConfig, _result: &mut ExecutionResult) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Hooks by name, in the order they were first registered
+pub type Hooks = Vec<(String, Arc<dyn RunHook>)>;
+
+fn registry() -> &'static Mutex<Hooks> {
+    static HOOKS: OnceLock<Mutex<Hooks>> = OnceLock::new();
+    HOOKS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Add hook `name`, or replace it where it stands.
+#[cfg(feature = "python")]
+pub fn register(name: &str, hook: Arc<dyn RunHook>) -> Result<(), VMError> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(VMError::VMConfiguration(format!(
+            "invalid hook name '{}': use letters, digits, '-', '_' and '.'",
+            name
+        )));
+    }
+    let mut hooks = registry().lock().unwrap_or_else(|e| e.into_inner());
+    match hooks.iter_mut().find(|(n, _)| n == name) {
+        Some(entry) => entry.1 = hook,
+        None => hooks.push((name.to_string(), hook)),
+    }
+    Ok(())
+}
+
+/// Remove hook `name`; false when there was none.
+#[cfg(feature = "python")]
+pub fn unregister(name: &str) -> bool {
+    let mut hooks = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let before = hooks.len();
+    hooks.retain(|(n, _)| n != name);
+    hooks.len() != before
+}
+
+#[cfg(feature = "python")]
+pub fn names() -> Vec<String> {
+    registry().lock().unwrap_or_else(|e| e.into_inner()).iter().map(|(n, _)| n.clone()).collect()
+}
+
+/// The hooks a run is made with; later registrations don't affect it.
+pub fn snapshot() -> Hooks {
+    registry().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn before_run(hooks: &Hooks, ctx: &RunContext, config: &mut VMConfig) -> Result<(), VMError> {
+    for (name, hook) in hooks {
+        hook.before_run(ctx, config)
+            .map_err(|reason| VMError::PolicyViolation(format!("run vetoed by hook '{}': {}", name, reason)))?;
+    }
+    Ok(())
+}
+
+pub fn after_run(hooks: &Hooks, ctx: &RunContext, config: &VMConfig, result: &mut ExecutionResult) -> Result<(), VMError> {
+    for (name, hook) in hooks {
+        hook.after_run(ctx, config, result)
+            .map_err(|e| VMError::Execution(format!("hook '{}' failed after the run: {}", name, e)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,353 @@
+use crate::concurrency;
+use crate::config::{CaptureMode, ExecutionResult, FileOutput, VMConfigFile};
+use crate::daemon::{self, InputFile, KillOnDrop};
+use crate::error::VMError;
+use crate::image_resolver::ImageResolver;
+use crate::rate_limit;
+use crate::runs::RunRegistry;
+use crate::telemetry;
+use crate::tenants;
+use crate::vm_runner::{OutputListener, VMRunner};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Extension, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::StreamExt;
+
+/// How often the server checks whether the daemon is stopping
+const STOP_POLL: Duration = Duration::from_millis(200);
+
+/// Bind `addr` and serve the HTTP API on a thread of its own until `stop` is set. When
+/// `FLASHVM_HTTP_TOKEN` is set (one or more comma-separated API keys), every endpoint but
+/// `/healthz` needs one of them as a bearer token.
+pub fn start(addr: SocketAddr, stop: &'static AtomicBool) -> Result<std::thread::JoinHandle<()>, VMError> {
+    let listener = std::net::TcpListener::bind(addr)
+        .map_err(|e| VMError::VMConfiguration(format!("cannot listen on {}: {}", addr, e)))?;
+    listener.set_nonblocking(true)?;
+    let tokens: Vec<String> = std::env::var("FLASHVM_HTTP_TOKEN")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect();
+    let runtime = tokio::runtime::Runtime::new()?;
+    Ok(std::thread::spawn(move || {
+        let served = runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            axum::serve(listener, router(Arc::new(tokens)))
+                .with_graceful_shutdown(async {
+                    while !stop.load(Ordering::SeqCst) {
+                        tokio::time::sleep(STOP_POLL).await;
+                    }
+                })
+                .await
+        });
+        if let Err(e) = served {
+            eprintln!("flashvmd: HTTP server on {} stopped: {}", addr, e);
+        }
+    }))
+}
+
+fn router(tokens: Arc<Vec<String>>) -> Router {
+    Router::new()
+        .route("/run", post(run))
+        .route("/runs", get(list_runs))
+        .route("/runs/{id}", get(get_run).delete(kill_run))
+        .route("/runs/{id}/pause", post(pause_run))
+        .route("/runs/{id}/resume", post(resume_run))
+        .route("/runs/{id}/extend", post(extend_run))
+        .route("/images", get(list_images))
+        .route("/scheduler", get(scheduler_stats))
+        .route_layer(middleware::from_fn_with_state(tokens, authorize))
+        // Load balancer health checks don't carry the token
+        .route("/healthz", get(|| async { Json(json!({ "ok": true, "version": env!("CARGO_PKG_VERSION") })) }))
+}
+
+/// The API key a request was authorized with; None when the API is open.
+#[derive(Clone)]
+struct ApiKey(Option<Arc<str>>);
+
+async fn authorize(State(tokens): State<Arc<Vec<String>>>, mut request: Request, next: Next) -> Response {
+    let mut key = None;
+    if !tokens.is_empty() {
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        key = given.and_then(|given| tokens.iter().find(|t| constant_time_eq(given.as_bytes(), t.as_bytes())));
+        if key.is_none() {
+            let body = json!({ "error": { "code": "UNAUTHORIZED", "message": "missing or invalid bearer token" } });
+            return (StatusCode::UNAUTHORIZED, Json(body)).into_response();
+        }
+    }
+    request.extensions_mut().insert(ApiKey(key.map(|k| Arc::from(k.as_str()))));
+    next.run(request).await
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// A `VMError` as an HTTP response: `{"error": {code, message, image, command, stderr}}`
+struct ApiError(VMError);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0.code() {
+            "VM_CONFIGURATION" | "VALIDATION" => StatusCode::BAD_REQUEST,
+            "POLICY_VIOLATION" => StatusCode::FORBIDDEN,
+            "RATE_LIMITED" => StatusCode::TOO_MANY_REQUESTS,
+            "KILLED" => StatusCode::CONFLICT,
+            "TIMEOUT" | "PULL_TIMEOUT" | "GUEST_UNRESPONSIVE" => StatusCode::GATEWAY_TIMEOUT,
+            "MISSING_DEPENDENCY" | "OVERLOADED" => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let mut response = (status, Json(json!({ "error": error_json(&self.0) }))).into_response();
+        if let Some(retry_after) = self.0.retry_after() {
+            response.headers_mut().insert(header::RETRY_AFTER, (retry_after.as_secs_f64().ceil() as u64).into());
+        }
+        response
+    }
+}
+
+impl From<VMError> for ApiError {
+    fn from(e: VMError) -> Self {
+        ApiError(e)
+    }
+}
+
+fn error_json(e: &VMError) -> Value {
+    let mut error = daemon::error_data(e);
+    error["message"] = json!(e.to_string());
+    error
+}
+
+/// Runs the blocking `f` off the async runtime.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, VMError> + Send + 'static) -> Result<T, ApiError> {
+    tokio::task::spawn_blocking(f).await.map_err(|e| VMError::Execution(e.to_string()))?.map_err(ApiError)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RunBody {
+    code: String,
+    profile: Option<String>,
+    /// `run` settings, with the config-file keys
+    config: Option<VMConfigFile>,
+    /// Staged under /work/in
+    #[serde(default)]
+    files: Vec<InputFile>,
+    #[serde(default)]
+    expect: Vec<String>,
+    /// UUID to run under, so the run can be looked up or killed before it returns
+    run_id: Option<String>,
+}
+
+/// The result as JSON, or as server-sent events when the client accepts `text/event-stream`.
+async fn run(
+    Extension(ApiKey(key)): Extension<ApiKey>,
+    headers: HeaderMap,
+    body: Result<Json<RunBody>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    rate_limit::enforce_api_key(key.as_deref())?;
+    let Json(mut body) = body.map_err(|e| VMError::VMConfiguration(e.body_text()))?;
+    let run_id = daemon::claim_run_id(body.run_id.take())?;
+    let streaming = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"));
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let span = telemetry::request_span(header("traceparent"), header("tracestate"));
+    if streaming {
+        return Ok(stream_run(run_id, body, span).into_response());
+    }
+    let kill = KillOnDrop::new(&run_id);
+    let runner = VMRunner::new().with_run_id(Some(run_id));
+    let result = blocking(move || span.in_scope(|| execute(runner, body, false))).await;
+    kill.finished.store(true, Ordering::SeqCst);
+    Ok(Json(result?).into_response())
+}
+
+/// Events: `run` with the run ID, `output` for each chunk while the code runs, then `result`
+/// or `error`.
+fn stream_run(run_id: String, body: RunBody, span: tracing::Span) -> Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let _ = tx.send(event("run", &json!({ "run_id": run_id })));
+    let output = tx.clone();
+    let listener: OutputListener = Arc::new(move |chunk| {
+        let _ = output.send(event("output", chunk));
+    });
+    let runner = VMRunner::new().with_run_id(Some(run_id.clone())).with_output_listener(Some(listener));
+    let kill = KillOnDrop::new(&run_id);
+    let done = kill.finished.clone();
+    tokio::task::spawn_blocking(move || {
+        let event = match span.in_scope(|| execute(runner, body, true)) {
+            Ok(result) => event("result", &result),
+            Err(e) => event("error", &error_json(&e)),
+        };
+        done.store(true, Ordering::SeqCst);
+        let _ = tx.send(event);
+    });
+    // The run is killed if the client disconnects before the last event
+    let events = UnboundedReceiverStream::new(rx).map(move |event| {
+        let _ = &kill;
+        Ok(event)
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn event(name: &str, data: &impl serde::Serialize) -> Event {
+    Event::default().event(name).json_data(data).unwrap_or_default()
+}
+
+fn execute(runner: VMRunner, body: RunBody, stream: bool) -> Result<Value, VMError> {
+    let mut config = daemon::run_config(body.profile.as_deref(), body.config)?;
+    // Live output comes from the guest's output records
+    if stream && config.effective_capture() == CaptureMode::Stdio {
+        config.capture = CaptureMode::JsonLines;
+    }
+    let (_staging, files) = daemon::stage_inputs(daemon::decode_inputs(body.files)?)?;
+    let expect = body.expect.into_iter().map(|pattern| FileOutput { pattern }).collect();
+    let result = runner.execute_python_code(&body.code, &config, files, expect)?;
+    result_json(result)
+}
+
+fn result_json(result: ExecutionResult) -> Result<Value, VMError> {
+    let mut artifacts = Vec::new();
+    for artifact in result.artifacts {
+        let content = match artifact.content {
+            Some(content) => content.to_vec(),
+            None => std::fs::read(&artifact.host_path)?,
+        };
+        artifacts.push(json!({
+            "guest_path": artifact.guest_path,
+            "content": BASE64.encode(content),
+            "size_bytes": artifact.size_bytes,
+        }));
+    }
+    Ok(json!({
+        "stdout": result.stdout,
+        "stderr": result.stderr,
+        "exit_code": result.exit_code,
+        "execution_time_ms": result.execution_time.as_millis() as u64,
+        "timings": result.timings.to_json(),
+        "image_used": result.image_used,
+        "run_id": result.run_id,
+        "attempts": result.attempts,
+        "cached": result.cached,
+        "peak_memory_bytes": result.peak_memory_bytes,
+        "coverage": result.coverage,
+        "cprofile": result.cprofile,
+        "display": result.display,
+        "value": result.value,
+        "artifacts": artifacts,
+    }))
+}
+
+fn run_json(r: crate::runs::RunRecord) -> Value {
+    let timeout = r.timeout();
+    json!({
+        "run_id": r.run_id,
+        "vm_name": r.vm_name,
+        "owner_pid": r.owner_pid,
+        "image": r.image,
+        "started_at": r.started_at.to_rfc3339(),
+        "kept": r.kept,
+        "paused": r.paused,
+        "timeout_seconds": timeout.as_secs_f64(),
+    })
+}
+
+async fn list_runs() -> Result<Json<Value>, ApiError> {
+    let runs = blocking(|| RunRegistry::new().list()).await?;
+    Ok(Json(runs.into_iter().map(run_json).collect()))
+}
+
+/// 404 once the run has finished (unless its VM was kept).
+async fn get_run(Path(id): Path<String>) -> Result<Response, ApiError> {
+    Ok(match blocking(move || RunRegistry::new().get(&id)).await? {
+        Some(record) => Json(run_json(record)).into_response(),
+        None => not_found(),
+    })
+}
+
+async fn kill_run(Path(id): Path<String>) -> Result<Response, ApiError> {
+    Ok(match blocking(move || RunRegistry::new().kill(&id)).await? {
+        true => Json(json!({ "killed": true })).into_response(),
+        false => not_found(),
+    })
+}
+
+async fn pause_run(Path(id): Path<String>) -> Result<Response, ApiError> {
+    Ok(match blocking(move || RunRegistry::new().pause(&id)).await? {
+        true => Json(json!({ "paused": true })).into_response(),
+        false => not_found(),
+    })
+}
+
+async fn resume_run(Path(id): Path<String>) -> Result<Response, ApiError> {
+    Ok(match blocking(move || RunRegistry::new().resume(&id)).await? {
+        true => Json(json!({ "paused": false })).into_response(),
+        false => not_found(),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExtendBody {
+    seconds: u64,
+}
+
+async fn extend_run(
+    Path(id): Path<String>,
+    body: Result<Json<ExtendBody>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(body) = body.map_err(|e| VMError::VMConfiguration(e.body_text()))?;
+    if body.seconds == 0 {
+        return Err(VMError::VMConfiguration("seconds must be at least 1".to_string()).into());
+    }
+    Ok(match blocking(move || RunRegistry::new().extend_timeout(&id, body.seconds)).await? {
+        true => Json(json!({ "extended": true })).into_response(),
+        false => not_found(),
+    })
+}
+
+fn not_found() -> Response {
+    let body = json!({ "error": { "code": "NOT_FOUND", "message": "no such run" } });
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
+#[derive(Deserialize)]
+struct SchedulerQuery {
+    tenant: Option<String>,
+}
+
+async fn scheduler_stats(Query(query): Query<SchedulerQuery>) -> Result<Json<Value>, ApiError> {
+    let stats = match query.tenant {
+        Some(name) => tenants::get(&name)?.stats(),
+        None => concurrency::scheduler().stats(),
+    };
+    Ok(Json(json!(stats)))
+}
+
+async fn list_images() -> Result<Json<Value>, ApiError> {
+    let images = blocking(|| ImageResolver::new().list_cached_images()).await?;
+    Ok(Json(serde_json::to_value(images).map_err(|e| VMError::Cache(e.to_string()))?))
+}
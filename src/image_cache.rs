@@ -0,0 +1,511 @@
+use crate::concurrency::FileLock;
+use crate::config::CacheConfig;
+use crate::error::VMError;
+use crate::scan::ScanReport;
+use crate::storage_tool::StorageTool;
+use crate::tenants;
+use crate::unshare;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+/// How an image got into containers-storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageKind {
+    /// The OCI layout shipped in the wheel
+    Embedded,
+    /// Built by `prepare_image(packages=...)` / `pip_prepare_image`
+    Pip,
+    /// Built by `conda_prepare_image`
+    Conda,
+    /// Built by `os_prepare_image`
+    Os,
+    /// Built from a Containerfile by `build_from_containerfile`
+    Containerfile,
+    /// `oci:` references copied into storage so krunvm can boot them
+    Imported,
+}
+
+impl ImageKind {
+    /// Built or imported by flashvm on the user's behalf (everything but the embedded image)
+    #[cfg(any(feature = "python", feature = "cli"))]
+    pub fn is_derived(&self) -> bool {
+        !matches!(self, ImageKind::Embedded)
+    }
+
+    #[cfg(any(feature = "python", feature = "cli"))]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageKind::Embedded => "embedded",
+            ImageKind::Pip => "pip",
+            ImageKind::Conda => "conda",
+            ImageKind::Os => "os",
+            ImageKind::Containerfile => "containerfile",
+            ImageKind::Imported => "imported",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedImage {
+    /// containers-storage name, e.g. `localhost/flashvm:python-basic` (see `image_name`)
+    pub name: String,
+    pub kind: ImageKind,
+    pub created_at: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+    /// As reported by `buildah images`; 0 when unknown
+    pub size_bytes: u64,
+    /// `pip freeze` of pip-built images
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pip_freeze: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    images: BTreeMap<String, CachedImage>,
+    /// Latest vulnerability scan per image name, tracked or not
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    scans: BTreeMap<String, ScanReport>,
+}
+
+/// FlashVM-owned images, tracked in `<cache_dir>/images.json`. Images pulled by the user or
+/// other tools are never listed or removed.
+pub struct ImageCache {
+    dir: PathBuf,
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        Self { dir: PathBuf::from(cache_config().cache_dir) }
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.dir.join("images.json")
+    }
+
+    fn load(&self) -> Result<Manifest, VMError> {
+        match fs::read(self.manifest_path()) {
+            Ok(data) => Ok(serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("Ignoring corrupt image manifest: {}", e);
+                Manifest::default()
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(e) => Err(VMError::Cache(format!("Failed to read image manifest: {}", e))),
+        }
+    }
+
+    fn save(&self, manifest: &Manifest) -> Result<(), VMError> {
+        let data = serde_json::to_vec_pretty(manifest).map_err(|e| VMError::Cache(e.to_string()))?;
+        // Write-then-rename so readers never observe a partial manifest
+        let tmp = self.dir.join(".images.json.tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, self.manifest_path())?;
+        Ok(())
+    }
+
+    /// Run `f` on the manifest under the lock and persist whatever it changed.
+    fn update<T>(&self, f: impl FnOnce(&mut Manifest) -> Result<T, VMError>) -> Result<T, VMError> {
+        fs::create_dir_all(&self.dir)?;
+        // Processes must not lose each other's updates
+        let _lock = FileLock::acquire(&self.dir.join("images.lock"))?;
+        let mut manifest = self.load()?;
+        let out = f(&mut manifest)?;
+        self.save(&manifest)?;
+        Ok(out)
+    }
+
+    /// Track an image flashvm just created (or re-created) in storage.
+    pub fn record(&self, name: &str, kind: ImageKind) -> Result<(), VMError> {
+        let size_bytes = image_size(name).unwrap_or(0);
+        let now = Utc::now();
+        self.update(|m| {
+            m.images.insert(
+                name.to_string(),
+                CachedImage {
+                    name: name.to_string(),
+                    kind,
+                    created_at: now,
+                    last_used: now,
+                    size_bytes,
+                    pip_freeze: None,
+                },
+            );
+            Ok(())
+        })
+    }
+
+    /// Bump `last_used` of a tracked image; returns false when `name` isn't tracked.
+    pub fn touch(&self, name: &str) -> Result<bool, VMError> {
+        self.update(|m| match m.images.get_mut(name) {
+            Some(img) => {
+                img.last_used = Utc::now();
+                Ok(true)
+            }
+            None => Ok(false),
+        })
+    }
+
+    #[cfg(any(feature = "python", feature = "cli"))]
+    pub fn set_pip_freeze(&self, name: &str, freeze: Vec<String>) -> Result<(), VMError> {
+        self.update(|m| {
+            if let Some(img) = m.images.get_mut(name) {
+                img.pip_freeze = Some(freeze);
+            }
+            Ok(())
+        })
+    }
+
+    pub fn set_scan(&self, name: &str, report: ScanReport) -> Result<(), VMError> {
+        self.update(|m| {
+            m.scans.insert(name.to_string(), report);
+            Ok(())
+        })
+    }
+
+    /// The last scan recorded for `name`, whatever image ID it had then.
+    pub fn scan(&self, name: &str) -> Result<Option<ScanReport>, VMError> {
+        Ok(self.load()?.scans.get(name).cloned())
+    }
+
+    /// Tracked images that still exist in containers-storage, most recently used first.
+    /// Entries whose image was removed behind flashvm's back are dropped.
+    pub fn list(&self) -> Result<Vec<CachedImage>, VMError> {
+        let present = storage_image_names()?;
+        let mut images = self.update(|m| {
+            m.images.retain(|name, _| present.iter().any(|p| p == name));
+            m.scans.retain(|name, _| present.iter().any(|p| p == name));
+            Ok(m.images.values().cloned().collect::<Vec<_>>())
+        })?;
+        images.sort_by_key(|img| std::cmp::Reverse(img.last_used));
+        Ok(images)
+    }
+
+    /// Remove one tracked image from storage. Returns false when `name` isn't tracked.
+    #[cfg(feature = "python")]
+    pub fn remove(&self, name: &str) -> Result<bool, VMError> {
+        self.update(|m| {
+            if !m.images.contains_key(name) {
+                return Ok(false);
+            }
+            rmi(name)?;
+            m.images.remove(name);
+            Ok(true)
+        })
+    }
+
+    /// Evict tracked images unused for longer than the TTL, then least recently used ones
+    /// until the total fits `max_cache_size_mb`. Images still used by a VM are skipped.
+    /// Returns the names removed.
+    pub fn evict(&self, config: &CacheConfig) -> Result<Vec<String>, VMError> {
+        self.evict_where(config.cache_ttl_seconds, config.max_cache_size_mb, |_| true)
+    }
+
+    /// `evict` restricted to derived images (pip/conda/os/Containerfile builds and `oci:`
+    /// imports), so the embedded image is never collected. Derived images left in storage
+    /// by older versions, before they were tracked, are adopted first and count as the least
+    /// recently used. 0 disables a limit.
+    #[cfg(any(feature = "python", feature = "cli"))]
+    pub fn gc_derived(&self, max_age_seconds: u64, max_total_mb: u64) -> Result<Vec<String>, VMError> {
+        let untracked: Vec<(String, ImageKind)> = {
+            let tracked = self.load()?;
+            storage_image_names()?
+                .into_iter()
+                .filter(|name| !tracked.images.contains_key(name))
+                .filter_map(|name| {
+                    let (_, kind) = DERIVED_PREFIXES.iter().find(|(p, _)| name.starts_with(&image_name(p)))?;
+                    Some((name, *kind))
+                })
+                .collect()
+        };
+        if !untracked.is_empty() {
+            self.update(|m| {
+                for (name, kind) in &untracked {
+                    m.images.insert(
+                        name.clone(),
+                        CachedImage {
+                            name: name.clone(),
+                            kind: *kind,
+                            created_at: DateTime::UNIX_EPOCH,
+                            last_used: DateTime::UNIX_EPOCH,
+                            size_bytes: image_size(name).unwrap_or(0),
+                            pip_freeze: None,
+                        },
+                    );
+                }
+                Ok(())
+            })?;
+        }
+        self.evict_where(max_age_seconds, max_total_mb, |img| img.kind.is_derived())
+    }
+
+    fn evict_where(
+        &self,
+        ttl_seconds: u64,
+        max_mb: u64,
+        eligible: impl Fn(&CachedImage) -> bool,
+    ) -> Result<Vec<String>, VMError> {
+        // Also drops entries whose image is already gone, so sizes reflect storage
+        self.list()?;
+        let now = Utc::now();
+        self.update(|m| {
+            let mut by_age: Vec<CachedImage> = m.images.values().filter(|img| eligible(img)).cloned().collect();
+            by_age.sort_by_key(|img| img.last_used);
+            let max_bytes = max_mb.saturating_mul(1024 * 1024);
+            let mut total: u64 = by_age.iter().map(|img| img.size_bytes).sum();
+            let mut removed = Vec::new();
+            for img in by_age {
+                let age = (now - img.last_used).num_seconds().max(0) as u64;
+                let expired = ttl_seconds > 0 && age > ttl_seconds;
+                let over_budget = max_mb > 0 && total > max_bytes;
+                if !expired && !over_budget {
+                    continue;
+                }
+                match rmi(&img.name) {
+                    Ok(()) => {
+                        total = total.saturating_sub(img.size_bytes);
+                        m.images.remove(&img.name);
+                        removed.push(img.name);
+                    }
+                    Err(e) => debug!("Not evicting {}: {}", img.name, e),
+                }
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Remove every tracked image. Images still used by a VM (kept runs, pooled VMs) can't
+    /// be removed and stay tracked; they are returned as errors after the rest are gone.
+    #[cfg(feature = "python")]
+    pub fn clear(&self) -> Result<usize, VMError> {
+        let (removed, failed) = self.update(|m| {
+            let mut removed = 0;
+            let mut failed = Vec::new();
+            let names: Vec<String> = m.images.keys().cloned().collect();
+            for name in names {
+                match rmi(&name) {
+                    Ok(()) => {
+                        m.images.remove(&name);
+                        removed += 1;
+                    }
+                    Err(e) => failed.push(e.to_string()),
+                }
+            }
+            Ok((removed, failed))
+        })?;
+        if !failed.is_empty() {
+            return Err(VMError::Cache(format!(
+                "removed {} image(s), but some are still in use: {}",
+                removed,
+                failed.join("; ")
+            )));
+        }
+        Ok(removed)
+    }
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tag prefixes flashvm gives derived images; untracked ones are adopted by `gc_derived`
+#[cfg(any(feature = "python", feature = "cli"))]
+const DERIVED_PREFIXES: &[(&str, ImageKind)] = &[
+    ("python-pip-", ImageKind::Pip),
+    ("python-project-", ImageKind::Pip),
+    ("python-conda-", ImageKind::Conda),
+    ("python-os-", ImageKind::Os),
+    ("containerfile-", ImageKind::Containerfile),
+    ("imported-", ImageKind::Imported),
+];
+
+/// Process-wide cache settings, changed through `configure_cache`.
+fn settings() -> &'static Mutex<CacheConfig> {
+    static SETTINGS: OnceLock<Mutex<CacheConfig>> = OnceLock::new();
+    SETTINGS.get_or_init(|| {
+        let mut config = CacheConfig::default();
+        if let Err(e) = validate_namespace(&config.image_namespace) {
+            warn!("Ignoring FLASHVM_IMAGE_NAMESPACE: {}", e);
+            config.image_namespace = "localhost/flashvm".to_string();
+        }
+        Mutex::new(config)
+    })
+}
+
+/// Cache settings for the current thread: the process' own, or its tenant's when a run is
+/// working for one (see `tenants::enter`).
+pub fn cache_config() -> CacheConfig {
+    let mut config = host_cache_config();
+    if let Some(tenant) = tenants::current() {
+        tenant.overlay(&mut config);
+    }
+    config
+}
+
+/// The process' cache settings, whatever tenant the thread works for.
+pub fn host_cache_config() -> CacheConfig {
+    settings().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(feature = "python")]
+pub fn configure(config: CacheConfig) {
+    *settings().lock().unwrap_or_else(|e| e.into_inner()) = config;
+}
+
+/// Absolute form of a cache directory override. The directory is created on first use.
+pub fn validate_cache_dir(dir: &str) -> Result<String, VMError> {
+    if dir.trim().is_empty() {
+        return Err(VMError::VMConfiguration("cache_dir must not be empty".to_string()));
+    }
+    let path = std::path::absolute(dir)?;
+    if path.exists() && !path.is_dir() {
+        return Err(VMError::VMConfiguration(format!("cache_dir {} is not a directory", path.display())));
+    }
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// `<image_namespace>:<tag>`, the containers-storage name of a flashvm image.
+pub fn image_name(tag: &str) -> String {
+    format!("{}:{}", cache_config().image_namespace, tag)
+}
+
+/// Whether `name` is in this process' image namespace.
+pub fn in_namespace(name: &str) -> bool {
+    name.strip_prefix(cache_config().image_namespace.as_str()).is_some_and(|rest| rest.starts_with(':'))
+}
+
+/// A namespace must be a plain repository (`registry/path`), lowercase and without a tag.
+pub fn validate_namespace(ns: &str) -> Result<(), VMError> {
+    let valid = !ns.is_empty()
+        && ns.len() <= 200
+        && ns.split('/').all(|part| {
+            !part.is_empty()
+                && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+                && !part.starts_with(['.', '_', '-'])
+        });
+    if !valid {
+        return Err(VMError::VMConfiguration(format!(
+            "invalid image namespace '{}': use lowercase repository path components like localhost/tenant-a",
+            ns
+        )));
+    }
+    Ok(())
+}
+
+/// Eviction pass ahead of an import or build, when `evict_before_import` is on. Never fails
+/// the import itself.
+pub fn evict_before_import() {
+    let config = cache_config();
+    if !config.evict_before_import {
+        return;
+    }
+    match ImageCache::new().evict(&config) {
+        Ok(removed) if !removed.is_empty() => info!("Evicted cached images: {}", removed.join(", ")),
+        Ok(_) => {}
+        Err(e) => warn!("Cache eviction failed: {}", e),
+    }
+}
+
+/// Where the shared pip cache is mounted in build containers and runs
+pub const PIP_CACHE_MOUNT: &str = "/var/cache/flashvm-pip";
+
+/// Host directory of the shared pip (and uv) cache, created on first use; None when
+/// `pip_cache` is off.
+pub fn pip_cache_dir() -> Result<Option<PathBuf>, VMError> {
+    let config = cache_config();
+    if !config.pip_cache {
+        return Ok(None);
+    }
+    let dir = PathBuf::from(&config.cache_dir).join("pip");
+    fs::create_dir_all(&dir)?;
+    Ok(Some(dir))
+}
+
+/// Delete the least recently modified files of the pip cache until it fits
+/// `pip_cache_max_mb`. Never fails the build or run that triggered it.
+pub fn trim_pip_cache() {
+    let config = cache_config();
+    if !config.pip_cache || config.pip_cache_max_mb == 0 {
+        return;
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::from(&config.cache_dir).join("pip")];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.metadata() else { continue };
+            if meta.is_dir() {
+                dirs.push(entry.path());
+            } else if meta.is_file() {
+                let modified = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
+                files.push((modified, meta.len(), entry.path()));
+            }
+        }
+    }
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    let budget = config.pip_cache_max_mb * 1024 * 1024;
+    if total <= budget {
+        return;
+    }
+    files.sort();
+    for (_, len, path) in files {
+        if total <= budget {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => total -= len,
+            Err(e) => debug!("Failed to trim {}: {}", path.to_string_lossy(), e),
+        }
+    }
+    info!("Trimmed the pip cache to {} MB", total / (1024 * 1024));
+}
+
+/// `buildah rmi`, treating an already-missing image as removed.
+fn rmi(name: &str) -> Result<(), VMError> {
+    let argv = [StorageTool::current().binary(), "rmi", name];
+    let out = unshare::run_argv(&argv)?;
+    if out.success() || out.stderr.contains("image not known") || out.stderr.contains("no such image") {
+        debug!("Removed cached image {}", name);
+        return Ok(());
+    }
+    Err(VMError::Cache(format!("failed to remove {}: {}", name, out.stderr.trim())).with_command(&argv, &out.stderr))
+}
+
+fn storage_image_names() -> Result<Vec<String>, VMError> {
+    let tool = StorageTool::current();
+    let out = unshare::run_argv(&[tool.binary(), "images", "--format", tool.image_name_format()])?;
+    if !out.success() {
+        return Err(VMError::Cache(format!("Failed to list images in containers-storage: {}", out.stderr)));
+    }
+    Ok(out.stdout.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect())
+}
+
+pub fn image_size(name: &str) -> Option<u64> {
+    let out = unshare::run_argv(&[StorageTool::current().binary(), "images", "--format", "{{.Size}}", name]).ok()?;
+    if !out.success() {
+        return None;
+    }
+    parse_human_size(out.stdout.lines().next()?)
+}
+
+/// Parse buildah's human-readable sizes ("85.3 MB", "1.2 GB", "512 B"); units are decimal.
+fn parse_human_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let value: f64 = s[..split].trim().parse().ok()?;
+    let mult = match s[split..].trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "kb" => 1e3,
+        "mb" => 1e6,
+        "gb" => 1e9,
+        "tb" => 1e12,
+        _ => return None,
+    };
+    Some((value * mult) as u64)
+}
@@ -1,25 +1,194 @@
+use crate::concurrency::FileLock;
 use crate::config::CacheConfig;
+#[cfg(any(feature = "python", feature = "cli"))]
+use crate::config::PipRequirements;
+#[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+use crate::config::ProxyConfig;
+#[cfg(feature = "python")]
+use crate::config::{CondaSpec, RegistryAuth};
 use crate::error::VMError;
+use crate::image_cache::{self, CachedImage, ImageCache, ImageKind};
+#[cfg(feature = "python")]
+use crate::lockfile;
+use crate::oci_layout;
+use crate::platform;
+use crate::scan;
+use crate::settings;
+use crate::storage_tool::{self, StorageTool};
+use crate::unshare;
+#[cfg(feature = "python")]
 use crate::wheel_resources::WheelResources;
 use anyhow::Result;
+#[cfg(feature = "python")]
+use base64::engine::general_purpose::STANDARD as BASE64;
+#[cfg(feature = "python")]
+use base64::Engine;
 use log::{debug, info, warn};
+#[cfg(feature = "python")]
 use pyo3::Python;
-use std::collections::hash_map::DefaultHasher;
+#[cfg(feature = "python")]
+use std::collections::BTreeMap;
+#[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
-use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// What `inspect_image` reports about an image.
+#[cfg(feature = "python")]
+#[derive(Debug, Clone)]
+pub struct ImageInfo {
+    pub name: String,
+    pub id: String,
+    pub created: Option<String>,
+    pub architecture: Option<String>,
+    pub os: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    /// Manifest layer digests, base layer first
+    pub layers: Vec<String>,
+    pub python_version: Option<String>,
+    /// `(name, version)` of installed distributions, when asked for
+    pub packages: Option<Vec<(String, String)>>,
+}
+
+/// Receives build output line by line; see `ImageResolver::with_progress`
+pub type BuildProgress = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Output lines kept in build errors
+#[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+const ERROR_TAIL_LINES: usize = 20;
 
 pub struct ImageResolver {
     cache_config: CacheConfig,
+    #[cfg_attr(not(any(feature = "python", feature = "cli", feature = "grpc")), allow(dead_code))]
+    progress: Option<BuildProgress>,
+}
+
+/// OCI label holding `pip freeze` output (one requirement per line) of pip-built images
+#[cfg(any(feature = "python", feature = "cli"))]
+pub const PIP_FREEZE_LABEL: &str = "io.flashvm.pip-freeze";
+/// OCI labels naming the lockfile a `build_from_project` image was built from, and its sha256
+#[cfg(feature = "python")]
+pub const LOCKFILE_LABEL: &str = "io.flashvm.lockfile";
+#[cfg(feature = "python")]
+pub const LOCKFILE_SHA256_LABEL: &str = "io.flashvm.lockfile-sha256";
+/// Where the embedded image is imported: `<image_namespace>:python-basic`
+pub fn canonical_image() -> String {
+    image_cache::image_name(EMBEDDED_TAG)
 }
 
-const CANONICAL_IMAGE: &str = "localhost/flashvm:python-basic";
+/// `sha256:` ID of `image` in local storage, or None when it isn't there.
+pub fn local_image_digest(image: &str) -> Option<String> {
+    let name = image.strip_prefix("containers-storage:").unwrap_or(image);
+    let tool = StorageTool::current();
+    let mut argv = tool.inspect_image();
+    argv.extend(["--format", tool.id_format(), name]);
+    let out = unshare::run_argv(&argv).ok()?;
+    let id = out.stdout.trim();
+    if !out.success() || id.is_empty() {
+        return None;
+    }
+    Some(if id.starts_with("sha256:") { id.to_string() } else { format!("sha256:{}", id) })
+}
+
+/// Pull a registry image into local storage, killing the pull once it stalls for the pull
+/// timeout (`settings::pull_timeout`).
+pub fn pull_image(image: &str, on_line: &mut dyn FnMut(&str)) -> Result<(), VMError> {
+    let argv = [StorageTool::current().binary(), "pull", image];
+    let stall = settings::pull_timeout();
+    let out = unshare::run_argv_streaming(&argv, Some(stall), on_line)?;
+    if out.stalled {
+        return Err(VMError::PullTimeout(format!("pull of {} made no progress for {}s", image, stall.as_secs()))
+            .with_command(&argv, &out.stderr));
+    }
+    if !out.success() {
+        return Err(VMError::ImageResolution(format!("pull failed: {}", out.stderr.trim()))
+            .with_command(&argv, &out.stderr));
+    }
+    Ok(())
+}
+
+/// Directory of the embedded OCI layout: `FLASHVM_EMBEDDED_OCI` when set, else the copy in
+/// the installed `flashvm` package, which only the Python module can locate.
+fn embedded_oci_dir() -> Result<Option<PathBuf>, VMError> {
+    if let Some(dir) = std::env::var_os("FLASHVM_EMBEDDED_OCI").filter(|d| !d.is_empty()) {
+        return Ok(Some(PathBuf::from(dir)));
+    }
+    #[cfg(feature = "python")]
+    return Python::with_gil(|py| {
+        WheelResources::find_embedded_data_path(py)
+            .map_err(|e| VMError::ImageResolution(format!("Failed to locate embedded data: {}", e)))
+    });
+    #[cfg(not(feature = "python"))]
+    Ok(None)
+}
+
+/// Curated bases per Python minor version; `None` is the embedded image
+/// (docker/Dockerfile.python-basic), used whenever it matches so no pull is needed.
+#[cfg(feature = "python")]
+const PYTHON_BASES: &[(&str, Option<&str>)] = &[
+    ("3.9", Some("docker.io/library/python:3.9-alpine")),
+    ("3.10", Some("docker.io/library/python:3.10-alpine")),
+    ("3.11", Some("docker.io/library/python:3.11-alpine")),
+    ("3.12", None),
+    ("3.13", Some("docker.io/library/python:3.13-alpine")),
+];
 const EMBEDDED_TAG: &str = "python-basic";
 
+/// Import the embedded image on a background thread, so the process's first run finds it in
+/// storage instead of waiting on skopeo/buildah. A run that starts meanwhile waits on the same
+/// import lock. The layout is located here: the thread must not need the GIL, which it could be
+/// waiting on while the interpreter shuts down.
+#[cfg(feature = "python")]
+pub fn import_embedded_in_background() {
+    let oci_dir = match embedded_oci_dir() {
+        Ok(Some(dir)) => dir,
+        Ok(None) => return,
+        Err(e) => {
+            debug!("Not importing the embedded image in the background: {}", e);
+            return;
+        }
+    };
+    let spawned = std::thread::Builder::new().name("flashvm-preimport".to_string()).spawn(move || {
+        if let Err(e) = ImageResolver::new().import_embedded_from(|| Ok(Some(oci_dir))) {
+            debug!("Background import of the embedded image failed (the first run retries it): {}", e);
+        }
+    });
+    if let Err(e) = spawned {
+        debug!("Not importing the embedded image in the background: {}", e);
+    }
+}
+
 impl ImageResolver {
-    pub fn new() -> Self { Self { cache_config: CacheConfig::default() } }
+    pub fn new() -> Self { Self { cache_config: image_cache::cache_config(), progress: None } }
+
+    /// Stream the output of image builds (pip, micromamba, package managers, `buildah bud`)
+    /// to `progress`, along with `flashvm: ...` lines announcing each step.
+    #[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+    pub fn with_progress(mut self, progress: Option<BuildProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    #[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+    fn step(&self, msg: &str) {
+        info!("{}", msg);
+        if let Some(cb) = &self.progress {
+            cb(&format!("flashvm: {}", msg));
+        }
+    }
+
+    /// Run a long build command, streaming its output to the progress callback if any.
+    #[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+    fn run_build_step(&self, argv: &[&str]) -> Result<unshare::UnshareOutput, VMError> {
+        match &self.progress {
+            Some(cb) => unshare::run_argv_streaming(argv, None, &mut |line| cb(line)),
+            None => unshare::run_argv(argv),
+        }
+    }
 
     /// Resolve image reference:
     /// - None => import (once) the embedded OCI layout → containers-storage: and return canonical name
@@ -28,44 +197,105 @@ impl ImageResolver {
         match image_ref {
             None => {
                 self.ensure_embedded_image_imported()?;
-                Ok(CANONICAL_IMAGE.to_string())
+                Ok(canonical_image())
+            }
+            Some(s) => {
+                let validated = self.validate_image_ref(s)?;
+                self.select_platform(&validated)
+            }
+        }
+    }
+
+    /// For registry refs, pin the host architecture's manifest out of a manifest list
+    /// (`name@sha256:...`) rather than letting buildah pick, and fail clearly when the image
+    /// doesn't exist for this architecture. Images already in local storage are checked
+    /// without touching the network.
+    fn select_platform(&self, image_ref: &str) -> Result<String, VMError> {
+        let local = ["containers-storage:", "oci:", "dir:", "oci-archive:"];
+        if local.iter().any(|p| image_ref.starts_with(p)) {
+            return Ok(image_ref.to_string());
+        }
+        let tool = StorageTool::current();
+        let mut inspect_argv = tool.inspect_image();
+        inspect_argv.extend(["--format", tool.platform_format(), image_ref]);
+        let inspect = unshare::run_argv(&inspect_argv)?;
+        if inspect.success() {
+            if let Ok(config) = serde_json::from_str(inspect.stdout.trim()) {
+                platform::check_config_arch(image_ref, &config)?;
+            }
+            return Ok(image_ref.to_string());
+        }
+        if !self.command_exists("skopeo") {
+            debug!("skopeo not found; leaving architecture selection of {} to buildah", image_ref);
+            return Ok(image_ref.to_string());
+        }
+        let transport_ref = format!("docker://{}", image_ref);
+        let argv = ["skopeo", "inspect", "--raw", &transport_ref];
+        let out = unshare::run_argv(&argv)?;
+        if !out.success() {
+            // Offline or a registry hiccup: krunvm create will report the real problem
+            debug!("Could not inspect {}: {}", image_ref, out.stderr.trim());
+            return Ok(image_ref.to_string());
+        }
+        let manifest: serde_json::Value = serde_json::from_str(&out.stdout)
+            .map_err(|e| VMError::ImageResolution(format!("Invalid manifest for {}: {}", image_ref, e)))?;
+        if let Some(digest) = platform::select_from_index(image_ref, &manifest)? {
+            let selected = format!("{}@{}", strip_tag(image_ref.split('@').next().unwrap_or(image_ref)), digest);
+            info!("Selected linux/{} manifest of {}: {}", platform::host_arch(), image_ref, selected);
+            return Ok(selected);
+        }
+        let argv = ["skopeo", "inspect", "--config", &transport_ref];
+        let out = unshare::run_argv(&argv)?;
+        if out.success() {
+            if let Ok(config) = serde_json::from_str(&out.stdout) {
+                platform::check_config_arch(image_ref, &config)?;
             }
-            Some(s) => self.validate_image_ref(s),
         }
+        Ok(image_ref.to_string())
     }
 
     /// Import the embedded OCI layout into containers-storage (idempotent).
     fn ensure_embedded_image_imported(&self) -> Result<(), VMError> {
-        if self.image_exists_in_storage(CANONICAL_IMAGE)? {
-            debug!("Image already present in containers-storage: {}", CANONICAL_IMAGE);
-            return Ok(());
+        self.import_embedded_from(embedded_oci_dir)
+    }
+
+    /// As `ensure_embedded_image_imported`, with `locate` finding the layout if it's needed.
+    fn import_embedded_from(&self, locate: impl FnOnce() -> Result<Option<PathBuf>, VMError>) -> Result<(), VMError> {
+        // Concurrent first runs in one process must not import twice
+        static IMPORT_LOCK: Mutex<()> = Mutex::new(());
+        let _import = IMPORT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if self.image_exists_in_storage(&canonical_image())? {
+            return self.note_embedded_present();
+        }
+        // Nor may other processes: parallel imports clobber each other's buildah containers.
+        // Check again under the lock, the holder may just have finished.
+        let lock_path = PathBuf::from(&self.cache_config.cache_dir).join("state").join("embedded_import.lock");
+        let _import_file = FileLock::acquire(&lock_path)?;
+        if self.image_exists_in_storage(&canonical_image())? {
+            return self.note_embedded_present();
         }
 
-        let oci_path: PathBuf = Python::with_gil(|py| {
-            WheelResources::find_embedded_data_path(py).map_err(|e| {
-                VMError::ImageResolution(format!("Failed to locate embedded data: {}", e))
-            })
-        })?
-        .ok_or_else(|| {
+        image_cache::evict_before_import();
+        let oci_path: PathBuf = locate()?.ok_or_else(|| {
             VMError::ImageResolution(
-                "Embedded OCI image not found (flashvm/data/oci)".to_string(),
+                "Embedded OCI image not found (flashvm/data/oci); set FLASHVM_EMBEDDED_OCI to its directory".to_string(),
             )
         })?;
 
         self.validate_oci_layout_dir(&oci_path)?;
+        let tag = platform::layout_tag_for_host(&oci_path, EMBEDDED_TAG);
+        platform::check_oci_layout(&oci_path, Some(&tag))?;
 
-        let source_oci = format!("oci:{}:{}", oci_path.to_string_lossy(), EMBEDDED_TAG);
+        let source_oci = format!("oci:{}:{}", oci_path.to_string_lossy(), tag);
 
         if self.command_exists("skopeo") {
             info!(
                 "Importing embedded image with skopeo: {} -> containers-storage:{}",
-                source_oci, CANONICAL_IMAGE
-            );
-            let copy_cmd = format!(
-                "skopeo copy --insecure-policy '{}' 'containers-storage:{}'",
-                source_oci, CANONICAL_IMAGE
+                source_oci,
+                canonical_image()
             );
-            if self.run_in_buildah_unshare(&copy_cmd)? {
+            let dest = format!("containers-storage:{}", canonical_image());
+            if unshare::run_argv(&["skopeo", "copy", "--insecure-policy", source_oci.as_str(), dest.as_str()])?.success() {
                 self.mark_import_sentinel(&oci_path)?;
                 return Ok(());
             } else {
@@ -73,9 +303,10 @@ impl ImageResolver {
             }
         }
 
+        storage_tool::require_buildah("importing the embedded image without skopeo")?;
         info!("Importing via buildah (fallback) from {}", source_oci);
-        let from_out = self.run_in_buildah_unshare_capture(&format!("buildah from '{}'", source_oci))?;
-        if !from_out.success {
+        let from_out = unshare::run_argv(&["buildah", "from", source_oci.as_str()])?;
+        if !from_out.success() {
             return Err(VMError::ImageResolution(format!(
                 "buildah from failed: {}",
                 from_out.stderr
@@ -87,9 +318,8 @@ impl ImageResolver {
                 "buildah from did not return a container name".to_string(),
             ));
         }
-        let ok_commit =
-            self.run_in_buildah_unshare(&format!("buildah commit '{}' '{}'", container_name, CANONICAL_IMAGE))?;
-        let _ = self.run_in_buildah_unshare(&format!("buildah rm '{}'", container_name));
+        let ok_commit = unshare::run_argv(&["buildah", "commit", container_name, canonical_image().as_str()])?.success();
+        let _ = unshare::run_argv(&["buildah", "rm", container_name]);
         if !ok_commit {
             return Err(VMError::ImageResolution(
                 "buildah commit failed in fallback".to_string(),
@@ -99,13 +329,28 @@ impl ImageResolver {
         Ok(())
     }
 
+    fn note_embedded_present(&self) -> Result<(), VMError> {
+        debug!("Image already present in containers-storage: {}", canonical_image());
+        // Imported before the manifest existed
+        if !ImageCache::new().touch(&canonical_image()).unwrap_or(true) {
+            track(&canonical_image(), ImageKind::Embedded);
+        }
+        Ok(())
+    }
+
+    fn import_sentinel_path(&self) -> PathBuf {
+        PathBuf::from(&self.cache_config.cache_dir).join("state").join("embedded_import.json")
+    }
+
     fn mark_import_sentinel(&self, oci_path: &Path) -> Result<(), VMError> {
-        let cache_dir = PathBuf::from(&self.cache_config.cache_dir).join("state");
-        fs::create_dir_all(&cache_dir)?;
-        let sentinel_path = cache_dir.join("embedded_import.json");
+        track(&canonical_image(), ImageKind::Embedded);
+        let sentinel_path = self.import_sentinel_path();
+        if let Some(dir) = sentinel_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
         let content = format!(
             "{{\n  \"image\": \"{}\",\n  \"oci_path\": \"{}\",\n  \"version\": \"{}\"\n}}\n",
-            CANONICAL_IMAGE,
+            canonical_image(),
             oci_path.to_string_lossy(),
             env!("CARGO_PKG_VERSION")
         );
@@ -115,8 +360,9 @@ impl ImageResolver {
     }
 
     fn image_exists_in_storage(&self, name: &str) -> Result<bool, VMError> {
-        let out = self.run_in_buildah_unshare_capture("buildah images --format '{{.Name}}:{{.Tag}}'")?;
-        if !out.success {
+        let tool = StorageTool::current();
+        let out = unshare::run_argv(&[tool.binary(), "images", "--format", tool.image_name_format()])?;
+        if !out.success() {
             return Err(VMError::Execution(format!(
                 "Failed to list images in containers-storage: {}",
                 out.stderr
@@ -125,28 +371,6 @@ impl ImageResolver {
         Ok(out.stdout.lines().any(|l| l.trim() == name))
     }
 
-    fn run_in_buildah_unshare(&self, sh_cmd: &str) -> Result<bool, VMError> {
-        debug!("Executing: buildah unshare sh -c '{}'", sh_cmd);
-        let status = Command::new("buildah")
-            .args(["unshare", "sh", "-c", sh_cmd])
-            .status()
-            .map_err(|e| VMError::Execution(format!("Failed to execute command: {}", e)))?;
-        Ok(status.success())
-    }
-
-    fn run_in_buildah_unshare_capture(&self, sh_cmd: &str) -> Result<Captured, VMError> {
-        debug!("Executing: buildah unshare sh -c '{}'", sh_cmd);
-        let output = Command::new("buildah")
-            .args(["unshare", "sh", "-c", sh_cmd])
-            .output()
-            .map_err(|e| VMError::Execution(format!("Failed to execute command: {}", e)))?;
-        Ok(Captured {
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-            success: output.status.success(),
-        })
-    }
-
     fn validate_oci_layout_dir(&self, oci_dir: &Path) -> Result<(), VMError> {
         let layout_file = oci_dir.join("oci-layout");
         let index_file = oci_dir.join("index.json");
@@ -157,12 +381,25 @@ impl ImageResolver {
                 oci_dir.to_string_lossy()
             )));
         }
-        Ok(())
+        oci_layout::verify_blobs(oci_dir)
     }
 
     fn validate_image_ref(&self, image_ref: &str) -> Result<String, VMError> {
         info!("Validating image reference: {}", image_ref);
 
+        let local = ["containers-storage:", "oci:", "dir:", "oci-archive:"];
+        // Bare names already in local storage (e.g. derived `localhost/flashvm:...` images)
+        // resolve without a pull
+        if settings::offline()
+            && !local.iter().any(|p| image_ref.starts_with(p))
+            && (image_ref.starts_with("docker://") || !self.image_exists_in_storage(image_ref)?)
+        {
+            settings::require_online(&format!(
+                "{} is pulled from a registry (use containers-storage:<name> for images already in local storage), so it",
+                image_ref
+            ))?;
+        }
+
         if image_ref.starts_with("docker://") {
             self.validate_docker_ref(image_ref)
         } else if image_ref.starts_with("containers-storage:") {
@@ -175,6 +412,7 @@ impl ImageResolver {
             self.get_image_path_ref(image_ref)
         } else {
             debug!("Assuming simple name (docker): {}", image_ref);
+            check_digest(image_ref)?;
             Ok(image_ref.to_string())
         }
     }
@@ -187,10 +425,10 @@ impl ImageResolver {
             return self.validate_docker_ref(image_ref);
         }
 
-        let path_ref = if image_ref.starts_with("dir:") {
-            &image_ref[4..]
-        } else if image_ref.starts_with("oci-archive:") {
-            &image_ref[12..]
+        let path_ref = if let Some(p) = image_ref.strip_prefix("dir:") {
+            p
+        } else if let Some(p) = image_ref.strip_prefix("oci-archive:") {
+            p
         } else {
             image_ref
         };
@@ -215,10 +453,10 @@ impl ImageResolver {
             ));
         }
         let path_part = parts[1];
-        let (path, _tag) = if let Some(colon_pos) = path_part.rfind(':') {
-            (&path_part[..colon_pos], &path_part[colon_pos + 1..])
+        let (path, tag) = if let Some(colon_pos) = path_part.rfind(':') {
+            (&path_part[..colon_pos], Some(&path_part[colon_pos + 1..]))
         } else {
-            (path_part, "latest")
+            (path_part, None)
         };
 
         let oci_path = Path::new(path);
@@ -237,6 +475,7 @@ impl ImageResolver {
                 path
             )));
         }
+        platform::check_oci_layout(oci_path, tag)?;
         debug!("OCI reference validated: {}", oci_ref);
         Ok(oci_ref.to_string())
     }
@@ -253,14 +492,126 @@ impl ImageResolver {
                 "Docker image name cannot be empty".to_string(),
             ));
         }
+        check_digest(image_name)?;
         debug!("Docker reference validated ⇒ {}", image_name);
         Ok(image_name.to_string())
     }
 
-    pub fn list_cached_images(&self) -> Result<Vec<String>, VMError> {
-        Ok(vec![])
+    /// Resolve a registry reference's tag to the digest it currently points at, returning
+    /// `name@sha256:...`. Refs that already carry a digest, and local/layout refs, are
+    /// returned unchanged. Pulling by digest makes buildah verify the content against it.
+    pub fn pin_digest(&self, image_ref: &str) -> Result<String, VMError> {
+        let local = ["containers-storage:", "oci:", "dir:", "oci-archive:"];
+        if local.iter().any(|p| image_ref.starts_with(p)) || image_ref.contains('@') {
+            return Ok(image_ref.to_string());
+        }
+        let digest = if self.command_exists("skopeo") {
+            let transport_ref = format!("docker://{}", image_ref);
+            let argv = ["skopeo", "inspect", "--format", "{{.Digest}}", &transport_ref];
+            let out = unshare::run_argv(&argv)?;
+            if !out.success() {
+                return Err(VMError::ImageResolution(format!("Failed to resolve digest of {}: {}", image_ref, out.stderr.trim()))
+                    .with_command(&argv, &out.stderr));
+            }
+            out.stdout.trim().to_string()
+        } else {
+            let tool = StorageTool::current();
+            pull_image(image_ref, &mut |_| {})?;
+            let mut argv = tool.inspect_image();
+            argv.extend(["--format", tool.digest_format(), image_ref]);
+            let out = unshare::run_argv(&argv)?;
+            if !out.success() {
+                return Err(VMError::ImageResolution(format!("Failed to read digest of {}: {}", image_ref, out.stderr.trim()))
+                    .with_command(&argv, &out.stderr));
+            }
+            out.stdout.trim().to_string()
+        };
+        let pinned = format!("{}@{}", strip_tag(image_ref), digest);
+        check_digest(&pinned)?;
+        info!("Pinned {} to {}", image_ref, pinned);
+        Ok(pinned)
+    }
+
+    /// Fail with a specific error when `image` (a containers-storage or registry name, as
+    /// passed to krunvm) has no python3 for the guest runner, instead of letting the guest
+    /// exit with 127. Images flashvm built are skipped, and each image is probed once per
+    /// process; a probe that can't run is not an error (krunvm reports the real problem).
+    pub fn check_has_python(&self, image: &str) -> Result<(), VMError> {
+        static CHECKED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+        if image_cache::in_namespace(image) || StorageTool::current() == StorageTool::Podman {
+            return Ok(());
+        }
+        let checked = CHECKED.get_or_init(|| Mutex::new(HashSet::new()));
+        if checked.lock().unwrap_or_else(|e| e.into_inner()).contains(image) {
+            return Ok(());
+        }
+        let out = unshare::run_argv(&["sh", "-c", CHECK_PYTHON, "sh", image])?;
+        let missing = match out.code {
+            0 => None,
+            3 => Some("python3"),
+            4 => Some("/usr/bin/env"),
+            _ => {
+                debug!("Could not probe {} for python3: {}", image, out.stderr.trim());
+                return Ok(());
+            }
+        };
+        if let Some(what) = missing {
+            return Err(VMError::ImageResolution(format!(
+                "{} has no {}, which flashvm needs to start Python in the guest; use a Python base image \
+                 (e.g. python:3.12-slim, or flashvm.resolve_base()) or install Python with os_prepare_image",
+                image, what
+            ))
+            .with_image(image));
+        }
+        checked.lock().unwrap_or_else(|e| e.into_inner()).insert(image.to_string());
+        Ok(())
+    }
+
+    /// Image reference for a Python version (`"3.11"`), or the embedded image for `None`.
+    /// Registry bases are pinned to their current digest, so later runs get the same image.
+    #[cfg(feature = "python")]
+    pub fn resolve_base(&self, python: Option<&str>) -> Result<String, VMError> {
+        let version = python.map(str::trim).unwrap_or("3.12");
+        let Some((_, base)) = PYTHON_BASES.iter().find(|(v, _)| *v == version) else {
+            let supported: Vec<&str> = PYTHON_BASES.iter().map(|(v, _)| *v).collect();
+            return Err(VMError::VMConfiguration(format!(
+                "no base image for Python {} (supported: {})",
+                version,
+                supported.join(", ")
+            )));
+        };
+        match base {
+            None => {
+                self.ensure_embedded_image_imported()?;
+                Ok(format!("containers-storage:{}", canonical_image()))
+            }
+            Some(image) => {
+                settings::require_online(&format!("the Python {} base ({})", version, image))?;
+                self.pin_digest(image)
+            }
+        }
+    }
+
+    pub fn list_cached_images(&self) -> Result<Vec<CachedImage>, VMError> {
+        ImageCache::new().list()
+    }
+    #[cfg(feature = "python")]
+    pub fn remove_cached_image(&self, name: &str) -> Result<bool, VMError> {
+        let removed = ImageCache::new().remove(name)?;
+        if removed && name == canonical_image() {
+            // Next resolve must import again
+            let _ = fs::remove_file(self.import_sentinel_path());
+        }
+        Ok(removed)
+    }
+    #[cfg(feature = "python")]
+    pub fn clear_cache(&self) -> Result<usize, VMError> {
+        let result = ImageCache::new().clear();
+        if !self.image_exists_in_storage(&canonical_image()).unwrap_or(true) {
+            let _ = fs::remove_file(self.import_sentinel_path());
+        }
+        result
     }
-    pub fn clear_cache(&self) -> Result<(), VMError> { Ok(()) }
 
     fn command_exists(&self, command: &str) -> bool {
         Command::new("which")
@@ -270,113 +621,977 @@ impl ImageResolver {
             .unwrap_or(false)
     }
 
+    #[cfg(feature = "python")]
     pub fn embedded_is_imported(&self) -> Result<bool, VMError> {
-        self.image_exists_in_storage(CANONICAL_IMAGE)
+        self.image_exists_in_storage(&canonical_image())
     }
+    #[cfg(feature = "python")]
     pub fn import_embedded_now(&self) -> Result<(), VMError> {
         self.ensure_embedded_image_imported()
     }
 
-    fn sh_q(s: &str) -> String {
-        if s.chars().all(|c| c.is_ascii_alphanumeric() || "/-_.:@+=,[]".contains(c)) {
-            s.to_string()
-        } else {
-            let escaped = s.replace('\'', "'\\''");
-            format!("'{}'", escaped)
+    /// Content identity of a build base: its image ID when it's in local storage, else the
+    /// registry digest, else the reference itself.
+    #[cfg(any(feature = "python", feature = "cli"))]
+    fn base_image_id(&self, base_ref: &str) -> String {
+        let name = base_ref
+            .strip_prefix("containers-storage:")
+            .or_else(|| base_ref.strip_prefix("docker://"))
+            .unwrap_or(base_ref);
+        let tool = StorageTool::current();
+        let mut argv = tool.inspect_image();
+        argv.extend(["--format", tool.id_format(), name]);
+        if let Ok(out) = unshare::run_argv(&argv) {
+            if out.success() && !out.stdout.trim().is_empty() {
+                return out.stdout.trim().to_string();
+            }
+        }
+        if !base_ref.starts_with("containers-storage:") && self.command_exists("skopeo") {
+            if let Ok(pinned) = self.pin_digest(name) {
+                return pinned;
+            }
         }
+        base_ref.to_string()
     }
 
+    #[cfg(any(feature = "python", feature = "cli"))]
     pub fn pip_install_into_image(
         &self,
         base_image: Option<&str>,
-        packages: &[String],
+        reqs: &PipRequirements,
         tag: Option<&str>,
         index_url: Option<&str>,
         extra_index_url: Option<&str>,
+        proxy: Option<&ProxyConfig>,
+    ) -> Result<String, VMError> {
+        if reqs.is_empty() {
+            return Err(VMError::VMConfiguration(
+                "packages list cannot be empty (or pass a requirements file)".to_string(),
+            ));
+        }
+        // Read up front: a missing file fails before any container is created, and the
+        // contents feed the default tag
+        let read_file = |path: &Option<PathBuf>| -> Result<Option<Vec<u8>>, VMError> {
+            path.as_ref()
+                .map(|p| {
+                    fs::read(p).map_err(|e| {
+                        VMError::VMConfiguration(format!("cannot read {}: {}", p.to_string_lossy(), e))
+                    })
+                })
+                .transpose()
+        };
+        let requirements_txt = read_file(&reqs.requirements_file)?;
+        let constraints_txt = read_file(&reqs.constraints_file)?;
+        let wheels: Option<Vec<String>> = match &reqs.wheel_dir {
+            Some(dir) => {
+                if index_url.is_some() || extra_index_url.is_some() {
+                    return Err(VMError::VMConfiguration(
+                        "wheel_dir installs with --no-index; it can't be combined with index URLs".to_string(),
+                    ));
+                }
+                Some(wheel_names(dir)?)
+            }
+            None => {
+                settings::require_online("a pip build without wheel_dir")?;
+                None
+            }
+        };
+        // Before resolving the base, so the pass can't evict it from under the build
+        image_cache::evict_before_import();
+
+        let base_ref = self.build_base_ref(base_image)?;
+        let target_name = match tag {
+            Some(t) => image_cache::image_name(t),
+            None => {
+                let mut packages: Vec<&str> = reqs.packages.iter().map(|p| p.trim()).collect();
+                packages.sort_unstable();
+                packages.dedup();
+                let mut key = serde_json::json!({
+                    "base": self.base_image_id(&base_ref),
+                    "packages": packages,
+                    "requirements": requirements_txt.as_deref().map(sha256::digest),
+                    "constraints": constraints_txt.as_deref().map(sha256::digest),
+                    "index_url": index_url,
+                    "extra_index_url": extra_index_url,
+                    "wheels": wheels,
+                });
+                // uv can resolve differently from pip; keyed only when set so pip tags are unchanged
+                if reqs.use_uv {
+                    key["installer"] = serde_json::Value::from("uv");
+                }
+                match self.derived_image("python-pip", &key)? {
+                    (name, true) => return Ok(format!("containers-storage:{}", name)),
+                    (name, false) => name,
+                }
+            }
+        };
+
+        let proxy_vars = Self::proxy_vars(proxy);
+        self.step(&format!("creating build container from {}", base_ref));
+        let container = self.working_container(&base_ref, &proxy_vars)?;
+
+        // Ensure base image has python and pip available for system install; try best-effort fixes
+        let _ = unshare::run_argv(&["buildah", "run", "--user", "root", container.as_str(), "--", "sh", "-lc", ENSURE_PIP]);
+
+        // Lockfiles and wheels go in by `buildah copy`; they are deleted again before the commit
+        let mut copied = Vec::new();
+        for (path, dest) in [
+            (&reqs.requirements_file, "/tmp/flashvm-requirements.txt"),
+            (&reqs.constraints_file, "/tmp/flashvm-constraints.txt"),
+            (&reqs.wheel_dir, "/tmp/flashvm-wheels"),
+        ] {
+            let Some(path) = path else { continue };
+            let src = path.to_string_lossy();
+            let argv = ["buildah", "copy", container.as_str(), src.as_ref(), dest];
+            let out = unshare::run_argv(&argv)?;
+            if !out.success() {
+                let _ = unshare::run_argv(&["buildah", "rm", container.as_str()]);
+                return Err(VMError::Execution(format!("buildah copy of {} failed: {}", src, out.stderr.trim()))
+                    .with_command(&argv, &out.stderr));
+            }
+            copied.push(dest);
+        }
+
+        // Ignore user configs and root warnings; with the shared cache mounted, pip and uv keep
+        // downloads there instead of not at all
+        let pip_cache = image_cache::pip_cache_dir()?;
+        let mut env_vars = vec!["PIP_CONFIG_FILE=/dev/null".to_string(), "PIP_ROOT_USER_ACTION=ignore".to_string()];
+        match &pip_cache {
+            Some(_) => env_vars.extend([
+                format!("PIP_CACHE_DIR={}", image_cache::PIP_CACHE_MOUNT),
+                format!("UV_CACHE_DIR={}/uv", image_cache::PIP_CACHE_MOUNT),
+            ]),
+            None => env_vars.extend(["PIP_NO_CACHE_DIR=1".to_string(), "UV_NO_CACHE=1".to_string()]),
+        }
+        env_vars.extend(proxy_vars);
+        env_vars.extend(reproducible_env());
+        // uv takes the same arguments as pip
+        let mut args: Vec<&str> = vec![if reqs.use_uv { "uv" } else { "pip" }];
+        if reqs.wheel_dir.is_some() {
+            args.extend(["--no-index", "--find-links", "/tmp/flashvm-wheels"]);
+        }
+        if let Some(u) = index_url {
+            args.extend(["--index-url", u]);
+        }
+        if let Some(u) = extra_index_url {
+            args.extend(["--extra-index-url", u]);
+        }
+        if reqs.requirements_file.is_some() {
+            args.extend(["-r", "/tmp/flashvm-requirements.txt"]);
+        }
+        if reqs.constraints_file.is_some() {
+            args.extend(["-c", "/tmp/flashvm-constraints.txt"]);
+        }
+        args.extend(reqs.packages.iter().map(String::as_str));
+
+        // Run as root to install into system site-packages so it's importable by any user
+        self.step(if reqs.use_uv { "running uv pip install" } else { "running pip install" });
+        let volume = pip_cache
+            .as_ref()
+            .map(|dir| format!("{}:{}", dir.to_string_lossy(), image_cache::PIP_CACHE_MOUNT));
+        let mut pip_argv = vec!["buildah", "run", "--user", "root"];
+        if let Some(v) = &volume {
+            pip_argv.extend(["--volume", v.as_str()]);
+        }
+        pip_argv.extend([container.as_str(), "--", "env"]);
+        pip_argv.extend(env_vars.iter().map(String::as_str));
+        pip_argv.extend(["sh", "-lc", PIP_INSTALL, "sh"]);
+        pip_argv.extend(args);
+        let out = self.run_build_step(&pip_argv)?;
+        if pip_cache.is_some() {
+            image_cache::trim_pip_cache();
+        }
+        if !out.success() {
+            let _ = unshare::run_argv(&["buildah", "rm", container.as_str()]);
+            return Err(VMError::Execution(format!("pip install failed inside buildah run:\n{}", out.tail(ERROR_TAIL_LINES)))
+                .with_command(&pip_argv, &out.stderr));
+        }
+        if !copied.is_empty() {
+            let mut rm_argv = vec!["buildah", "run", "--user", "root", container.as_str(), "--", "rm", "-rf"];
+            rm_argv.extend(copied);
+            let _ = unshare::run_argv(&rm_argv);
+        }
+
+        // Exact record of what the image contains, as a label and in the cache manifest
+        let freeze_argv = [
+            "buildah", "run", "--user", "root", container.as_str(), "--",
+            "env", "PIP_CONFIG_FILE=/dev/null", "python3", "-m", "pip", "freeze",
+        ];
+        let freeze: Option<Vec<String>> = match unshare::run_argv(&freeze_argv) {
+            Ok(out) if out.success() => {
+                Some(out.stdout.lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect())
+            }
+            Ok(out) => {
+                warn!("pip freeze failed in {}: {}", container, out.stderr.trim());
+                None
+            }
+            Err(e) => {
+                warn!("pip freeze failed in {}: {}", container, e);
+                None
+            }
+        };
+        if let Some(lines) = &freeze {
+            let label = format!("{}={}", PIP_FREEZE_LABEL, lines.join("\n"));
+            let _ = unshare::run_argv(&["buildah", "config", "--label", label.as_str(), container.as_str()]);
+        }
+        for (key, value) in &reqs.labels {
+            let label = format!("{}={}", key, value);
+            let _ = unshare::run_argv(&["buildah", "config", "--label", label.as_str(), container.as_str()]);
+        }
+
+        let image = self.commit_container(&container, &target_name, ImageKind::Pip)?;
+        if let Some(lines) = freeze {
+            if let Err(e) = ImageCache::new().set_pip_freeze(&target_name, lines) {
+                warn!("Failed to record pip freeze of {}: {}", target_name, e);
+            }
+        }
+        Ok(image)
+    }
+
+    /// Install the pinned versions from a project's lockfile (see `lockfile::detect`) into
+    /// a pip-built image. Without a tag the image is named after the lockfile's hash, so an
+    /// unchanged lockfile reuses the image without resolving anything.
+    #[cfg(feature = "python")]
+    pub fn build_from_project(
+        &self,
+        dir: &Path,
+        base_image: Option<&str>,
+        tag: Option<&str>,
+        proxy: Option<&ProxyConfig>,
+        use_uv: bool,
+        wheel_dir: Option<&Path>,
+    ) -> Result<String, VMError> {
+        let lock = lockfile::detect(dir)?;
+        if lock.pins.as_ref().is_some_and(|p| p.is_empty()) {
+            return Err(VMError::VMConfiguration(format!(
+                "{} pins no packages from an index",
+                lock.path.to_string_lossy()
+            )));
+        }
+        let tag = match tag {
+            Some(t) => t.to_string(),
+            None => {
+                let base_ref = self.build_base_ref(base_image)?;
+                let key = serde_json::json!({
+                    "base": self.base_image_id(&base_ref),
+                    "lockfile": lock.file_name(),
+                    "sha256": lock.sha256,
+                    "uv": use_uv,
+                    "wheels": wheel_dir.map(wheel_names).transpose()?,
+                });
+                match self.derived_image("python-project", &key)? {
+                    (name, true) => return Ok(format!("containers-storage:{}", name)),
+                    // The namespace may contain ':' (a registry port); the tag can't
+                    (name, false) => name.rsplit(':').next().unwrap_or_default().to_string(),
+                }
+            }
+        };
+
+        self.step(&format!("installing the pins from {}", lock.path.to_string_lossy()));
+        let pinned = match &lock.pins {
+            Some(pins) => {
+                let mut file = tempfile::Builder::new().prefix("flashvm-pins-").suffix(".txt").tempfile()?;
+                file.write_all(format!("{}\n", pins.join("\n")).as_bytes())?;
+                Some(file)
+            }
+            None => None,
+        };
+        let reqs = PipRequirements {
+            requirements_file: Some(pinned.as_ref().map(|f| f.path().to_path_buf()).unwrap_or_else(|| lock.path.clone())),
+            wheel_dir: wheel_dir.map(Path::to_path_buf),
+            use_uv,
+            labels: vec![
+                (LOCKFILE_LABEL.to_string(), lock.file_name()),
+                (LOCKFILE_SHA256_LABEL.to_string(), lock.sha256.clone()),
+            ],
+            ..PipRequirements::default()
+        };
+        self.pip_install_into_image(base_image, &reqs, Some(&tag), None, None, proxy)
+    }
+
+    /// Build an image with a conda environment at `/opt/conda`, created by micromamba inside
+    /// the build container. Its `python` replaces `python3` on the guest's PATH, so runs on
+    /// the image use the environment.
+    #[cfg(feature = "python")]
+    pub fn conda_install_into_image(
+        &self,
+        base_image: Option<&str>,
+        spec: &CondaSpec,
+        tag: Option<&str>,
+        proxy: Option<&ProxyConfig>,
+    ) -> Result<String, VMError> {
+        if spec.is_empty() {
+            return Err(VMError::VMConfiguration(
+                "conda packages list cannot be empty (or pass an environment file)".to_string(),
+            ));
+        }
+        settings::require_online("conda_prepare_image (micromamba and conda channels)")?;
+        let env_yaml = spec
+            .env_file
+            .as_ref()
+            .map(|p| fs::read(p).map_err(|e| VMError::VMConfiguration(format!("cannot read {}: {}", p.to_string_lossy(), e))))
+            .transpose()?;
+        let mamba_platform = match platform::host_arch() {
+            "amd64" => "linux-64",
+            "arm64" => "linux-aarch64",
+            "ppc64le" => "linux-ppc64le",
+            other => {
+                return Err(VMError::VMConfiguration(format!("micromamba is not available for {}", other)));
+            }
+        };
+        image_cache::evict_before_import();
+
+        let base_ref = self.build_base_ref(base_image)?;
+        let mut packages: Vec<String> = spec.packages.iter().map(|p| p.trim().to_string()).collect();
+        // The environment must bring its own interpreter to replace the image's
+        if spec.env_file.is_none() && !packages.iter().any(|p| is_python_spec(p)) {
+            packages.push("python".to_string());
+        }
+        packages.sort_unstable();
+        packages.dedup();
+        let channels = if spec.channels.is_empty() { vec!["conda-forge".to_string()] } else { spec.channels.clone() };
+        let target_name = match tag {
+            Some(t) => image_cache::image_name(t),
+            None => {
+                let key = serde_json::json!({
+                    "base": self.base_image_id(&base_ref),
+                    "packages": packages,
+                    "env_file": env_yaml.as_deref().map(sha256::digest),
+                    "channels": channels,
+                });
+                match self.derived_image("python-conda", &key)? {
+                    (name, true) => return Ok(format!("containers-storage:{}", name)),
+                    (name, false) => name,
+                }
+            }
+        };
+
+        let proxy_vars = Self::proxy_vars(proxy);
+        self.step(&format!("creating build container from {}", base_ref));
+        let container = self.working_container(&base_ref, &proxy_vars)?;
+        let fail = |msg: String, argv: &[&str], stderr: &str| -> VMError {
+            let _ = unshare::run_argv(&["buildah", "rm", container.as_str()]);
+            VMError::Execution(msg).with_command(argv, stderr)
+        };
+        let reproducible_vars = reproducible_env();
+        let run_in = |cmd: &[&str]| -> Result<unshare::UnshareOutput, VMError> {
+            let mut argv = vec!["buildah", "run", "--user", "root", container.as_str(), "--", "env"];
+            argv.extend(proxy_vars.iter().map(String::as_str));
+            argv.extend(reproducible_vars.iter().map(String::as_str));
+            argv.push("MAMBA_ROOT_PREFIX=/opt/flashvm/mamba");
+            argv.extend_from_slice(cmd);
+            self.run_build_step(&argv)
+        };
+
+        let url = format!("https://micro.mamba.pm/api/micromamba/{}/latest", mamba_platform);
+        let fetch = ["python3", "-c", FETCH_MICROMAMBA, url.as_str()];
+        self.step("downloading micromamba");
+        let out = run_in(&fetch)?;
+        if !out.success() {
+            return Err(fail(format!("downloading micromamba failed:\n{}", out.tail(ERROR_TAIL_LINES)), &fetch, &out.stderr));
+        }
+
+        let mut create: Vec<&str> = vec!["/opt/flashvm/micromamba", "create", "-y", "-p", "/opt/conda"];
+        for c in &channels {
+            create.extend(["-c", c.as_str()]);
+        }
+        if let Some(path) = &spec.env_file {
+            let src = path.to_string_lossy();
+            let argv = ["buildah", "copy", container.as_str(), src.as_ref(), "/tmp/flashvm-environment.yml"];
+            let out = unshare::run_argv(&argv)?;
+            if !out.success() {
+                return Err(fail(format!("buildah copy of {} failed: {}", src, out.stderr.trim()), &argv, &out.stderr));
+            }
+            create.extend(["-f", "/tmp/flashvm-environment.yml"]);
+        }
+        create.extend(packages.iter().map(String::as_str));
+        self.step("creating the conda environment");
+        let out = run_in(&create)?;
+        if !out.success() {
+            return Err(fail(format!("micromamba create failed:\n{}", out.tail(ERROR_TAIL_LINES)), &create, &out.stderr));
+        }
+
+        // Point python3 at the environment; drop micromamba and its package cache
+        let finish = [
+            "sh",
+            "-c",
+            "test -x /opt/conda/bin/python || { echo 'environment has no python' >&2; exit 1; }; \
+             mkdir -p /usr/local/bin && ln -sf /opt/conda/bin/python /usr/local/bin/python3 && \
+             ln -sf /opt/conda/bin/python /usr/local/bin/python && \
+             rm -rf /opt/flashvm /tmp/flashvm-environment.yml",
+        ];
+        let out = run_in(&finish)?;
+        if !out.success() {
+            return Err(fail(format!("conda environment setup failed: {}", out.stderr.trim()), &finish, &out.stderr));
+        }
+        let _ = unshare::run_argv(&[
+            "buildah",
+            "config",
+            "--env",
+            "PATH=/opt/conda/bin:/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+            container.as_str(),
+        ]);
+        self.commit_container(&container, &target_name, ImageKind::Conda)
+    }
+
+    /// Install distro packages (e.g. shared libraries wheels link against) into an image,
+    /// with whichever of apk, apt-get, dnf, microdnf or yum the base image has.
+    #[cfg(feature = "python")]
+    pub fn os_install_into_image(
+        &self,
+        base_image: Option<&str>,
+        packages: &[String],
+        tag: Option<&str>,
+        proxy: Option<&ProxyConfig>,
     ) -> Result<String, VMError> {
         if packages.is_empty() {
             return Err(VMError::VMConfiguration("packages list cannot be empty".to_string()));
         }
+        settings::require_online("os_prepare_image (distro package repositories)")?;
+        let mut packages: Vec<&str> = packages.iter().map(|p| p.trim()).collect();
+        // Passed as arguments to the package manager: an option would change what it does
+        if let Some(bad) = packages.iter().find(|p| p.is_empty() || p.starts_with('-')) {
+            return Err(VMError::VMConfiguration(format!("invalid system package name '{}'", bad)));
+        }
+        packages.sort_unstable();
+        packages.dedup();
+        image_cache::evict_before_import();
+
+        let base_ref = self.build_base_ref(base_image)?;
+        let target_name = match tag {
+            Some(t) => image_cache::image_name(t),
+            None => {
+                let key = serde_json::json!({ "base": self.base_image_id(&base_ref), "packages": packages });
+                match self.derived_image("python-os", &key)? {
+                    (name, true) => return Ok(format!("containers-storage:{}", name)),
+                    (name, false) => name,
+                }
+            }
+        };
+
+        let proxy_vars = Self::proxy_vars(proxy);
+        self.step(&format!("creating build container from {}", base_ref));
+        let container = self.working_container(&base_ref, &proxy_vars)?;
+        let mut argv = vec!["buildah", "run", "--user", "root", container.as_str(), "--", "env"];
+        argv.extend(proxy_vars.iter().map(String::as_str));
+        let reproducible_vars = reproducible_env();
+        argv.extend(reproducible_vars.iter().map(String::as_str));
+        argv.extend(["sh", "-c", OS_INSTALL, "sh"]);
+        argv.extend(packages.iter().copied());
+        self.step("installing system packages");
+        let out = self.run_build_step(&argv)?;
+        if !out.success() {
+            let _ = unshare::run_argv(&["buildah", "rm", container.as_str()]);
+            let err = if out.code == OS_INSTALL_NO_MANAGER {
+                VMError::VMConfiguration(format!(
+                    "{} has no supported package manager (apk, apt-get, dnf, microdnf, yum)",
+                    base_ref
+                ))
+            } else {
+                VMError::Execution(format!("system package install failed:\n{}", out.tail(ERROR_TAIL_LINES)))
+            };
+            return Err(err.with_command(&argv, &out.stderr).with_image(&base_ref));
+        }
+        self.commit_container(&container, &target_name, ImageKind::Os)
+    }
+
+    /// `buildah bud` a Containerfile/Dockerfile into `<image_namespace>:<tag>`. The context
+    /// defaults to the file's directory and the tag to a hash of the file's path, so
+    /// rebuilding the same file replaces its image.
+    #[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+    pub fn build_from_containerfile(
+        &self,
+        containerfile: &Path,
+        context_dir: Option<&Path>,
+        tag: Option<&str>,
+        build_args: &HashMap<String, String>,
+        proxy: Option<&ProxyConfig>,
+    ) -> Result<String, VMError> {
+        let file = containerfile.canonicalize().map_err(|e| {
+            VMError::VMConfiguration(format!("cannot read {}: {}", containerfile.to_string_lossy(), e))
+        })?;
+        let context = match context_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => file.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from(".")),
+        };
+        if !context.is_dir() {
+            return Err(VMError::VMConfiguration(format!(
+                "build context {} is not a directory",
+                context.to_string_lossy()
+            )));
+        }
+        let tag = match tag {
+            Some(t) => {
+                let valid = !t.is_empty()
+                    && t.len() <= 128
+                    && !t.starts_with(['.', '-'])
+                    && t.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+                if !valid {
+                    return Err(VMError::VMConfiguration(format!(
+                        "invalid tag '{}': use up to 128 letters, digits, '_', '.' and '-'",
+                        t
+                    )));
+                }
+                t.to_string()
+            }
+            // Reproducible builds are named after their inputs rather than the file's path
+            None => match settings::source_date_epoch() {
+                Some(epoch) => {
+                    let contents = fs::read(&file)?;
+                    let mut args: Vec<(&String, &String)> = build_args.iter().collect();
+                    args.sort();
+                    let key = serde_json::json!({
+                        "containerfile": sha256::digest(contents.as_slice()),
+                        "build_args": args,
+                        "source_date_epoch": epoch,
+                    });
+                    format!("containerfile-{}", &sha256::digest(key.to_string())[..16])
+                }
+                None => format!("containerfile-{}", &sha256::digest(file.to_string_lossy().as_bytes())[..16]),
+            },
+        };
+        let target_name = image_cache::image_name(&tag);
+        image_cache::evict_before_import();
+
+        let proxy_vars: Vec<String> =
+            proxy.map(|p| p.env_vars()).unwrap_or_default().iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        let mut args: Vec<String> = build_args.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        args.sort();
+        // Proxy variables are predefined build args, so RUN steps see them without ARG lines
+        args.extend(proxy_vars.iter().cloned());
+        let file_s = file.to_string_lossy();
+        let context_s = context.to_string_lossy();
+        let mut argv: Vec<&str> = vec!["env"];
+        argv.extend(proxy_vars.iter().map(String::as_str));
+        argv.extend(StorageTool::current().build());
+        argv.extend(["--layers", "-f", file_s.as_ref(), "-t", target_name.as_str()]);
+        if settings::offline() {
+            // Base images must already be local and RUN steps get no network
+            let pull_never = if StorageTool::current() == StorageTool::Podman { "--pull=never" } else { "--pull-never" };
+            argv.extend([pull_never, "--network", "none"]);
+        }
+        let epoch = settings::source_date_epoch().map(|e| e.to_string());
+        if let Some(e) = &epoch {
+            argv.extend(["--timestamp", e.as_str()]);
+            args.push(format!("SOURCE_DATE_EPOCH={}", e));
+        }
+        for a in &args {
+            argv.extend(["--build-arg", a.as_str()]);
+        }
+        argv.push(context_s.as_ref());
+        self.step(&format!("building {} from {}", target_name, file_s));
+        let out = self.run_build_step(&argv)?;
+        if !out.success() {
+            return Err(VMError::Execution(format!(
+                "{} failed for {}:\n{}",
+                StorageTool::current().build().join(" "),
+                file_s,
+                out.tail(ERROR_TAIL_LINES)
+            ))
+            .with_command(&argv, &out.stderr));
+        }
+        track(&target_name, ImageKind::Containerfile);
+        Ok(format!("containers-storage:{}", target_name))
+    }
+
+    /// Copy a local image to a registry with skopeo. Returns `remote@sha256:...` for the
+    /// pushed manifest, so other hosts can pull exactly this image.
+    #[cfg(feature = "python")]
+    pub fn push_image(&self, local_name: &str, remote_ref: &str, auth: Option<&RegistryAuth>) -> Result<String, VMError> {
+        settings::require_online("push_image")?;
+        if !self.command_exists("skopeo") {
+            return Err(VMError::MissingDependency("skopeo not found. Required to push images.".to_string()));
+        }
+        let local = local_name.strip_prefix("containers-storage:").unwrap_or(local_name);
+        let remote = remote_ref.strip_prefix("docker://").unwrap_or(remote_ref);
+        if remote.is_empty() || remote.contains('@') {
+            return Err(VMError::VMConfiguration(format!(
+                "invalid push destination '{}': expected registry/name[:tag]",
+                remote_ref
+            )));
+        }
+        let tmp = tempfile::Builder::new().prefix("flashvm-push-").tempdir()?;
+        let digest_file = tmp.path().join("digest");
+        // Credentials go through an authfile (mode 0600 inside a private temp dir) rather
+        // than argv, where other users could read them from /proc
+        let authfile = match auth {
+            None => None,
+            Some(RegistryAuth::AuthFile(p)) => Some(p.clone()),
+            Some(RegistryAuth::Credentials { username, password }) => {
+                let path = tmp.path().join("auth.json");
+                let token = BASE64.encode(format!("{}:{}", username, password));
+                let config = serde_json::json!({ "auths": { registry_host(remote): { "auth": token } } });
+                fs::write(&path, config.to_string())?;
+                Some(path)
+            }
+        };
+
+        let src = format!("containers-storage:{}", local);
+        let dest = format!("docker://{}", remote);
+        let digest_s = digest_file.to_string_lossy();
+        let mut argv: Vec<&str> = vec!["skopeo", "copy", "--digestfile", digest_s.as_ref()];
+        let authfile_s = authfile.as_ref().map(|p| p.to_string_lossy().to_string());
+        if let Some(a) = &authfile_s {
+            argv.extend(["--dest-authfile", a.as_str()]);
+        }
+        argv.extend([src.as_str(), dest.as_str()]);
+        info!("Pushing {} to {}", local, dest);
+        let out = unshare::run_argv(&argv)?;
+        if !out.success() {
+            let last = out.stderr.trim().lines().last().unwrap_or("").to_string();
+            return Err(VMError::Execution(format!("pushing {} to {} failed: {}", local, remote, last))
+                .with_command(&argv, &out.stderr)
+                .with_image(local));
+        }
+        let digest = fs::read_to_string(&digest_file)?.trim().to_string();
+        Ok(format!("{}@{}", strip_tag(remote), digest))
+    }
 
-        // Ensure base image reference
-        let base_ref = match base_image {
+    /// Describe an image: metadata from `buildah inspect` (pulling it first if it isn't
+    /// local) plus the python version, and optionally the installed distributions, read by
+    /// running python3 in a throwaway container without network.
+    #[cfg(feature = "python")]
+    pub fn inspect_image(&self, image_ref: Option<&str>, with_packages: bool) -> Result<ImageInfo, VMError> {
+        storage_tool::require_buildah("inspect_image")?;
+        let resolved = self.resolve_image_ref(image_ref)?;
+        let name = resolved
+            .strip_prefix("containers-storage:")
+            .or_else(|| resolved.strip_prefix("docker://"))
+            .unwrap_or(&resolved)
+            .to_string();
+        if resolved.starts_with("oci:") || resolved.starts_with("dir:") || resolved.starts_with("oci-archive:") {
+            return Err(VMError::ImageResolution(format!(
+                "inspect_image needs a registry or containers-storage image, got {}",
+                resolved
+            )));
+        }
+        let inspect_argv = ["buildah", "inspect", "--type", "image", name.as_str()];
+        let mut out = unshare::run_argv(&inspect_argv)?;
+        if !out.success() {
+            pull_image(&name, &mut |_| {}).map_err(|e| e.with_image(&name))?;
+            out = unshare::run_argv(&inspect_argv)?;
+            if !out.success() {
+                return Err(VMError::ImageResolution(format!("buildah inspect failed: {}", out.stderr.trim()))
+                    .with_command(&inspect_argv, &out.stderr)
+                    .with_image(&name));
+            }
+        }
+        let meta: serde_json::Value = serde_json::from_str(&out.stdout)
+            .map_err(|e| VMError::ImageResolution(format!("Invalid buildah inspect output: {}", e)))?;
+        let oci = &meta["OCIv1"];
+        let text = |v: &serde_json::Value| v.as_str().filter(|s| !s.is_empty()).map(str::to_string);
+        let labels = oci["config"]["Labels"]
+            .as_object()
+            .map(|m| m.iter().map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string())).collect())
+            .unwrap_or_default();
+        // The manifest has the (compressed) layer digests registries show; diff_ids are the fallback
+        let manifest: serde_json::Value =
+            meta["Manifest"].as_str().and_then(|m| serde_json::from_str(m).ok()).unwrap_or_default();
+        let mut layers: Vec<String> =
+            manifest["layers"].as_array().map(|l| l.iter().filter_map(|d| text(&d["digest"])).collect()).unwrap_or_default();
+        if layers.is_empty() {
+            layers = oci["rootfs"]["diff_ids"].as_array().map(|l| l.iter().filter_map(text).collect()).unwrap_or_default();
+        }
+        if let Err(e) = ImageCache::new().touch(&name) {
+            debug!("Failed to update image cache manifest: {}", e);
+        }
+
+        let (python_version, packages) = self.probe_python(&name, with_packages)?;
+        Ok(ImageInfo {
+            id: text(&meta["FromImageID"]).unwrap_or_default(),
+            created: text(&oci["created"]),
+            architecture: text(&oci["architecture"]),
+            os: text(&oci["os"]),
+            labels,
+            layers,
+            python_version,
+            packages,
+            name,
+        })
+    }
+
+    /// Python version (and installed distributions) of a local image; `None` when the image
+    /// has no python3.
+    #[cfg(feature = "python")]
+    fn probe_python(&self, name: &str, with_packages: bool) -> Result<PythonProbe, VMError> {
+        let from_argv = ["buildah", "from", "--pull-never", name];
+        let from = unshare::run_argv(&from_argv)?;
+        if !from.success() {
+            return Err(VMError::Execution(format!("buildah from failed: {}", from.stderr.trim()))
+                .with_command(&from_argv, &from.stderr));
+        }
+        let container = from.stdout.trim().to_string();
+        let mut argv = vec!["buildah", "run", "--network", "none", container.as_str(), "--", "python3", "-c", PROBE_PYTHON];
+        if with_packages {
+            argv.push("--packages");
+        }
+        let out = unshare::run_argv(&argv);
+        let _ = unshare::run_argv(&["buildah", "rm", container.as_str()]);
+        let out = out?;
+        if !out.success() {
+            debug!("No usable python3 in {}: {}", name, out.stderr.trim());
+            return Ok((None, with_packages.then(Vec::new)));
+        }
+        let probe: serde_json::Value = serde_json::from_str(out.stdout.trim())
+            .map_err(|e| VMError::Execution(format!("Invalid python probe output: {}", e)))?;
+        let packages = probe["packages"].as_array().map(|list| {
+            list.iter()
+                .filter_map(|p| Some((p[0].as_str()?.to_string(), p[1].as_str()?.to_string())))
+                .collect()
+        });
+        Ok((probe["version"].as_str().map(str::to_string), packages))
+    }
+
+    /// Base of a derived-image build: the embedded image (imported on demand) or a
+    /// validated user ref, keeping its original transport.
+    #[cfg(any(feature = "python", feature = "cli"))]
+    fn build_base_ref(&self, base_image: Option<&str>) -> Result<String, VMError> {
+        match base_image {
             None => {
                 self.ensure_embedded_image_imported()?;
-                format!("containers-storage:{}", CANONICAL_IMAGE)
+                Ok(format!("containers-storage:{}", canonical_image()))
             }
             Some(img) => {
-                // validate but keep original transport if present
                 let _ = self.validate_image_ref(img)?;
-                img.to_string()
+                Ok(img.to_string())
             }
-        };
+        }
+    }
+
+    /// Content-addressed name `<image_namespace>:<prefix>-<hash of key>` for a derived image,
+    /// and whether it already exists (in which case the build is skipped).
+    #[cfg(any(feature = "python", feature = "cli"))]
+    fn derived_image(&self, prefix: &str, key: &serde_json::Value) -> Result<(String, bool), VMError> {
+        // Reproducible builds get their own names; keyed only when set so other names are unchanged
+        let mut key = key.clone();
+        if let Some(epoch) = settings::source_date_epoch() {
+            key["source_date_epoch"] = serde_json::Value::from(epoch);
+        }
+        let name = image_cache::image_name(&format!("{}-{}", prefix, &sha256::digest(key.to_string())[..16]));
+        if !self.image_exists_in_storage(&name)? {
+            return Ok((name, false));
+        }
+        info!("Reusing cached image {}", name);
+        if let Err(e) = ImageCache::new().touch(&name) {
+            debug!("Failed to update image cache manifest: {}", e);
+        }
+        Ok((name, true))
+    }
+
+    /// `K=V` arguments for `env`, so base image pulls and installers go through the proxy.
+    #[cfg(any(feature = "python", feature = "cli"))]
+    fn proxy_vars(proxy: Option<&ProxyConfig>) -> Vec<String> {
+        proxy.map(|p| p.env_vars()).unwrap_or_default().iter().map(|(k, v)| format!("{}={}", k, v)).collect()
+    }
 
-        // Create working container
-        let from = self.run_in_buildah_unshare_capture(&format!("buildah from '{}'", base_ref))?;
-        if !from.success {
+    /// Create the working container for a build.
+    #[cfg(any(feature = "python", feature = "cli"))]
+    fn working_container(&self, base_ref: &str, proxy_vars: &[String]) -> Result<String, VMError> {
+        storage_tool::require_buildah("building images from packages")?;
+        let mut argv = vec!["env"];
+        argv.extend(proxy_vars.iter().map(String::as_str));
+        argv.extend(["buildah", "from", base_ref]);
+        let from = unshare::run_argv(&argv)?;
+        if !from.success() {
             return Err(VMError::Execution(format!("buildah from failed: {}", from.stderr)));
         }
         let container = from.stdout.trim().to_string();
         if container.is_empty() {
             return Err(VMError::Execution("buildah from returned empty container name".to_string()));
         }
+        Ok(container)
+    }
 
-        // Ensure base image has python and pip available for system install; try best-effort fixes
-        let _ = self.run_in_buildah_unshare(&format!(
-            "buildah run --user root '{}' -- sh -lc {}",
-            container,
-            Self::sh_q(
-                "command -v python3 >/dev/null 2>&1 || true; \
-                 command -v pip3 >/dev/null 2>&1 || python3 -m ensurepip --upgrade >/dev/null 2>&1 || true; \
-                 [ -x /usr/bin/python3 ] || ln -sf $(command -v python3) /usr/bin/python3 || true"
-            )
-        ));
-
-        // Build pip command (force system site-packages, ignore user configs and root warnings)
-        let mut pip_cmd = String::from(
-            "env PIP_CONFIG_FILE=/dev/null PIP_ROOT_USER_ACTION=ignore \
-             python3 -m pip install --no-cache-dir --no-user --disable-pip-version-check --break-system-packages"
-        );
-        if let Some(u) = index_url { pip_cmd.push_str(&format!(" --index-url {}", Self::sh_q(u))); }
-        if let Some(u) = extra_index_url { pip_cmd.push_str(&format!(" --extra-index-url {}", Self::sh_q(u))); }
-        for p in packages { pip_cmd.push(' '); pip_cmd.push_str(&Self::sh_q(p)); }
-
-        // Run as root to install into system site-packages so it's importable by any user
-        let run_ok = self.run_in_buildah_unshare(&format!(
-            "buildah run --user root '{}' -- sh -lc {}",
-            container,
-            Self::sh_q(&pip_cmd)
-        ))?;
-        if !run_ok {
-            let _ = self.run_in_buildah_unshare(&format!("buildah rm '{}'", container));
-            return Err(VMError::Execution("pip install failed inside buildah run".to_string()));
-        }
-
-        // Determine target tag
-        let target_tag = if let Some(t) = tag {
-            t.to_string()
-        } else {
-            let mut hasher = DefaultHasher::new();
-            packages.hash(&mut hasher);
-            let h = hasher.finish();
-            format!("python-pip-{:016x}", h)
-        };
-        let target_name = format!("localhost/flashvm:{}", target_tag);
-
-        let ok_commit = self.run_in_buildah_unshare(&format!("buildah commit '{}' '{}'", container, target_name))?;
-        let _ = self.run_in_buildah_unshare(&format!("buildah rm '{}'", container));
+    /// Commit the working container as `target_name`, remove it and track the new image.
+    #[cfg(any(feature = "python", feature = "cli"))]
+    fn commit_container(&self, container: &str, target_name: &str, kind: ImageKind) -> Result<String, VMError> {
+        self.step(&format!("committing {}", target_name));
+        // --timestamp also sets the mtime of every file in the new layer
+        let timestamp = settings::source_date_epoch().map(|e| e.to_string());
+        let mut argv = vec!["buildah", "commit"];
+        if let Some(t) = &timestamp {
+            argv.extend(["--timestamp", t.as_str()]);
+        }
+        argv.extend([container, target_name]);
+        let ok_commit = unshare::run_argv(&argv)?.success();
+        let _ = unshare::run_argv(&["buildah", "rm", container]);
         if !ok_commit {
             return Err(VMError::Execution("buildah commit failed".to_string()));
         }
+        track(target_name, kind);
         Ok(format!("containers-storage:{}", target_name))
     }
 }
 
 impl Default for ImageResolver { fn default() -> Self { Self::new() } }
 
-#[derive(Debug)]
-struct Captured {
-    stdout: String,
-    stderr: String,
-    success: bool,
+/// Build-step environment for reproducible builds: the pinned `SOURCE_DATE_EPOCH` (which
+/// also makes pip/py_compile write hash-based .pyc files) and a fixed hash seed.
+#[cfg(any(feature = "python", feature = "cli"))]
+fn reproducible_env() -> Vec<String> {
+    match settings::source_date_epoch() {
+        Some(epoch) => vec![format!("SOURCE_DATE_EPOCH={}", epoch), "PYTHONHASHSEED=0".to_string()],
+        None => vec![],
+    }
+}
+
+/// Sorted file names in a wheel directory, for derived-image keys
+#[cfg(any(feature = "python", feature = "cli"))]
+fn wheel_names(dir: &Path) -> Result<Vec<String>, VMError> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map_err(|e| VMError::VMConfiguration(format!("cannot read {}: {}", dir.to_string_lossy(), e)))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Runs in the build container's python3: download the micromamba tarball at argv[1] and
+/// extract `bin/micromamba` to /opt/flashvm (tarfile, since curl/bzip2 may be missing).
+#[cfg(feature = "python")]
+const FETCH_MICROMAMBA: &str = "\
+import io, os, sys, tarfile, urllib.request
+data = urllib.request.urlopen(sys.argv[1], timeout=120).read()
+with tarfile.open(fileobj=io.BytesIO(data), mode='r:bz2') as t:
+    member = t.getmember('bin/micromamba')
+    member.name = 'micromamba'
+    t.extract(member, '/opt/flashvm')
+os.chmod('/opt/flashvm/micromamba', 0o755)
+";
+
+/// Best-effort fixes run in a pip build container before installing: pip via ensurepip when the
+/// image has none, and a `/usr/bin/python3` link.
+#[cfg(any(feature = "python", feature = "cli"))]
+const ENSURE_PIP: &str = "\
+command -v pip3 >/dev/null 2>&1 || python3 -m ensurepip --upgrade >/dev/null 2>&1 || true
+[ -x /usr/bin/python3 ] || ln -sf \"$(command -v python3)\" /usr/bin/python3 || true
+";
+
+/// Runs in a pip build container as `sh -lc PIP_INSTALL sh <pip|uv> <install args...>`: uv when
+/// asked for and the image has it, pip otherwise, into the system site-packages either way.
+#[cfg(any(feature = "python", feature = "cli"))]
+const PIP_INSTALL: &str = "\
+installer=$1
+shift
+if [ \"$installer\" = uv ]; then
+    if command -v uv >/dev/null 2>&1; then
+        exec uv pip install --no-config --system --break-system-packages \"$@\"
+    fi
+    echo 'flashvm: uv not found in image, using pip' >&2
+fi
+exec python3 -m pip install --no-user --disable-pip-version-check --break-system-packages \"$@\"
+";
+
+/// Runs in the build container as `sh -c OS_INSTALL sh <packages...>`; exits with
+/// `OS_INSTALL_NO_MANAGER` when the image has no package manager it knows.
+#[cfg(feature = "python")]
+const OS_INSTALL: &str = "\
+set -e
+if command -v apk >/dev/null 2>&1; then
+    apk add --no-cache \"$@\"
+elif command -v apt-get >/dev/null 2>&1; then
+    export DEBIAN_FRONTEND=noninteractive
+    apt-get update
+    apt-get install -y --no-install-recommends \"$@\"
+    rm -rf /var/lib/apt/lists/*
+elif command -v dnf >/dev/null 2>&1; then
+    dnf install -y \"$@\"
+    dnf clean all
+elif command -v microdnf >/dev/null 2>&1; then
+    microdnf install -y \"$@\"
+    microdnf clean all
+elif command -v yum >/dev/null 2>&1; then
+    yum install -y \"$@\"
+    yum clean all
+else
+    exit 200
+fi
+";
+#[cfg(feature = "python")]
+const OS_INSTALL_NO_MANAGER: i32 = 200;
+
+/// Python version and, when asked for, installed `(name, version)` distributions
+#[cfg(feature = "python")]
+type PythonProbe = (Option<String>, Option<Vec<(String, String)>>);
+
+/// Runs as `python3 -c PROBE_PYTHON [--packages]` inside the image being inspected.
+#[cfg(feature = "python")]
+const PROBE_PYTHON: &str = "\
+import json, platform, sys
+out = {'version': platform.python_version()}
+if '--packages' in sys.argv:
+    try:
+        from importlib import metadata
+        pkgs = {(d.metadata['Name'] or '', d.version) for d in metadata.distributions()}
+        out['packages'] = sorted(p for p in pkgs if p[0])
+    except ImportError:
+        out['packages'] = []
+print(json.dumps(out))
+";
+
+/// Runs inside the unshare namespace as `sh -c CHECK_PYTHON sh <image>`: mounts a throwaway
+/// container of the image and looks for what the guest runner is started with
+/// (`/usr/bin/env python3`). Exits 3 without python3, 4 without /usr/bin/env, 2 when the
+/// image couldn't be mounted.
+const CHECK_PYTHON: &str = "\
+c=$(buildah from --quiet \"$1\" 2>/dev/null) || exit 2
+m=$(buildah mount \"$c\") || { buildah rm \"$c\" >/dev/null 2>&1; exit 2; }
+has() { [ -e \"$m/$1\" ] || [ -L \"$m/$1\" ]; }
+rc=3
+for d in usr/local/sbin usr/local/bin usr/sbin usr/bin sbin bin opt/conda/bin; do
+    if has \"$d/python3\"; then rc=0; break; fi
+done
+if [ $rc -eq 0 ] && ! has usr/bin/env; then rc=4; fi
+buildah umount \"$c\" >/dev/null 2>&1
+buildah rm \"$c\" >/dev/null 2>&1
+exit $rc
+";
+
+/// Registry host of a docker reference, with Docker Hub for bare names (`python:3.11`).
+pub(crate) fn registry_host(image_ref: &str) -> &str {
+    match image_ref.split_once('/') {
+        Some((first, _)) if first.contains(['.', ':']) || first == "localhost" => first,
+        _ => "docker.io",
+    }
+}
+
+/// `python`, `python=3.11`, `python>=3.10` ... but not `python-dateutil`.
+#[cfg(feature = "python")]
+fn is_python_spec(spec: &str) -> bool {
+    spec.strip_prefix("python").is_some_and(|rest| rest.is_empty() || rest.starts_with(['=', '<', '>', '!', ' ']))
+}
+
+/// Reject `name@<digest>` refs whose digest isn't `sha256:` followed by 64 hex digits.
+fn check_digest(image_ref: &str) -> Result<(), VMError> {
+    let Some((_, digest)) = image_ref.split_once('@') else {
+        return Ok(());
+    };
+    let valid = digest
+        .strip_prefix("sha256:")
+        .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c)));
+    if !valid {
+        return Err(VMError::ImageResolution(format!(
+            "Invalid digest in {}: expected sha256:<64 lowercase hex digits>",
+            image_ref
+        )));
+    }
+    Ok(())
+}
+
+/// `registry/name:tag` → `registry/name` (a `:` before the last `/` is a registry port).
+fn strip_tag(image_ref: &str) -> &str {
+    let name_start = image_ref.rfind('/').map_or(0, |i| i + 1);
+    match image_ref[name_start..].rfind(':') {
+        Some(i) => &image_ref[..name_start + i],
+        None => image_ref,
+    }
+}
+
+/// Record an image in the cache manifest; a manifest problem never fails the import itself.
+fn track(name: &str, kind: ImageKind) {
+    if let Err(e) = ImageCache::new().record(name, kind) {
+        warn!("Failed to track cached image {}: {}", name, e);
+    }
+    scan::after_build_or_pull(name);
 }
@@ -0,0 +1,66 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The zone's TZif data from the host's tz database, for images that don't ship one (under
+/// /work/scripts)
+pub const ZONEINFO_FILE: &str = "localtime";
+
+const MAX_NAME_LEN: usize = 64;
+
+/// The host's tz database: `TZDIR` when set, else where glibc and musl look
+fn zoneinfo_dir() -> PathBuf {
+    std::env::var_os("TZDIR")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/zoneinfo"))
+}
+
+/// The host's TZif file for `name`, if its tz database has that zone
+fn host_zoneinfo(name: &str) -> Option<PathBuf> {
+    let path = zoneinfo_dir().join(name);
+    let magic = fs::read(&path).ok()?;
+    magic.starts_with(b"TZif").then_some(path)
+}
+
+/// Fail unless `name` is an IANA zone name (`Europe/Berlin`, `UTC`). Hosts with a tz database
+/// also reject zones it doesn't have, since the guest wouldn't know them either.
+pub fn validate_timezone(name: &str) -> Result<(), String> {
+    let component_ok = |c: &str| {
+        !c.is_empty() && !c.starts_with('.') && c.chars().all(|ch| ch.is_ascii_alphanumeric() || "_-+".contains(ch))
+    };
+    if name.len() > MAX_NAME_LEN || !name.split('/').all(component_ok) {
+        return Err(format!("invalid timezone '{}': expected an IANA zone name such as 'Europe/Berlin'", name));
+    }
+    if zoneinfo_dir().is_dir() && host_zoneinfo(name).is_none() {
+        return Err(format!("unknown timezone '{}'", name));
+    }
+    Ok(())
+}
+
+/// Fail unless `name` looks like a locale (`de_DE.UTF-8`, `C.UTF-8`, `sr_RS@latin`)
+pub fn validate_locale(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_LEN
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || "_.-@".contains(c))
+    {
+        return Err(format!("invalid locale '{}': expected a name such as 'de_DE.UTF-8'", name));
+    }
+    Ok(())
+}
+
+/// Environment the guest gets for `locale`; the caller's `env` entries override these.
+pub fn env_vars(locale: Option<&str>) -> Vec<(String, String)> {
+    locale
+        .map(|l| vec![("LANG".to_string(), l.to_string()), ("LC_ALL".to_string(), l.to_string())])
+        .unwrap_or_default()
+}
+
+/// Copy the zone's TZif file into `scripts_dir`. False when the host has no data for it, and
+/// the guest has only its own tz database to go on.
+pub fn stage_zoneinfo(name: &str, scripts_dir: &Path) -> io::Result<bool> {
+    match host_zoneinfo(name) {
+        Some(path) => fs::copy(path, scripts_dir.join(ZONEINFO_FILE)).map(|_| true),
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,116 @@
+use crate::error::VMError;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lockfiles `build_from_project` understands, in the order they are looked for
+const LOCKFILES: &[&str] = &["poetry.lock", "uv.lock", "requirements.txt"];
+
+/// The lockfile found in a project directory and the pins read from it.
+#[derive(Debug, Clone)]
+pub struct ProjectLock {
+    pub path: PathBuf,
+    /// sha256 of the lockfile's contents
+    pub sha256: String,
+    /// `name==version` lines for poetry.lock/uv.lock; None for requirements.txt, which is
+    /// installed as is
+    pub pins: Option<Vec<String>>,
+}
+
+impl ProjectLock {
+    pub fn file_name(&self) -> String {
+        self.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+    }
+}
+
+/// Find the project's lockfile (poetry.lock, then uv.lock, then requirements.txt) and read
+/// its pinned versions.
+pub fn detect(dir: &Path) -> Result<ProjectLock, VMError> {
+    if !dir.is_dir() {
+        return Err(VMError::VMConfiguration(format!(
+            "project directory not found: {}",
+            dir.to_string_lossy()
+        )));
+    }
+    let Some(path) = LOCKFILES.iter().map(|f| dir.join(f)).find(|p| p.is_file()) else {
+        let hint = if dir.join("pyproject.toml").is_file() {
+            "; pyproject.toml alone has no pinned versions, lock it first (poetry lock / uv lock)"
+        } else {
+            ""
+        };
+        return Err(VMError::VMConfiguration(format!(
+            "no lockfile in {} (looked for {}){}",
+            dir.to_string_lossy(),
+            LOCKFILES.join(", "),
+            hint
+        )));
+    };
+    let data = fs::read(&path)
+        .map_err(|e| VMError::VMConfiguration(format!("cannot read {}: {}", path.to_string_lossy(), e)))?;
+    let pins = match path.file_name().and_then(|n| n.to_str()) {
+        Some("requirements.txt") => None,
+        _ => Some(toml_pins(&path, &String::from_utf8_lossy(&data))?),
+    };
+    Ok(ProjectLock { sha256: sha256::digest(data.as_slice()), path, pins })
+}
+
+/// `name==version` for every `[[package]]` of a poetry.lock or uv.lock that comes from a
+/// package index. Packages from git, paths or URLs (including the project itself in
+/// uv.lock) are skipped. Only the keys needed here are read, so no TOML parser is needed.
+fn toml_pins(path: &Path, text: &str) -> Result<Vec<String>, VMError> {
+    #[derive(Default)]
+    struct Package {
+        name: Option<String>,
+        version: Option<String>,
+        from_index: bool,
+    }
+    let mut packages: Vec<Package> = Vec::new();
+    // Which table the current line belongs to: the package itself, its `[package.source]`
+    // (poetry), or anything else
+    let mut table = "";
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') && !line.starts_with("[ ") {
+            table = match line {
+                "[[package]]" => {
+                    packages.push(Package { from_index: true, ..Package::default() });
+                    "package"
+                }
+                "[package.source]" => "source",
+                _ => "",
+            };
+            continue;
+        }
+        let Some(package) = packages.last_mut() else { continue };
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let (key, value) = (key.trim(), value.trim());
+        match (table, key) {
+            ("package", "name") => package.name = Some(unquote(value)),
+            ("package", "version") => package.version = Some(unquote(value)),
+            // uv.lock: `source = { registry = "..." }` vs editable/virtual/directory/path/git/url
+            ("package", "source") => package.from_index = value.contains("registry"),
+            // poetry.lock: `type = "legacy"` is a private index; the rest aren't indexes
+            ("source", "type") => package.from_index = unquote(value) == "legacy",
+            _ => {}
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut pins = Vec::new();
+    for package in packages.into_iter().filter(|p| p.from_index) {
+        let (Some(name), Some(version)) = (package.name, package.version) else { continue };
+        if !seen.insert(name.to_ascii_lowercase().replace('_', "-")) {
+            return Err(VMError::VMConfiguration(format!(
+                "{} pins several versions of {} (one per platform or Python version); \
+                 export the pins for the image's platform to requirements.txt instead",
+                path.to_string_lossy(),
+                name
+            )));
+        }
+        pins.push(format!("{}=={}", name, version));
+    }
+    Ok(pins)
+}
+
+fn unquote(value: &str) -> String {
+    value.trim_matches('"').trim_matches('\'').to_string()
+}
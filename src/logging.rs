@@ -0,0 +1,42 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes flashvm's log records to stderr. Only installed when `FLASHVM_LOG_LEVEL` is set;
+/// otherwise logging stays off as before.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = match record.level() {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        eprintln!("flashvm {} {}: {}", level, record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Install the stderr logger at `FLASHVM_LOG_LEVEL` (`error`, `warn`, `info`, `debug`,
+/// `trace` or `off`). Leaves an already-installed logger alone.
+pub fn init_from_env() {
+    let Ok(value) = std::env::var("FLASHVM_LOG_LEVEL") else { return };
+    let Ok(level) = value.trim().parse::<LevelFilter>() else {
+        eprintln!("flashvm: ignoring FLASHVM_LOG_LEVEL={:?}; use error, warn, info, debug, trace or off", value);
+        return;
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::error::VMError;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Check every blob reachable from `index.json` (nested indexes, manifests, configs and
+/// layers) exists with the size and sha256 its descriptor records, so a truncated wheel
+/// extraction or corrupted download fails here with the blob named rather than later in
+/// buildah/skopeo.
+pub fn verify_blobs(layout: &Path) -> Result<(), VMError> {
+    let index_path = layout.join("index.json");
+    let index = parse(&fs::read(&index_path)?, &index_path.to_string_lossy())?;
+    let mut seen = HashSet::new();
+    for desc in children(&index) {
+        verify_descriptor(layout, desc, "index.json", &mut seen, 0)?;
+    }
+    Ok(())
+}
+
+fn verify_descriptor(
+    layout: &Path,
+    desc: &Value,
+    parent: &str,
+    seen: &mut HashSet<String>,
+    depth: u32,
+) -> Result<(), VMError> {
+    let digest = desc
+        .get("digest")
+        .and_then(Value::as_str)
+        .ok_or_else(|| corrupt(layout, format!("a descriptor in {} has no digest", parent)))?;
+    if !seen.insert(digest.to_string()) {
+        return Ok(());
+    }
+    let hex = digest
+        .strip_prefix("sha256:")
+        .filter(|h| h.len() == 64 && h.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| corrupt(layout, format!("unsupported or malformed digest {} in {}", digest, parent)))?;
+    let path = layout.join("blobs").join("sha256").join(hex);
+    let meta = fs::metadata(&path)
+        .map_err(|_| corrupt(layout, format!("blob {} (referenced by {}) is missing", digest, parent)))?;
+    if let Some(size) = desc.get("size").and_then(Value::as_u64) {
+        if meta.len() != size {
+            return Err(corrupt(
+                layout,
+                format!("blob {} is {} bytes, {} records {}", digest, meta.len(), parent, size),
+            ));
+        }
+    }
+    let actual = sha256::try_digest(path.as_path())?;
+    if actual != hex {
+        return Err(corrupt(layout, format!("blob {} has content sha256:{}", digest, actual)));
+    }
+
+    // Manifests and indexes reference further blobs; layers and configs don't
+    let media_type = desc.get("mediaType").and_then(Value::as_str).unwrap_or("");
+    let is_json = media_type.contains("manifest") || media_type.contains("index") || media_type.is_empty();
+    if !is_json || depth > 4 {
+        return Ok(());
+    }
+    let Ok(doc) = serde_json::from_slice::<Value>(&fs::read(&path)?) else {
+        return Ok(());
+    };
+    for child in children(&doc) {
+        verify_descriptor(layout, child, digest, seen, depth + 1)?;
+    }
+    Ok(())
+}
+
+/// Descriptors a manifest or index points at
+fn children(doc: &Value) -> Vec<&Value> {
+    let mut out: Vec<&Value> = Vec::new();
+    for key in ["manifests", "layers"] {
+        if let Some(list) = doc.get(key).and_then(Value::as_array) {
+            out.extend(list);
+        }
+    }
+    if let Some(config) = doc.get("config").filter(|c| c.get("digest").is_some()) {
+        out.push(config);
+    }
+    out
+}
+
+fn parse(data: &[u8], what: &str) -> Result<Value, VMError> {
+    serde_json::from_slice(data).map_err(|e| VMError::ImageResolution(format!("Invalid {}: {}", what, e)))
+}
+
+fn corrupt(layout: &Path, detail: String) -> VMError {
+    VMError::ImageResolution(format!("Corrupted OCI layout at {}: {}", layout.to_string_lossy(), detail))
+}
+
+/// Media type suffix of zstd-compressed layers
+const ZSTD_SUFFIX: &str = "+zstd";
+
+/// `oci:<path>[:tag]` split into path and tag, as `validate_oci_ref` reads it.
+pub fn split_ref(oci_ref: &str) -> (&str, Option<&str>) {
+    let rest = oci_ref.strip_prefix("oci:").unwrap_or(oci_ref);
+    match rest.rfind(':') {
+        Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+        None => (rest, None),
+    }
+}
+
+/// Whether any manifest in the layout has a zstd-compressed layer.
+pub fn has_zstd_layers(layout: &Path) -> Result<bool, VMError> {
+    let index_path = layout.join("index.json");
+    let index = parse(&fs::read(&index_path)?, &index_path.to_string_lossy())?;
+    let mut stack: Vec<Value> = children(&index).into_iter().cloned().collect();
+    let mut depth = 0;
+    while !stack.is_empty() && depth <= 4 {
+        let mut next = Vec::new();
+        for desc in stack {
+            let media_type = desc.get("mediaType").and_then(Value::as_str).unwrap_or("");
+            if media_type.ends_with(ZSTD_SUFFIX) {
+                return Ok(true);
+            }
+            if media_type.contains("manifest") || media_type.contains("index") {
+                if let Some(doc) = read_json_blob(layout, &desc) {
+                    next.extend(children(&doc).into_iter().cloned());
+                }
+            }
+        }
+        stack = next;
+        depth += 1;
+    }
+    Ok(false)
+}
+
+/// Copy of the layout (in a temporary directory) whose zstd layers are decompressed to
+/// plain tar layers, for storage backends that can't read zstd. Untouched blobs are
+/// symlinked; rewritten manifests and indexes get new digests. Needs the `zstd` CLI.
+pub fn decompress_zstd_layers(layout: &Path) -> Result<tempfile::TempDir, VMError> {
+    let tmp = tempfile::Builder::new().prefix("flashvm-oci-").tempdir()?;
+    let blobs = tmp.path().join("blobs").join("sha256");
+    fs::create_dir_all(&blobs)?;
+    for entry in fs::read_dir(layout.join("blobs").join("sha256"))? {
+        let entry = entry?;
+        std::os::unix::fs::symlink(entry.path(), blobs.join(entry.file_name()))?;
+    }
+    fs::copy(layout.join("oci-layout"), tmp.path().join("oci-layout"))?;
+
+    let index_path = layout.join("index.json");
+    let mut index = parse(&fs::read(&index_path)?, &index_path.to_string_lossy())?;
+    rewrite_children(layout, &blobs, &mut index, 0)?;
+    fs::write(tmp.path().join("index.json"), serde_json::to_vec(&index).map_err(|e| VMError::Execution(e.to_string()))?)?;
+    Ok(tmp)
+}
+
+/// Rewrite `doc`'s descriptors in place; returns whether anything changed.
+fn rewrite_children(layout: &Path, blobs: &Path, doc: &mut Value, depth: u32) -> Result<bool, VMError> {
+    let mut changed = false;
+    for key in ["manifests", "layers"] {
+        let Some(list) = doc.get_mut(key).and_then(Value::as_array_mut) else { continue };
+        for desc in list.iter_mut() {
+            let media_type = desc.get("mediaType").and_then(Value::as_str).unwrap_or("").to_string();
+            if let Some(plain) = media_type.strip_suffix(ZSTD_SUFFIX) {
+                let (digest, size) = decompress_blob(layout, blobs, desc)?;
+                desc["mediaType"] = Value::from(plain);
+                desc["digest"] = Value::from(digest);
+                desc["size"] = Value::from(size);
+                changed = true;
+            } else if (media_type.contains("manifest") || media_type.contains("index")) && depth <= 4 {
+                let Some(mut child) = read_json_blob(layout, desc) else { continue };
+                if rewrite_children(layout, blobs, &mut child, depth + 1)? {
+                    let data = serde_json::to_vec(&child).map_err(|e| VMError::Execution(e.to_string()))?;
+                    let hex = sha256::digest(data.as_slice());
+                    // Never write through a symlink into the source layout
+                    let _ = fs::remove_file(blobs.join(&hex));
+                    fs::write(blobs.join(&hex), &data)?;
+                    desc["digest"] = Value::from(format!("sha256:{}", hex));
+                    desc["size"] = Value::from(data.len() as u64);
+                    changed = true;
+                }
+            }
+        }
+    }
+    Ok(changed)
+}
+
+fn decompress_blob(layout: &Path, blobs: &Path, desc: &Value) -> Result<(String, u64), VMError> {
+    let digest = desc.get("digest").and_then(Value::as_str).unwrap_or("");
+    let src = layout.join("blobs").join("sha256").join(digest.trim_start_matches("sha256:"));
+    let out_path = blobs.join(".decompressing");
+    let out_file = fs::File::create(&out_path)?;
+    let status = std::process::Command::new("zstd")
+        .args(["-d", "-c", "-q"])
+        .arg(&src)
+        .stdout(out_file)
+        .status()
+        .map_err(|e| {
+            VMError::MissingDependency(format!(
+                "zstd not found ({}); it is needed to convert the zstd-compressed layers of {}",
+                e,
+                layout.to_string_lossy()
+            ))
+        })?;
+    if !status.success() {
+        let _ = fs::remove_file(&out_path);
+        return Err(corrupt(layout, format!("zstd could not decompress layer {}", digest)));
+    }
+    let hex = sha256::try_digest(out_path.as_path())?;
+    let size = fs::metadata(&out_path)?.len();
+    let dest = blobs.join(&hex);
+    let _ = fs::remove_file(&dest);
+    fs::rename(&out_path, &dest)?;
+    Ok((format!("sha256:{}", hex), size))
+}
+
+fn read_json_blob(layout: &Path, desc: &Value) -> Option<Value> {
+    let digest = desc.get("digest").and_then(Value::as_str)?;
+    let data = fs::read(layout.join("blobs").join("sha256").join(digest.strip_prefix("sha256:")?)).ok()?;
+    serde_json::from_slice(&data).ok()
+}
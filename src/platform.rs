@@ -0,0 +1,214 @@
+use crate::error::VMError;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+#[cfg(target_os = "macos")]
+use std::process::Command;
+
+/// The host architecture in OCI/Go naming (`amd64`, `arm64`, ...), which is what krunvm can boot.
+pub fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        other => other,
+    }
+}
+
+/// Whether storage and krunvm commands run under `buildah unshare`. Rootless containers-storage
+/// lives in a user namespace on Linux; macOS has none, and its storage belongs to the user.
+pub fn needs_unshare() -> bool {
+    cfg!(target_os = "linux")
+}
+
+/// Fail unless the host can run VMs: KVM on Linux, Hypervisor.framework (what libkrun uses
+/// there) on macOS.
+#[cfg(target_os = "linux")]
+pub fn check_hypervisor() -> Result<(), VMError> {
+    if !Path::new("/dev/kvm").exists() {
+        return Err(VMError::MissingDependency("KVM not available. Ensure virtualization is enabled.".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn check_hypervisor() -> Result<(), VMError> {
+    if !hvf_supported() {
+        return Err(VMError::MissingDependency(
+            "Hypervisor.framework not available (sysctl kern.hv_support is not 1).".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The kernel's Landlock ABI version, or `None` without Landlock. Network rules, which
+/// `allow_egress_ports` relies on, arrived in version 4 (Linux 6.7).
+#[cfg(target_os = "linux")]
+pub fn landlock_abi() -> Option<i64> {
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+    // SAFETY: with a null attr and size 0, this flag only asks for the ABI version; the kernel
+    // reads no memory and returns no fd.
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<libc::c_void>(),
+            0usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    (abi > 0).then_some(abi)
+}
+
+#[cfg(target_os = "macos")]
+pub fn landlock_abi() -> Option<i64> {
+    None
+}
+
+/// `kern.hv_support`, which macOS sets when the CPU and OS allow Hypervisor.framework
+#[cfg(target_os = "macos")]
+pub fn hvf_supported() -> bool {
+    Command::new("sysctl")
+        .args(["-n", "kern.hv_support"])
+        .output()
+        .map(|o| o.status.success() && String::from_utf8_lossy(&o.stdout).trim() == "1")
+        .unwrap_or(false)
+}
+
+/// `os/arch[/variant]` of a descriptor's `platform` field, if it has one.
+fn platform_of(desc: &Value) -> Option<(String, String)> {
+    let p = desc.get("platform")?;
+    let os = p.get("os").and_then(Value::as_str).unwrap_or("linux").to_string();
+    let mut arch = p.get("architecture").and_then(Value::as_str)?.to_string();
+    if let Some(variant) = p.get("variant").and_then(Value::as_str) {
+        arch = format!("{}/{}", arch, variant);
+    }
+    Some((os, arch))
+}
+
+fn matches_host(os: &str, arch: &str) -> bool {
+    os == "linux" && arch.split('/').next() == Some(host_arch())
+}
+
+/// From a manifest list / OCI index, the digest of the linux manifest for the host
+/// architecture. `Ok(None)` when `manifest` is a single-image manifest.
+pub fn select_from_index(image_ref: &str, manifest: &Value) -> Result<Option<String>, VMError> {
+    let Some(entries) = manifest.get("manifests").and_then(Value::as_array) else {
+        return Ok(None);
+    };
+    let mut available = Vec::new();
+    for desc in entries {
+        let Some((os, arch)) = platform_of(desc) else { continue };
+        if matches_host(&os, &arch) {
+            if let Some(digest) = desc.get("digest").and_then(Value::as_str) {
+                return Ok(Some(digest.to_string()));
+            }
+        }
+        // Attestation manifests show up as unknown/unknown
+        if os != "unknown" {
+            available.push(format!("{}/{}", os, arch));
+        }
+    }
+    Err(mismatch(image_ref, &available))
+}
+
+/// Fail unless an image config's `architecture` is the host's.
+pub fn check_config_arch(image_ref: &str, config: &Value) -> Result<(), VMError> {
+    let os = config.get("os").and_then(Value::as_str).unwrap_or("linux");
+    match config.get("architecture").and_then(Value::as_str) {
+        Some(arch) if !matches_host(os, arch) => Err(mismatch(image_ref, &[format!("{}/{}", os, arch)])),
+        _ => Ok(()),
+    }
+}
+
+fn mismatch(image_ref: &str, available: &[String]) -> VMError {
+    VMError::ImageResolution(format!(
+        "{} has no linux/{} image (available: {}); krunvm can only boot images for the host architecture",
+        image_ref,
+        host_arch(),
+        if available.is_empty() { "none".to_string() } else { available.join(", ") }
+    ))
+}
+
+fn read_blob(layout: &Path, digest: &str) -> Result<Value, VMError> {
+    let (alg, hex) = digest
+        .split_once(':')
+        .ok_or_else(|| VMError::ImageResolution(format!("Invalid digest in OCI layout: {}", digest)))?;
+    read_blob_file(&layout.join("blobs").join(alg).join(hex))
+}
+
+/// Platforms an OCI descriptor provides, following nested indexes and reading the image
+/// config when the descriptor doesn't say.
+fn descriptor_platforms(layout: &Path, desc: &Value, depth: u32) -> Result<Vec<(String, String)>, VMError> {
+    if let Some(p) = platform_of(desc) {
+        return Ok(vec![p]);
+    }
+    let Some(digest) = desc.get("digest").and_then(Value::as_str) else {
+        return Ok(vec![]);
+    };
+    let blob = read_blob(layout, digest)?;
+    if let Some(children) = blob.get("manifests").and_then(Value::as_array) {
+        if depth > 2 {
+            return Ok(vec![]);
+        }
+        let mut out = Vec::new();
+        for child in children {
+            out.extend(descriptor_platforms(layout, child, depth + 1)?);
+        }
+        return Ok(out);
+    }
+    let Some(config_digest) = blob.pointer("/config/digest").and_then(Value::as_str) else {
+        return Ok(vec![]);
+    };
+    let config = read_blob(layout, config_digest)?;
+    Ok(match config.get("architecture").and_then(Value::as_str) {
+        Some(arch) => vec![(config.get("os").and_then(Value::as_str).unwrap_or("linux").to_string(), arch.to_string())],
+        None => vec![],
+    })
+}
+
+/// `<tag>-<host arch>` (`python-basic-arm64`) when the OCI layout at `layout` has that tag, so
+/// one layout can carry an image per architecture; `tag` otherwise.
+pub fn layout_tag_for_host(layout: &Path, tag: &str) -> String {
+    let arch_tag = format!("{}-{}", tag, host_arch());
+    let has_arch_tag = read_blob_file(&layout.join("index.json"))
+        .ok()
+        .and_then(|index| index.get("manifests").and_then(Value::as_array).cloned())
+        .unwrap_or_default()
+        .iter()
+        .any(|d| {
+            d.pointer("/annotations/org.opencontainers.image.ref.name").and_then(Value::as_str) == Some(arch_tag.as_str())
+        });
+    if has_arch_tag { arch_tag } else { tag.to_string() }
+}
+
+/// Fail unless the OCI layout at `layout` (restricted to `tag` when given) contains an
+/// image for the host architecture. Layouts that don't record any platform pass.
+pub fn check_oci_layout(layout: &Path, tag: Option<&str>) -> Result<(), VMError> {
+    let index = read_blob_file(&layout.join("index.json"))?;
+    let entries = index.get("manifests").and_then(Value::as_array).cloned().unwrap_or_default();
+    let tagged: Vec<&Value> = entries
+        .iter()
+        .filter(|d| {
+            tag.is_some()
+                && d.pointer("/annotations/org.opencontainers.image.ref.name").and_then(Value::as_str) == tag
+        })
+        .collect();
+    let candidates: Vec<&Value> = if tagged.is_empty() { entries.iter().collect() } else { tagged };
+
+    let mut platforms = Vec::new();
+    for desc in candidates {
+        platforms.extend(descriptor_platforms(layout, desc, 0)?);
+    }
+    if platforms.is_empty() || platforms.iter().any(|(os, arch)| matches_host(os, arch)) {
+        return Ok(());
+    }
+    let available: Vec<String> = platforms.iter().map(|(os, arch)| format!("{}/{}", os, arch)).collect();
+    Err(mismatch(&format!("OCI layout {}", layout.to_string_lossy()), &available))
+}
+
+fn read_blob_file(path: &Path) -> Result<Value, VMError> {
+    let data = fs::read(path)?;
+    serde_json::from_slice(&data)
+        .map_err(|e| VMError::ImageResolution(format!("Invalid {}: {}", path.to_string_lossy(), e)))
+}
@@ -0,0 +1,158 @@
+use crate::config::VMConfig;
+use crate::error::VMError;
+use crate::global_config;
+use crate::image_resolver;
+use crate::tenants;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// What runs may ask for. Unset fields don't constrain anything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub max_cpus: Option<u32>,
+    pub max_memory_mb: Option<u32>,
+    pub max_timeout_seconds: Option<u64>,
+    /// false rejects runs with `network` on
+    pub allow_network: Option<bool>,
+    /// Registry hosts images may come from (`docker.io`, `ghcr.io`, `localhost` for images
+    /// flashvm built); `oci:`/`dir:` layouts are rejected while this is set
+    pub allowed_registries: Option<Vec<String>>,
+    /// Registry images must be pinned as `name@sha256:...`
+    pub require_digest: Option<bool>,
+    /// The only digests pinned images may use
+    pub allowed_digests: Option<Vec<String>>,
+    /// Distributions `requirements` may name
+    pub allowed_packages: Option<Vec<String>>,
+}
+
+impl Policy {
+    /// Every way `config` breaks this policy, in one message.
+    pub fn check(&self, config: &VMConfig) -> Result<(), String> {
+        let mut violations = Vec::new();
+        if let Some(max) = self.max_cpus.filter(|&max| config.cpus > max) {
+            violations.push(format!("cpus={} exceeds max_cpus={}", config.cpus, max));
+        }
+        if let Some(max) = self.max_memory_mb.filter(|&max| config.memory_mb > max) {
+            violations.push(format!("memory_mb={} exceeds max_memory_mb={}", config.memory_mb, max));
+        }
+        violations.extend(self.timeout_violation(config.timeout));
+        if config.network && self.allow_network == Some(false) {
+            violations.push("network is not allowed".to_string());
+        }
+        if let Some(image) = &config.image {
+            violations.extend(self.image_violation(image, config.pin_digest));
+        }
+        if let Some(allowed) = &self.allowed_packages {
+            let allowed: Vec<String> = allowed.iter().map(|p| normalize_package(p)).collect();
+            for requirement in &config.requirements {
+                let name = normalize_package(requirement_name(requirement));
+                if !allowed.contains(&name) {
+                    violations.push(format!("requirement '{}' is not in allowed_packages", requirement));
+                }
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations.join("; "))
+        }
+    }
+
+    fn timeout_violation(&self, timeout: Duration) -> Option<String> {
+        let max = self.max_timeout_seconds.filter(|&max| timeout.as_secs_f64() > max as f64)?;
+        Some(format!("timeout of {:?} exceeds max_timeout_seconds={}", timeout, max))
+    }
+
+    /// `pin_digest` runs count as pinned for `require_digest`; they are pinned once resolved.
+    fn image_violation(&self, image: &str, pin_digest: bool) -> Option<String> {
+        let layout = ["oci:", "dir:", "oci-archive:"].iter().any(|p| image.starts_with(p));
+        let name = image.strip_prefix("docker://").or_else(|| image.strip_prefix("containers-storage:")).unwrap_or(image);
+        if let Some(registries) = &self.allowed_registries {
+            if layout {
+                return Some(format!("image {} is a local layout, not from allowed_registries", image));
+            }
+            let host = image_resolver::registry_host(name);
+            if !registries.iter().any(|r| r == host) {
+                return Some(format!("registry '{}' of image {} is not in allowed_registries", host, image));
+            }
+        }
+        if layout {
+            return None;
+        }
+        let digest = name.split_once('@').map(|(_, digest)| digest);
+        match (digest, &self.allowed_digests) {
+            (None, None) if self.require_digest == Some(true) && !pin_digest => {
+                Some(format!("image {} must be pinned by digest (name@sha256:...)", image))
+            }
+            (None, Some(_)) => Some(format!("image {} must be pinned to one of allowed_digests", image)),
+            (Some(digest), Some(allowed)) if !allowed.iter().any(|d| d == digest) => {
+                Some(format!("digest {} of image {} is not in allowed_digests", digest, image))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `Requests[security]>=2; python_version<"4"` → `Requests`
+fn requirement_name(requirement: &str) -> &str {
+    let requirement = requirement.trim();
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    &requirement[..end]
+}
+
+/// PEP 503 normalization: case-insensitive, with runs of `-`, `_` and `.` equivalent.
+fn normalize_package(name: &str) -> String {
+    let mut out = String::new();
+    for part in name.trim().to_ascii_lowercase().split(['-', '_', '.']).filter(|p| !p.is_empty()) {
+        if !out.is_empty() {
+            out.push('-');
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+/// The host's policy: the global config's `[policy]` until `configure_policy` replaces it.
+fn host_policy() -> &'static Mutex<Policy> {
+    static POLICY: OnceLock<Mutex<Policy>> = OnceLock::new();
+    POLICY.get_or_init(|| Mutex::new(global_config::global().policy.clone().unwrap_or_default()))
+}
+
+pub fn current() -> Policy {
+    host_policy().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(feature = "python")]
+pub fn configure(policy: Policy) {
+    *host_policy().lock().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Reject `config` when it breaks the host's policy or its tenant's.
+pub fn enforce(config: &VMConfig) -> Result<(), VMError> {
+    current().check(config).map_err(VMError::PolicyViolation)?;
+    if let Some(tenant) = tenants::current() {
+        if let Some(policy) = &tenant.settings().policy {
+            policy.check(config).map_err(|e| VMError::PolicyViolation(format!("tenant {}: {}", tenant.name, e)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Reject a running execution's timeout, extensions included, past the host's
+/// `max_timeout_seconds` or its tenant's. Tenants this process doesn't know don't constrain it.
+#[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+pub fn enforce_timeout(timeout: Duration, tenant: Option<&str>) -> Result<(), VMError> {
+    if let Some(violation) = current().timeout_violation(timeout) {
+        return Err(VMError::PolicyViolation(violation));
+    }
+    if let Some(tenant) = tenant.and_then(|name| tenants::get(name).ok()) {
+        if let Some(violation) = tenant.settings().policy.as_ref().and_then(|p| p.timeout_violation(timeout)) {
+            return Err(VMError::PolicyViolation(format!("tenant {}: {}", tenant.name, violation)));
+        }
+    }
+    Ok(())
+}
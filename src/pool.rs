@@ -0,0 +1,157 @@
+use crate::config::VMConfig;
+use crate::global_config;
+use crate::secrets;
+use crate::unshare;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use tempfile::TempDir;
+
+/// VMs can only be reused by runs asking for the same image and VM shape,
+/// since krunvm fixes these at create time.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    image: String,
+    cpus: u32,
+    memory_mb: u32,
+    workdir: String,
+    ports: Vec<(u16, u16)>,
+    /// The shared pip cache is a volume, fixed when the VM is created
+    pip_cache: bool,
+    /// Tenants never share a VM
+    tenant: Option<String>,
+}
+
+impl PoolKey {
+    /// `image` is the resolved (pre-normalization) reference.
+    pub fn new(image: &str, config: &VMConfig) -> Self {
+        Self {
+            image: image.to_string(),
+            cpus: config.cpus,
+            memory_mb: config.memory_mb,
+            workdir: config.workdir.clone(),
+            ports: if config.network { config.ports.clone() } else { vec![] },
+            pip_cache: config.pip_cache,
+            tenant: config.tenant.clone(),
+        }
+    }
+}
+
+/// Created-but-idle krunvm VM plus the workspace mounted as its /work
+pub struct PooledVm {
+    pub vm_name: String,
+    /// Normalized image the VM was created from
+    pub image_ref: String,
+    pub workspace: TempDir,
+    /// Runs already served by this VM
+    pub uses: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Idle VMs kept per key; extras are deleted on return
+    pub max_idle_per_key: usize,
+    /// Recycle a VM after this many runs
+    pub max_uses: u32,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        let pool = global_config::global().pool.clone().unwrap_or_default();
+        Self { max_idle_per_key: pool.max_idle_per_key.unwrap_or(2), max_uses: pool.max_uses.unwrap_or(20) }
+    }
+}
+
+pub struct VmPool {
+    inner: Mutex<PoolState>,
+}
+
+struct PoolState {
+    config: PoolConfig,
+    idle: HashMap<PoolKey, Vec<PooledVm>>,
+}
+
+impl VmPool {
+    pub fn new_vm_name() -> String {
+        format!("flashvm-pool-{}", uuid::Uuid::new_v4())
+    }
+
+    /// Take an idle VM for `key`, discarding any that krunvm no longer knows about.
+    pub fn checkout(&self, key: &PoolKey) -> Option<PooledVm> {
+        loop {
+            let vm = {
+                let mut st = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+                st.idle.get_mut(key).and_then(|v| v.pop())?
+            };
+            if vm_exists(&vm.vm_name) {
+                debug!("Reusing pooled VM {} (use #{})", vm.vm_name, vm.uses + 1);
+                return Some(vm);
+            }
+            info!("Pooled VM {} disappeared; discarding", vm.vm_name);
+        }
+    }
+
+    /// Return a VM after a healthy run; it is deleted instead when worn out or the pool is full.
+    pub fn checkin(&self, key: PoolKey, vm: PooledVm) {
+        let rejected = {
+            let mut st = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            let (max_uses, max_idle) = (st.config.max_uses, st.config.max_idle_per_key);
+            let slot = st.idle.entry(key).or_default();
+            if vm.uses < max_uses && slot.len() < max_idle {
+                slot.push(vm);
+                None
+            } else {
+                Some(vm)
+            }
+        };
+        if let Some(vm) = rejected {
+            debug!("Recycling pooled VM {} after {} uses", vm.vm_name, vm.uses);
+            delete_vm(&vm.vm_name);
+        }
+    }
+
+    #[cfg(feature = "python")]
+    pub fn config(&self) -> PoolConfig {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).config.clone()
+    }
+
+    #[cfg(feature = "python")]
+    pub fn configure(&self, config: PoolConfig) {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).config = config;
+    }
+
+    /// Delete every idle VM (and its workspace). Returns how many were removed.
+    #[cfg(any(feature = "python", feature = "daemon"))]
+    pub fn drain(&self) -> usize {
+        let drained: Vec<PooledVm> = {
+            let mut st = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            st.idle.drain().flat_map(|(_, v)| v).collect()
+        };
+        for vm in &drained {
+            delete_vm(&vm.vm_name);
+        }
+        drained.len()
+    }
+}
+
+/// Process-wide pool of warm krunvm VMs.
+pub fn vm_pool() -> &'static VmPool {
+    static POOL: OnceLock<VmPool> = OnceLock::new();
+    POOL.get_or_init(|| VmPool {
+        inner: Mutex::new(PoolState { config: PoolConfig::default(), idle: HashMap::new() }),
+    })
+}
+
+fn vm_exists(vm_name: &str) -> bool {
+    unshare::run_argv(&["krunvm", "list"])
+        .map(|o| o.success() && o.stdout.lines().any(|l| l.trim() == vm_name))
+        .unwrap_or(false)
+}
+
+fn delete_vm(vm_name: &str) {
+    let forced = unshare::run_argv(&["krunvm", "delete", "-f", vm_name]).map(|o| o.success()).unwrap_or(false);
+    if !forced {
+        let _ = unshare::run_argv(&["krunvm", "delete", vm_name]);
+    }
+    secrets::remove_dir(vm_name);
+}
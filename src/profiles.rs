@@ -0,0 +1,68 @@
+use crate::config::{VMConfig, VMConfigFile};
+use crate::error::VMError;
+use crate::global_config;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Named `run` presets, as partial settings in the config-file format. Starts with the
+/// built-in profiles and the global config's `[profiles]`; `register_profile` adds or
+/// replaces entries for this process.
+fn registry() -> &'static Mutex<BTreeMap<String, VMConfigFile>> {
+    static PROFILES: OnceLock<Mutex<BTreeMap<String, VMConfigFile>>> = OnceLock::new();
+    PROFILES.get_or_init(|| {
+        let small = VMConfigFile { cpus: Some(1), memory_mb: Some(256), timeout_seconds: Some(10), ..Default::default() };
+        let ml = VMConfigFile { cpus: Some(4), memory_mb: Some(4096), timeout_seconds: Some(600), ..Default::default() };
+        let network = VMConfigFile { network: Some(true), ..Default::default() };
+        let mut profiles = BTreeMap::from([
+            ("small".to_string(), small),
+            ("ml".to_string(), ml),
+            ("network-enabled".to_string(), network),
+        ]);
+        profiles.extend(global_config::global().profiles.clone().unwrap_or_default());
+        Mutex::new(profiles)
+    })
+}
+
+#[cfg(feature = "python")]
+pub fn register(name: &str, profile: VMConfigFile) -> Result<(), VMError> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(VMError::VMConfiguration(format!(
+            "invalid profile name '{}': use letters, digits, '-', '_' and '.'",
+            name
+        )));
+    }
+    registry().lock().unwrap_or_else(|e| e.into_inner()).insert(name.to_string(), profile);
+    Ok(())
+}
+
+pub fn all() -> BTreeMap<String, VMConfigFile> {
+    registry().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Apply profile `name` on top of `config`.
+pub fn apply(name: &str, config: &mut VMConfig) -> Result<(), VMError> {
+    let profiles = all();
+    let profile = profiles.get(name).cloned().ok_or_else(|| {
+        VMError::VMConfiguration(format!(
+            "unknown profile '{}' (known: {})",
+            name,
+            profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))
+    })?;
+    profile.apply(config)
+}
+
+/// Settings a run starts from before its own arguments: the defaults, then the profile, then
+/// the config file.
+pub fn base_config(profile: Option<&str>, config_file: Option<&Path>) -> Result<VMConfig, VMError> {
+    let mut config = VMConfig::default();
+    if let Some(name) = profile {
+        apply(name, &mut config)?;
+    }
+    match config_file {
+        Some(path) => config.with_file(path),
+        None => Ok(config),
+    }
+}
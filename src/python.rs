@@ -0,0 +1,1598 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::{
+    audit, concurrency, config, diagnostics, error, global_config, hooks, image_cache, image_resolver, logging, platform, policy, pool, profiles, rate_limit, result_cache,
+    scan, seccomp, settings, storage_tool, telemetry, tenants, wheel_resources,
+};
+
+use crate::vm_runner::VMRunner;
+use crate::image_resolver::ImageResolver;
+use crate::config::{ArtifactContent, CaptureMode, CondaSpec, EgressPorts, ExecutionResult, FileInput, FileOutput, KeepVm, PipRequirements, ProxyConfig, PullProgress, RegistryAuth, RetryPolicy, Rlimits, TraceContext, VMConfig};
+use crate::runs::RunRegistry;
+use crate::secrets::Secrets;
+use crate::error::VMError as InternalVMError;
+use crate::wheel_resources::find_embedded_data_path;
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (
+    code,
+    image = None,
+    cpus = None,
+    memory_mb = None,
+    env = None,
+    timeout_seconds = None,
+    workdir = None,
+    python_args = None,
+    network = None,
+    ports = None,
+    files_in = None,
+    expect = None,
+    max_bytes_inline = None,
+    keep_vm = None,
+    retry_policy = None,
+    use_pool = None,
+    pin_digest = None,
+    requirements = None,
+    wheel_dir = None,
+    use_uv = None,
+    pip_cache = None,
+    proxy = None,
+    allow_egress_ports = None,
+    allow_privileged_ports = None,
+    capture = None,
+    merge_streams = None,
+    run_as = None,
+    rlimits = None,
+    seccomp = None,
+    secrets = None,
+    labels = None,
+    result_cache = None,
+    queue_timeout_seconds = None,
+    tenant = None,
+    coverage = None,
+    cprofile = None,
+    display = None,
+    trace = None,
+    timezone = None,
+    locale = None,
+    config_file = None,
+    profile = None,
+))]
+fn run(
+    py: Python,
+    code: String,
+    image: Option<String>,
+    cpus: Option<u32>,
+    memory_mb: Option<u32>,
+    env: Option<HashMap<String, String>>,
+    timeout_seconds: Option<u64>,
+    workdir: Option<String>,
+    python_args: Option<Vec<String>>,
+    network: Option<bool>,
+    ports: Option<Vec<(u16, u16)>>,
+    files_in: Option<Vec<(String, String)>>,
+    expect: Option<Vec<String>>,
+    max_bytes_inline: Option<u64>,
+    keep_vm: Option<String>,
+    retry_policy: Option<Bound<PyDict>>,
+    use_pool: Option<bool>,
+    pin_digest: Option<bool>,
+    requirements: Option<Vec<String>>,
+    wheel_dir: Option<String>,
+    use_uv: Option<bool>,
+    pip_cache: Option<bool>,
+    proxy: Option<Bound<PyDict>>,
+    allow_egress_ports: Option<Vec<u16>>,
+    allow_privileged_ports: Option<bool>,
+    capture: Option<String>,
+    merge_streams: Option<bool>,
+    run_as: Option<(u32, u32)>,
+    rlimits: Option<HashMap<String, u64>>,
+    seccomp: Option<Bound<PyAny>>,
+    secrets: Option<HashMap<String, String>>,
+    labels: Option<HashMap<String, String>>,
+    result_cache: Option<bool>,
+    queue_timeout_seconds: Option<f64>,
+    tenant: Option<String>,
+    coverage: Option<bool>,
+    cprofile: Option<bool>,
+    display: Option<bool>,
+    trace: Option<HashMap<String, String>>,
+    timezone: Option<String>,
+    locale: Option<String>,
+    config_file: Option<String>,
+    profile: Option<String>,
+) -> PyResult<PyObject> {
+    let base = base_config(profile.as_deref(), config_file.as_deref())?;
+    let keep_vm = match keep_vm {
+        Some(k) => KeepVm::parse(&k).map_err(error::config_error)?,
+        None => base.keep_vm,
+    };
+    let config = VMConfig {
+        image: image.or(base.image),
+        cpus: cpus.unwrap_or(base.cpus),
+        memory_mb: memory_mb.unwrap_or(base.memory_mb),
+        env: env.unwrap_or(base.env),
+        workdir: workdir.unwrap_or(base.workdir),
+        timeout: timeout_seconds.map(Duration::from_secs).unwrap_or(base.timeout),
+        network: network.unwrap_or(base.network),
+        ports: ports.unwrap_or(base.ports),
+        allow_privileged_ports: allow_privileged_ports.unwrap_or(base.allow_privileged_ports),
+        python_args: python_args.unwrap_or(base.python_args),
+        max_bytes_inline: max_bytes_inline.unwrap_or(base.max_bytes_inline),
+        keep_vm,
+        retry_policy: match retry_policy {
+            Some(d) => retry_policy_from_py(&d)?,
+            None => base.retry_policy,
+        },
+        use_pool: use_pool.unwrap_or(base.use_pool),
+        pin_digest: pin_digest.unwrap_or(base.pin_digest),
+        requirements: requirements.unwrap_or(base.requirements),
+        wheel_dir: wheel_dir.map(std::path::PathBuf::from).or(base.wheel_dir),
+        use_uv: use_uv.unwrap_or(base.use_uv),
+        pip_cache: pip_cache.unwrap_or(base.pip_cache),
+        proxy: match proxy.as_ref() {
+            Some(p) => Some(proxy_from_py(p)?),
+            None => base.proxy,
+        },
+        egress: match allow_egress_ports {
+            Some(p) => Some(EgressPorts::new(&p).map_err(error::config_error)?),
+            None => base.egress,
+        },
+        capture: match capture.as_deref() {
+            Some(c) => CaptureMode::parse(c).map_err(error::config_error)?,
+            None => base.capture,
+        },
+        merge_streams: merge_streams.unwrap_or(base.merge_streams),
+        run_as: run_as.or(base.run_as),
+        rlimits: match rlimits {
+            Some(r) => Rlimits::from_map(&r).map_err(error::config_error)?,
+            None => base.rlimits,
+        },
+        seccomp: match seccomp {
+            Some(s) => Some(seccomp_from_py(py, &s)?),
+            None => base.seccomp,
+        },
+        secrets: Secrets::new(secrets.unwrap_or_default()).map_err(error::config_error)?,
+        labels: base.labels.into_iter().chain(labels.unwrap_or_default()).collect(),
+        result_cache: result_cache.unwrap_or(base.result_cache),
+        queue_timeout: match queue_timeout_seconds {
+            Some(t) => Some(queue_timeout_from_py(t)?),
+            None => base.queue_timeout,
+        },
+        tenant: tenant.or(base.tenant),
+        coverage: coverage.unwrap_or(base.coverage),
+        cprofile: cprofile.unwrap_or(base.cprofile),
+        display: display.unwrap_or(base.display),
+        trace: match trace {
+            Some(t) => TraceContext::from_map(&t).map_err(error::config_error)?,
+            None => base.trace,
+        },
+        timezone: timezone.or(base.timezone),
+        locale: locale.or(base.locale),
+    };
+
+    if !config.workdir.starts_with('/') || config.workdir.matches('/').count() > 1 {
+        return Err(error::config_error(
+            "workdir must be a top-level directory (e.g., /work)".to_string(),
+        ));
+    }
+
+    let files_in_vec: Vec<FileInput> = files_in
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(host, guest)| FileInput {
+            host_path: std::path::PathBuf::from(host),
+            guest_path: guest,
+        })
+        .collect();
+
+    let expect_vec: Vec<FileOutput> = expect
+        .unwrap_or_default()
+        .into_iter()
+        .map(|pat| FileOutput { pattern: pat })
+        .collect();
+
+    let result = py.allow_threads(|| {
+        let runner = VMRunner::new();
+        runner.execute_python_code(&code, &config, files_in_vec, expect_vec)
+    });
+
+    match result {
+        Ok(execution_result) => execution_result_to_py(py, execution_result),
+        Err(e) => Err(error::to_py_err("Execution error", e)),
+    }
+}
+
+#[pyfunction]
+fn run_with_config(py: Python, code: String, config: &Bound<PyDict>) -> PyResult<PyObject> {
+    let base = base_config(
+        config.get_item("profile")?.and_then(|v| v.extract::<String>().ok()).as_deref(),
+        config.get_item("config_file")?.and_then(|v| v.extract::<String>().ok()).as_deref(),
+    )?;
+    let image = config.get_item("image")?.and_then(|v| v.extract::<String>().ok()).or(base.image);
+    let cpus = config.get_item("cpus")?.and_then(|v| v.extract::<u32>().ok()).unwrap_or(base.cpus);
+    let memory_mb = config.get_item("memory_mb")?.and_then(|v| v.extract::<u32>().ok()).unwrap_or(base.memory_mb);
+    let env = config.get_item("env")?.and_then(|v| v.extract::<HashMap<String, String>>().ok()).unwrap_or(base.env);
+    let timeout = config.get_item("timeout_seconds")?.and_then(|v| v.extract::<u64>().ok()).map(Duration::from_secs).unwrap_or(base.timeout);
+    let workdir = config.get_item("workdir")?.and_then(|v| v.extract::<String>().ok()).unwrap_or(base.workdir);
+    let python_args = config.get_item("python_args")?.and_then(|v| v.extract::<Vec<String>>().ok()).unwrap_or(base.python_args);
+    let network = config.get_item("network")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.network);
+    let ports = config.get_item("ports")?.and_then(|v| v.extract::<Vec<(u16,u16)>>().ok()).unwrap_or(base.ports);
+    let allow_privileged_ports = config.get_item("allow_privileged_ports")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.allow_privileged_ports);
+    let files_in = config.get_item("files_in")?.and_then(|v| v.extract::<Vec<(String,String)>>().ok()).unwrap_or_default();
+    let expect = config.get_item("expect")?.and_then(|v| v.extract::<Vec<String>>().ok()).unwrap_or_default();
+    let max_bytes_inline = config.get_item("max_bytes_inline")?.and_then(|v| v.extract::<u64>().ok()).unwrap_or(base.max_bytes_inline);
+    let keep_vm = match config.get_item("keep_vm")?.and_then(|v| v.extract::<String>().ok()) {
+        Some(k) => KeepVm::parse(&k).map_err(error::config_error)?,
+        None => base.keep_vm,
+    };
+
+    let retry_policy = match config.get_item("retry_policy")? {
+        Some(v) => retry_policy_from_py(v.downcast::<PyDict>()?)?,
+        None => base.retry_policy,
+    };
+
+    let use_pool = config.get_item("use_pool")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.use_pool);
+    let pin_digest = config.get_item("pin_digest")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.pin_digest);
+    let requirements = config.get_item("requirements")?.and_then(|v| v.extract::<Vec<String>>().ok()).unwrap_or(base.requirements);
+    let proxy = match config.get_item("proxy")? {
+        Some(v) if !v.is_none() => Some(proxy_from_py(v.downcast::<PyDict>()?)?),
+        _ => base.proxy,
+    };
+    let egress = config
+        .get_item("allow_egress_ports")?
+        .and_then(|v| v.extract::<Vec<u16>>().ok())
+        .map(|p| EgressPorts::new(&p))
+        .transpose()
+        .map_err(error::config_error)?
+        .or(base.egress);
+    let capture = config
+        .get_item("capture")?
+        .and_then(|v| v.extract::<String>().ok())
+        .map(|c| CaptureMode::parse(&c))
+        .transpose()
+        .map_err(error::config_error)?
+        .unwrap_or(base.capture);
+    let merge_streams = config.get_item("merge_streams")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.merge_streams);
+    let run_as = config.get_item("run_as")?.and_then(|v| v.extract::<(u32, u32)>().ok()).or(base.run_as);
+    let rlimits = config
+        .get_item("rlimits")?
+        .and_then(|v| v.extract::<HashMap<String, u64>>().ok())
+        .map(|r| Rlimits::from_map(&r))
+        .transpose()
+        .map_err(error::config_error)?
+        .unwrap_or(base.rlimits);
+    let seccomp = match config.get_item("seccomp")? {
+        Some(s) => Some(seccomp_from_py(py, &s)?),
+        None => base.seccomp,
+    };
+    let secrets = config
+        .get_item("secrets")?
+        .and_then(|v| v.extract::<HashMap<String, String>>().ok())
+        .unwrap_or_default();
+    let secrets = Secrets::new(secrets).map_err(error::config_error)?;
+    let wheel_dir = config.get_item("wheel_dir")?.and_then(|v| v.extract::<String>().ok()).map(std::path::PathBuf::from).or(base.wheel_dir);
+    let use_uv = config.get_item("use_uv")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.use_uv);
+    let pip_cache = config.get_item("pip_cache")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.pip_cache);
+    let labels = config.get_item("labels")?.and_then(|v| v.extract::<HashMap<String, String>>().ok()).unwrap_or_default();
+    let labels = base.labels.into_iter().chain(labels).collect();
+    let result_cache = config.get_item("result_cache")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.result_cache);
+    let queue_timeout = match config.get_item("queue_timeout_seconds")?.and_then(|v| v.extract::<f64>().ok()) {
+        Some(t) => Some(queue_timeout_from_py(t)?),
+        None => base.queue_timeout,
+    };
+    let tenant = config.get_item("tenant")?.and_then(|v| v.extract::<String>().ok()).or(base.tenant);
+    let coverage = config.get_item("coverage")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.coverage);
+    let cprofile = config.get_item("cprofile")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.cprofile);
+    let display = config.get_item("display")?.and_then(|v| v.extract::<bool>().ok()).unwrap_or(base.display);
+    let trace = config
+        .get_item("trace")?
+        .and_then(|v| v.extract::<HashMap<String, String>>().ok())
+        .map(|t| TraceContext::from_map(&t))
+        .transpose()
+        .map_err(error::config_error)?
+        .unwrap_or(base.trace);
+    let timezone = config.get_item("timezone")?.and_then(|v| v.extract::<String>().ok()).or(base.timezone);
+    let locale = config.get_item("locale")?.and_then(|v| v.extract::<String>().ok()).or(base.locale);
+
+    let vm_config = VMConfig {
+        image,
+        cpus,
+        memory_mb,
+        env,
+        workdir,
+        timeout,
+        network,
+        ports,
+        allow_privileged_ports,
+        python_args,
+        max_bytes_inline,
+        keep_vm,
+        retry_policy,
+        use_pool,
+        pin_digest,
+        requirements,
+        wheel_dir,
+        use_uv,
+        pip_cache,
+        proxy,
+        egress,
+        capture,
+        merge_streams,
+        run_as,
+        rlimits,
+        seccomp,
+        secrets,
+        labels,
+        result_cache,
+        queue_timeout,
+        tenant,
+        coverage,
+        cprofile,
+        display,
+        trace,
+        timezone,
+        locale,
+    };
+
+    if !vm_config.workdir.starts_with('/') || vm_config.workdir.matches('/').count() > 1 {
+        return Err(error::config_error("workdir must be top-level (e.g., /work)".to_string()));
+    }
+
+    let files_in_vec: Vec<FileInput> = files_in
+        .into_iter()
+        .map(|(host, guest)| FileInput {
+            host_path: std::path::PathBuf::from(host),
+            guest_path: guest,
+        })
+        .collect();
+
+    let expect_vec: Vec<FileOutput> = expect.into_iter().map(|pat| FileOutput { pattern: pat }).collect();
+
+    let result = py.allow_threads(|| {
+        let runner = VMRunner::new();
+        runner.execute_python_code(&code, &vm_config, files_in_vec, expect_vec)
+    });
+
+    match result {
+        Ok(execution_result) => execution_result_to_py(py, execution_result),
+        Err(e) => Err(error::to_py_err("Execution error", e)),
+    }
+}
+
+/// Settings `run` starts from before its arguments (see `profiles::base_config`).
+fn base_config(profile: Option<&str>, config_file: Option<&str>) -> PyResult<VMConfig> {
+    profiles::base_config(profile, config_file.map(std::path::Path::new))
+        .map_err(|e| error::to_py_err("Invalid configuration", e))
+}
+
+/// Register (or replace) a named profile for `run(..., profile=name)`. `settings` uses the
+/// config-file keys.
+#[pyfunction]
+fn register_profile(py: Python, name: String, settings: &Bound<PyDict>) -> PyResult<()> {
+    let json: String = py.import_bound("json")?.call_method1("dumps", (settings,))?.extract()?;
+    let profile: config::VMConfigFile = serde_json::from_str(&json)
+        .map_err(|e| error::config_error(format!("invalid profile '{}': {}", name, e)))?;
+    // Catch bad values now rather than on the first run that uses the profile
+    profile.clone().apply(&mut VMConfig::default()).map_err(|e| error::to_py_err("Invalid profile", e))?;
+    profiles::register(&name, profile).map_err(|e| error::to_py_err("Invalid profile", e))
+}
+
+/// Register (or replace) tenant `name` for `run(..., tenant=name)`. Keys: `image_namespace`,
+/// `cache_dir`, `max_vms`, `max_memory_mb`, `max_queue`, `max_storage_mb`, `policy` and
+/// `rate_limit`.
+#[pyfunction]
+#[pyo3(signature = (name, settings=None))]
+fn register_tenant(py: Python, name: String, settings: Option<&Bound<PyDict>>) -> PyResult<()> {
+    let tenant = match settings {
+        Some(settings) => {
+            let json: String = py.import_bound("json")?.call_method1("dumps", (settings,))?.extract()?;
+            serde_json::from_str(&json).map_err(|e| error::config_error(format!("invalid tenant '{}': {}", name, e)))?
+        }
+        None => Default::default(),
+    };
+    tenants::register(&name, tenant).map_err(|e| error::to_py_err("Invalid tenant", e))
+}
+
+/// Replace the host's sandbox policy; None removes every limit. Keys: `max_cpus`,
+/// `max_memory_mb`, `max_timeout_seconds`, `allow_network`, `allowed_registries`,
+/// `require_digest`, `allowed_digests` and `allowed_packages`.
+#[pyfunction]
+#[pyo3(signature = (policy=None))]
+fn configure_policy(py: Python, policy: Option<&Bound<PyDict>>) -> PyResult<()> {
+    let policy = match policy {
+        Some(policy) => {
+            let json: String = py.import_bound("json")?.call_method1("dumps", (policy,))?.extract()?;
+            serde_json::from_str(&json).map_err(|e| error::config_error(format!("invalid policy: {}", e)))?
+        }
+        None => Default::default(),
+    };
+    policy::configure(policy);
+    Ok(())
+}
+
+/// `before(ctx)` and `after(ctx, result)` callables given to `register_hook`.
+struct PyHook {
+    before: Option<PyObject>,
+    after: Option<PyObject>,
+}
+
+impl hooks::RunHook for PyHook {
+    /// A dict `before` returns is applied like a config file; an exception vetoes the run.
+    fn before_run(&self, ctx: &hooks::RunContext, config: &mut VMConfig) -> Result<(), String> {
+        let Some(before) = &self.before else { return Ok(()) };
+        let changes = Python::with_gil(|py| -> PyResult<Option<String>> {
+            let returned = before.call1(py, (hook_context(py, ctx, config)?,))?;
+            if returned.is_none(py) {
+                return Ok(None);
+            }
+            Ok(Some(py.import_bound("json")?.call_method1("dumps", (returned,))?.extract()?))
+        })
+        .map_err(|e| e.to_string())?;
+        if let Some(json) = changes {
+            let changes: config::VMConfigFile =
+                serde_json::from_str(&json).map_err(|e| format!("invalid settings returned: {}", e))?;
+            changes.apply(config).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// `after` may return a dict with a new `stdout` and/or `stderr`.
+    fn after_run(&self, ctx: &hooks::RunContext, config: &VMConfig, result: &mut ExecutionResult) -> Result<(), String> {
+        let Some(after) = &self.after else { return Ok(()) };
+        Python::with_gil(|py| -> PyResult<()> {
+            let summary = execution_result_to_py(py, result.clone())?;
+            let returned = after.call1(py, (hook_context(py, ctx, config)?, summary))?;
+            if returned.is_none(py) {
+                return Ok(());
+            }
+            for (key, value) in returned.downcast_bound::<PyDict>(py)?.iter() {
+                match key.extract::<String>()?.as_str() {
+                    "stdout" => result.stdout = value.extract()?,
+                    "stderr" => result.stderr = value.extract()?,
+                    other => {
+                        return Err(error::config_error(format!("after hooks can only replace stdout and stderr, not '{}'", other)))
+                    }
+                }
+            }
+            Ok(())
+        })
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// The run as hooks see it: its ID and code, plus the settings most hooks care about
+fn hook_context(py: Python, ctx: &hooks::RunContext, config: &VMConfig) -> PyResult<PyObject> {
+    let value = serde_json::json!({
+        "run_id": ctx.run_id,
+        "code": ctx.code,
+        "tenant": config.tenant,
+        "labels": config.labels,
+        "image": config.image,
+        "cpus": config.cpus,
+        "memory_mb": config.memory_mb,
+        "timeout_seconds": config.timeout.as_secs_f64(),
+        "network": config.network,
+        "requirements": config.requirements,
+    });
+    Ok(py.import_bound("json")?.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
+/// Call `before(ctx)` ahead of every run and `after(ctx, result)` once it is collected,
+/// replacing any hook already registered as `name`.
+#[pyfunction]
+#[pyo3(signature = (name, before=None, after=None))]
+fn register_hook(name: String, before: Option<PyObject>, after: Option<PyObject>) -> PyResult<()> {
+    if before.is_none() && after.is_none() {
+        return Err(error::config_error("register_hook needs a before or an after callable"));
+    }
+    hooks::register(&name, std::sync::Arc::new(PyHook { before, after })).map_err(|e| error::to_py_err("Invalid hook", e))
+}
+
+/// Remove hook `name`; False when there was none.
+#[pyfunction]
+fn unregister_hook(name: String) -> bool {
+    hooks::unregister(&name)
+}
+
+/// Names of the registered hooks, in the order they run.
+#[pyfunction]
+fn list_hooks() -> Vec<String> {
+    hooks::names()
+}
+
+/// Every tenant as `{name: {image_namespace, cache_dir, max_storage_mb, rate_limit, scheduler}}`,
+/// with the namespace and cache directory its runs actually use.
+#[pyfunction]
+fn list_tenants(py: Python) -> PyResult<PyObject> {
+    let host = image_cache::host_cache_config();
+    let mut out = serde_json::Map::new();
+    for (name, tenant) in tenants::all() {
+        let mut cache = host.clone();
+        tenant.overlay(&mut cache);
+        out.insert(
+            name,
+            serde_json::json!({
+                "image_namespace": cache.image_namespace,
+                "cache_dir": cache.cache_dir,
+                "max_storage_mb": tenant.settings().max_storage_mb,
+                "rate_limit": tenant.settings().rate_limit,
+                "scheduler": tenant.stats(),
+            }),
+        );
+    }
+    let json = serde_json::Value::Object(out).to_string();
+    Ok(py.import_bound("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// Every registered profile, as `{name: settings}` with only the keys each one sets.
+#[pyfunction]
+fn list_profiles(py: Python) -> PyResult<PyObject> {
+    let mut out = serde_json::Map::new();
+    for (name, profile) in profiles::all() {
+        let mut value = serde_json::to_value(&profile).map_err(|e| error::config_error(e.to_string()))?;
+        if let Some(map) = value.as_object_mut() {
+            map.retain(|_, v| !v.is_null());
+        }
+        out.insert(name, value);
+    }
+    let json = serde_json::Value::Object(out).to_string();
+    Ok(py.import_bound("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// Settings in effect for this process after the global config files, environment variables
+/// and `configure_*`/`set_*` calls, plus the files that were read and any that were ignored.
+#[pyfunction]
+fn effective_config(py: Python) -> PyResult<PyObject> {
+    let loaded = global_config::loaded();
+    let cache = image_cache::cache_config();
+    let pool = pool::vm_pool().config();
+    let run = VMConfig::default();
+    let value = serde_json::json!({
+        "config_files": loaded.files.iter().map(|p| p.to_string_lossy().to_string()).collect::<Vec<_>>(),
+        "errors": loaded.errors,
+        "storage_tool": storage_tool::StorageTool::current().binary(),
+        "cache_dir": cache.cache_dir,
+        "image_namespace": cache.image_namespace,
+        "offline": settings::offline(),
+        "preimport": settings::preimport(),
+        "pull_timeout_seconds": settings::pull_timeout().as_secs(),
+        "heartbeat_timeout_seconds": settings::heartbeat_timeout().map_or(0, |d| d.as_secs()),
+        "audit_log": audit::path().map(|p| p.to_string_lossy().to_string()),
+        "cache": {
+            "ttl_seconds": cache.cache_ttl_seconds,
+            "max_size_mb": cache.max_cache_size_mb,
+            "evict_before_import": cache.evict_before_import,
+            "pip_cache": cache.pip_cache,
+            "pip_cache_max_mb": cache.pip_cache_max_mb,
+            "result_ttl_seconds": cache.result_ttl_seconds,
+        },
+        "pool": { "max_idle_per_key": pool.max_idle_per_key, "max_uses": pool.max_uses },
+        "scheduler": concurrency::scheduler().config(),
+        "policy": policy::current(),
+        "rate_limit": rate_limit::current(),
+        "run": {
+            "image": run.image,
+            "cpus": run.cpus,
+            "memory_mb": run.memory_mb,
+            "timeout_seconds": run.timeout.as_secs_f64(),
+            "network": run.network,
+            "workdir": run.workdir,
+        },
+        "profiles": profiles::all().into_keys().collect::<Vec<_>>(),
+    });
+    Ok(py.import_bound("json")?.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
+/// A preset name ("default") or a dict with `deny` (syscall names) and `deny_raw_sockets`
+fn seccomp_from_py(py: Python, value: &Bound<PyAny>) -> PyResult<seccomp::SeccompProfile> {
+    let setting = match value.extract::<String>() {
+        Ok(name) => seccomp::SeccompSetting::Preset(name),
+        Err(_) => {
+            let json: String = py.import_bound("json")?.call_method1("dumps", (value,))?.extract()?;
+            let profile = serde_json::from_str(&json)
+                .map_err(|e| error::config_error(format!("invalid seccomp profile: {}", e)))?;
+            seccomp::SeccompSetting::Profile(profile)
+        }
+    };
+    setting.resolve().map_err(error::config_error)
+}
+
+/// Keys: http, https, no_proxy (str or list of str); all optional
+fn proxy_from_py(d: &Bound<PyDict>) -> PyResult<ProxyConfig> {
+    let mut proxy = ProxyConfig::default();
+    if let Some(v) = d.get_item("http")? { proxy.http = v.extract()?; }
+    if let Some(v) = d.get_item("https")? { proxy.https = v.extract()?; }
+    if let Some(v) = d.get_item("no_proxy")? {
+        proxy.no_proxy = match v.extract::<String>() {
+            Ok(s) => s.split(',').map(|h| h.trim().to_string()).filter(|h| !h.is_empty()).collect(),
+            Err(_) => v.extract()?,
+        };
+    }
+    for url in proxy.http.iter().chain(&proxy.https) {
+        if !url.contains("://") {
+            return Err(error::config_error(format!("proxy URL must include a scheme (e.g. http://): {}", url)));
+        }
+    }
+    Ok(proxy)
+}
+
+/// Keys: max_attempts, initial_backoff_ms, backoff_multiplier, max_backoff_ms, permanent_errors (all optional)
+fn retry_policy_from_py(d: &Bound<PyDict>) -> PyResult<RetryPolicy> {
+    let mut policy = RetryPolicy::default();
+    if let Some(v) = d.get_item("max_attempts")? { policy.max_attempts = v.extract()?; }
+    if let Some(v) = d.get_item("initial_backoff_ms")? { policy.initial_backoff = Duration::from_millis(v.extract()?); }
+    if let Some(v) = d.get_item("backoff_multiplier")? { policy.backoff_multiplier = v.extract()?; }
+    if let Some(v) = d.get_item("max_backoff_ms")? { policy.max_backoff = Duration::from_millis(v.extract()?); }
+    if let Some(v) = d.get_item("permanent_errors")? {
+        policy.permanent_errors = v
+            .extract()
+            .map_err(|_| error::config_error("retry_policy.permanent_errors must be a list of strings"))?;
+    }
+    if policy.max_attempts == 0 {
+        return Err(error::config_error("retry_policy.max_attempts must be at least 1".to_string()));
+    }
+    Ok(policy)
+}
+
+fn queue_timeout_from_py(seconds: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| error::config_error("queue_timeout_seconds must be a non-negative number of seconds"))
+}
+
+pub(crate) fn execution_result_to_py(py: Python, execution_result: ExecutionResult) -> PyResult<PyObject> {
+    let stdout = execution_result.stdout;
+    let mut stderr = execution_result.stderr;
+    let exit_code = execution_result.exit_code;
+
+    // Se houve erro e STDERR vier vazio (krunvm pode colapsar streams), duplica STDOUT.
+    // Output records come from separate pipes, so streams are already accurate with them.
+    if execution_result.output.is_none() && exit_code != 0 && stderr.trim().is_empty() && !stdout.trim().is_empty() {
+        stderr = stdout.clone();
+    }
+
+    let dict = PyDict::new_bound(py);
+    dict.set_item("stdout", stdout)?;
+    dict.set_item("stderr", stderr)?;
+    dict.set_item("exit_code", exit_code)?;
+    let exec_ms = std::cmp::max(1, execution_result.execution_time.as_millis() as u64);
+    dict.set_item("execution_time_ms", exec_ms)?;
+    dict.set_item("image_used", execution_result.image_used)?;
+    dict.set_item("run_id", execution_result.run_id)?;
+    dict.set_item("attempts", execution_result.attempts)?;
+    dict.set_item("cached", execution_result.cached)?;
+    dict.set_item("peak_memory_bytes", execution_result.peak_memory_bytes)?;
+    match execution_result.kept_vm {
+        Some(kept) => {
+            let k = PyDict::new_bound(py);
+            k.set_item("vm_name", kept.vm_name)?;
+            k.set_item("workspace", kept.workspace.to_string_lossy().to_string())?;
+            k.set_item("input_dir", kept.workspace.join("in").to_string_lossy().to_string())?;
+            k.set_item("output_dir", kept.workspace.join("out").to_string_lossy().to_string())?;
+            dict.set_item("kept_vm", k)?;
+        }
+        None => dict.set_item("kept_vm", py.None())?,
+    }
+    match execution_result.output {
+        Some(records) => {
+            let list = PyList::empty_bound(py);
+            for r in records {
+                let d = PyDict::new_bound(py);
+                d.set_item("stream", r.stream)?;
+                d.set_item("ts", r.ts)?;
+                d.set_item("chunk", r.chunk)?;
+                list.append(d)?;
+            }
+            dict.set_item("output", list)?;
+        }
+        None => dict.set_item("output", py.None())?,
+    }
+    dict.set_item("merged_output", execution_result.merged_output)?;
+    let json = serde_json::json!({
+        "coverage": execution_result.coverage,
+        "cprofile": execution_result.cprofile,
+        "display": execution_result.display,
+        "value": execution_result.value,
+        "timings": execution_result.timings.to_json(),
+    })
+    .to_string();
+    let reports = py.import_bound("json")?.call_method1("loads", (json,))?;
+    dict.call_method1("update", (reports,))?;
+
+    let artifacts_py = PyList::empty_bound(py);
+    for a in execution_result.artifacts {
+        let a_dict = PyDict::new_bound(py);
+        a_dict.set_item("guest_path", a.guest_path)?;
+        a_dict.set_item("host_path", a.host_path.to_string_lossy().to_string())?;
+        a_dict.set_item("size_bytes", a.size_bytes)?;
+        if let Some(content) = a.content {
+            a_dict.set_item("content", Py::new(py, PyArtifactContent { content })?)?;
+        }
+        artifacts_py.append(a_dict)?;
+    }
+    dict.set_item("artifacts", artifacts_py)?;
+    Ok(dict.into())
+}
+
+/// Inline artifact bytes, still backed by the file mapping: nothing is copied into Python until `read` or
+/// `bytes()` asks for it, so a large artifact that's only sliced or measured stays out of the heap.
+#[pyclass(name = "ArtifactContent", module = "flashvm", frozen)]
+struct PyArtifactContent {
+    content: ArtifactContent,
+}
+
+#[pymethods]
+impl PyArtifactContent {
+    /// Copy out `size` bytes starting at `offset` (the rest of the content when `size` is omitted).
+    #[pyo3(signature = (offset=0, size=None))]
+    fn read<'py>(&self, py: Python<'py>, offset: usize, size: Option<usize>) -> Bound<'py, PyBytes> {
+        let start = offset.min(self.content.len());
+        let end = size.map_or(self.content.len(), |size| start.saturating_add(size).min(self.content.len()));
+        PyBytes::new_bound(py, &self.content[start..end])
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.content)
+    }
+
+    fn __len__(&self) -> usize {
+        self.content.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ArtifactContent({} bytes)", self.content.len())
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    image=None,
+    packages=None,
+    tag=None,
+    index_url=None,
+    extra_index_url=None,
+    proxy=None,
+    requirements_file=None,
+    constraints_file=None,
+    wheel_dir=None,
+    use_uv=false,
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn prepare_image(
+    py: Python,
+    image: Option<String>,
+    packages: Option<Vec<String>>, 
+    tag: Option<String>,
+    index_url: Option<String>,
+    extra_index_url: Option<String>,
+    proxy: Option<Bound<PyDict>>,
+    requirements_file: Option<String>,
+    constraints_file: Option<String>,
+    wheel_dir: Option<String>,
+    use_uv: bool,
+    progress: Option<PyObject>,
+) -> PyResult<bool> {
+    let proxy = proxy.as_ref().map(proxy_from_py).transpose()?;
+    let reqs = (packages.is_some() || requirements_file.is_some()).then(|| PipRequirements {
+        packages: packages.unwrap_or_default(),
+        requirements_file: requirements_file.map(std::path::PathBuf::from),
+        constraints_file: constraints_file.map(std::path::PathBuf::from),
+        wheel_dir: wheel_dir.map(std::path::PathBuf::from),
+        use_uv,
+        labels: vec![],
+    });
+    let progress = build_progress_from_py(progress);
+    let result: Result<bool, InternalVMError> = py.allow_threads(|| {
+        let resolver = ImageResolver::new().with_progress(progress);
+        match (image, reqs) {
+            (None, None) => {
+                // Import embedded image (idempotent)
+                resolver.import_embedded_now()?;
+                Ok(true)
+            }
+            (Some(img), None) => {
+                // Validate and pre-pull docker-like refs (keeps behavior)
+                let validated = resolver.resolve_image_ref(Some(&img))?;
+                let is_docker_like = img.starts_with("docker://") || !img.starts_with("oci:");
+                if is_docker_like {
+                    let runner = VMRunner::new();
+                    runner.pre_pull_image(&validated)?;
+                }
+                Ok(true)
+            }
+            // With packages: layer pip installs on top of base (embedded or provided)
+            (img_opt, Some(reqs)) => {
+                let base = img_opt.as_deref();
+                // Default tag: overwrite canonical so image=None uses the baked image next runs
+                let target_tag = tag.as_deref().unwrap_or("python-basic");
+                let _new_img = resolver.pip_install_into_image(
+                    base,
+                    &reqs,
+                    Some(target_tag),
+                    index_url.as_deref(),
+                    extra_index_url.as_deref(),
+                    proxy.as_ref(),
+                )?;
+                Ok(true)
+            }
+        }
+    });
+
+    match result {
+        Ok(v) => Ok(v),
+        Err(e) => Err(error::to_py_err("Error preparing image", e)),
+    }
+}
+
+/// Image reference for a Python version, e.g. `resolve_base("3.11")`; pass it as `image=`.
+#[pyfunction]
+#[pyo3(signature = (python=None))]
+fn resolve_base(py: Python, python: Option<String>) -> PyResult<String> {
+    py.allow_threads(|| ImageResolver::new().resolve_base(python.as_deref()))
+        .map_err(|e| error::to_py_err("Error resolving base image", e))
+}
+
+/// Time `runs` runs of a standard snippet and return p50/p95 per phase (see `bench::run`).
+#[pyfunction]
+#[pyo3(name = "bench", signature = (runs=10, image=None, use_pool=false, profile=None, config_file=None))]
+fn run_bench(
+    py: Python,
+    runs: usize,
+    image: Option<String>,
+    use_pool: bool,
+    profile: Option<String>,
+    config_file: Option<String>,
+) -> PyResult<PyObject> {
+    let mut config = base_config(profile.as_deref(), config_file.as_deref())?;
+    if image.is_some() {
+        config.image = image;
+    }
+    config.use_pool |= use_pool;
+    let report = py.allow_threads(|| crate::bench::run(&config, runs)).map_err(|e| error::to_py_err("Bench error", e))?;
+    let json = serde_json::json!(report).to_string();
+    Ok(py.import_bound("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// Pre-pull several images concurrently to warm the host. `progress`, when given, is called
+/// with a dict (`image`, `event`, `layers`, `bytes`, `error`) as each image advances.
+/// Returns `{image: None}` for images that are ready, or the error message for those that failed.
+#[pyfunction]
+#[pyo3(signature = (images, max_parallel=4, progress=None))]
+fn pre_pull_images(
+    py: Python,
+    images: Vec<String>,
+    max_parallel: usize,
+    progress: Option<PyObject>,
+) -> PyResult<PyObject> {
+    let report = |p: PullProgress| {
+        let Some(cb) = &progress else { return };
+        Python::with_gil(|py| {
+            let call = || -> PyResult<()> {
+                let event = PyDict::new_bound(py);
+                event.set_item("image", &p.image)?;
+                event.set_item("event", p.event)?;
+                event.set_item("layers", p.layers)?;
+                event.set_item("bytes", p.bytes)?;
+                event.set_item("error", &p.error)?;
+                cb.call1(py, (event,))?;
+                Ok(())
+            };
+            // A broken callback must not abort the pulls
+            if let Err(e) = call() {
+                log::warn!("pre_pull_images progress callback failed: {}", e);
+            }
+        })
+    };
+    let results = py.allow_threads(|| VMRunner::new().pre_pull_images(&images, max_parallel, &report));
+    let dict = PyDict::new_bound(py);
+    for (image, result) in images.iter().zip(results) {
+        dict.set_item(image, result.err().map(|e| e.to_string()))?;
+    }
+    Ok(dict.into())
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    packages=None,
+    base_image=None,
+    tag=None,
+    index_url=None,
+    extra_index_url=None,
+    proxy=None,
+    requirements_file=None,
+    constraints_file=None,
+    wheel_dir=None,
+    use_uv=false,
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn pip_prepare_image(
+    packages: Option<Vec<String>>,
+    base_image: Option<String>,
+    tag: Option<String>,
+    index_url: Option<String>,
+    extra_index_url: Option<String>,
+    proxy: Option<Bound<PyDict>>,
+    requirements_file: Option<String>,
+    constraints_file: Option<String>,
+    wheel_dir: Option<String>,
+    use_uv: bool,
+    progress: Option<PyObject>,
+) -> PyResult<String> {
+    let proxy = proxy.as_ref().map(proxy_from_py).transpose()?;
+    let reqs = PipRequirements {
+        packages: packages.unwrap_or_default(),
+        requirements_file: requirements_file.map(std::path::PathBuf::from),
+        constraints_file: constraints_file.map(std::path::PathBuf::from),
+        wheel_dir: wheel_dir.map(std::path::PathBuf::from),
+        use_uv,
+        labels: vec![],
+    };
+    let resolver = ImageResolver::new().with_progress(build_progress_from_py(progress));
+    let image = resolver
+        .pip_install_into_image(
+            base_image.as_deref(),
+            &reqs,
+            tag.as_deref(),
+            index_url.as_deref(),
+            extra_index_url.as_deref(),
+            proxy.as_ref(),
+        )
+        .map_err(|e| error::to_py_err("pip_prepare_image error", e))?;
+    Ok(image)
+}
+
+#[pyfunction]
+#[pyo3(signature = (packages=None, base_image=None, tag=None, env_file=None, channels=None, proxy=None, progress=None))]
+#[allow(clippy::too_many_arguments)]
+fn conda_prepare_image(
+    py: Python,
+    packages: Option<Vec<String>>,
+    base_image: Option<String>,
+    tag: Option<String>,
+    env_file: Option<String>,
+    channels: Option<Vec<String>>,
+    proxy: Option<Bound<PyDict>>,
+    progress: Option<PyObject>,
+) -> PyResult<String> {
+    let proxy = proxy.as_ref().map(proxy_from_py).transpose()?;
+    let spec = CondaSpec {
+        packages: packages.unwrap_or_default(),
+        env_file: env_file.map(std::path::PathBuf::from),
+        channels: channels.unwrap_or_default(),
+    };
+    let progress = build_progress_from_py(progress);
+    py.allow_threads(|| {
+        ImageResolver::new().with_progress(progress).conda_install_into_image(base_image.as_deref(), &spec, tag.as_deref(), proxy.as_ref())
+    })
+    .map_err(|e| error::to_py_err("conda_prepare_image error", e))
+}
+
+#[pyfunction]
+#[pyo3(signature = (packages, base_image=None, tag=None, proxy=None, progress=None))]
+fn os_prepare_image(
+    py: Python,
+    packages: Vec<String>,
+    base_image: Option<String>,
+    tag: Option<String>,
+    proxy: Option<Bound<PyDict>>,
+    progress: Option<PyObject>,
+) -> PyResult<String> {
+    let proxy = proxy.as_ref().map(proxy_from_py).transpose()?;
+    let progress = build_progress_from_py(progress);
+    py.allow_threads(|| {
+        ImageResolver::new().with_progress(progress).os_install_into_image(base_image.as_deref(), &packages, tag.as_deref(), proxy.as_ref())
+    })
+    .map_err(|e| error::to_py_err("os_prepare_image error", e))
+}
+
+#[pyfunction]
+#[pyo3(signature = (path, context_dir=None, tag=None, build_args=None, proxy=None, progress=None))]
+#[allow(clippy::too_many_arguments)]
+fn build_from_containerfile(
+    py: Python,
+    path: String,
+    context_dir: Option<String>,
+    tag: Option<String>,
+    build_args: Option<HashMap<String, String>>,
+    proxy: Option<Bound<PyDict>>,
+    progress: Option<PyObject>,
+) -> PyResult<String> {
+    let proxy = proxy.as_ref().map(proxy_from_py).transpose()?;
+    let build_args = build_args.unwrap_or_default();
+    let progress = build_progress_from_py(progress);
+    py.allow_threads(|| {
+        ImageResolver::new().with_progress(progress).build_from_containerfile(
+            std::path::Path::new(&path),
+            context_dir.as_deref().map(std::path::Path::new),
+            tag.as_deref(),
+            &build_args,
+            proxy.as_ref(),
+        )
+    })
+    .map_err(|e| error::to_py_err("build_from_containerfile error", e))
+}
+
+#[pyfunction]
+#[pyo3(signature = (path, base_image=None, tag=None, proxy=None, use_uv=false, wheel_dir=None, progress=None))]
+#[allow(clippy::too_many_arguments)]
+fn build_from_project(
+    py: Python,
+    path: String,
+    base_image: Option<String>,
+    tag: Option<String>,
+    proxy: Option<Bound<PyDict>>,
+    use_uv: bool,
+    wheel_dir: Option<String>,
+    progress: Option<PyObject>,
+) -> PyResult<String> {
+    let proxy = proxy.as_ref().map(proxy_from_py).transpose()?;
+    let progress = build_progress_from_py(progress);
+    py.allow_threads(|| {
+        ImageResolver::new().with_progress(progress).build_from_project(
+            std::path::Path::new(&path),
+            base_image.as_deref(),
+            tag.as_deref(),
+            proxy.as_ref(),
+            use_uv,
+            wheel_dir.as_deref().map(std::path::Path::new),
+        )
+    })
+    .map_err(|e| error::to_py_err("build_from_project error", e))
+}
+
+/// Build output goes to a Python callable, one line per call; its exceptions are logged and
+/// dropped so a broken callback can't fail the build.
+fn build_progress_from_py(progress: Option<PyObject>) -> Option<image_resolver::BuildProgress> {
+    let cb = progress?;
+    Some(std::sync::Arc::new(move |line: &str| {
+        Python::with_gil(|py| {
+            if let Err(e) = cb.call1(py, (line,)) {
+                log::warn!("build progress callback failed: {}", e);
+            }
+        })
+    }))
+}
+
+/// `auth` is `{"username", "password"}` or `{"authfile"}`.
+fn registry_auth_from_py(d: &Bound<PyDict>) -> PyResult<RegistryAuth> {
+    if let Some(path) = d.get_item("authfile")? {
+        return Ok(RegistryAuth::AuthFile(std::path::PathBuf::from(path.extract::<String>()?)));
+    }
+    match (d.get_item("username")?, d.get_item("password")?) {
+        (Some(u), Some(p)) => Ok(RegistryAuth::Credentials { username: u.extract()?, password: p.extract()? }),
+        _ => Err(error::config_error(
+            "auth must have 'username' and 'password', or 'authfile'".to_string(),
+        )),
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (local_name, remote_ref, auth=None))]
+fn push_image(py: Python, local_name: String, remote_ref: String, auth: Option<Bound<PyDict>>) -> PyResult<String> {
+    let auth = auth.as_ref().map(registry_auth_from_py).transpose()?;
+    py.allow_threads(|| ImageResolver::new().push_image(&local_name, &remote_ref, auth.as_ref()))
+        .map_err(|e| error::to_py_err("push_image error", e))
+}
+
+#[pyfunction]
+#[pyo3(signature = (image=None, packages=false))]
+fn inspect_image(py: Python, image: Option<String>, packages: bool) -> PyResult<PyObject> {
+    let info = py
+        .allow_threads(|| ImageResolver::new().inspect_image(image.as_deref(), packages))
+        .map_err(|e| error::to_py_err("inspect_image error", e))?;
+    let d = PyDict::new_bound(py);
+    d.set_item("name", info.name)?;
+    d.set_item("id", info.id)?;
+    d.set_item("created", info.created)?;
+    d.set_item("architecture", info.architecture)?;
+    d.set_item("os", info.os)?;
+    d.set_item("labels", info.labels)?;
+    d.set_item("layers", info.layers)?;
+    d.set_item("python_version", info.python_version)?;
+    match info.packages {
+        Some(pkgs) => {
+            let list = PyList::empty_bound(py);
+            for (name, version) in pkgs {
+                let p = PyDict::new_bound(py);
+                p.set_item("name", name)?;
+                p.set_item("version", version)?;
+                list.append(p)?;
+            }
+            d.set_item("packages", list)?;
+        }
+        None => d.set_item("packages", py.None())?,
+    }
+    Ok(d.into())
+}
+
+#[pyfunction]
+fn list_cached_images(py: Python) -> PyResult<PyObject> {
+    let result = py.allow_threads(|| {
+        let resolver = ImageResolver::new();
+        resolver.list_cached_images()
+    });
+    let images = result.map_err(|e| error::to_py_err("Error listing images", e))?;
+    let list = PyList::empty_bound(py);
+    for img in images {
+        let d = PyDict::new_bound(py);
+        d.set_item("name", &img.name)?;
+        d.set_item("kind", img.kind.as_str())?;
+        d.set_item("size_bytes", img.size_bytes)?;
+        d.set_item("created_at", img.created_at.to_rfc3339())?;
+        d.set_item("last_used", img.last_used.to_rfc3339())?;
+        d.set_item("pip_freeze", img.pip_freeze)?;
+        let scan = image_cache::ImageCache::new().scan(&img.name).ok().flatten();
+        d.set_item("scan", scan.map(|r| scan_report_to_py(py, &r)).transpose()?)?;
+        list.append(d)?;
+    }
+    Ok(list.into())
+}
+
+/// Set (or with `command=None`, remove) the vulnerability scanner run after builds and
+/// pulls, and the severity at which `run` refuses an image.
+#[pyfunction]
+#[pyo3(signature = (command=None, fail_on=None))]
+fn configure_scan(command: Option<Vec<String>>, fail_on: Option<String>) -> PyResult<()> {
+    let fail_on = fail_on
+        .map(|f| {
+            scan::Severity::parse(&f).ok_or_else(|| {
+                error::config_error(format!("invalid fail_on '{}': use critical, high, medium, low or unknown", f))
+            })
+        })
+        .transpose()?;
+    let config = match command {
+        Some(command) if command.is_empty() => {
+            return Err(error::config_error("scan command must not be empty"));
+        }
+        Some(command) => Some(scan::ScanConfig { command, fail_on }),
+        None if fail_on.is_some() => {
+            return Err(error::config_error("fail_on needs a scan command"));
+        }
+        None => None,
+    };
+    scan::configure(config);
+    Ok(())
+}
+
+/// Append a JSON line per run to `path` (None turns the audit log off).
+#[pyfunction]
+#[pyo3(signature = (path=None))]
+fn configure_audit(path: Option<String>) {
+    audit::configure(path.map(std::path::PathBuf::from));
+}
+
+/// Scan a local image now with the configured scanner; the report is also recorded.
+#[pyfunction]
+fn scan_image(py: Python, image: String) -> PyResult<PyObject> {
+    let report = py.allow_threads(|| scan::scan_image(&image)).map_err(|e| error::to_py_err("scan_image error", e))?;
+    scan_report_to_py(py, &report)
+}
+
+fn scan_report_to_py(py: Python, report: &scan::ScanReport) -> PyResult<PyObject> {
+    let d = PyDict::new_bound(py);
+    d.set_item("image_id", &report.image_id)?;
+    d.set_item("scanner", &report.scanner)?;
+    d.set_item("scanned_at", report.scanned_at.to_rfc3339())?;
+    d.set_item("counts", report.counts())?;
+    d.set_item("worst", report.worst().map(|s| s.as_str()))?;
+    let findings = PyList::empty_bound(py);
+    for f in &report.findings {
+        let fd = PyDict::new_bound(py);
+        fd.set_item("id", &f.id)?;
+        fd.set_item("package", &f.package)?;
+        fd.set_item("version", &f.version)?;
+        fd.set_item("severity", f.severity.as_str())?;
+        findings.append(fd)?;
+    }
+    d.set_item("findings", findings)?;
+    Ok(d.into())
+}
+
+#[pyfunction]
+fn remove_cached_image(py: Python, name: String) -> PyResult<bool> {
+    py.allow_threads(|| ImageResolver::new().remove_cached_image(&name))
+        .map_err(|e| error::to_py_err("Error removing image", e))
+}
+
+#[pyfunction]
+fn clear_cache(py: Python) -> PyResult<bool> {
+    let result = py.allow_threads(|| {
+        let resolver = ImageResolver::new();
+        resolver.clear_cache()
+    });
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) => Err(error::to_py_err("Error clearing cache", e)),
+    }
+}
+
+/// Remove every stored run result; returns how many there were.
+#[pyfunction]
+fn clear_result_cache(py: Python) -> PyResult<usize> {
+    py.allow_threads(result_cache::clear).map_err(|e| error::to_py_err("Error clearing result cache", e))
+}
+
+#[pyfunction]
+fn list_runs(py: Python) -> PyResult<PyObject> {
+    let result = py.allow_threads(|| RunRegistry::new().list());
+    let runs = result.map_err(|e| error::to_py_err("Error listing runs", e))?;
+    let list = PyList::empty_bound(py);
+    for r in runs {
+        let d = PyDict::new_bound(py);
+        let timeout = r.timeout();
+        d.set_item("run_id", r.run_id)?;
+        d.set_item("vm_name", r.vm_name)?;
+        d.set_item("owner_pid", r.owner_pid)?;
+        d.set_item("image", r.image)?;
+        d.set_item("started_at", r.started_at.to_rfc3339())?;
+        d.set_item("kept", r.kept)?;
+        d.set_item("paused", r.paused)?;
+        d.set_item("timeout_seconds", timeout.as_secs_f64())?;
+        list.append(d)?;
+    }
+    Ok(list.into())
+}
+
+#[pyfunction]
+fn kill(py: Python, run_id: String) -> PyResult<bool> {
+    let result = py.allow_threads(|| RunRegistry::new().kill(&run_id));
+    result.map_err(|e| error::to_py_err(&format!("Error killing run {}", run_id), e))
+}
+
+/// Suspend a running VM where it is, until `resume`; the paused time doesn't count toward its timeout.
+#[pyfunction]
+fn pause(py: Python, run_id: String) -> PyResult<bool> {
+    let result = py.allow_threads(|| RunRegistry::new().pause(&run_id));
+    result.map_err(|e| error::to_py_err(&format!("Error pausing run {}", run_id), e))
+}
+
+#[pyfunction]
+fn resume(py: Python, run_id: String) -> PyResult<bool> {
+    let result = py.allow_threads(|| RunRegistry::new().resume(&run_id));
+    result.map_err(|e| error::to_py_err(&format!("Error resuming run {}", run_id), e))
+}
+
+/// Give a running execution `seconds` more before it times out; recorded in its audit record.
+#[pyfunction]
+fn extend_timeout(py: Python, run_id: String, seconds: u64) -> PyResult<bool> {
+    if seconds == 0 {
+        return Err(error::config_error("seconds must be at least 1"));
+    }
+    let result = py.allow_threads(|| RunRegistry::new().extend_timeout(&run_id, seconds));
+    result.map_err(|e| error::to_py_err(&format!("Error extending run {}", run_id), e))
+}
+
+#[pyfunction]
+#[pyo3(signature = (run_id, shell=None))]
+fn attach(py: Python, run_id: String, shell: Option<String>) -> PyResult<i32> {
+    let shell = shell.unwrap_or_else(|| "/bin/sh".to_string());
+    let result = py.allow_threads(|| VMRunner::new().attach(&run_id, &shell));
+    result.map_err(|e| error::to_py_err(&format!("Error attaching to run {}", run_id), e))
+}
+
+/// Cap how many VMs this process runs at once (extra `run` calls wait for a slot).
+#[pyfunction]
+fn set_max_concurrent_vms(limit: usize) -> PyResult<()> {
+    if limit == 0 {
+        return Err(error::config_error("limit must be at least 1".to_string()));
+    }
+    let scheduler = concurrency::scheduler();
+    scheduler.configure(concurrency::SchedulerConfig { max_vms: limit, ..scheduler.config() });
+    Ok(())
+}
+
+/// Set the scheduler's limits; arguments left out keep their current values.
+#[pyfunction]
+#[pyo3(signature = (max_vms=None, max_memory_mb=None, max_queue=None))]
+fn configure_scheduler(max_vms: Option<usize>, max_memory_mb: Option<u64>, max_queue: Option<usize>) -> PyResult<()> {
+    if max_vms == Some(0) {
+        return Err(error::config_error("max_vms must be at least 1".to_string()));
+    }
+    let scheduler = concurrency::scheduler();
+    let current = scheduler.config();
+    scheduler.configure(concurrency::SchedulerConfig {
+        max_vms: max_vms.unwrap_or(current.max_vms),
+        max_memory_mb: max_memory_mb.unwrap_or(current.max_memory_mb),
+        max_queue: max_queue.unwrap_or(current.max_queue),
+    });
+    Ok(())
+}
+
+/// Set how many runs each tenant, and each daemon API key, may start per minute, with bursts
+/// of up to `burst`; arguments left out keep their current values and 0 runs per minute
+/// turns the limit off.
+#[pyfunction]
+#[pyo3(signature = (runs_per_minute=None, burst=None))]
+fn configure_rate_limit(runs_per_minute: Option<u32>, burst: Option<u32>) -> PyResult<()> {
+    if burst == Some(0) {
+        return Err(error::config_error("burst must be at least 1".to_string()));
+    }
+    let current = rate_limit::current();
+    rate_limit::configure(rate_limit::RateLimit {
+        runs_per_minute: runs_per_minute.unwrap_or(current.runs_per_minute),
+        burst: burst.or(current.burst),
+    });
+    Ok(())
+}
+
+/// Limits, usage and queue counters of the host's scheduler, or of `tenant`'s.
+#[pyfunction]
+#[pyo3(signature = (tenant=None))]
+fn scheduler_stats(py: Python, tenant: Option<String>) -> PyResult<PyObject> {
+    let stats = match tenant {
+        Some(name) => tenants::get(&name).map_err(|e| error::to_py_err("Unknown tenant", e))?.stats(),
+        None => concurrency::scheduler().stats(),
+    };
+    let value = serde_json::to_value(stats).unwrap_or_default();
+    Ok(py.import_bound("json")?.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
+#[pyfunction]
+#[pyo3(signature = (max_idle_per_key=None, max_uses=None))]
+fn configure_pool(max_idle_per_key: Option<usize>, max_uses: Option<u32>) -> PyResult<()> {
+    let mut config = pool::vm_pool().config();
+    if let Some(max) = max_idle_per_key {
+        config.max_idle_per_key = max;
+    }
+    if let Some(max) = max_uses {
+        config.max_uses = max;
+    }
+    pool::vm_pool().configure(config);
+    Ok(())
+}
+
+#[pyfunction]
+#[pyo3(signature = (ttl_seconds=None, max_size_mb=None, evict_before_import=None, image_namespace=None, pip_cache=None, pip_cache_max_mb=None, cache_dir=None, result_ttl_seconds=None))]
+#[allow(clippy::too_many_arguments)]
+fn configure_cache(
+    ttl_seconds: Option<u64>,
+    max_size_mb: Option<u64>,
+    evict_before_import: Option<bool>,
+    image_namespace: Option<String>,
+    pip_cache: Option<bool>,
+    pip_cache_max_mb: Option<u64>,
+    cache_dir: Option<String>,
+    result_ttl_seconds: Option<u64>,
+) -> PyResult<()> {
+    let mut config = image_cache::host_cache_config();
+    if let Some(dir) = cache_dir {
+        config.cache_dir = image_cache::validate_cache_dir(&dir)
+            .map_err(|e| error::to_py_err("Invalid cache configuration", e))?;
+    }
+    if let Some(ns) = image_namespace {
+        image_cache::validate_namespace(&ns).map_err(|e| error::to_py_err("Invalid cache configuration", e))?;
+        config.image_namespace = ns;
+    }
+    if let Some(ttl) = ttl_seconds {
+        config.cache_ttl_seconds = ttl;
+    }
+    if let Some(max) = max_size_mb {
+        config.max_cache_size_mb = max;
+    }
+    if let Some(evict) = evict_before_import {
+        config.evict_before_import = evict;
+    }
+    if let Some(enabled) = pip_cache {
+        config.pip_cache = enabled;
+    }
+    if let Some(max) = pip_cache_max_mb {
+        config.pip_cache_max_mb = max;
+    }
+    if let Some(ttl) = result_ttl_seconds {
+        config.result_ttl_seconds = ttl;
+    }
+    image_cache::configure(config);
+    Ok(())
+}
+
+/// Evict cached images per the TTL and size budget; returns the names removed.
+#[pyfunction]
+fn evict_cache(py: Python) -> PyResult<Vec<String>> {
+    py.allow_threads(|| image_cache::ImageCache::new().evict(&image_cache::cache_config()))
+        .map_err(|e| error::to_py_err("Error evicting cache", e))
+}
+
+/// Remove least recently used derived images (pip/conda/os/Containerfile builds and `oci:`
+/// imports) older than `max_age` seconds, then until they fit `max_total_mb`; returns the
+/// names removed. The embedded image is never collected.
+#[pyfunction]
+#[pyo3(signature = (max_total_mb=None, max_age=None))]
+fn gc_images(py: Python, max_total_mb: Option<u64>, max_age: Option<f64>) -> PyResult<Vec<String>> {
+    if max_age.is_some_and(|a| !a.is_finite() || a < 0.0) {
+        return Err(error::config_error("max_age must be a non-negative number of seconds"));
+    }
+    let max_age_seconds = max_age.map(|a| a.ceil() as u64).unwrap_or(0);
+    py.allow_threads(|| image_cache::ImageCache::new().gc_derived(max_age_seconds, max_total_mb.unwrap_or(0)))
+        .map_err(|e| error::to_py_err("Error collecting images", e))
+}
+
+/// Turn offline (air-gapped) mode on or off for this process. While on, anything that would
+/// reach a registry or package index fails instead.
+#[pyfunction]
+#[pyo3(signature = (enabled=true))]
+fn set_offline(enabled: bool) {
+    settings::set_offline(enabled);
+}
+
+#[pyfunction]
+fn is_offline() -> bool {
+    settings::offline()
+}
+
+/// Turn reproducible builds on or off for this process. While on, every build pins its
+/// timestamps to `source_date_epoch` (default: the `SOURCE_DATE_EPOCH` environment variable,
+/// else 0).
+#[pyfunction]
+#[pyo3(signature = (enabled=true, source_date_epoch=None))]
+fn set_reproducible(enabled: bool, source_date_epoch: Option<u64>) {
+    let epoch = source_date_epoch.or_else(settings::env_source_date_epoch).unwrap_or(0);
+    settings::set_source_date_epoch(enabled.then_some(epoch));
+}
+
+/// The `SOURCE_DATE_EPOCH` builds are pinned to, or None when reproducible builds are off.
+#[pyfunction]
+fn source_date_epoch() -> Option<u64> {
+    settings::source_date_epoch()
+}
+
+/// Delete all idle pooled VMs; returns how many were removed.
+#[pyfunction]
+fn drain_pool(py: Python) -> PyResult<usize> {
+    Ok(py.allow_threads(|| pool::vm_pool().drain()))
+}
+
+#[pyfunction]
+fn doctor(py: Python) -> PyResult<PyObject> {
+    let dict: Bound<'_, PyDict> = PyDict::new_bound(py);
+
+    let checks = diagnostics::run_checks();
+    let passed = |name: &str| checks.iter().any(|c| c.name == name && c.ok);
+    let krunvm_available = passed("krunvm");
+    let buildah_available = passed("buildah");
+    let skopeo_available = passed("skopeo");
+    // On macOS the hypervisor check is Hypervisor.framework's; the flag keeps its name
+    let kvm_available = passed(if cfg!(target_os = "macos") { "hvf" } else { "kvm" });
+
+    let offline_available = wheel_resources::WheelResources::check_embedded_image_available(py, "python-basic")
+        .unwrap_or(false);
+
+    let mut embedded_imported = false;
+    if offline_available {
+        if let Ok(resolver) = std::panic::catch_unwind(ImageResolver::new) {
+            if let Ok(imported) = resolver.embedded_is_imported() {
+                embedded_imported = imported;
+            }
+        }
+    }
+
+    let mut offline_message: Option<String> = None;
+    if !offline_available {
+        if let Ok(Some(oci_path)) = wheel_resources::WheelResources::find_embedded_data_path(py) {
+            let layout = oci_path.join("oci-layout");
+            let index = oci_path.join("index.json");
+            let blobs = oci_path.join("blobs").join("sha256");
+            let mut missing = vec![];
+            if !layout.exists() { missing.push("oci-layout"); }
+            if !index.exists() { missing.push("index.json"); }
+            if !blobs.exists() { missing.push("blobs/sha256"); }
+            if !missing.is_empty() {
+                offline_message = Some(format!(
+                    "Embedded OCI layout incomplete at {}: missing {}",
+                    oci_path.to_string_lossy(),
+                    missing.join(", ")
+                ));
+            } else {
+                offline_message = Some("Failed to validate embedded OCI layout".to_string());
+            }
+        } else {
+            offline_message = Some("Embedded OCI image not found (flashvm/data/oci)".to_string());
+        }
+    }
+
+    dict.set_item("krunvm", krunvm_available)?;
+    dict.set_item("buildah", buildah_available)?;
+    dict.set_item("skopeo", skopeo_available)?;
+    dict.set_item("kvm", kvm_available)?;
+    dict.set_item("offline_mode", offline_available)?;
+    dict.set_item("offline_enforced", settings::offline())?;
+    dict.set_item("storage_tool", storage_tool::StorageTool::current().binary())?;
+    dict.set_item("arch", platform::host_arch())?;
+    dict.set_item("embedded_imported", embedded_imported)?;
+    if !skopeo_available {
+        dict.set_item("note", "skopeo not found; import will use buildah fallback and may be slower")?;
+    }
+    if let Some(msg) = offline_message { dict.set_item("offline_message", msg)?; }
+
+    let check_list = PyList::empty_bound(py);
+    let hints = PyList::empty_bound(py);
+    for check in &checks {
+        let entry = PyDict::new_bound(py);
+        entry.set_item("name", check.name)?;
+        entry.set_item("ok", check.ok)?;
+        entry.set_item("required", diagnostics::is_required(check.name))?;
+        entry.set_item("detail", &check.detail)?;
+        entry.set_item("hint", &check.hint)?;
+        check_list.append(entry)?;
+        if let (false, Some(hint)) = (check.ok, &check.hint) {
+            hints.append(format!("{}: {}", check.name, hint))?;
+        }
+    }
+    dict.set_item("checks", check_list)?;
+    dict.set_item("hints", hints)?;
+    dict.set_item("ready", checks.iter().all(|c| c.ok || !diagnostics::is_required(c.name)))?;
+
+    Ok(dict.into())
+}
+
+#[pymodule]
+#[pyo3(name = "_core")]
+fn flashvm(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    logging::init_from_env();
+    telemetry::init_from_env();
+    global_config::loaded();
+    settings::env_defaults();
+    // Only worth it when runs default to the embedded image
+    if settings::preimport() && VMConfig::default().image.is_none() {
+        image_resolver::import_embedded_in_background();
+    }
+    error::register_exceptions(m)?;
+    m.add_class::<PyArtifactContent>()?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    m.add_function(wrap_pyfunction!(run_with_config, m)?)?;
+    m.add_function(wrap_pyfunction!(register_profile, m)?)?;
+    m.add_function(wrap_pyfunction!(list_profiles, m)?)?;
+    m.add_function(wrap_pyfunction!(register_tenant, m)?)?;
+    m.add_function(wrap_pyfunction!(list_tenants, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_policy, m)?)?;
+    m.add_function(wrap_pyfunction!(register_hook, m)?)?;
+    m.add_function(wrap_pyfunction!(unregister_hook, m)?)?;
+    m.add_function(wrap_pyfunction!(list_hooks, m)?)?;
+    m.add_function(wrap_pyfunction!(effective_config, m)?)?;
+    m.add_function(wrap_pyfunction!(prepare_image, m)?)?;
+    m.add_function(wrap_pyfunction!(pre_pull_images, m)?)?;
+    m.add_function(wrap_pyfunction!(run_bench, m)?)?;
+    m.add_function(wrap_pyfunction!(resolve_base, m)?)?;
+    m.add_function(wrap_pyfunction!(pip_prepare_image, m)?)?;
+    m.add_function(wrap_pyfunction!(conda_prepare_image, m)?)?;
+    m.add_function(wrap_pyfunction!(os_prepare_image, m)?)?;
+    m.add_function(wrap_pyfunction!(build_from_containerfile, m)?)?;
+    m.add_function(wrap_pyfunction!(build_from_project, m)?)?;
+    m.add_function(wrap_pyfunction!(push_image, m)?)?;
+    m.add_function(wrap_pyfunction!(inspect_image, m)?)?;
+    m.add_function(wrap_pyfunction!(list_cached_images, m)?)?;
+    m.add_function(wrap_pyfunction!(remove_cached_image, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_scan, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_audit, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_result_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(evict_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(gc_images, m)?)?;
+    m.add_function(wrap_pyfunction!(set_offline, m)?)?;
+    m.add_function(wrap_pyfunction!(is_offline, m)?)?;
+    m.add_function(wrap_pyfunction!(set_reproducible, m)?)?;
+    m.add_function(wrap_pyfunction!(source_date_epoch, m)?)?;
+    m.add_function(wrap_pyfunction!(list_runs, m)?)?;
+    m.add_function(wrap_pyfunction!(kill, m)?)?;
+    m.add_function(wrap_pyfunction!(pause, m)?)?;
+    m.add_function(wrap_pyfunction!(resume, m)?)?;
+    m.add_function(wrap_pyfunction!(extend_timeout, m)?)?;
+    m.add_function(wrap_pyfunction!(attach, m)?)?;
+    m.add_function(wrap_pyfunction!(set_max_concurrent_vms, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_scheduler, m)?)?;
+    m.add_function(wrap_pyfunction!(scheduler_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_rate_limit, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_pool, m)?)?;
+    m.add_function(wrap_pyfunction!(drain_pool, m)?)?;
+    m.add_function(wrap_pyfunction!(doctor, m)?)?;
+    m.add_function(wrap_pyfunction!(find_embedded_data_path, m)?)?;
+    Ok(())
+}
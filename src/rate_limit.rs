@@ -0,0 +1,118 @@
+use crate::error::VMError;
+use crate::global_config;
+use crate::tenants;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Past this many callers, buckets that have refilled are forgotten
+const MAX_BUCKETS: usize = 1024;
+
+/// A token bucket per caller: `burst` runs back to back, refilled at `runs_per_minute`.
+/// 0 runs per minute means no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    #[serde(default)]
+    pub runs_per_minute: u32,
+    /// Defaults to `runs_per_minute`
+    pub burst: Option<u32>,
+}
+
+impl RateLimit {
+    fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.runs_per_minute).max(1) as f64
+    }
+
+    fn per_second(&self) -> f64 {
+        self.runs_per_minute as f64 / 60.0
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    limit: RateLimit,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let earned = now.duration_since(self.updated).as_secs_f64() * limit.per_second();
+        self.tokens = (self.tokens + earned).min(limit.capacity());
+        self.updated = now;
+        self.limit = limit;
+    }
+}
+
+fn buckets() -> &'static Mutex<HashMap<String, Bucket>> {
+    static BUCKETS: OnceLock<Mutex<HashMap<String, Bucket>>> = OnceLock::new();
+    BUCKETS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The limit for each caller: the global config's `[rate_limit]` until `configure_rate_limit`
+/// replaces it. Tenants can have their own.
+fn host_limit() -> &'static Mutex<RateLimit> {
+    static LIMIT: OnceLock<Mutex<RateLimit>> = OnceLock::new();
+    LIMIT.get_or_init(|| Mutex::new(global_config::global().rate_limit.unwrap_or_default()))
+}
+
+pub fn current() -> RateLimit {
+    *host_limit().lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "python")]
+pub fn configure(limit: RateLimit) {
+    *host_limit().lock().unwrap_or_else(|e| e.into_inner()) = limit;
+}
+
+/// Take a run from `caller`'s bucket, or fail with how long until one is there.
+fn acquire(caller: &str, limit: RateLimit) -> Result<(), VMError> {
+    if limit.runs_per_minute == 0 {
+        return Ok(());
+    }
+    let now = Instant::now();
+    let mut buckets = buckets().lock().unwrap_or_else(|e| e.into_inner());
+    if buckets.len() >= MAX_BUCKETS {
+        buckets.retain(|_, b| {
+            let limit = b.limit;
+            b.refill(limit, now);
+            b.tokens < limit.capacity()
+        });
+    }
+    let bucket = buckets
+        .entry(caller.to_string())
+        .or_insert(Bucket { tokens: limit.capacity(), updated: now, limit });
+    bucket.refill(limit, now);
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        return Ok(());
+    }
+    let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second());
+    Err(VMError::RateLimited(
+        format!("no runs left for {} (limit: {} per minute)", caller, limit.runs_per_minute),
+        retry_after,
+    ))
+}
+
+/// Charge a run to the current tenant, under its own limit or else the host's.
+pub fn enforce_tenant() -> Result<(), VMError> {
+    match tenants::current() {
+        Some(tenant) => {
+            let limit = tenant.settings().rate_limit.unwrap_or_else(current);
+            acquire(&format!("tenant '{}'", tenant.name), limit)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Charge a daemon request to the API key it was made with; requests without one share a
+/// bucket. Only a digest of the key is kept.
+#[cfg(feature = "daemon")]
+pub fn enforce_api_key(key: Option<&str>) -> Result<(), VMError> {
+    let caller = match key {
+        Some(key) => format!("API key {}", &sha256::digest(key)[..12]),
+        None => "unauthenticated callers".to_string(),
+    };
+    acquire(&caller, current())
+}
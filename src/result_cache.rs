@@ -0,0 +1,196 @@
+use crate::config::{ArtifactContent, ExecutionResult, FileInput, FileOutput, VMConfig};
+use crate::error::VMError;
+use crate::image_cache;
+use crate::image_resolver;
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// A stored result, with its artifacts' contents in `files/<index>` next to it.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    stored_at: DateTime<Utc>,
+    result: ExecutionResult,
+}
+
+fn results_dir() -> PathBuf {
+    PathBuf::from(image_cache::cache_config().cache_dir).join("results")
+}
+
+/// Key of a run: the image's content digest, the code, the input files' contents and every
+/// option that can change the outcome. None when the image has no digest yet (a registry
+/// image that isn't pulled), so the run can't be looked up.
+pub fn key(
+    image: &str,
+    code: &str,
+    config: &VMConfig,
+    files_in: &[FileInput],
+    expect: &[FileOutput],
+) -> Result<Option<String>, VMError> {
+    let Some(image_digest) = image_digest(image) else { return Ok(None) };
+    let mut files = Vec::new();
+    for file in files_in {
+        let content = fs::read(&file.host_path).map_err(|e| {
+            VMError::Validation(format!("cannot read input file {}: {}", file.host_path.display(), e))
+        })?;
+        files.push((file.guest_path.as_str(), sha256::digest(content.as_slice())));
+    }
+    let env: BTreeMap<_, _> = config.env.iter().collect();
+    let material = json!({
+        "flashvm": env!("CARGO_PKG_VERSION"),
+        "image": image_digest,
+        "code": sha256::digest(code),
+        "files": files,
+        "expect": expect.iter().map(|e| e.pattern.as_str()).collect::<Vec<_>>(),
+        "cpus": config.cpus,
+        "memory_mb": config.memory_mb,
+        "env": env,
+        "workdir": config.workdir,
+        "timeout_ms": config.timeout.as_millis() as u64,
+        "network": config.network,
+        "ports": config.ports,
+        "python_args": config.python_args,
+        "requirements": config.requirements,
+        "wheel_dir": config.wheel_dir,
+        "use_uv": config.use_uv,
+        "proxy": format!("{:?}", config.proxy),
+        "egress": format!("{:?}", config.egress),
+        "capture": format!("{:?}", config.effective_capture()),
+        "merge_streams": config.merge_streams,
+        "run_as": config.run_as,
+        "rlimits": config.rlimits,
+        "seccomp": format!("{:?}", config.seccomp),
+        "secrets": config.secrets.fingerprint(),
+        "coverage": config.coverage,
+        "cprofile": config.cprofile,
+        "display": config.display,
+        "timezone": config.timezone,
+        "locale": config.locale,
+    });
+    Ok(Some(sha256::digest(material.to_string())))
+}
+
+/// Content digest of `image`: its ID in local storage, or for an `oci:` layout the hash of
+/// the layout's index.
+fn image_digest(image: &str) -> Option<String> {
+    match image.strip_prefix("oci:") {
+        Some(layout) => {
+            let dir = layout.rsplit_once(':').map_or(layout, |(dir, _tag)| dir);
+            let index = fs::read(Path::new(dir).join("index.json")).ok()?;
+            Some(format!("oci:{}", sha256::digest(index.as_slice())))
+        }
+        None => image_resolver::local_image_digest(image),
+    }
+}
+
+/// The stored result for `key`, if there is one younger than the TTL. Artifacts up to
+/// `max_inline` bytes come back inline; larger ones point into the cache.
+pub fn lookup(key: &str, max_inline: u64) -> Option<ExecutionResult> {
+    let dir = results_dir().join(key);
+    let data = fs::read(dir.join("entry.json")).ok()?;
+    let Ok(entry) = serde_json::from_slice::<Entry>(&data) else {
+        warn!("Removing unreadable result cache entry {}", dir.display());
+        let _ = fs::remove_dir_all(&dir);
+        return None;
+    };
+    let ttl = image_cache::cache_config().result_ttl_seconds;
+    let age = Utc::now().signed_duration_since(entry.stored_at).num_seconds().max(0) as u64;
+    if ttl > 0 && age > ttl {
+        debug!("Result cache entry {} expired", key);
+        let _ = fs::remove_dir_all(&dir);
+        return None;
+    }
+    let mut result = entry.result;
+    for (index, artifact) in result.artifacts.iter_mut().enumerate() {
+        artifact.host_path = dir.join("files").join(index.to_string());
+        if artifact.size_bytes <= max_inline {
+            artifact.content = Some(ArtifactContent::map(&artifact.host_path).ok()?);
+        }
+    }
+    result.cached = true;
+    Some(result)
+}
+
+/// Store `result` under `key` unless an entry is already there. Entries are write-once,
+/// because `lookup` maps their files: they are only ever removed, never rewritten. The entry
+/// is written to a temporary directory and renamed into place, so readers never see half of
+/// one. Expired entries are removed on the way.
+pub fn store(key: &str, result: &ExecutionResult) -> Result<(), VMError> {
+    let root = results_dir();
+    fs::create_dir_all(&root)?;
+    remove_expired(&root);
+    let dest = root.join(key);
+    if dest.exists() {
+        return Ok(());
+    }
+    let tmp = root.join(format!(".tmp-{}", Uuid::new_v4()));
+    let written = write_entry(&tmp, result).and_then(|_| match fs::rename(&tmp, &dest) {
+        Ok(()) => Ok(()),
+        // Another run stored the same key first; keep its entry
+        Err(_) if dest.exists() => Ok(()),
+        Err(e) => Err(e.into()),
+    });
+    if tmp.exists() {
+        let _ = fs::remove_dir_all(&tmp);
+    }
+    written
+}
+
+/// Entries (and abandoned temporary directories) last renamed into place longer than the
+/// TTL ago.
+fn remove_expired(root: &Path) {
+    let ttl = image_cache::cache_config().result_ttl_seconds;
+    let Ok(entries) = fs::read_dir(root) else { return };
+    if ttl == 0 {
+        return;
+    }
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age.as_secs() > ttl);
+        if expired {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+fn write_entry(dir: &Path, result: &ExecutionResult) -> Result<(), VMError> {
+    let files = dir.join("files");
+    fs::create_dir_all(&files)?;
+    let mut stored = result.clone();
+    for (index, artifact) in stored.artifacts.iter_mut().enumerate() {
+        let path = files.join(index.to_string());
+        match artifact.content.take() {
+            Some(content) => fs::write(&path, content)?,
+            None => fs::copy(&artifact.host_path, &path).map(|_| ())?,
+        }
+        artifact.host_path = PathBuf::new();
+    }
+    let entry = Entry { stored_at: Utc::now(), result: stored };
+    let data = serde_json::to_vec(&entry).map_err(|e| VMError::Cache(e.to_string()))?;
+    fs::write(dir.join("entry.json"), data)?;
+    Ok(())
+}
+
+/// Remove every stored result; returns how many there were.
+#[cfg(feature = "python")]
+pub fn clear() -> Result<usize, VMError> {
+    let root = results_dir();
+    let Ok(entries) = fs::read_dir(&root) else { return Ok(0) };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        fs::remove_dir_all(entry.path())?;
+        if !entry.file_name().to_string_lossy().starts_with(".tmp-") {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
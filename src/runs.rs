@@ -0,0 +1,319 @@
+use crate::concurrency::FileLock;
+use crate::error::VMError;
+use crate::image_cache;
+#[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+use crate::policy;
+#[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+use crate::secrets;
+#[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+use crate::unshare;
+use chrono::{DateTime, Utc};
+#[cfg(any(feature = "python", feature = "http"))]
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// In-flight execution, persisted under `<cache_dir>/runs/<run_id>.json` so other processes can see it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub vm_name: String,
+    /// Process that owns the run (the one that called `run`)
+    pub owner_pid: u32,
+    /// Process group of the `buildah unshare` child driving krunvm (0 until spawned)
+    pub pgid: i32,
+    pub image: String,
+    pub started_at: DateTime<Utc>,
+    /// The run's own timeout, before any extension
+    #[serde(default)]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub timeout_extensions: Vec<TimeoutExtension>,
+    #[serde(default)]
+    pub killed: bool,
+    /// Stopped with `pause` and not resumed yet
+    #[serde(default)]
+    pub paused: bool,
+    /// Run finished but its VM/workspace were kept (keep-alive mode); never pruned automatically
+    #[serde(default)]
+    pub kept: bool,
+    #[serde(default)]
+    pub workspace: Option<PathBuf>,
+}
+
+impl RunRecord {
+    /// Time added to the run's timeout with `extend_timeout`
+    pub fn extension(&self) -> Duration {
+        Duration::from_secs(self.timeout_extensions.iter().map(|e| e.seconds).sum())
+    }
+
+    /// The run's timeout, extensions included
+    #[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms) + self.extension()
+    }
+}
+
+/// One `extend_timeout` of a run, as its audit record keeps it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeoutExtension {
+    pub ts: DateTime<Utc>,
+    pub seconds: u64,
+    /// The process that extended the run and its user
+    pub pid: u32,
+    pub uid: u32,
+}
+
+pub struct RunRegistry {
+    dir: PathBuf,
+}
+
+impl RunRegistry {
+    pub fn new() -> Self {
+        // One registry for the host, so runs of every tenant can be listed and killed
+        let dir = PathBuf::from(image_cache::host_cache_config().cache_dir).join("runs");
+        Self { dir }
+    }
+
+    pub fn new_run_id() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    pub fn vm_name_for(run_id: &str) -> String {
+        format!("flashvm-{}", run_id)
+    }
+
+    fn record_path(&self, run_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", run_id))
+    }
+
+    /// Held around every read-modify-write of a record, so concurrent updates from this or
+    /// other processes don't lose each other's changes
+    fn lock(&self, run_id: &str) -> Result<FileLock, VMError> {
+        FileLock::acquire(&self.lock_path(run_id))
+    }
+
+    fn lock_path(&self, run_id: &str) -> PathBuf {
+        self.dir.join(format!(".{}.lock", run_id))
+    }
+
+    pub fn register(&self, record: &RunRecord) -> Result<(), VMError> {
+        let _lock = self.lock(&record.run_id)?;
+        self.write(record)
+    }
+
+    fn write(&self, record: &RunRecord) -> Result<(), VMError> {
+        fs::create_dir_all(&self.dir)?;
+        let data = serde_json::to_vec_pretty(record).map_err(|e| VMError::Execution(e.to_string()))?;
+        // Write-then-rename so readers never observe a partial record
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        tmp.write_all(&data)?;
+        tmp.persist(self.record_path(&record.run_id)).map_err(|e| VMError::IO(e.error))?;
+        Ok(())
+    }
+
+    /// Change a record in place under its lock and return it as stored; None when no such run
+    /// is registered. Nothing is written when `f` fails.
+    fn update(
+        &self,
+        run_id: &str,
+        f: impl FnOnce(&mut RunRecord) -> Result<(), VMError>,
+    ) -> Result<Option<RunRecord>, VMError> {
+        let _lock = self.lock(run_id)?;
+        let Some(mut record) = self.get(run_id)? else { return Ok(None) };
+        f(&mut record)?;
+        self.write(&record)?;
+        Ok(Some(record))
+    }
+
+    pub fn get(&self, run_id: &str) -> Result<Option<RunRecord>, VMError> {
+        let path = self.record_path(run_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path)?;
+        let record = serde_json::from_slice(&data)
+            .map_err(|e| VMError::Execution(format!("Corrupt run record {}: {}", path.to_string_lossy(), e)))?;
+        Ok(Some(record))
+    }
+
+    /// Record the process group driving the run; returns whether the run was killed meanwhile.
+    pub fn set_pgid(&self, run_id: &str, pgid: i32) -> Result<bool, VMError> {
+        let record = self.update(run_id, |r| {
+            r.pgid = pgid;
+            Ok(())
+        })?;
+        Ok(record.is_some_and(|r| r.killed))
+    }
+
+    pub fn remove(&self, run_id: &str) {
+        let _lock = self.lock(run_id);
+        let _ = fs::remove_file(self.record_path(run_id));
+        let _ = fs::remove_file(self.lock_path(run_id));
+    }
+
+    /// Mark a finished run as kept so it stays listed (and killable) after its owner exits.
+    pub fn mark_kept(&self, run_id: &str, workspace: &Path) -> Result<(), VMError> {
+        self.update(run_id, |r| {
+            r.kept = true;
+            r.pgid = 0;
+            r.paused = false;
+            r.workspace = Some(workspace.to_path_buf());
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Running (or kept) executions across all processes; records whose owner died are pruned.
+    #[cfg(any(feature = "python", feature = "http"))]
+    pub fn list(&self) -> Result<Vec<RunRecord>, VMError> {
+        let mut runs = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(e) => e,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(runs),
+            Err(e) => return Err(VMError::IO(e)),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(run_id) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+            match self.get(run_id) {
+                Ok(Some(record)) => {
+                    if record.kept || process_alive(record.owner_pid as i32) {
+                        runs.push(record);
+                    } else {
+                        debug!("Pruning stale run record {}", record.run_id);
+                        self.remove(&record.run_id);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("{}", e),
+            }
+        }
+        runs.sort_by_key(|r| r.started_at);
+        Ok(runs)
+    }
+
+    /// Kill a running execution: SIGKILL its process group and delete the krunvm VM.
+    /// For kept runs this also removes the workspace and the record.
+    /// Returns false when no such run is registered.
+    #[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+    pub fn kill(&self, run_id: &str) -> Result<bool, VMError> {
+        // Once this is stored, a process group recorded later is killed by its runner
+        let killed = self.update(run_id, |r| {
+            r.killed = true;
+            Ok(())
+        })?;
+        let Some(record) = killed else { return Ok(false) };
+
+        if record.pgid > 0 {
+            // SAFETY: plain syscall; a stale pgid just yields ESRCH
+            unsafe {
+                libc::kill(-record.pgid, libc::SIGKILL);
+            }
+        }
+        let forced = unshare::run_argv(&["krunvm", "delete", "-f", &record.vm_name])
+            .map(|o| o.success())
+            .unwrap_or(false);
+        if !forced {
+            let _ = unshare::run_argv(&["krunvm", "delete", &record.vm_name]);
+        }
+        secrets::remove_dir(&record.vm_name);
+        if record.kept {
+            if let Some(ws) = &record.workspace {
+                let _ = fs::remove_dir_all(ws);
+            }
+            self.remove(run_id);
+        }
+        Ok(true)
+    }
+
+    pub fn was_killed(&self, run_id: &str) -> bool {
+        matches!(self.get(run_id), Ok(Some(r)) if r.killed)
+    }
+
+    /// Suspend a running execution in place: SIGSTOP its process group, which stops krunvm's
+    /// vCPU threads with the guest's memory as it is. Returns false when no such run is
+    /// registered.
+    #[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+    pub fn pause(&self, run_id: &str) -> Result<bool, VMError> {
+        self.set_paused(run_id, true)
+    }
+
+    /// Let a paused execution carry on where it stopped (SIGCONT). Returns false when no such
+    /// run is registered.
+    #[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+    pub fn resume(&self, run_id: &str) -> Result<bool, VMError> {
+        self.set_paused(run_id, false)
+    }
+
+    #[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+    fn set_paused(&self, run_id: &str, paused: bool) -> Result<bool, VMError> {
+        let updated = self.update(run_id, |r| {
+            if r.pgid <= 0 {
+                let state = if r.kept { "was kept after it finished" } else { "hasn't started its VM yet" };
+                return Err(VMError::Execution(format!("run {} {}; only a running VM can be paused", run_id, state)));
+            }
+            r.paused = paused;
+            Ok(())
+        })?;
+        let Some(record) = updated else { return Ok(false) };
+        let signal = if paused { libc::SIGSTOP } else { libc::SIGCONT };
+        // SAFETY: plain syscall on the group the run was started in
+        if unsafe { libc::kill(-record.pgid, signal) } != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ESRCH) {
+                // The VM exited between the lookup and the signal
+                return Ok(false);
+            }
+            return Err(VMError::IO(err));
+        }
+        Ok(true)
+    }
+
+    /// Give a running execution `seconds` more before it times out, within the sandbox policy's
+    /// `max_timeout_seconds`. Returns false when no such run is registered.
+    #[cfg(any(feature = "python", feature = "grpc", feature = "http"))]
+    pub fn extend_timeout(&self, run_id: &str, seconds: u64) -> Result<bool, VMError> {
+        let updated = self.update(run_id, |r| {
+            if r.kept {
+                let msg = format!("run {} was kept after it finished; it has no timeout left", run_id);
+                return Err(VMError::Execution(msg));
+            }
+            let timeout = r.timeout() + Duration::from_secs(seconds);
+            policy::enforce_timeout(timeout, r.tenant.as_deref())?;
+            r.timeout_extensions.push(TimeoutExtension {
+                ts: Utc::now(),
+                seconds,
+                pid: std::process::id(),
+                // SAFETY: getuid has no preconditions
+                uid: unsafe { libc::getuid() },
+            });
+            Ok(())
+        })?;
+        Ok(updated.is_some())
+    }
+}
+
+impl Default for RunRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(feature = "python", feature = "http"))]
+fn process_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: signal 0 performs only the permission/existence check
+    let rc = unsafe { libc::kill(pid, 0) };
+    rc == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
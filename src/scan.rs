@@ -0,0 +1,257 @@
+use crate::error::VMError;
+use crate::image_cache::ImageCache;
+use crate::storage_tool::StorageTool;
+use crate::unshare;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+/// Placeholder in the scan command for the OCI layout directory of the image
+const LAYOUT_PLACEHOLDER: &str = "{layout}";
+
+/// Findings listed in policy errors
+const LISTED_FINDINGS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Unknown,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// trivy (`CRITICAL`) and grype (`Critical`, `Negligible`) spellings
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "critical" => Some(Severity::Critical),
+            "high" => Some(Severity::High),
+            "medium" | "moderate" => Some(Severity::Medium),
+            "low" | "negligible" => Some(Severity::Low),
+            "unknown" => Some(Severity::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Unknown => "unknown",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Scanner to run after builds and pulls, and the severity at which `run` refuses an image.
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    /// argv; `{layout}` is replaced by the image's OCI layout directory (appended when absent)
+    pub command: Vec<String>,
+    pub fail_on: Option<Severity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub id: String,
+    pub package: String,
+    pub version: String,
+    pub severity: Severity,
+}
+
+/// Result of scanning one image, kept in the image cache manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanReport {
+    /// Image ID the report belongs to; a rebuilt image under the same name is scanned again
+    pub image_id: String,
+    pub scanner: String,
+    pub scanned_at: DateTime<Utc>,
+    /// Most severe first
+    pub findings: Vec<Finding>,
+}
+
+impl ScanReport {
+    pub fn counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for f in &self.findings {
+            *counts.entry(f.severity.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[cfg(feature = "python")]
+    pub fn worst(&self) -> Option<Severity> {
+        self.findings.iter().map(|f| f.severity).max()
+    }
+}
+
+fn settings() -> &'static Mutex<Option<ScanConfig>> {
+    static SCAN: OnceLock<Mutex<Option<ScanConfig>>> = OnceLock::new();
+    SCAN.get_or_init(|| Mutex::new(None))
+}
+
+pub fn config() -> Option<ScanConfig> {
+    settings().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(feature = "python")]
+pub fn configure(config: Option<ScanConfig>) {
+    *settings().lock().unwrap_or_else(|e| e.into_inner()) = config;
+}
+
+/// Scan `name` (a containers-storage image) with the configured scanner and record the
+/// report. Fails when no scanner is configured.
+pub fn scan_image(name: &str) -> Result<ScanReport, VMError> {
+    let config = config()
+        .ok_or_else(|| VMError::VMConfiguration("no scanner configured; call configure_scan first".to_string()))?;
+    let name = name.strip_prefix("containers-storage:").unwrap_or(name);
+    let image_id = image_id(name)?;
+
+    let tmp = tempfile::Builder::new().prefix("flashvm-scan-").tempdir()?;
+    let layout = tmp.path().join("layout");
+    let layout_s = layout.to_string_lossy().to_string();
+    let dest = format!("oci:{}", layout_s);
+    let argv = [StorageTool::current().binary(), "push", name, dest.as_str()];
+    let out = unshare::run_argv(&argv)?;
+    if !out.success() {
+        return Err(VMError::ImageResolution(format!("exporting {} for scanning failed: {}", name, out.stderr.trim()))
+            .with_command(&argv, &out.stderr));
+    }
+
+    let mut command: Vec<String> = config.command.iter().map(|a| a.replace(LAYOUT_PLACEHOLDER, &layout_s)).collect();
+    if !config.command.iter().any(|a| a.contains(LAYOUT_PLACEHOLDER)) {
+        command.push(layout_s.clone());
+    }
+    info!("Scanning {} with {}", name, command[0]);
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .output()
+        .map_err(|e| VMError::MissingDependency(format!("scanner {} could not be started: {}", command[0], e)))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let argv: Vec<&str> = command.iter().map(String::as_str).collect();
+    let findings = serde_json::from_str::<Value>(&stdout)
+        .ok()
+        .and_then(|doc| parse_findings(&doc))
+        .ok_or_else(|| {
+            VMError::Execution(format!(
+                "scanner {} exited with {} without trivy or grype JSON output: {}",
+                command[0],
+                output.status,
+                stderr.trim()
+            ))
+            .with_command(&argv, &stderr)
+        })?;
+
+    let report = ScanReport { image_id, scanner: command[0].clone(), scanned_at: Utc::now(), findings };
+    ImageCache::new().set_scan(name, report.clone())?;
+    Ok(report)
+}
+
+/// Scan a freshly built or pulled image when a scanner is configured. A failed scan is
+/// logged, not fatal: `enforce` scans again before the image is run.
+pub fn after_build_or_pull(name: &str) {
+    if config().is_none() {
+        return;
+    }
+    match scan_image(name) {
+        Ok(report) => info!("Scanned {}: {:?}", name, report.counts()),
+        Err(e) => warn!("Scanning {} failed: {}", name, e),
+    }
+}
+
+/// Refuse `name` when the policy's `fail_on` severity is set and its (recorded or fresh)
+/// scan has a finding at or above it. Scan failures refuse the image too.
+pub fn enforce(name: &str) -> Result<(), VMError> {
+    let Some(threshold) = config().and_then(|c| c.fail_on) else {
+        return Ok(());
+    };
+    let name = name.strip_prefix("containers-storage:").unwrap_or(name);
+    let current_id = match image_id(name) {
+        Ok(id) => id,
+        Err(_) => {
+            // krunvm would pull a registry image itself; it has to be scanned first
+            let argv = [StorageTool::current().binary(), "pull", name];
+            let out = unshare::run_argv(&argv)?;
+            if !out.success() {
+                return Err(VMError::ImageResolution(format!("pull failed: {}", out.stderr.trim()))
+                    .with_command(&argv, &out.stderr));
+            }
+            image_id(name)?
+        }
+    };
+    let report = match ImageCache::new().scan(name)? {
+        Some(report) if report.image_id == current_id => report,
+        _ => scan_image(name)
+            .map_err(|e| VMError::ImageResolution(format!("scan policy: cannot scan {}: {}", name, e)))?,
+    };
+    let blocking: Vec<&Finding> = report.findings.iter().filter(|f| f.severity >= threshold).collect();
+    if blocking.is_empty() {
+        return Ok(());
+    }
+    let listed: Vec<String> = blocking
+        .iter()
+        .take(LISTED_FINDINGS)
+        .map(|f| format!("{} ({} {}, {})", f.id, f.package, f.version, f.severity.as_str()))
+        .collect();
+    Err(VMError::ImageResolution(format!(
+        "scan policy: {} has {} finding(s) at or above {}: {}{}",
+        name,
+        blocking.len(),
+        threshold.as_str(),
+        listed.join(", "),
+        if blocking.len() > LISTED_FINDINGS { ", ..." } else { "" }
+    )))
+}
+
+fn image_id(name: &str) -> Result<String, VMError> {
+    let tool = StorageTool::current();
+    let mut argv = tool.inspect_image();
+    argv.extend(["--format", tool.id_format(), name]);
+    let out = unshare::run_argv(&argv)?;
+    if !out.success() || out.stdout.trim().is_empty() {
+        return Err(VMError::ImageResolution(format!("image {} is not in local storage", name))
+            .with_command(&argv, &out.stderr));
+    }
+    Ok(out.stdout.trim().to_string())
+}
+
+/// Findings from trivy (`Results[].Vulnerabilities[]`) or grype (`matches[]`) JSON output,
+/// most severe first; None for anything else.
+fn parse_findings(doc: &Value) -> Option<Vec<Finding>> {
+    let str_at = |v: &Value, path: &[&str]| -> String {
+        path.iter().try_fold(v, |v, k| v.get(*k)).and_then(Value::as_str).unwrap_or("").to_string()
+    };
+    let mut findings = Vec::new();
+    if let Some(results) = doc.get("Results").and_then(Value::as_array) {
+        for vuln in results.iter().filter_map(|r| r.get("Vulnerabilities")?.as_array()).flatten() {
+            findings.push(Finding {
+                id: str_at(vuln, &["VulnerabilityID"]),
+                package: str_at(vuln, &["PkgName"]),
+                version: str_at(vuln, &["InstalledVersion"]),
+                severity: Severity::parse(&str_at(vuln, &["Severity"])).unwrap_or(Severity::Unknown),
+            });
+        }
+    } else if let Some(matches) = doc.get("matches").and_then(Value::as_array) {
+        for m in matches {
+            findings.push(Finding {
+                id: str_at(m, &["vulnerability", "id"]),
+                package: str_at(m, &["artifact", "name"]),
+                version: str_at(m, &["artifact", "version"]),
+                severity: Severity::parse(&str_at(m, &["vulnerability", "severity"])).unwrap_or(Severity::Unknown),
+            });
+        }
+    } else if doc.get("SchemaVersion").is_none() {
+        // trivy omits `Results` for a clean image but always has `SchemaVersion`
+        return None;
+    }
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.id.cmp(&b.id)));
+    Some(findings)
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_os = "linux")]
+mod nr {
+    pub use libc::{
+        SYS_ptrace, SYS_process_vm_readv, SYS_process_vm_writev, SYS_mount, SYS_umount2, SYS_pivot_root,
+        SYS_chroot, SYS_unshare, SYS_setns, SYS_kexec_load, SYS_init_module, SYS_finit_module,
+        SYS_delete_module, SYS_reboot, SYS_swapon, SYS_swapoff, SYS_bpf, SYS_perf_event_open, SYS_keyctl,
+        SYS_add_key, SYS_request_key, SYS_userfaultfd, SYS_personality, SYS_acct, SYS_syslog,
+        SYS_settimeofday, SYS_clock_settime, SYS_open_by_handle_at, SYS_io_uring_setup, SYS_io_uring_enter,
+        SYS_io_uring_register, SYS_socket,
+    };
+}
+
+/// A macOS host runs Linux guests on Apple silicon, whose syscalls are numbered by the generic
+/// table (`asm-generic/unistd.h`); libc has no Linux numbers there.
+#[cfg(target_os = "macos")]
+#[allow(non_upper_case_globals)]
+mod nr {
+    use libc::c_long;
+    pub const SYS_ptrace: c_long = 117;
+    pub const SYS_process_vm_readv: c_long = 270;
+    pub const SYS_process_vm_writev: c_long = 271;
+    pub const SYS_mount: c_long = 40;
+    pub const SYS_umount2: c_long = 39;
+    pub const SYS_pivot_root: c_long = 41;
+    pub const SYS_chroot: c_long = 51;
+    pub const SYS_unshare: c_long = 97;
+    pub const SYS_setns: c_long = 268;
+    pub const SYS_kexec_load: c_long = 104;
+    pub const SYS_init_module: c_long = 105;
+    pub const SYS_finit_module: c_long = 273;
+    pub const SYS_delete_module: c_long = 106;
+    pub const SYS_reboot: c_long = 142;
+    pub const SYS_swapon: c_long = 224;
+    pub const SYS_swapoff: c_long = 225;
+    pub const SYS_bpf: c_long = 280;
+    pub const SYS_perf_event_open: c_long = 241;
+    pub const SYS_keyctl: c_long = 219;
+    pub const SYS_add_key: c_long = 217;
+    pub const SYS_request_key: c_long = 218;
+    pub const SYS_userfaultfd: c_long = 282;
+    pub const SYS_personality: c_long = 92;
+    pub const SYS_acct: c_long = 89;
+    pub const SYS_syslog: c_long = 116;
+    pub const SYS_settimeofday: c_long = 170;
+    pub const SYS_clock_settime: c_long = 112;
+    pub const SYS_open_by_handle_at: c_long = 265;
+    pub const SYS_io_uring_setup: c_long = 425;
+    pub const SYS_io_uring_enter: c_long = 426;
+    pub const SYS_io_uring_register: c_long = 427;
+    pub const SYS_socket: c_long = 198;
+}
+
+/// Syscalls a profile may deny, by name. The guest runs the host's architecture, so on Linux
+/// the host's numbers are the guest's.
+const SYSCALLS: &[(&str, libc::c_long)] = &[
+    ("ptrace", nr::SYS_ptrace),
+    ("process_vm_readv", nr::SYS_process_vm_readv),
+    ("process_vm_writev", nr::SYS_process_vm_writev),
+    ("mount", nr::SYS_mount),
+    ("umount2", nr::SYS_umount2),
+    ("pivot_root", nr::SYS_pivot_root),
+    ("chroot", nr::SYS_chroot),
+    ("unshare", nr::SYS_unshare),
+    ("setns", nr::SYS_setns),
+    ("kexec_load", nr::SYS_kexec_load),
+    ("init_module", nr::SYS_init_module),
+    ("finit_module", nr::SYS_finit_module),
+    ("delete_module", nr::SYS_delete_module),
+    ("reboot", nr::SYS_reboot),
+    ("swapon", nr::SYS_swapon),
+    ("swapoff", nr::SYS_swapoff),
+    ("bpf", nr::SYS_bpf),
+    ("perf_event_open", nr::SYS_perf_event_open),
+    ("keyctl", nr::SYS_keyctl),
+    ("add_key", nr::SYS_add_key),
+    ("request_key", nr::SYS_request_key),
+    ("userfaultfd", nr::SYS_userfaultfd),
+    ("personality", nr::SYS_personality),
+    ("acct", nr::SYS_acct),
+    ("syslog", nr::SYS_syslog),
+    ("settimeofday", nr::SYS_settimeofday),
+    ("clock_settime", nr::SYS_clock_settime),
+    ("open_by_handle_at", nr::SYS_open_by_handle_at),
+    ("io_uring_setup", nr::SYS_io_uring_setup),
+    ("io_uring_enter", nr::SYS_io_uring_enter),
+    ("io_uring_register", nr::SYS_io_uring_register),
+];
+
+/// Denied by the `default` preset, along with raw and packet sockets
+const DEFAULT_DENY: &[&str] = &[
+    "ptrace",
+    "process_vm_readv",
+    "process_vm_writev",
+    "mount",
+    "umount2",
+    "pivot_root",
+    "chroot",
+    "unshare",
+    "setns",
+    "kexec_load",
+    "init_module",
+    "finit_module",
+    "delete_module",
+    "reboot",
+    "swapon",
+    "swapoff",
+    "bpf",
+    "perf_event_open",
+    "keyctl",
+    "add_key",
+    "request_key",
+    "userfaultfd",
+];
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Syscalls the guest runner denies to user code with a seccomp filter (they fail with
+/// EPERM), as defense in depth inside the VM.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeccompProfile {
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Deny `socket()` for SOCK_RAW and AF_PACKET sockets
+    #[serde(default)]
+    pub deny_raw_sockets: bool,
+}
+
+/// A profile as written in a config file: a preset name or a table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SeccompSetting {
+    Preset(String),
+    Profile(SeccompProfile),
+}
+
+impl SeccompSetting {
+    pub fn resolve(self) -> Result<SeccompProfile, String> {
+        match self {
+            SeccompSetting::Preset(name) => SeccompProfile::preset(&name),
+            SeccompSetting::Profile(profile) => profile.validated(),
+        }
+    }
+}
+
+impl SeccompProfile {
+    pub fn preset(name: &str) -> Result<Self, String> {
+        match name {
+            "default" => Ok(SeccompProfile {
+                deny: DEFAULT_DENY.iter().map(|s| s.to_string()).collect(),
+                deny_raw_sockets: true,
+            }),
+            other => Err(format!("unknown seccomp preset '{}': expected 'default' or a profile dict", other)),
+        }
+    }
+
+    /// `self` when every denied syscall is one flashvm knows the number of.
+    pub fn validated(self) -> Result<Self, String> {
+        if let Some(unknown) = self.deny.iter().find(|name| syscall_number(name).is_none()) {
+            return Err(format!(
+                "unsupported syscall '{}' in seccomp profile (supported: {})",
+                unknown,
+                SYSCALLS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        Ok(self)
+    }
+
+    /// What the guest runner needs to build the filter.
+    pub fn guest_filter(&self) -> Result<GuestFilter, String> {
+        let arch = AUDIT_ARCH.ok_or("seccomp profiles are supported on x86_64 and aarch64 hosts only")?;
+        Ok(GuestFilter {
+            arch,
+            deny: self.deny.iter().filter_map(|name| syscall_number(name)).collect(),
+            socket: self.deny_raw_sockets.then_some(i64::from(nr::SYS_socket)),
+        })
+    }
+}
+
+/// Seccomp part of the guest spec, with syscall numbers resolved for the guest's architecture
+#[derive(Debug, Clone, Serialize)]
+pub struct GuestFilter {
+    /// AUDIT_ARCH_* value; calls made under any other ABI are denied
+    pub arch: u32,
+    pub deny: Vec<i64>,
+    /// `socket` syscall number when raw and packet sockets are denied
+    pub socket: Option<i64>,
+}
+
+fn syscall_number(name: &str) -> Option<i64> {
+    SYSCALLS.iter().find(|(n, _)| *n == name).map(|(_, nr)| i64::from(*nr))
+}
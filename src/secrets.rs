@@ -0,0 +1,108 @@
+use crate::error::VMError;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+/// Guest mount point of the per-VM host secrets dir; the runner moves files from here into a tmpfs
+pub const GUEST_SECRETS_IN: &str = "/run/flashvm-secrets-in";
+
+/// Secret values by name. `Debug` never prints the values, so configs can be logged.
+#[derive(Clone, Default)]
+pub struct Secrets(HashMap<String, String>);
+
+impl Secrets {
+    #[cfg(feature = "python")]
+    pub fn new(values: HashMap<String, String>) -> Result<Self, String> {
+        for name in values.keys() {
+            let valid = !name.is_empty()
+                && !name.starts_with('.')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+            if !valid {
+                return Err(format!(
+                    "invalid secret name '{}': use letters, digits, '_', '-' and '.'",
+                    name
+                ));
+            }
+        }
+        Ok(Self(values))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// SHA-256 over the sorted names and values, to tell secret sets apart without keeping them.
+    pub fn fingerprint(&self) -> String {
+        let sorted: std::collections::BTreeMap<_, _> = self.0.iter().collect();
+        sha256::digest(serde_json::to_string(&sorted).unwrap_or_default())
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.0.keys().collect();
+        names.sort();
+        write!(f, "Secrets({:?} redacted)", names)
+    }
+}
+
+/// Host directory mounted at `GUEST_SECRETS_IN` for `vm_name`. Lives on the runtime tmpfs
+/// (never the workspace or cache) and is private to the current user.
+pub fn host_dir_for(vm_name: &str) -> PathBuf {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|p| p.is_dir())
+        .unwrap_or_else(runtime_fallback);
+    // SAFETY: getuid never fails
+    let uid = unsafe { libc::getuid() };
+    base.join(format!("flashvm-{}", uid)).join("secrets").join(vm_name)
+}
+
+/// macOS has no /dev/shm; its per-user temp dir is private to the user
+fn runtime_fallback() -> PathBuf {
+    if cfg!(target_os = "macos") {
+        std::env::temp_dir()
+    } else {
+        PathBuf::from("/dev/shm")
+    }
+}
+
+/// Create the (empty) secrets dir a VM is created with.
+pub fn ensure_dir(vm_name: &str) -> Result<PathBuf, VMError> {
+    let dir = host_dir_for(vm_name);
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    Ok(dir)
+}
+
+pub fn remove_dir(vm_name: &str) {
+    let _ = fs::remove_dir_all(host_dir_for(vm_name));
+}
+
+/// Secret files written for one run; they are removed on drop in case the guest didn't
+/// consume them (e.g. the VM failed to start).
+pub struct StagedSecrets {
+    dir: PathBuf,
+    names: Vec<String>,
+}
+
+impl Drop for StagedSecrets {
+    fn drop(&mut self) {
+        for name in &self.names {
+            let _ = fs::remove_file(self.dir.join(name));
+        }
+    }
+}
+
+pub fn stage(vm_name: &str, secrets: &Secrets) -> Result<StagedSecrets, VMError> {
+    let dir = ensure_dir(vm_name)?;
+    let mut staged = StagedSecrets { dir: dir.clone(), names: vec![] };
+    for (name, value) in &secrets.0 {
+        let mut f = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(dir.join(name))?;
+        staged.names.push(name.clone());
+        f.write_all(value.as_bytes())?;
+    }
+    Ok(staged)
+}
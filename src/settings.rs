@@ -0,0 +1,129 @@
+use crate::error::VMError;
+use crate::global_config;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_PULL_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_HEARTBEAT_TIMEOUT_SECS: u64 = 30;
+
+/// Air-gapped mode: no image operation may touch the network. Starts from `FLASHVM_OFFLINE`
+/// (`1`/`true`/`yes`), else the global config's `offline`, and can be changed with `set_offline`.
+fn offline_flag() -> &'static AtomicBool {
+    static OFFLINE: OnceLock<AtomicBool> = OnceLock::new();
+    OFFLINE.get_or_init(|| {
+        let from_env = std::env::var("FLASHVM_OFFLINE")
+            .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or_else(|_| global_config::global().offline.unwrap_or(false));
+        AtomicBool::new(from_env)
+    })
+}
+
+pub fn offline() -> bool {
+    offline_flag().load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "python")]
+pub fn set_offline(enabled: bool) {
+    offline_flag().store(enabled, Ordering::Relaxed);
+}
+
+/// Whether importing the Python module starts importing the embedded image in the background.
+/// `FLASHVM_PREIMPORT` (`0`/`false`/`no` to turn it off), else the global config's `preimport`;
+/// on by default.
+#[cfg(feature = "python")]
+pub fn preimport() -> bool {
+    match std::env::var("FLASHVM_PREIMPORT") {
+        Ok(v) => !matches!(v.trim().to_ascii_lowercase().as_str(), "0" | "false" | "no"),
+        Err(_) => global_config::global().preimport.unwrap_or(true),
+    }
+}
+
+/// How long an image pull may go without progress (output, or bytes coming in) before it is
+/// killed: `FLASHVM_PULL_TIMEOUT` seconds, else the global config's `pull_timeout_seconds`,
+/// else 5 minutes. The clock restarts whenever the pull makes progress, so large images on slow
+/// links get as long as they need; the run's own timeout doesn't cover pulls.
+pub fn pull_timeout() -> Duration {
+    let secs = env_secs("FLASHVM_PULL_TIMEOUT").or(global_config::global().pull_timeout_seconds);
+    Duration::from_secs(secs.unwrap_or(DEFAULT_PULL_TIMEOUT_SECS))
+}
+
+/// How long a running guest may go without a heartbeat from its runner before the run is
+/// treated as hung and killed: `FLASHVM_HEARTBEAT_TIMEOUT` seconds, else the global config's
+/// `heartbeat_timeout_seconds`, else 30 seconds. None (0) turns the watchdog off.
+pub fn heartbeat_timeout() -> Option<Duration> {
+    let secs = env_secs("FLASHVM_HEARTBEAT_TIMEOUT").or(global_config::global().heartbeat_timeout_seconds);
+    Some(Duration::from_secs(secs.unwrap_or(DEFAULT_HEARTBEAT_TIMEOUT_SECS))).filter(|d| !d.is_zero())
+}
+
+fn env_secs(name: &str) -> Option<u64> {
+    let v = std::env::var(name).ok()?;
+    v.trim()
+        .parse()
+        .map_err(|_| log::warn!("Ignoring {}={:?}: expected whole seconds", name, v))
+        .ok()
+}
+
+/// Fail with a clear error when `what` would need the network and offline mode is on.
+pub fn require_online(what: &str) -> Result<(), VMError> {
+    if offline() {
+        return Err(VMError::VMConfiguration(format!("offline mode: {} needs network access", what)));
+    }
+    Ok(())
+}
+
+/// Reproducible builds: the `SOURCE_DATE_EPOCH` every build pins its timestamps to, or None.
+/// Starts from the `SOURCE_DATE_EPOCH` environment variable and can be changed with
+/// `set_reproducible`.
+#[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+fn epoch_setting() -> &'static Mutex<Option<u64>> {
+    static EPOCH: OnceLock<Mutex<Option<u64>>> = OnceLock::new();
+    EPOCH.get_or_init(|| Mutex::new(env_source_date_epoch()))
+}
+
+#[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+pub fn env_source_date_epoch() -> Option<u64> {
+    std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|v| v.trim().parse().ok())
+}
+
+#[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+pub fn source_date_epoch() -> Option<u64> {
+    *epoch_setting().lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "python")]
+pub fn set_source_date_epoch(epoch: Option<u64>) {
+    *epoch_setting().lock().unwrap_or_else(|e| e.into_inner()) = epoch;
+}
+
+/// Defaults taken from `FLASHVM_*` environment variables, read once when the module loads.
+#[derive(Debug, Default)]
+pub struct EnvDefaults {
+    /// `FLASHVM_IMAGE`: image for runs that don't pass one
+    pub image: Option<String>,
+    /// `FLASHVM_TIMEOUT`: run timeout in seconds
+    pub timeout: Option<Duration>,
+    /// `FLASHVM_CACHE_DIR`, else the global config's `cache_dir`: replaces `~/.cache/flashvm`
+    pub cache_dir: Option<String>,
+}
+
+pub fn env_defaults() -> &'static EnvDefaults {
+    static DEFAULTS: OnceLock<EnvDefaults> = OnceLock::new();
+    DEFAULTS.get_or_init(|| {
+        let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let timeout = var("FLASHVM_TIMEOUT").and_then(|v| match v.parse::<u64>() {
+            Ok(secs) => Some(Duration::from_secs(secs)),
+            Err(_) => {
+                log::warn!("Ignoring FLASHVM_TIMEOUT={:?}: expected whole seconds", v);
+                None
+            }
+        });
+        EnvDefaults {
+            image: var("FLASHVM_IMAGE"),
+            timeout,
+            cache_dir: var("FLASHVM_CACHE_DIR").or_else(|| global_config::global().cache_dir.clone()),
+        }
+    })
+}
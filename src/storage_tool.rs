@@ -0,0 +1,103 @@
+use crate::error::VMError;
+use crate::global_config;
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// CLI that manages containers-storage for flashvm. buildah is preferred; podman is used on
+/// hosts that only ship podman. krunvm itself shells out to buildah, so booting VMs and the
+/// working-container builders (pip/conda/os) still need buildah; pulling, listing, removing,
+/// pinning and Containerfile builds work with either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageTool {
+    Buildah,
+    Podman,
+}
+
+impl StorageTool {
+    /// The tool for this process: `FLASHVM_STORAGE_TOOL` or the global config's `storage_tool`
+    /// (`buildah`/`podman`) when set, else buildah if it's on PATH, else podman if it is.
+    pub fn current() -> Self {
+        static TOOL: OnceLock<StorageTool> = OnceLock::new();
+        *TOOL.get_or_init(|| {
+            let configured =
+                std::env::var("FLASHVM_STORAGE_TOOL").ok().or_else(|| global_config::global().storage_tool.clone());
+            match configured.as_deref().map(str::trim) {
+                Some("podman") => StorageTool::Podman,
+                Some("buildah") => StorageTool::Buildah,
+                _ if !on_path("buildah") && on_path("podman") => StorageTool::Podman,
+                _ => StorageTool::Buildah,
+            }
+        })
+    }
+
+    pub fn binary(self) -> &'static str {
+        match self {
+            StorageTool::Buildah => "buildah",
+            StorageTool::Podman => "podman",
+        }
+    }
+
+    /// `images --format` template printing `name:tag`
+    pub fn image_name_format(self) -> &'static str {
+        match self {
+            StorageTool::Buildah => "{{.Name}}:{{.Tag}}",
+            StorageTool::Podman => "{{.Repository}}:{{.Tag}}",
+        }
+    }
+
+    /// argv prefix inspecting an image (append `--format ... <image>`)
+    pub fn inspect_image(self) -> Vec<&'static str> {
+        match self {
+            StorageTool::Buildah => vec!["buildah", "inspect", "--type", "image"],
+            StorageTool::Podman => vec!["podman", "image", "inspect"],
+        }
+    }
+
+    /// Inspect template for the registry digest an image was pulled by
+    pub fn digest_format(self) -> &'static str {
+        match self {
+            StorageTool::Buildah => "{{.FromImageDigest}}",
+            StorageTool::Podman => "{{.Digest}}",
+        }
+    }
+
+    /// Inspect template for the image ID
+    pub fn id_format(self) -> &'static str {
+        match self {
+            StorageTool::Buildah => "{{.FromImageID}}",
+            StorageTool::Podman => "{{.Id}}",
+        }
+    }
+
+    /// Inspect template printing a JSON object with the OCI config's `os` and `architecture`
+    pub fn platform_format(self) -> &'static str {
+        match self {
+            StorageTool::Buildah => "{{json .OCIv1}}",
+            StorageTool::Podman => r#"{"os":"{{.Os}}","architecture":"{{.Architecture}}"}"#,
+        }
+    }
+
+    /// argv prefix building a Containerfile
+    #[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+    pub fn build(self) -> [&'static str; 2] {
+        match self {
+            StorageTool::Buildah => ["buildah", "bud"],
+            StorageTool::Podman => ["podman", "build"],
+        }
+    }
+}
+
+/// Fail with `MissingDependency` when `what` needs buildah but only podman is available.
+pub fn require_buildah(what: &str) -> Result<(), VMError> {
+    if StorageTool::current() == StorageTool::Podman {
+        return Err(VMError::MissingDependency(format!(
+            "{} needs buildah (working containers have no podman equivalent); install buildah",
+            what
+        )));
+    }
+    Ok(())
+}
+
+fn on_path(command: &str) -> bool {
+    Command::new("which").arg(command).output().map(|o| o.status.success()).unwrap_or(false)
+}
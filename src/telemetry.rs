@@ -0,0 +1,157 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{info_span, Span};
+
+/// How often the boot/exec split is checked while the guest runs
+const PHASE_POLL: Duration = Duration::from_millis(10);
+
+/// Span for a run that arrived with a W3C `traceparent` (a daemon request header), so its
+/// run span joins the caller's trace. A plain span without the `otel` feature.
+#[cfg(any(feature = "grpc", feature = "http"))]
+pub(crate) fn request_span(traceparent: Option<&str>, tracestate: Option<&str>) -> Span {
+    let span = info_span!("flashvm.request");
+    #[cfg(feature = "otel")]
+    if let Some(traceparent) = traceparent {
+        otel::set_remote_parent(&span, traceparent, tracestate);
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (traceparent, tracestate);
+    span
+}
+
+/// Parent a top-level run span on the `TRACEPARENT` environment variable, if set.
+pub(crate) fn adopt_env_parent(span: &Span) {
+    #[cfg(feature = "otel")]
+    if let Ok(traceparent) = std::env::var("TRACEPARENT") {
+        otel::set_remote_parent(span, &traceparent, std::env::var("TRACESTATE").ok().as_deref());
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = span;
+}
+
+/// Parent a run span on the `traceparent` its caller passed in.
+pub(crate) fn adopt_parent(span: &Span, traceparent: &str, tracestate: Option<&str>) {
+    #[cfg(feature = "otel")]
+    otel::set_remote_parent(span, traceparent, tracestate);
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, traceparent, tracestate);
+}
+
+/// `span` as a W3C `traceparent`, when spans are being exported.
+pub(crate) fn traceparent_of(span: &Span) -> Option<String> {
+    #[cfg(feature = "otel")]
+    return otel::traceparent_of(span);
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = span;
+        None
+    }
+}
+
+/// Splits a `krunvm start` into `flashvm.boot` (until the guest runner touches `marker`,
+/// right before user code) and `flashvm.exec` (until `done`).
+pub(crate) fn trace_boot_and_exec(parent: &Span, marker: &Path, done: &AtomicBool) {
+    let boot = info_span!(parent: parent, "flashvm.boot");
+    while !marker.exists() {
+        if done.load(Ordering::SeqCst) {
+            return;
+        }
+        std::thread::sleep(PHASE_POLL);
+    }
+    drop(boot);
+    let _exec = info_span!(parent: parent, "flashvm.exec");
+    while !done.load(Ordering::SeqCst) {
+        std::thread::sleep(PHASE_POLL);
+    }
+}
+
+/// Export spans over OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is set. Spans are flushed at process exit.
+#[cfg(feature = "otel")]
+pub fn init_from_env() {
+    otel::init_from_env();
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn init_from_env() {}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use opentelemetry::propagation::TextMapPropagator;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use std::collections::HashMap;
+    use std::sync::OnceLock;
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+    pub(super) fn init_from_env() {
+        let configured = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+            .iter()
+            .any(|var| std::env::var(var).is_ok_and(|v| !v.is_empty()));
+        let disabled = std::env::var("OTEL_SDK_DISABLED").is_ok_and(|v| v.eq_ignore_ascii_case("true"));
+        if !configured || disabled || PROVIDER.get().is_some() {
+            return;
+        }
+        let exporter = match opentelemetry_otlp::SpanExporter::builder().with_http().build() {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                eprintln!("flashvm: OTLP trace export disabled: {}", e);
+                return;
+            }
+        };
+        let mut resource = Resource::builder();
+        if std::env::var("OTEL_SERVICE_NAME").is_err() {
+            resource = resource.with_service_name("flashvm");
+        }
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource.build())
+            .build();
+        let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("flashvm"));
+        // Leaves an already-installed subscriber (an embedding application's) alone
+        if tracing_subscriber::registry().with(layer).try_init().is_err() || PROVIDER.set(provider).is_err() {
+            return;
+        }
+        // SAFETY: registers a plain function with the C runtime
+        unsafe {
+            libc::atexit(shutdown);
+        }
+    }
+
+    extern "C" fn shutdown() {
+        if let Some(provider) = PROVIDER.get() {
+            let _ = provider.shutdown();
+        }
+    }
+
+    pub(super) fn traceparent_of(span: &Span) -> Option<String> {
+        PROVIDER.get()?;
+        let context = span.context();
+        let span_context = context.span().span_context().clone();
+        span_context.is_valid().then(|| {
+            format!(
+                "00-{}-{}-{:02x}",
+                span_context.trace_id(),
+                span_context.span_id(),
+                span_context.trace_flags().to_u8()
+            )
+        })
+    }
+
+        pub(super) fn set_remote_parent(span: &Span, traceparent: &str, tracestate: Option<&str>) {
+        let mut carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+        if let Some(tracestate) = tracestate {
+            carrier.insert("tracestate".to_string(), tracestate.to_string());
+        }
+        let _ = span.set_parent(TraceContextPropagator::new().extract(&carrier));
+    }
+}
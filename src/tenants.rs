@@ -0,0 +1,157 @@
+use crate::concurrency::{self, Scheduler, SchedulerConfig};
+#[cfg(any(feature = "python", feature = "daemon"))]
+use crate::concurrency::SchedulerStats;
+use crate::config::CacheConfig;
+use crate::error::VMError;
+use crate::global_config::{self, TenantSection};
+use crate::image_cache;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+/// An independent user of a shared host: its own image namespace, cache directory, storage
+/// budget and scheduler limits.
+#[derive(Clone)]
+pub struct Tenant {
+    pub name: String,
+    settings: TenantSection,
+    /// Admits the tenant's runs before the host's scheduler does
+    pub scheduler: &'static Scheduler,
+}
+
+impl Tenant {
+    /// `config` (the process' cache settings) as seen by this tenant. The namespace defaults
+    /// to `<image_namespace>/<name>` and the cache directory to `<cache_dir>/tenants/<name>`.
+    pub fn overlay(&self, config: &mut CacheConfig) {
+        config.image_namespace = match &self.settings.image_namespace {
+            Some(ns) => ns.clone(),
+            None => format!("{}/{}", config.image_namespace, self.name),
+        };
+        config.cache_dir = match &self.settings.cache_dir {
+            Some(dir) => dir.clone(),
+            None => format!("{}/tenants/{}", config.cache_dir.trim_end_matches('/'), self.name),
+        };
+        if let Some(max) = self.settings.max_storage_mb {
+            config.max_cache_size_mb = max;
+            config.evict_before_import = true;
+        }
+    }
+
+    pub fn settings(&self) -> &TenantSection {
+        &self.settings
+    }
+
+    #[cfg(any(feature = "python", feature = "daemon"))]
+    pub fn stats(&self) -> SchedulerStats {
+        self.scheduler.stats()
+    }
+}
+
+/// Tenants known to this process: the global config's `[tenants]`, plus `register_tenant`.
+fn registry() -> &'static Mutex<BTreeMap<String, Tenant>> {
+    static TENANTS: OnceLock<Mutex<BTreeMap<String, Tenant>>> = OnceLock::new();
+    TENANTS.get_or_init(|| {
+        let mut tenants = BTreeMap::new();
+        for (name, settings) in global_config::global().tenants.clone().unwrap_or_default() {
+            match new_tenant(&name, settings, None) {
+                Ok(tenant) => {
+                    tenants.insert(name, tenant);
+                }
+                Err(e) => log::warn!("Ignoring tenant '{}' in the global config: {}", name, e),
+            }
+        }
+        Mutex::new(tenants)
+    })
+}
+
+fn new_tenant(name: &str, mut settings: TenantSection, scheduler: Option<&'static Scheduler>) -> Result<Tenant, VMError> {
+    let valid = !name.is_empty()
+        && name.len() <= 63
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_'))
+        && !name.starts_with(['-', '_']);
+    if !valid {
+        return Err(VMError::VMConfiguration(format!(
+            "invalid tenant name '{}': use lowercase letters, digits, '-' and '_'",
+            name
+        )));
+    }
+    if let Some(ns) = &settings.image_namespace {
+        image_cache::validate_namespace(ns)?;
+    }
+    if let Some(dir) = &settings.cache_dir {
+        settings.cache_dir = Some(image_cache::validate_cache_dir(dir)?);
+    }
+    if settings.max_vms == Some(0) {
+        return Err(VMError::VMConfiguration("max_vms must be at least 1".to_string()));
+    }
+    let limits = SchedulerConfig {
+        max_vms: settings.max_vms.unwrap_or_else(|| concurrency::scheduler().config().max_vms),
+        max_memory_mb: settings.max_memory_mb.unwrap_or(0),
+        max_queue: settings.max_queue.unwrap_or(0),
+    };
+    // Schedulers live as long as the process, so a replaced tenant keeps its counters
+    let scheduler = match scheduler {
+        Some(scheduler) => {
+            scheduler.configure(limits);
+            scheduler
+        }
+        None => Box::leak(Box::new(Scheduler::new(limits))),
+    };
+    Ok(Tenant { name: name.to_string(), settings, scheduler })
+}
+
+/// Add or replace tenant `name`.
+#[cfg(feature = "python")]
+pub fn register(name: &str, settings: TenantSection) -> Result<(), VMError> {
+    let mut tenants = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let tenant = new_tenant(name, settings, tenants.get(name).map(|t| t.scheduler))?;
+    tenants.insert(name.to_string(), tenant);
+    Ok(())
+}
+
+#[cfg(feature = "python")]
+pub fn all() -> BTreeMap<String, Tenant> {
+    registry().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn get(name: &str) -> Result<Tenant, VMError> {
+    let tenants = registry().lock().unwrap_or_else(|e| e.into_inner());
+    tenants.get(name).cloned().ok_or_else(|| {
+        VMError::VMConfiguration(format!(
+            "unknown tenant '{}' (known: {})",
+            name,
+            tenants.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))
+    })
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Tenant>> = const { RefCell::new(None) };
+}
+
+/// The tenant the current thread is working for, if any.
+pub fn current() -> Option<Tenant> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Work on this thread is done for the tenant until the scope is dropped.
+pub struct TenantScope {
+    previous: Option<Tenant>,
+}
+
+/// Enter tenant `name` (None leaves the current one in place).
+pub fn enter(name: Option<&str>) -> Result<TenantScope, VMError> {
+    let previous = current();
+    if let Some(name) = name {
+        let tenant = get(name)?;
+        CURRENT.with(|c| *c.borrow_mut() = Some(tenant));
+    }
+    Ok(TenantScope { previous })
+}
+
+impl Drop for TenantScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|c| *c.borrow_mut() = previous);
+    }
+}
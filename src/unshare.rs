@@ -0,0 +1,275 @@
+use crate::error::VMError;
+use crate::platform;
+use crate::storage_tool::StorageTool;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const HELPER_SOURCE: &str = include_str!("unshare_helper.py");
+
+/// How often a streaming command with a stall timeout is checked for progress
+const PROGRESS_POLL: Duration = Duration::from_secs(1);
+
+/// Result of a command run inside the buildah user namespace
+#[derive(Debug, Deserialize)]
+pub struct UnshareOutput {
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    /// Killed for making no progress (see `run_argv_streaming`)
+    #[serde(default)]
+    pub stalled: bool,
+}
+
+impl UnshareOutput {
+    pub fn success(&self) -> bool {
+        self.code == 0
+    }
+
+    /// Last `n` lines of stderr (of stdout when stderr is empty), for error messages.
+    #[cfg(any(feature = "python", feature = "cli", feature = "grpc"))]
+    pub fn tail(&self, n: usize) -> String {
+        let text = if self.stderr.trim().is_empty() { &self.stdout } else { &self.stderr };
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        lines[lines.len().saturating_sub(n)..].join("\n")
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    argv: &'a [&'a str],
+}
+
+/// One `buildah unshare python3` (or `podman unshare`) process serving commands over stdin/stdout.
+struct UnshareSession {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UnshareSession {
+    fn spawn() -> Result<Self, VMError> {
+        let mut child = Command::new(StorageTool::current().binary())
+            .args(["unshare", "python3", "-u", "-c", HELPER_SOURCE])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| VMError::Execution(format!("Failed to spawn unshare helper: {}", e)))?;
+        let stdin = child.stdin.take().ok_or_else(|| VMError::Execution("unshare helper has no stdin".to_string()))?;
+        let stdout = child.stdout.take().ok_or_else(|| VMError::Execution("unshare helper has no stdout".to_string()))?;
+        debug!("Started unshare helper (pid {})", child.id());
+        Ok(Self { child, stdin, stdout: BufReader::new(stdout) })
+    }
+
+    fn request(&mut self, argv: &[&str]) -> Result<UnshareOutput, VMError> {
+        let mut line = serde_json::to_string(&Request { argv }).map_err(|e| VMError::Execution(e.to_string()))?;
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes())?;
+        self.stdin.flush()?;
+        let mut resp = String::new();
+        if self.stdout.read_line(&mut resp)? == 0 {
+            return Err(VMError::Execution("unshare helper exited unexpectedly".to_string()));
+        }
+        serde_json::from_str(&resp).map_err(|e| VMError::Execution(format!("Bad unshare helper response: {}", e)))
+    }
+}
+
+impl Drop for UnshareSession {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Idle helpers; each caller checks one out so concurrent commands don't serialize.
+fn idle_sessions() -> &'static Mutex<Vec<UnshareSession>> {
+    static IDLE: OnceLock<Mutex<Vec<UnshareSession>>> = OnceLock::new();
+    IDLE.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Helpers kept around between commands; extra ones spawned under load are dropped.
+const MAX_IDLE_SESSIONS: usize = 4;
+
+/// Whether the host has the python3 the helper runs on. Probed once: without it every helper dies
+/// on its first request, so commands go straight to one-shot `buildah unshare`.
+fn helper_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        let found = Command::new("python3")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false);
+        if !found {
+            warn!("python3 not found; unshare commands will each start their own buildah unshare");
+        }
+        found
+    })
+}
+
+fn checkout() -> Result<UnshareSession, VMError> {
+    let idle = idle_sessions().lock().unwrap_or_else(|e| e.into_inner()).pop();
+    match idle {
+        Some(s) => Ok(s),
+        None => UnshareSession::spawn(),
+    }
+}
+
+fn checkin(session: UnshareSession) {
+    let mut idle = idle_sessions().lock().unwrap_or_else(|e| e.into_inner());
+    if idle.len() < MAX_IDLE_SESSIONS {
+        idle.push(session);
+    }
+}
+
+/// `tool unshare argv...`, or just `argv` where there is no user namespace to enter (macOS).
+pub fn command<S: AsRef<OsStr>>(tool: &str, argv: &[S]) -> Command {
+    if platform::needs_unshare() {
+        let mut command = Command::new(tool);
+        command.arg("unshare").args(argv);
+        command
+    } else {
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        command
+    }
+}
+
+/// Run `argv` inside a shared unshare helper, (re)starting helpers as needed.
+/// Falls back to a one-off `buildah unshare` when no helper can be used (or the host has no
+/// python3 to run one), and runs `argv` directly where there is no user namespace.
+pub fn run_argv(argv: &[&str]) -> Result<UnshareOutput, VMError> {
+    if !platform::needs_unshare() || !helper_available() {
+        return run_one_shot(argv);
+    }
+    debug!("Executing (unshare session): {:?}", argv);
+    for _ in 0..2 {
+        let mut session = match checkout() {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("{}; falling back to one-shot buildah unshare", e);
+                break;
+            }
+        };
+        match session.request(argv) {
+            Ok(out) => {
+                checkin(session);
+                return Ok(out);
+            }
+            // The broken helper is dropped (and killed) here; the next round spawns a fresh one
+            Err(e) => warn!("unshare helper failed ({}); restarting it", e),
+        }
+    }
+    run_one_shot(argv)
+}
+
+fn run_one_shot(argv: &[&str]) -> Result<UnshareOutput, VMError> {
+    let output = command(StorageTool::current().binary(), argv)
+        .output()
+        .map_err(|e| VMError::Execution(format!("Failed to execute command: {}", e)))?;
+    Ok(UnshareOutput {
+        code: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        stalled: false,
+    })
+}
+
+/// One-off `buildah unshare` that hands each output line (stdout and stderr, as they are
+/// printed) to `on_line`, for long commands whose progress callers want to report.
+/// With `stall`, the command is killed once it has gone that long without progress: no output
+/// and, on Linux, no bytes read by its processes (a large layer downloads without printing).
+pub fn run_argv_streaming(
+    argv: &[&str],
+    stall: Option<Duration>,
+    on_line: &mut dyn FnMut(&str),
+) -> Result<UnshareOutput, VMError> {
+    debug!("Executing (unshare, streaming): {:?}", argv);
+    let mut child = command(StorageTool::current().binary(), argv)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| VMError::Execution(format!("Failed to execute command: {}", e)))?;
+    let stdout = child.stdout.take().ok_or_else(|| VMError::Execution("command has no stdout".to_string()))?;
+    let stderr = child.stderr.take().ok_or_else(|| VMError::Execution("command has no stderr".to_string()))?;
+    // Both pipes are drained on their own threads so neither can block the child
+    let (tx, rx) = mpsc::channel::<(bool, String)>();
+    let forward = |pipe: Box<dyn Read + Send>, is_err: bool, tx: mpsc::Sender<(bool, String)>| {
+        std::thread::spawn(move || {
+            for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+                if tx.send((is_err, line)).is_err() {
+                    break;
+                }
+            }
+        })
+    };
+    let readers = [forward(Box::new(stdout), false, tx.clone()), forward(Box::new(stderr), true, tx)];
+    let (mut out_text, mut err_text) = (String::new(), String::new());
+    let pgid = child.id() as i32;
+    let (mut last_progress, mut bytes_read, mut stalled) = (Instant::now(), 0, false);
+    loop {
+        match rx.recv_timeout(PROGRESS_POLL) {
+            Ok((is_err, line)) => {
+                last_progress = Instant::now();
+                on_line(&line);
+                let text = if is_err { &mut err_text } else { &mut out_text };
+                text.push_str(&line);
+                text.push('\n');
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        let Some(stall) = stall else { continue };
+        if let Some(read) = group_bytes_read(pgid).filter(|read| *read != bytes_read) {
+            bytes_read = read;
+            last_progress = Instant::now();
+        }
+        if !stalled && last_progress.elapsed() >= stall {
+            warn!("{:?} made no progress for {}s; killing it", argv, stall.as_secs());
+            // SAFETY: kill(2) on the process group the child leads
+            unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            }
+            stalled = true;
+        }
+    }
+    for r in readers {
+        let _ = r.join();
+    }
+    let status = child.wait()?;
+    Ok(UnshareOutput { code: status.code().unwrap_or(-1), stdout: out_text, stderr: err_text, stalled })
+}
+
+/// Bytes read so far (network included) by the processes in group `pgid`
+#[cfg(target_os = "linux")]
+fn group_bytes_read(pgid: i32) -> Option<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let dir = entry.path();
+        let Ok(stat) = std::fs::read_to_string(dir.join("stat")) else { continue };
+        // The command name can hold spaces and parentheses; the fields after it can't
+        let group = stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().nth(2)?.parse::<i32>().ok());
+        if group != Some(pgid) {
+            continue;
+        }
+        let Ok(io) = std::fs::read_to_string(dir.join("io")) else { continue };
+        let rchar = io.lines().find_map(|l| l.strip_prefix("rchar:")).and_then(|v| v.trim().parse::<u64>().ok());
+        total += rchar.unwrap_or(0);
+    }
+    Some(total)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn group_bytes_read(_pgid: i32) -> Option<u64> {
+    None
+}
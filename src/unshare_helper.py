@@ -0,0 +1,22 @@
+"""Long-lived helper run inside `buildah unshare`.
+
+Reads one JSON request per line ({"argv": [...]}) and answers each with one
+JSON line ({"code", "stdout", "stderr"}), so the host pays the namespace setup once.
+"""
+import json
+import subprocess
+import sys
+
+for line in sys.stdin:
+    req = json.loads(line)
+    try:
+        p = subprocess.run(req["argv"], stdin=subprocess.DEVNULL, capture_output=True)
+        resp = {
+            "code": p.returncode,
+            "stdout": p.stdout.decode("utf-8", "replace"),
+            "stderr": p.stderr.decode("utf-8", "replace"),
+        }
+    except OSError as e:
+        resp = {"code": 127, "stdout": "", "stderr": str(e)}
+    sys.stdout.write(json.dumps(resp) + "\n")
+    sys.stdout.flush()
@@ -1,27 +1,122 @@
-use crate::config::{Artifact, ExecutionResult, FileInput, FileOutput, VMConfig};
+use crate::config::{
+    Artifact, ArtifactContent, CaptureMode, ExecutionResult, FileInput, FileOutput, KeepVm, KeptVm,
+    OutputChunk, PhaseTimings, VMConfig,
+};
+#[cfg(any(feature = "python", feature = "cli"))]
+use crate::config::PullProgress;
+use crate::audit::{self, AuditLog, AuditRecord};
+use crate::concurrency;
+use crate::coverage;
+use crate::cprofile;
+use crate::display;
 use crate::error::VMError;
-use crate::image_resolver::ImageResolver;
+use crate::guest_channel::{self, GuestCoverage, GuestHeartbeat, GuestProfile, GuestSpec, GuestStatus, GuestTimezone};
+use crate::hooks::{self, RunContext};
+use crate::unshare;
+use crate::image_resolver::{self, ImageResolver};
+use crate::locale;
+use crate::platform;
+use crate::policy;
+use crate::pool::{self, PoolKey, PooledVm, VmPool};
+use crate::rate_limit;
+use crate::result_cache;
+use crate::image_cache::{self, ImageCache, ImageKind};
+use crate::oci_layout;
+use crate::runs::{RunRecord, RunRegistry, TimeoutExtension};
+use crate::scan;
+use crate::secrets;
+use crate::settings;
+use crate::storage_tool::StorageTool;
+use crate::telemetry;
+use crate::tenants;
 use anyhow::Result;
 use glob::glob;
-use log::{debug, info};
-use serde_json;
+use log::{debug, info, warn};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::Write;
-use std::os::unix::process::ExitStatusExt;
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Component, Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tempfile::{NamedTempFile, TempDir};
+use tracing::{field, info_span, Span};
 use uuid::Uuid;
 
-fn shell_escape(s: &str) -> String {
-    if s.chars().all(|c| c.is_ascii_alphanumeric() || "/-_.:@".contains(c)) {
-        s.to_string()
+/// Guest-side entry point; reads its spec from /work/scripts (see `guest_channel`)
+const GUEST_RUNNER: &str = include_str!("guest/runner.py");
+
+/// Applies `VMConfig.egress` to the krunvm process before exec'ing it
+const EGRESS_EXEC: &str = include_str!("egress_exec.py");
+
+/// Written by the guest runner (under /work/scripts) right before user code starts.
+const STARTED_MARKER: &str = ".started";
+
+/// How often a running VM's registry record is checked for `pause`, `resume` and `extend_timeout`
+const RECORD_POLL: Duration = Duration::from_secs(1);
+
+/// Fewer artifacts than this are read on the calling thread
+const PARALLEL_COLLECT_MIN: usize = 32;
+
+/// Most threads reading artifacts at once
+const MAX_COLLECT_THREADS: usize = 8;
+
+/// Written by user code (under /work/out) to hand back a machine-readable answer
+const VALUE_FILE: &str = "flashvm_result.json";
+
+/// Map a `FileInput.guest_path` (relative, or absolute under /work/in) to a path relative
+/// to the input dir, refusing anything that would land outside it.
+fn staged_relative_path(guest_path: &str) -> Result<PathBuf, VMError> {
+    let path = Path::new(guest_path);
+    let relative = if path.is_absolute() {
+        path.strip_prefix("/work/in").map_err(|_| {
+            VMError::Validation(format!("guest_path must be under /work/in: {}", guest_path))
+        })?
     } else {
-        format!("'{}'", s.replace('\'', "'\\''"))
+        path
+    };
+    let mut clean = PathBuf::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(VMError::Validation(format!(
+                    "guest_path must stay under /work/in: {}",
+                    guest_path
+                )))
+            }
+        }
     }
+    if clean.as_os_str().is_empty() {
+        return Err(VMError::Validation(format!("guest_path names no file: {:?}", guest_path)));
+    }
+    Ok(clean)
 }
 
+/// Guest kernel messages that mean the OOM killer fired
+const OOM_MARKERS: &[&str] = &["Out of memory: Killed process", "oom-kill:"];
+
+/// Below this much free space in the guest's /work a run is blamed on a full workspace
+const DISK_FULL_THRESHOLD_BYTES: u64 = 1024 * 1024;
+
+/// Output records written by the guest runner in JSON-lines capture modes (under /work/scripts)
+const CAPTURE_FILE: &str = "capture.jsonl";
+
+/// How often the capture file is polled for records while an output listener is set
+const CAPTURE_POLL: Duration = Duration::from_millis(100);
+
+/// Receives guest output records as the guest writes them; see `VMRunner::with_output_listener`
+pub type OutputListener = Arc<dyn Fn(&OutputChunk) + Send + Sync>;
+
+/// Guest path where `VMConfig.wheel_dir` is staged
+const GUEST_WHEEL_DIR: &str = "/work/wheels";
+
 struct WorkDirectories {
     _temp_base: TempDir,
     input_dir: std::path::PathBuf,
@@ -30,71 +125,639 @@ struct WorkDirectories {
     scripts_dir: std::path::PathBuf,
 }
 
+impl WorkDirectories {
+    /// Lay out in/out/tmp/scripts under `temp_base`, wiping leftovers from a previous
+    /// run when the workspace is being reused (warm pool).
+    fn prepare(temp_base: TempDir) -> Result<Self, VMError> {
+        for entry in fs::read_dir(temp_base.path())? {
+            let path = entry?.path();
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+        let input_dir = temp_base.path().join("in");
+        let output_dir = temp_base.path().join("out");
+        let tmp_dir = temp_base.path().join("tmp");
+        let scripts_dir = temp_base.path().join("scripts");
+        for dir in [&input_dir, &output_dir, &tmp_dir, &scripts_dir] {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(WorkDirectories {
+            input_dir,
+            output_dir,
+            _tmp_dir: tmp_dir,
+            scripts_dir,
+            _temp_base: temp_base,
+        })
+    }
+}
+
+/// Holds no mutable state: one runner can serve many threads at once. VM names and
+/// workspaces are per-run, and process-wide VM concurrency and memory are bounded by
+/// `concurrency::scheduler()`.
 pub struct VMRunner {
     image_resolver: ImageResolver,
+    runs: RunRegistry,
+    output_listener: Option<OutputListener>,
+    run_id: Option<String>,
+}
+
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<VMRunner>();
+};
+
+/// Drops the run record once the execution is over, whatever the outcome (unless kept).
+struct RunGuard<'a> {
+    runs: &'a RunRegistry,
+    run_id: &'a str,
+    kept: bool,
+    /// Where the run's timeout extensions are handed back for its audit record
+    extensions: &'a mut Vec<TimeoutExtension>,
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(Some(record)) = self.runs.get(self.run_id) {
+            *self.extensions = record.timeout_extensions;
+        }
+        if !self.kept {
+            self.runs.remove(self.run_id);
+        }
+    }
 }
 
 impl VMRunner {
     pub fn new() -> Self {
         Self {
             image_resolver: ImageResolver::new(),
+            runs: RunRegistry::new(),
+            output_listener: None,
+            run_id: None,
         }
     }
 
+    /// Hand output records to `listener` while the code runs, not just in the result. Only
+    /// JSON-lines capture modes record them.
+    #[cfg(any(feature = "grpc", feature = "http"))]
+    pub fn with_output_listener(mut self, listener: Option<OutputListener>) -> Self {
+        self.output_listener = listener;
+        self
+    }
+
+    /// Run under `run_id` instead of a fresh one, so callers can `kill` it while it runs.
+    /// Must be a UUID not used by another run.
+    #[cfg(any(feature = "grpc", feature = "http"))]
+    pub fn with_run_id(mut self, run_id: Option<String>) -> Self {
+        self.run_id = run_id;
+        self
+    }
+
+    #[cfg(feature = "python")]
     pub fn pre_pull_image(&self, image_ref: &str) -> Result<(), VMError> {
-        let resolved = self.image_resolver.resolve_image_ref(Some(image_ref))?;
-        let normalized = self.normalize_image_for_krunvm(&resolved)?;
+        self.pre_pull_with_progress(image_ref, &|_| {})
+    }
+
+    /// Pre-pull several images, at most `max_parallel` at a time, reporting each one's
+    /// progress through `progress` (called from worker threads). Results are in input order.
+    #[cfg(any(feature = "python", feature = "cli"))]
+    pub fn pre_pull_images(
+        &self,
+        image_refs: &[String],
+        max_parallel: usize,
+        progress: &(dyn Fn(PullProgress) + Sync),
+    ) -> Vec<Result<(), VMError>> {
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<Result<(), VMError>>>> =
+            Mutex::new(image_refs.iter().map(|_| None).collect());
+        std::thread::scope(|scope| {
+            for _ in 0..max_parallel.clamp(1, image_refs.len().max(1)) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(image_ref) = image_refs.get(i) else { break };
+                    let result = self.pre_pull_with_progress(image_ref, progress);
+                    if let Err(e) = &result {
+                        progress(PullProgress {
+                            image: image_ref.clone(),
+                            event: "failed",
+                            layers: 0,
+                            bytes: 0,
+                            error: Some(e.to_string()),
+                        });
+                    }
+                    results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_iter()
+            .map(|r| r.unwrap_or_else(|| Err(VMError::Execution("pre-pull worker exited early".to_string()))))
+            .collect()
+    }
+
+    #[cfg(any(feature = "python", feature = "cli"))]
+    fn pre_pull_with_progress(&self, image_ref: &str, progress: &(dyn Fn(PullProgress) + Sync)) -> Result<(), VMError> {
+        let report = |event, layers, bytes| {
+            progress(PullProgress { image: image_ref.to_string(), event, layers, bytes, error: None })
+        };
+        report("started", 0, 0);
+        let resolved = self.image_resolver.resolve_image_ref(Some(image_ref)).map_err(|e| e.with_image(image_ref))?;
+        let mut layers = 0;
+        let local = ["containers-storage:", "oci:", "dir:", "oci-archive:"];
+        if !local.iter().any(|p| resolved.starts_with(p)) {
+            // Pull explicitly (rather than inside `krunvm create`) to see buildah's progress
+            image_resolver::pull_image(&resolved, &mut |line| {
+                if line.starts_with("Copying blob") {
+                    layers += 1;
+                    report("layer", layers, 0);
+                }
+            })
+            .map_err(|e| e.with_image(image_ref))?;
+        }
+        let normalized = self.normalize_image_for_krunvm(&resolved).map_err(|e| e.with_image(image_ref))?;
+        if !local.iter().any(|p| resolved.starts_with(p)) {
+            scan::after_build_or_pull(&normalized);
+        }
         let vm_name = format!("prepull-{}", &Uuid::new_v4().to_string()[..8]);
-        let script = format!(
-            "set -e\n\
-             krunvm create --cpus 1 --mem 256 --workdir /work --name {} {} >/dev/null\n\
-             krunvm delete -f {} >/dev/null 2>&1 || krunvm delete {} >/dev/null 2>&1 || true\n",
-            shell_escape(&vm_name),
-            normalized,
-            shell_escape(&vm_name),
-            shell_escape(&vm_name),
-        );
-        let out = self.run_in_buildah_unshare_capture(&script)?;
-        if !out.success {
-            return Err(VMError::VMConfiguration(format!("pre-pull failed: {}", out.stderr)));
+        let argv = [
+            "krunvm", "create", "--cpus", "1", "--mem", "256", "--workdir", "/work", "--name", &vm_name, &normalized,
+        ];
+        let out = unshare::run_argv(&argv)?;
+        self.delete_vm(&vm_name);
+        if !out.success() {
+            return Err(VMError::VMConfiguration(format!("pre-pull failed: {}", out.stderr))
+                .with_command(&argv, &out.stderr)
+                .with_image(image_ref));
         }
+        report("done", layers, image_cache::image_size(&normalized).unwrap_or(0));
         Ok(())
     }
 
+    /// Open an interactive shell in a VM kept by `KeepVm`, wired to this process' terminal.
+    /// Returns the shell's exit code.
+    #[cfg(feature = "python")]
+    pub fn attach(&self, run_id: &str, shell: &str) -> Result<i32, VMError> {
+        let record = self.runs.get(run_id)?.ok_or_else(|| {
+            VMError::VMConfiguration(format!("No run registered with id {}", run_id))
+        })?;
+        if !record.kept {
+            return Err(VMError::VMConfiguration(format!(
+                "Run {} is still executing; attach requires a kept VM (keep_vm='on_failure' or 'always')",
+                run_id
+            )));
+        }
+        self.check_dependencies()?;
+        info!("Attaching to VM {} (run {}) with {}", record.vm_name, run_id, shell);
+        let status = unshare::command("buildah", &["krunvm", "start", &record.vm_name, shell])
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|e| VMError::Execution(format!("Failed to start krunvm: {}", e)))?;
+        Ok(status.code().unwrap_or(-1))
+    }
+
     pub fn execute_python_code(
         &self,
         code: &str,
         config: &VMConfig,
         files_in: Vec<FileInput>,
         expect: Vec<FileOutput>,
+    ) -> Result<ExecutionResult, VMError> {
+        let run_id = self.run_id.clone().unwrap_or_else(RunRegistry::new_run_id);
+        // Phases (resolve, import, create, boot, exec, collect) are child spans of this one
+        let span = info_span!(
+            "flashvm.run",
+            flashvm.run_id = %run_id,
+            flashvm.tenant = config.tenant.as_deref(),
+            flashvm.image = field::Empty,
+            flashvm.pooled = field::Empty,
+            flashvm.exit_code = field::Empty,
+            flashvm.error = field::Empty,
+            otel.status_code = field::Empty,
+        );
+        match config.trace.traceparent.as_deref() {
+            Some(traceparent) => telemetry::adopt_parent(&span, traceparent, config.trace.tracestate.as_deref()),
+            None if Span::current().is_none() => telemetry::adopt_env_parent(&span),
+            None => {}
+        }
+        let audit_log = AuditLog::open()?;
+        let start_time = Instant::now();
+        let mut image_used = None;
+        let mut timeout_extensions = Vec::new();
+        let hooks = hooks::snapshot();
+        let ctx = RunContext { run_id: &run_id, code };
+        // Hooks may rewrite the settings; everything after them (audit included) sees their version
+        let mut config = Cow::Borrowed(config);
+        // When spans are exported, the guest's join the trace under this run's span
+        if let Some(traceparent) = telemetry::traceparent_of(&span) {
+            config.to_mut().trace.traceparent = Some(traceparent);
+        }
+        let mut result = span.in_scope(|| {
+            if !hooks.is_empty() {
+                hooks::before_run(&hooks, &ctx, config.to_mut())?;
+            }
+            // Images, caches and quotas are the tenant's for the rest of the run
+            let _tenant = tenants::enter(config.tenant.as_deref())?;
+            rate_limit::enforce_tenant()?;
+            let mut result = self.execute_run(
+                run_id.clone(),
+                code,
+                &config,
+                files_in,
+                expect,
+                &mut image_used,
+                &mut timeout_extensions,
+            )?;
+            hooks::after_run(&hooks, &ctx, &config, &mut result)?;
+            Ok(result)
+        });
+        if let Some(log) = audit_log {
+            let record = AuditRecord {
+                ts: chrono::Utc::now(),
+                run_id,
+                tenant: config.tenant.clone(),
+                labels: config.labels.clone(),
+                image_digest: image_used.as_deref().and_then(image_resolver::local_image_digest),
+                image: image_used.or_else(|| config.image.clone()),
+                code_sha256: sha256::digest(code),
+                limits: audit::Limits::of(&config),
+                timeout_extensions,
+                exit_code: result.as_ref().ok().map(|r| r.exit_code),
+                error: result.as_ref().err().map(VMError::code),
+                duration_ms: start_time.elapsed().as_millis() as u64,
+                pid: std::process::id(),
+                // SAFETY: getuid has no preconditions
+                uid: unsafe { libc::getuid() },
+            };
+            // A run that can't be audited fails, even though it ran
+            if let Err(e) = log.append(&record) {
+                result = result.and(Err(e));
+            }
+        }
+        match &result {
+            Ok(result) => {
+                span.record("flashvm.exit_code", result.exit_code);
+            }
+            Err(e) => {
+                span.record("flashvm.error", e.code());
+                span.record("otel.status_code", "ERROR");
+            }
+        }
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_run(
+        &self,
+        run_id: String,
+        code: &str,
+        config: &VMConfig,
+        files_in: Vec<FileInput>,
+        expect: Vec<FileOutput>,
+        image_used: &mut Option<String>,
+        timeout_extensions: &mut Vec<TimeoutExtension>,
     ) -> Result<ExecutionResult, VMError> {
         let start_time = Instant::now();
 
-        info!("Starting execution with config: {:?}", config);
+        info!("[run {}] Starting execution with config: {:?}", run_id, config);
 
+        self.validate_config(config)?;
+        policy::enforce(config)?;
+        for file_input in &files_in {
+            staged_relative_path(&file_input.guest_path)?;
+        }
         self.check_dependencies()?;
+        if config.egress.is_some() {
+            self.check_egress_support()?;
+        }
+
+        // The runner's resolver was made outside the tenant's scope
+        let tenant_resolver = config.tenant.is_some().then(ImageResolver::new);
+        let resolver = tenant_resolver.as_ref().unwrap_or(&self.image_resolver);
 
         // Resolve image → nome aceitável pelo krunvm
-        let resolved_image = self.image_resolver.resolve_image_ref(config.image.as_deref())?;
-        let image_ref = self.normalize_image_for_krunvm(&resolved_image)?;
-        info!("Using image: {}", image_ref);
+        let canonical = image_resolver::canonical_image();
+        let requested_image = config.image.as_deref().unwrap_or(&canonical);
+        let mut timings = PhaseTimings::default();
+        let phase = Instant::now();
+        let resolved_image = info_span!("flashvm.resolve").in_scope(|| {
+            let resolved = resolver
+                .resolve_image_ref(config.image.as_deref())
+                .map_err(|e| e.with_image(requested_image))?;
+            if !config.pin_digest {
+                return Ok::<_, VMError>(resolved);
+            }
+            resolver.pin_digest(&resolved).map_err(|e| e.with_image(requested_image))
+        })?;
+        timings.resolve = phase.elapsed();
+
+        let cache_key = match config.result_cache {
+            true => result_cache::key(&resolved_image, code, config, &files_in, &expect)?,
+            false => None,
+        };
+        if let Some(mut cached) = cache_key.as_deref().and_then(|k| result_cache::lookup(k, config.max_bytes_inline)) {
+            info!("[run {}] Returning cached result of run {}", run_id, cached.run_id);
+            *image_used = Some(cached.image_used.clone());
+            cached.run_id = run_id;
+            cached.execution_time = start_time.elapsed();
+            cached.timings = timings;
+            return Ok(cached);
+        }
+
+        // A warm VM skips image normalization and `krunvm create` entirely
+        let pool_key = config.use_pool.then(|| PoolKey::new(&resolved_image, config));
+        let pooled = pool_key.as_ref().and_then(|k| pool::vm_pool().checkout(k));
+        let (vm_name, image_ref, temp_dirs, uses, needs_create) = match pooled {
+            Some(vm) => (vm.vm_name, vm.image_ref, WorkDirectories::prepare(vm.workspace)?, vm.uses, false),
+            None => {
+                let phase = Instant::now();
+                let image_ref = info_span!("flashvm.import").in_scope(|| {
+                    let image_ref =
+                        self.normalize_image_for_krunvm(&resolved_image).map_err(|e| e.with_image(&resolved_image))?;
+                    // Registry images are pulled here, under the pull timeout, rather than by
+                    // `buildah from` or `krunvm create` under the run's
+                    let present = image_cache::in_namespace(&image_ref) || image_resolver::local_image_digest(&image_ref).is_some();
+                    if !present {
+                        image_resolver::pull_image(&image_ref, &mut |_| {}).map_err(|e| e.with_image(requested_image))?;
+                    }
+                    if config.image.is_some() {
+                        resolver.check_has_python(&image_ref)?;
+                    }
+                    scan::enforce(&image_ref).map_err(|e| e.with_image(requested_image))?;
+                    Ok::<_, VMError>(image_ref)
+                })?;
+                timings.resolve += phase.elapsed();
+                let vm_name = if pool_key.is_some() {
+                    VmPool::new_vm_name()
+                } else {
+                    RunRegistry::vm_name_for(&run_id)
+                };
+                let work_dirs = self.setup_work_directories(&vm_name)?;
+                (vm_name, image_ref, work_dirs, 0, true)
+            }
+        };
+        info!("[run {}] Using image: {} (VM {})", run_id, image_ref, vm_name);
+        Span::current().record("flashvm.image", image_ref.as_str()).record("flashvm.pooled", !needs_create);
+        *image_used = Some(image_ref.clone());
+        if let Err(e) = ImageCache::new().touch(&image_ref) {
+            debug!("[run {}] Failed to update image cache manifest: {}", run_id, e);
+        }
 
-        let temp_dirs = self.setup_work_directories()?;
-        self.prepare_input_files(&files_in, &temp_dirs.input_dir)?;
+        let phase = Instant::now();
+        self.prepare_input_files(&run_id, &files_in, &temp_dirs.input_dir)?;
+        if let Some(wheel_dir) = &config.wheel_dir {
+            self.stage_wheel_dir(wheel_dir, &temp_dirs._temp_base.path().join("wheels"))?;
+        }
         let script_file = self.create_python_script(code)?;
+        timings.stage = phase.elapsed();
 
-        let vm_result = self.run_vm_with_krunvm(&image_ref, &script_file, config, &temp_dirs)?;
-        let artifacts = self.collect_artifacts(&expect, &temp_dirs.output_dir, config.max_bytes_inline)?;
+        self.runs.register(&RunRecord {
+            run_id: run_id.clone(),
+            vm_name: vm_name.clone(),
+            owner_pid: std::process::id(),
+            pgid: 0,
+            image: image_ref.clone(),
+            started_at: chrono::Utc::now(),
+            timeout_ms: config.timeout.as_millis() as u64,
+            tenant: config.tenant.clone(),
+            timeout_extensions: Vec::new(),
+            killed: false,
+            paused: false,
+            kept: false,
+            workspace: None,
+        })?;
+        let mut guard = RunGuard { runs: &self.runs, run_id: &run_id, kept: false, extensions: timeout_extensions };
+        self.ensure_not_killed(&run_id)?;
+
+        // A tenant's own limits first, so a tenant over quota doesn't hold a place in the host's queue
+        let tenant = tenants::current();
+        let schedulers = tenant.iter().map(|t| t.scheduler).chain([concurrency::scheduler()]);
+        let deadline = config.queue_timeout.map(|t| Instant::now() + t);
+        let mut admissions = Vec::new();
+        for scheduler in schedulers {
+            if scheduler.busy(config.memory_mb) {
+                let stats = scheduler.stats();
+                info!(
+                    "[run {}] Queued for admission ({} running, {} MiB in use, {} waiting)",
+                    run_id, stats.running, stats.memory_in_use_mb, stats.queued
+                );
+            }
+            admissions.push(info_span!("flashvm.queue").in_scope(|| scheduler.admit(config.memory_mb, deadline))?);
+        }
+        let follow_done = AtomicBool::new(false);
+        let vm_result = std::thread::scope(|scope| {
+            if let Some(listener) = self.output_listener.as_deref() {
+                if config.effective_capture() != CaptureMode::Stdio {
+                    let path = temp_dirs.scripts_dir.join(CAPTURE_FILE);
+                    let done = &follow_done;
+                    scope.spawn(move || follow_capture(&path, done, listener));
+                }
+            }
+            let result = self.run_vm_with_krunvm(
+                &run_id,
+                &vm_name,
+                needs_create,
+                &image_ref,
+                &script_file,
+                config,
+                &temp_dirs,
+            );
+            follow_done.store(true, Ordering::SeqCst);
+            result
+        })?;
+        drop(admissions);
+        timings.stage += vm_result.stage_time;
+        timings.create = vm_result.create_time;
+        timings.boot = vm_result.boot_time;
+        timings.exec = vm_result.exec_time;
+        let phase = Instant::now();
+        let collect = info_span!("flashvm.collect").entered();
+        let status = guest_channel::read_status(&temp_dirs.scripts_dir);
+        let oom = self.guest_ran_out_of_memory(status.as_ref(), &vm_result);
+        let (stdout, stderr, output) = self.apply_capture(
+            &run_id,
+            config.effective_capture(),
+            &temp_dirs.scripts_dir,
+            vm_result.stdout,
+            vm_result.stderr,
+        );
+        let disk_full = self.workspace_full(status.as_ref());
+        let mut expect = expect;
+        if config.coverage {
+            expect.push(FileOutput { pattern: coverage::DATA_FILE.to_string() });
+        }
+        if config.cprofile {
+            expect.push(FileOutput { pattern: cprofile::STATS_FILE.to_string() });
+        }
+        if config.display {
+            expect.push(FileOutput { pattern: display::PATTERN.to_string() });
+        }
+        // A kept VM (or a shell attached to it) can still change its outputs, so those are copied
+        let map = !vm_result.vm_kept;
+        let artifacts = self.collect_artifacts(&expect, &temp_dirs.output_dir, config.max_bytes_inline, map)?;
+        let coverage = config.coverage.then(|| coverage::read_report(&temp_dirs.scripts_dir)).flatten();
+        let profile = config.cprofile.then(|| cprofile::read_report(&temp_dirs.scripts_dir)).flatten();
+        let value = read_value(&run_id, &temp_dirs.output_dir);
+        let displayed = if config.display { display::read_index(&temp_dirs.output_dir) } else { Vec::new() };
+        drop(collect);
+        timings.collect = phase.elapsed();
         let execution_time = start_time.elapsed();
 
-        Ok(ExecutionResult {
-            stdout: vm_result.stdout,
-            stderr: vm_result.stderr,
+        let kept_vm = if vm_result.vm_kept {
+            let workspace = temp_dirs._temp_base.keep();
+            self.runs.mark_kept(&run_id, &workspace)?;
+            guard.kept = true;
+            info!(
+                "[run {}] Keeping VM {} and workspace {}",
+                run_id,
+                vm_name,
+                workspace.to_string_lossy()
+            );
+            Some(KeptVm { vm_name, workspace })
+        } else {
+            if let (Some(key), true) = (pool_key, vm_result.reusable) {
+                pool::vm_pool().checkin(
+                    key,
+                    PooledVm {
+                        vm_name,
+                        image_ref: image_ref.clone(),
+                        workspace: temp_dirs._temp_base,
+                        uses: uses + 1,
+                    },
+                );
+            }
+            None
+        };
+        if config.pip_cache {
+            image_cache::trim_pip_cache();
+        }
+
+        let result = ExecutionResult {
+            stdout,
+            stderr,
             exit_code: vm_result.exit_code,
             execution_time,
             artifacts,
             image_used: image_ref,
-        })
+            run_id: run_id.clone(),
+            kept_vm,
+            attempts: vm_result.attempts,
+            merged_output: output.as_deref().filter(|_| config.merge_streams).map(merge_streams),
+            output,
+            cached: false,
+            coverage,
+            cprofile: profile,
+            display: displayed,
+            value,
+            peak_memory_bytes: status.as_ref().and_then(|s| s.peak_rss_bytes),
+            timings,
+        };
+        // The partial result goes with the error: what ran before the kill is still worth having
+        if oom {
+            return Err(VMError::OutOfMemory(format!(
+                "guest ran out of memory with memory_mb={}; raise memory_mb (e.g. memory_mb={})",
+                config.memory_mb,
+                config.memory_mb.saturating_mul(2)
+            ))
+            .with_stderr(&result.stderr)
+            .with_result(result));
+        }
+        if let Some(msg) = disk_full {
+            return Err(VMError::DiskFull(msg).with_stderr(&result.stderr).with_result(result));
+        }
+        if let Some((at, into_run)) = vm_result.last_heartbeat {
+            return Err(VMError::GuestUnresponsive(format!(
+                "no heartbeat from the guest since {} ({:.1}s into the run); it was killed before its timeout",
+                chrono::DateTime::<chrono::Utc>::from(at).to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                into_run.as_secs_f64()
+            ))
+            .with_stderr(&result.stderr));
+        }
+        // Only results of code that ran to its own end are worth replaying
+        if let (Some(key), true) = (cache_key, vm_result.finished && result.kept_vm.is_none()) {
+            if let Err(e) = result_cache::store(&key, &result) {
+                warn!("[run {}] Failed to store result in the result cache: {}", run_id, e);
+            }
+        }
+        Ok(result)
+    }
+
+    /// OOM as reported by the guest runner, or by the guest kernel on the console when the
+    /// whole guest (runner included) went down.
+    fn guest_ran_out_of_memory(&self, status: Option<&GuestStatus>, vm_result: &VMExecutionResult) -> bool {
+        match status {
+            Some(status) => status.oom,
+            None => {
+                // Only the console carries the kernel's messages; user stdout could print anything
+                vm_result.exit_code != 0 && OOM_MARKERS.iter().any(|m| vm_result.stderr.contains(m))
+            }
+        }
+    }
+
+    /// Describe the /work usage when the workspace filled up, as the guest saw it after the run.
+    /// Output mentioning ENOSPC doesn't count (user code can print anything), and neither do the
+    /// host's numbers when the guest reported none.
+    fn workspace_full(&self, status: Option<&GuestStatus>) -> Option<String> {
+        let (free, total) = status.and_then(|s| s.work_free_bytes.zip(s.work_total_bytes))?;
+        // Even a clean exit is suspect with no space left: writes may have been silently dropped
+        if free >= DISK_FULL_THRESHOLD_BYTES {
+            return None;
+        }
+        const MIB: u64 = 1024 * 1024;
+        Some(format!(
+            "/work is full: {} MiB free of {} MiB ({} MiB used)",
+            free / MIB,
+            total / MIB,
+            total.saturating_sub(free) / MIB
+        ))
+    }
+
+    /// Read the guest's output records and, in `JsonLines` mode, rebuild stdout/stderr from
+    /// them. Console text (runner/krunvm diagnostics) is kept at the end of stderr.
+    fn apply_capture(
+        &self,
+        run_id: &str,
+        mode: CaptureMode,
+        scripts_dir: &Path,
+        console_stdout: String,
+        console_stderr: String,
+    ) -> (String, String, Option<Vec<OutputChunk>>) {
+        if mode == CaptureMode::Stdio {
+            return (console_stdout, console_stderr, None);
+        }
+        let records: Vec<OutputChunk> = match fs::read_to_string(scripts_dir.join(CAPTURE_FILE)) {
+            Ok(data) => data
+                .lines()
+                .filter_map(|line| match serde_json::from_str(line) {
+                    Ok(r) => Some(r),
+                    Err(e) => {
+                        debug!("[run {}] Skipping bad capture record: {}", run_id, e);
+                        None
+                    }
+                })
+                .collect(),
+            // User code never started (e.g. VM start failure): only the console has anything
+            Err(_) => return (console_stdout, console_stderr, Some(vec![])),
+        };
+        if mode == CaptureMode::Both {
+            return (console_stdout, console_stderr, Some(records));
+        }
+        let collect = |stream: &str| -> String {
+            records.iter().filter(|r| r.stream == stream).map(|r| r.chunk.as_str()).collect()
+        };
+        let stdout = collect("stdout");
+        let mut stderr = collect("stderr");
+        for console in [console_stdout, console_stderr] {
+            if !console.trim().is_empty() {
+                stderr.push_str(&console);
+            }
+        }
+        (stdout, stderr, Some(records))
     }
 
     fn normalize_image_for_krunvm(&self, image: &str) -> Result<String, VMError> {
@@ -102,40 +765,88 @@ impl VMRunner {
             return Ok(name.to_string());
         }
         if image.starts_with("oci:") {
-            let tmp_name = format!("localhost/flashvm:imported-{}", &Uuid::new_v4().to_string()[..8]);
+            let tmp_name = image_cache::image_name(&format!("imported-{}", &Uuid::new_v4().to_string()[..8]));
+            image_cache::evict_before_import();
             self.import_oci_to_storage(image, &tmp_name)?;
+            if let Err(e) = ImageCache::new().record(&tmp_name, ImageKind::Imported) {
+                warn!("Failed to track imported image {}: {}", tmp_name, e);
+            }
             return Ok(tmp_name);
         }
         Ok(image.to_string())
     }
 
+    /// Copy an `oci:` layout into storage. When that fails on a layout with zstd layers
+    /// (older skopeo/buildah/containers-storage can't read them), retry from a copy with
+    /// those layers decompressed.
     fn import_oci_to_storage(&self, oci_ref: &str, dest_name: &str) -> Result<(), VMError> {
-        if self.command_exists("skopeo") {
-            let cmd = format!(
-                "skopeo copy --insecure-policy '{}' 'containers-storage:{}'",
-                oci_ref, dest_name
-            );
-            if self.run_in_buildah_unshare(&cmd)? {
-                return Ok(());
-            }
+        let err = match self.copy_oci_to_storage(oci_ref, dest_name) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let (path, tag) = oci_layout::split_ref(oci_ref);
+        if !oci_layout::has_zstd_layers(Path::new(path)).unwrap_or(false) {
+            return Err(err);
         }
-        let from_out = self.run_in_buildah_unshare_capture(&format!("buildah from '{}'", oci_ref))?;
-        if !from_out.success {
-            return Err(VMError::Execution(format!("buildah from failed: {}", from_out.stderr)));
+        warn!("Importing {} failed ({}); retrying with its zstd layers decompressed", oci_ref, err);
+        let converted = oci_layout::decompress_zstd_layers(Path::new(path))?;
+        let converted_ref = match tag {
+            Some(t) => format!("oci:{}:{}", converted.path().to_string_lossy(), t),
+            None => format!("oci:{}", converted.path().to_string_lossy()),
+        };
+        self.copy_oci_to_storage(&converted_ref, dest_name).map_err(|e| {
+            VMError::ImageResolution(format!(
+                "{} has zstd-compressed layers and could not be imported, even decompressed: {}",
+                oci_ref, e
+            ))
+        })
+    }
+
+    fn copy_oci_to_storage(&self, oci_ref: &str, dest_name: &str) -> Result<(), VMError> {
+        let dest = format!("containers-storage:{}", dest_name);
+        if self.command_exists("skopeo")
+            && unshare::run_argv(&["skopeo", "copy", "--insecure-policy", oci_ref, &dest])?.success()
+        {
+            return Ok(());
+        }
+        let from_argv = ["buildah", "from", oci_ref];
+        let from_out = unshare::run_argv(&from_argv)?;
+        if !from_out.success() {
+            return Err(VMError::ImageResolution(format!("buildah from failed: {}", from_out.stderr))
+                .with_command(&from_argv, &from_out.stderr));
         }
         let container = from_out.stdout.trim();
         if container.is_empty() {
             return Err(VMError::Execution("buildah from did not return a name".to_string()));
         }
-        let ok_commit =
-            self.run_in_buildah_unshare(&format!("buildah commit '{}' '{}'", container, dest_name))?;
-        let _ = self.run_in_buildah_unshare(&format!("buildah rm '{}'", container));
-        if !ok_commit {
-            return Err(VMError::Execution("buildah commit failed".to_string()));
+        let commit_argv = ["buildah", "commit", container, dest_name];
+        let commit = unshare::run_argv(&commit_argv)?;
+        let _ = unshare::run_argv(&["buildah", "rm", container]);
+        if !commit.success() {
+            return Err(VMError::ImageResolution(format!("buildah commit failed: {}", commit.stderr))
+                .with_command(&commit_argv, &commit.stderr));
         }
         Ok(())
     }
 
+    /// Best-effort `krunvm delete`, falling back to the pre `-f` syntax of older krunvm.
+    /// Fail with `KILLED` once `kill` has marked the run, so a run killed before its VM is up
+    /// never creates or boots it.
+    fn ensure_not_killed(&self, run_id: &str) -> Result<(), VMError> {
+        if self.runs.was_killed(run_id) {
+            return Err(VMError::Killed(format!("run {} was killed before its VM started", run_id)));
+        }
+        Ok(())
+    }
+
+    fn delete_vm(&self, vm_name: &str) {
+        let forced = unshare::run_argv(&["krunvm", "delete", "-f", vm_name]).map(|o| o.success()).unwrap_or(false);
+        if !forced {
+            let _ = unshare::run_argv(&["krunvm", "delete", vm_name]);
+        }
+        secrets::remove_dir(vm_name);
+    }
+
     fn check_dependencies(&self) -> Result<(), VMError> {
         if !self.command_exists("krunvm") {
             return Err(VMError::MissingDependency(
@@ -143,16 +854,31 @@ impl VMRunner {
             ));
         }
         if !self.command_exists("buildah") {
-            return Err(VMError::MissingDependency(
-                "buildah not found. Required for rootless.".to_string(),
-            ));
+            let msg = if StorageTool::current() == StorageTool::Podman {
+                "buildah not found. krunvm needs it to create VMs; with podman alone flashvm can only manage images."
+            } else {
+                "buildah not found. Required for rootless."
+            };
+            return Err(VMError::MissingDependency(msg.to_string()));
         }
-        if !Path::new("/dev/kvm").exists() {
+        platform::check_hypervisor()
+    }
+
+    /// `allow_egress_ports` is applied by `egress_exec.py`, which the host's python3 runs before
+    /// exec'ing krunvm, with Landlock network rules. Check both here rather than failing the start.
+    fn check_egress_support(&self) -> Result<(), VMError> {
+        if !self.command_exists("python3") {
             return Err(VMError::MissingDependency(
-                "KVM not available. Ensure virtualization is enabled.".to_string(),
+                "python3 not found. allow_egress_ports needs it on the host to apply the policy.".to_string(),
             ));
         }
-        Ok(())
+        match platform::landlock_abi() {
+            Some(abi) if abi >= 4 => Ok(()),
+            abi => Err(VMError::MissingDependency(format!(
+                "allow_egress_ports needs Landlock network rules (ABI 4, Linux 6.7+); this kernel has {}",
+                abi.map_or("no Landlock".to_string(), |abi| format!("ABI {}", abi)),
+            ))),
+        }
     }
 
     fn command_exists(&self, cmd: &str) -> bool {
@@ -163,42 +889,25 @@ impl VMRunner {
             .unwrap_or(false)
     }
 
-    fn run_in_buildah_unshare(&self, sh_cmd: &str) -> Result<bool, VMError> {
-        debug!("Executing: buildah unshare sh -c '{}'", sh_cmd);
-        let status = Command::new("buildah")
-            .args(["unshare", "sh", "-c", sh_cmd])
-            .status()
-            .map_err(|e| VMError::Execution(format!("Failed to execute command: {}", e)))?;
-        Ok(status.success())
-    }
-
-    fn run_in_buildah_unshare_capture(&self, sh_cmd: &str) -> Result<Captured, VMError> {
-        debug!("Executing: buildah unshare sh -c '{}'", sh_cmd);
-        let output = Command::new("buildah")
-            .args(["unshare", "sh", "-c", sh_cmd])
-            .output()
-            .map_err(|e| VMError::Execution(format!("Failed to execute command: {}", e)))?;
-        Ok(Captured {
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-            success: output.status.success(),
-            exit_code: output.status.code(),
-            timed_out: false,
-        })
-    }
-
+    /// Runs `argv` under `buildah unshare` in its own process group (so it can be killed
+    /// as a unit); `on_spawn` receives the group id right after the child starts.
     fn run_in_buildah_unshare_capture_timeout(
         &self,
-        sh_cmd: &str,
+        run_id: &str,
+        argv: &[String],
         timeout: Duration,
+        heartbeat: Option<&Path>,
+        on_spawn: &dyn Fn(i32),
     ) -> Result<Captured, VMError> {
-        debug!("Executing (timeout={:?}): buildah unshare sh -c '{}'", timeout, sh_cmd);
-        let mut child = Command::new("buildah")
-            .args(["unshare", "sh", "-c", sh_cmd])
+        debug!("[run {}] Executing (timeout={:?}): buildah unshare {:?}", run_id, timeout, argv);
+        let mut child = unshare::command("buildah", argv)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .process_group(0)
             .spawn()
             .map_err(|e| VMError::Execution(format!("Failed to spawn command: {}", e)))?;
+        let pgid = child.id() as i32;
+        on_spawn(pgid);
 
         let mut stdout = child
             .stdout
@@ -222,13 +931,49 @@ impl VMRunner {
 
         let start = Instant::now();
         let mut timed_out = false;
+        let mut unresponsive = None;
+        let watchdog = heartbeat.zip(settings::heartbeat_timeout());
+        // Time spent paused counts toward neither the timeout nor the heartbeat watchdog
+        let mut paused_for = Duration::ZERO;
+        let mut paused_since: Option<Instant> = None;
+        let mut resumed_at: Option<SystemTime> = None;
+        let mut extended = Duration::ZERO;
+        let mut record_checked = start;
         let status = loop {
             match child.try_wait() {
                 Ok(Some(status)) => break status,
                 Ok(None) => {
-                    if start.elapsed() >= timeout {
-                        timed_out = true;
-                        let _ = child.kill();
+                    if record_checked.elapsed() >= RECORD_POLL {
+                        record_checked = Instant::now();
+                        if let Ok(Some(record)) = self.runs.get(run_id) {
+                            extended = record.extension();
+                            match (record.paused, paused_since) {
+                                (true, None) => paused_since = Some(Instant::now()),
+                                (false, Some(since)) => {
+                                    paused_for += since.elapsed();
+                                    paused_since = None;
+                                    resumed_at = Some(SystemTime::now());
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    // Watched only once the runner is up: booting is covered by the run's timeout
+                    if let (Some((path, grace)), None) = (watchdog, paused_since) {
+                        let last = fs::metadata(path).and_then(|m| m.modified()).ok();
+                        let quiet_since = last.map(|t| resumed_at.map_or(t, |r| r.max(t)));
+                        if quiet_since.is_some_and(|t| t.elapsed().is_ok_and(|quiet| quiet > grace)) {
+                            unresponsive = last;
+                        }
+                    }
+                    let expired =
+                        paused_since.is_none() && start.elapsed().saturating_sub(paused_for) >= timeout + extended;
+                    if expired || unresponsive.is_some() {
+                        timed_out = unresponsive.is_none();
+                        // SAFETY: plain syscall on the group we created above
+                        unsafe {
+                            libc::kill(-pgid, libc::SIGKILL);
+                        }
                         // Single wait; if it fails, synthesize a 124 exit status (like coreutils timeout)
                         break child
                             .wait()
@@ -257,36 +1002,130 @@ impl VMRunner {
             success: status.success(),
             exit_code,
             timed_out,
+            last_heartbeat: unresponsive,
         })
     }
 
-    fn setup_work_directories(&self) -> Result<WorkDirectories, VMError> {
-        let temp_base = TempDir::new().map_err(VMError::IO)?;
-        let input_dir = temp_base.path().join("in");
-        let output_dir = temp_base.path().join("out");
-        let tmp_dir = temp_base.path().join("tmp");
-        let scripts_dir = temp_base.path().join("scripts");
-        fs::create_dir_all(&input_dir)?;
-        fs::create_dir_all(&output_dir)?;
-        fs::create_dir_all(&tmp_dir)?;
-        fs::create_dir_all(&scripts_dir)?;
-        Ok(WorkDirectories {
-            input_dir,
-            output_dir,
-            _tmp_dir: tmp_dir,
-            scripts_dir,
-            _temp_base: temp_base,
-        })
+    /// Workspace dirs are named after the VM so host paths can be traced back to a run.
+    fn setup_work_directories(&self, vm_name: &str) -> Result<WorkDirectories, VMError> {
+        let temp_base = tempfile::Builder::new()
+            .prefix(&format!("{}-", vm_name))
+            .tempdir()
+            .map_err(VMError::IO)?;
+        WorkDirectories::prepare(temp_base)
     }
 
-    fn prepare_input_files(&self, files_in: &[FileInput], input_dir: &Path) -> Result<(), VMError> {
+    fn prepare_input_files(&self, run_id: &str, files_in: &[FileInput], input_dir: &Path) -> Result<(), VMError> {
+        let input_root = input_dir.canonicalize()?;
         for file_input in files_in {
-            let target_path = input_dir.join(&file_input.guest_path);
+            let target_path = input_dir.join(staged_relative_path(&file_input.guest_path)?);
             if let Some(parent) = target_path.parent() {
                 fs::create_dir_all(parent)?;
+                // Lexical checks can't see symlinks; make sure the real directory is still ours
+                if !parent.canonicalize()?.starts_with(&input_root) {
+                    return Err(VMError::Validation(format!(
+                        "guest_path escapes /work/in: {}",
+                        file_input.guest_path
+                    )));
+                }
+            }
+            stage_file(&file_input.host_path, &target_path).map_err(VMError::IO)?;
+            debug!("[run {}] File copied: {:?} -> {:?}", run_id, file_input.host_path, target_path);
+        }
+        Ok(())
+    }
+
+    /// Option combinations that can be rejected before touching images or VMs.
+    fn validate_config(&self, config: &VMConfig) -> Result<(), VMError> {
+        if !config.requirements.is_empty() && !config.network && config.wheel_dir.is_none() {
+            return Err(VMError::VMConfiguration(
+                "requirements need network=True or a local wheel_dir".to_string(),
+            ));
+        }
+        if !config.requirements.is_empty() && config.wheel_dir.is_none() {
+            settings::require_online("requirements without wheel_dir")?;
+        }
+        if config.egress.is_some() && !config.network {
+            return Err(VMError::VMConfiguration("allow_egress_ports requires network=True".to_string()));
+        }
+        if config.pip_cache && !config.network {
+            return Err(VMError::VMConfiguration("pip_cache requires network=True".to_string()));
+        }
+        if matches!(config.run_as, Some((0, _))) {
+            return Err(VMError::VMConfiguration("run_as uid must not be 0 (root)".to_string()));
+        }
+        if config.rlimits.nofile == Some(0) || config.rlimits.nproc == Some(0) {
+            return Err(VMError::VMConfiguration("rlimits nofile and nproc must be at least 1".to_string()));
+        }
+        if let Some(profile) = &config.seccomp {
+            profile.guest_filter().map_err(VMError::VMConfiguration)?;
+        }
+        config.trace.validate().map_err(VMError::VMConfiguration)?;
+        if let Some(timezone) = &config.timezone {
+            locale::validate_timezone(timezone).map_err(VMError::VMConfiguration)?;
+        }
+        if let Some(name) = &config.locale {
+            locale::validate_locale(name).map_err(VMError::VMConfiguration)?;
+        }
+        self.validate_ports(config)
+    }
+
+    /// Catch bad port mappings up front; krunvm only reports them as an opaque start failure.
+    fn validate_ports(&self, config: &VMConfig) -> Result<(), VMError> {
+        if config.ports.is_empty() {
+            return Ok(());
+        }
+        if !config.network {
+            return Err(VMError::VMConfiguration("ports require network=True".to_string()));
+        }
+        let mut seen_host = std::collections::HashSet::new();
+        let mut seen_guest = std::collections::HashSet::new();
+        for &(host, guest) in &config.ports {
+            if host == 0 || guest == 0 {
+                return Err(VMError::VMConfiguration(format!("invalid port mapping {}:{}: port 0", host, guest)));
+            }
+            if !seen_host.insert(host) {
+                return Err(VMError::VMConfiguration(format!("host port {} is mapped more than once", host)));
+            }
+            if !seen_guest.insert(guest) {
+                return Err(VMError::VMConfiguration(format!("guest port {} is mapped more than once", guest)));
+            }
+            if host < 1024 && !config.allow_privileged_ports {
+                return Err(VMError::VMConfiguration(format!(
+                    "host port {} is privileged; pass allow_privileged_ports=True to map it",
+                    host
+                )));
+            }
+            if let Err(e) = std::net::TcpListener::bind(("0.0.0.0", host)) {
+                if e.kind() == std::io::ErrorKind::AddrInUse {
+                    return Err(VMError::VMConfiguration(format!("host port {} is already in use", host)));
+                }
+                // Other failures (e.g. EACCES for privileged ports) are left for krunvm to report
+                debug!("Could not probe host port {}: {}", host, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mirror the wheel directory into the workspace (hardlinks when possible).
+    fn stage_wheel_dir(&self, wheel_dir: &Path, target: &Path) -> Result<(), VMError> {
+        if !wheel_dir.is_dir() {
+            return Err(VMError::VMConfiguration(format!(
+                "wheel_dir is not a directory: {}",
+                wheel_dir.to_string_lossy()
+            )));
+        }
+        fs::create_dir_all(target)?;
+        for entry in fs::read_dir(wheel_dir)? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Some(name) = path.file_name() else { continue };
+            let dest = target.join(name);
+            if fs::hard_link(&path, &dest).is_err() {
+                fs::copy(&path, &dest)?;
             }
-            fs::copy(&file_input.host_path, &target_path).map_err(VMError::IO)?;
-            debug!("File copied: {:?} -> {:?}", file_input.host_path, target_path);
         }
         Ok(())
     }
@@ -298,38 +1137,88 @@ impl VMRunner {
         Ok(script_file)
     }
 
+    /// Stage the static runner plus its JSON spec (env, args, entry script) in `scripts_dir`.
     fn create_guest_runner(
         &self,
+        run_id: &str,
         config: &VMConfig,
         scripts_dir: &Path,
         main_script: &str,
     ) -> Result<String, VMError> {
-        let runner_path = scripts_dir.join("run.py");
-        let env_json = serde_json::to_string(&config.env).map_err(|e| VMError::Execution(e.to_string()))?;
-        let args_json =
-            serde_json::to_string(&config.python_args).map_err(|e| VMError::Execution(e.to_string()))?;
-        let runner_code = format!(
-            "#!/usr/bin/env python3\n\
-             import os, sys, json, subprocess\n\
-             ENV=json.loads(r'''{}''')\n\
-             PY_ARGS=json.loads(r'''{}''')\n\
-             os.environ.update({{k:str(v) for k,v in ENV.items()}})\n\
-             cmd=['/usr/bin/env','python3']+PY_ARGS+['/work/scripts/{}']\n\
-             res=subprocess.run(cmd)\n\
-             sys.exit(res.returncode)\n",
-            env_json, args_json, main_script
-        );
-        fs::write(&runner_path, runner_code.as_bytes())?;
+        // Explicit env entries win over the proxy defaults
+        let mut env: HashMap<String, String> = config
+            .proxy
+            .iter()
+            .flat_map(|p| p.env_vars())
+            .chain(config.trace.env_vars())
+            .chain(locale::env_vars(config.locale.as_deref()))
+            .collect();
+        if config.pip_cache {
+            env.insert("PIP_CACHE_DIR".to_string(), image_cache::PIP_CACHE_MOUNT.to_string());
+            env.insert("UV_CACHE_DIR".to_string(), format!("{}/uv", image_cache::PIP_CACHE_MOUNT));
+        }
+        env.extend(config.env.clone());
+        let spec = GuestSpec {
+            env: &env,
+            python_args: &config.python_args,
+            main: format!("/work/scripts/{}", main_script),
+            started_marker: format!("/work/scripts/{}", STARTED_MARKER),
+            run_id,
+            requirements: &config.requirements,
+            find_links: config.wheel_dir.as_ref().map(|_| GUEST_WHEEL_DIR),
+            use_uv: config.use_uv,
+            capture: config.effective_capture().as_str(),
+            capture_path: format!("/work/scripts/{}", CAPTURE_FILE),
+            status_path: format!("/work/scripts/{}", guest_channel::STATUS_FILE),
+            run_as: config.run_as,
+            rlimits: (!config.rlimits.is_empty()).then_some(config.rlimits),
+            seccomp: config
+                .seccomp
+                .as_ref()
+                .map(|p| p.guest_filter())
+                .transpose()
+                .map_err(VMError::VMConfiguration)?,
+            secrets_in: (!config.secrets.is_empty()).then_some(secrets::GUEST_SECRETS_IN),
+            coverage: config.coverage.then(|| GuestCoverage {
+                data_file: format!("/work/out/{}", coverage::DATA_FILE),
+                report: format!("/work/scripts/{}", coverage::REPORT_FILE),
+            }),
+            cprofile: config.cprofile.then(|| GuestProfile {
+                stats_file: format!("/work/out/{}", cprofile::STATS_FILE),
+                report: format!("/work/scripts/{}", cprofile::REPORT_FILE),
+                top: cprofile::TOP_N,
+            }),
+            display: config.display.then(|| format!("/work/out/{}", display::DIR)),
+            timezone: match &config.timezone {
+                Some(name) => Some(GuestTimezone {
+                    name: name.clone(),
+                    zoneinfo: locale::stage_zoneinfo(name, scripts_dir)?
+                        .then(|| format!("/work/scripts/{}", locale::ZONEINFO_FILE)),
+                }),
+                None => None,
+            },
+            heartbeat: settings::heartbeat_timeout().map(|_| GuestHeartbeat {
+                path: format!("/work/scripts/{}", guest_channel::HEARTBEAT_FILE),
+                interval_seconds: guest_channel::HEARTBEAT_INTERVAL.as_secs_f64(),
+            }),
+        };
+        guest_channel::write_spec(scripts_dir, &spec)?;
+        fs::write(scripts_dir.join("run.py"), GUEST_RUNNER.as_bytes())?;
         Ok("/work/scripts/run.py".to_string())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn run_vm_with_krunvm(
         &self,
+        run_id: &str,
+        vm_name: &str,
+        needs_create: bool,
         image_ref: &str,
         script_file: &NamedTempFile,
         config: &VMConfig,
         work_dirs: &WorkDirectories,
     ) -> Result<VMExecutionResult, VMError> {
+        let stage_start = Instant::now();
         // Copia o script principal para /work/scripts/main.py
         let script_filename = "main.py";
         let script_target = work_dirs.scripts_dir.join(script_filename);
@@ -341,68 +1230,193 @@ impl VMRunner {
             )));
         }
 
-        let runner_path_guest = self.create_guest_runner(config, &work_dirs.scripts_dir, script_filename)?;
+        let runner_path_guest = self.create_guest_runner(run_id, config, &work_dirs.scripts_dir, script_filename)?;
+        let stage_time = stage_start.elapsed();
 
         // Comando dentro da VM: rodar diretamente python sem shell
-        let start_cmd = format!(
-            "krunvm start {vm} /usr/bin/env python3 {runner}",
-            vm = "{vm}", // placeholder trocado no format abaixo
-            runner = shell_escape(&runner_path_guest),
+        let mut start_argv: Vec<String> = vec![];
+        if let Some(egress) = &config.egress {
+            let ports = serde_json::to_string(&egress.allowed_ports).map_err(|e| VMError::Execution(e.to_string()))?;
+            start_argv.extend(["python3".to_string(), "-c".to_string(), EGRESS_EXEC.to_string(), ports]);
+        }
+        start_argv.extend(
+            ["krunvm", "start", vm_name, "/usr/bin/env", "python3", &runner_path_guest]
+                .iter()
+                .map(|s| s.to_string()),
         );
 
-        // Script de controle (create → start com retries → delete)
-        let vm_name = format!("flashvm-{}", &Uuid::new_v4().to_string()[..8]);
         let work_base = work_dirs._temp_base.path().to_string_lossy().to_string();
-        let mut ports_args = String::new();
+        let mut create_argv: Vec<String> = vec![
+            "krunvm".into(),
+            "create".into(),
+            "--cpus".into(),
+            config.cpus.to_string(),
+            "--mem".into(),
+            config.memory_mb.to_string(),
+            "--workdir".into(),
+            config.workdir.clone(),
+            "--name".into(),
+            vm_name.to_string(),
+            "--volume".into(),
+            format!("{}:/work", work_base),
+        ];
+        if needs_create {
+            let secrets_dir = secrets::ensure_dir(vm_name)?;
+            create_argv.push("--volume".into());
+            create_argv.push(format!("{}:{}", secrets_dir.to_string_lossy(), secrets::GUEST_SECRETS_IN));
+        }
+        if config.pip_cache {
+            let dir = image_cache::pip_cache_dir()?.ok_or_else(|| {
+                VMError::VMConfiguration("pip_cache=True but the shared pip cache is disabled in configure_cache".to_string())
+            })?;
+            create_argv.push("--volume".into());
+            create_argv.push(format!("{}:{}", dir.to_string_lossy(), image_cache::PIP_CACHE_MOUNT));
+        }
         if config.network {
             for (host, guest) in &config.ports {
-                ports_args.push_str(&format!(" --port {}:{}", host, guest));
-            }
-        }
-
-        let shell_script = format!(
-            "set -e\n\
-             krunvm create --cpus {cpus} --mem {mem} --workdir {workdir} --name {vm} --volume '{work}:/work'{ports} {image} >/dev/null\n\
-             set +e\n\
-             tries=0\n\
-             ec=1\n\
-             while [ $tries -lt 3 ]; do\n\
-               {start}\n\
-               ec=$?\n\
-               [ $ec -eq 0 ] && break\n\
-               tries=$((tries+1))\n\
-               sleep 0.15\n\
-             done\n\
-             set -e\n\
-             krunvm delete -f {vm} >/dev/null 2>&1 || krunvm delete {vm} >/dev/null 2>&1 || true\n\
-             exit $ec\n",
-            cpus = config.cpus,
-            mem = config.memory_mb,
-            workdir = shell_escape(&config.workdir),
-            vm = shell_escape(&vm_name),
-            work = shell_escape(&work_base),
-            ports = ports_args,
-            image = image_ref,
-            start = start_cmd.replace("{vm}", &shell_escape(&vm_name)),
-        );
+                create_argv.push("--port".into());
+                create_argv.push(format!("{}:{}", host, guest));
+            }
+        }
+        create_argv.push(image_ref.to_string());
+        let record_pgid = |pgid: i32| match self.runs.set_pgid(run_id, pgid) {
+            // `kill` found no process group to signal when it marked the run
+            // SAFETY: plain syscall on the group just spawned; a stale pgid just yields ESRCH
+            Ok(true) => unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            },
+            Ok(false) => {}
+            Err(e) => debug!("[run {}] Failed to record process group: {}", run_id, e),
+        };
 
-        // Timeout total = timeout de usuário + pequena folga p/ create/delete
-        let hard_timeout = config.timeout + Duration::from_secs(2);
-        let out = self.run_in_buildah_unshare_capture_timeout(&shell_script, hard_timeout)?;
+        let create_start = Instant::now();
+        if needs_create {
+            self.ensure_not_killed(run_id)?;
+            let _create = info_span!("flashvm.create").entered();
+            let created =
+                self.run_in_buildah_unshare_capture_timeout(run_id, &create_argv, config.timeout, None, &record_pgid)?;
+            if !created.success {
+                self.delete_vm(vm_name);
+                self.ensure_not_killed(run_id)?;
+                let argv: Vec<&str> = create_argv.iter().map(String::as_str).collect();
+                let err = if created.timed_out {
+                    VMError::Timeout(format!("krunvm create timed out for {}", vm_name))
+                } else {
+                    VMError::VMConfiguration(format!("krunvm create failed for {}: {}", vm_name, created.stderr))
+                };
+                return Err(err.with_command(&argv, &created.stderr).with_image(image_ref));
+            }
+        }
+        let create_time = create_start.elapsed();
 
-        // Cleanup extra se houve timeout ou falha antes do delete interno
-        if out.timed_out || !out.success {
-            let _ = self.run_in_buildah_unshare(&format!(
-                "krunvm delete -f {vm} >/dev/null 2>&1 || krunvm delete {vm} >/dev/null 2>&1 || true",
-                vm = shell_escape(&vm_name)
-            ));
+        // Removed again when the run ends; the guest normally consumes them right after boot
+        let _staged_secrets = if config.secrets.is_empty() {
+            None
+        } else {
+            Some(secrets::stage(vm_name, &config.secrets)?)
+        };
+
+        // The runner touches this marker before launching user code: once it exists, a
+        // non-zero exit belongs to the user and must never be retried.
+        let started_marker = work_dirs.scripts_dir.join(STARTED_MARKER);
+        let heartbeat = work_dirs.scripts_dir.join(guest_channel::HEARTBEAT_FILE);
+        let policy = &config.retry_policy;
+        let deadline = Instant::now() + config.timeout;
+        let mut attempts = 0;
+        let start_done = AtomicBool::new(false);
+        let run_span = Span::current();
+        let started_at = (Instant::now(), SystemTime::now());
+        let out = std::thread::scope(|scope| {
+            if !run_span.is_disabled() {
+                scope.spawn(|| telemetry::trace_boot_and_exec(&run_span, &started_marker, &start_done));
+            }
+            let mut start = || loop {
+                if self.runs.was_killed(run_id) {
+                    // Created (or taken from the pool) before the kill's delete could see it
+                    self.delete_vm(vm_name);
+                    self.ensure_not_killed(run_id)?;
+                }
+                attempts += 1;
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let out = self.run_in_buildah_unshare_capture_timeout(
+                    run_id,
+                    &start_argv,
+                    remaining,
+                    Some(&heartbeat),
+                    &record_pgid,
+                )?;
+                if out.success
+                    || out.timed_out
+                    || out.last_heartbeat.is_some()
+                    || attempts >= policy.max_attempts
+                    || started_marker.exists()
+                    || self.runs.was_killed(run_id)
+                    || !policy.is_retriable(&out.stderr)
+                {
+                    break Ok::<_, VMError>(out);
+                }
+                let backoff = policy.backoff_for(attempts);
+                if Instant::now() + backoff >= deadline {
+                    break Ok(out);
+                }
+                warn!(
+                    "[run {}] krunvm start failed for {} (attempt {}/{}), retrying in {:?}: {}",
+                    run_id,
+                    vm_name,
+                    attempts,
+                    policy.max_attempts,
+                    backoff,
+                    out.stderr.trim()
+                );
+                std::thread::sleep(backoff);
+            };
+            let out = start();
+            start_done.store(true, Ordering::SeqCst);
+            out
+        })?;
+        // The marker is created through the /work share, so its mtime is on the host's clock
+        let start_time = started_at.0.elapsed();
+        let boot_time = fs::metadata(&started_marker)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(started_at.1).ok())
+            .map_or(start_time, |boot| boot.min(start_time));
+
+        let killed = self.runs.was_killed(run_id);
+        let vm_kept = !killed
+            && match config.keep_vm {
+                KeepVm::Never => false,
+                KeepVm::OnFailure => out.timed_out || out.last_heartbeat.is_some() || !out.success,
+                KeepVm::Always => true,
+            };
+        let finished = !killed && !out.timed_out && out.last_heartbeat.is_none() && started_marker.exists();
+        // Pooled VMs go back to the pool only if user code actually ran and finished on its own
+        let reusable = config.use_pool && !vm_kept && finished;
+        if !vm_kept && !reusable {
+            self.delete_vm(vm_name);
+        }
+
+        let mut stderr = out.stderr;
+        let mut exit_code = out.exit_code.unwrap_or(-1);
+        if killed {
+            // Same convention as a shell reporting SIGKILL
+            exit_code = 137;
+            stderr.push_str(&format!("\nflashvm: run {} was killed\n", run_id));
         }
 
         Ok(VMExecutionResult {
             stdout: out.stdout,
-            stderr: out.stderr,
-            exit_code: out.exit_code.unwrap_or(-1),
-            success: out.success,
+            stderr,
+            exit_code,
+            vm_kept,
+            attempts,
+            reusable,
+            finished,
+            stage_time,
+            create_time,
+            boot_time,
+            exec_time: start_time - boot_time,
+            last_heartbeat: out.last_heartbeat.map(|t| (t, t.duration_since(started_at.1).unwrap_or_default())),
         })
     }
 
@@ -411,37 +1425,164 @@ impl VMRunner {
         expect: &[FileOutput],
         output_dir: &Path,
         max_inline: u64,
+        map: bool,
     ) -> Result<Vec<Artifact>, VMError> {
-        let mut artifacts = Vec::new();
+        let mut paths = Vec::new();
+        let mut seen = HashSet::new();
         for file_output in expect {
             // Accept patterns either like "*.csv" or "out/*.csv" (docs show both styles)
             let mut user_pat = file_output.pattern.clone();
             if let Some(stripped) = user_pat.strip_prefix("out/") { user_pat = stripped.to_string(); }
             let pattern = output_dir.join(&user_pat);
             let pattern_str = pattern.to_string_lossy().to_string();
-            for entry in glob(&pattern_str).map_err(|e| VMError::Execution(e.msg.to_string()))? {
-                if let Ok(path) = entry {
-                    if path.is_file() {
-                        let metadata = fs::metadata(&path)?;
-                        let size_bytes = metadata.len();
-                        let content = if size_bytes <= max_inline { Some(fs::read(&path)?) } else { None };
-                        let guest_rel = path.strip_prefix(output_dir).unwrap_or(&path);
-                        let guest_path = format!("out/{}", guest_rel.to_string_lossy());
-                        artifacts.push(Artifact { guest_path, host_path: path.clone(), size_bytes, content });
-                    }
+            for path in glob(&pattern_str).map_err(|e| VMError::Execution(e.msg.to_string()))?.flatten() {
+                // Overlapping patterns (or the coverage data file) name a file once
+                if path.is_file() && seen.insert(path.clone()) {
+                    paths.push(path);
                 }
             }
         }
-        Ok(artifacts)
+        if paths.len() < PARALLEL_COLLECT_MIN {
+            return paths.iter().map(|path| read_artifact(path, output_dir, max_inline, map)).collect();
+        }
+        // Reading is what runs with thousands of small outputs wait on; artifacts keep glob order
+        let next = AtomicUsize::new(0);
+        let read: Mutex<Vec<Option<Result<Artifact, VMError>>>> = Mutex::new(paths.iter().map(|_| None).collect());
+        let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).min(MAX_COLLECT_THREADS);
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(path) = paths.get(i) else { break };
+                    let artifact = read_artifact(path, output_dir, max_inline, map);
+                    read.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(artifact);
+                });
+            }
+        });
+        read.into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_iter()
+            .map(|artifact| artifact.expect("every artifact path is read"))
+            .collect()
     }
 }
 
+fn read_artifact(path: &Path, output_dir: &Path, max_inline: u64, map: bool) -> Result<Artifact, VMError> {
+    let size_bytes = fs::metadata(path)?.len();
+    let content = match (size_bytes <= max_inline, map) {
+        (false, _) => None,
+        (true, true) => Some(ArtifactContent::map(path)?),
+        (true, false) => Some(ArtifactContent::read(path)?),
+    };
+    let guest_rel = path.strip_prefix(output_dir).unwrap_or(path);
+    let guest_path = format!("out/{}", guest_rel.to_string_lossy());
+    Ok(Artifact { guest_path, host_path: path.to_path_buf(), size_bytes, content })
+}
+
+/// Hand `listener` each record the guest appends to the capture file at `path`, until `done`
+/// is set and the file has been read to its end.
+fn follow_capture(path: &Path, done: &AtomicBool, listener: &(dyn Fn(&OutputChunk) + Send + Sync)) {
+    let mut offset = 0;
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let finished = done.load(Ordering::SeqCst);
+        if let Ok(mut file) = fs::File::open(path) {
+            if file.seek(SeekFrom::Start(offset)).is_ok() {
+                let before = pending.len();
+                let _ = file.read_to_end(&mut pending);
+                offset += (pending.len() - before) as u64;
+            }
+        }
+        // The guest writes whole lines, but a read can still land mid-line
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            if let Ok(chunk) = serde_json::from_slice::<OutputChunk>(&line) {
+                listener(&chunk);
+            }
+        }
+        if finished {
+            return;
+        }
+        std::thread::sleep(CAPTURE_POLL);
+    }
+}
+
+/// Copy an input into the workspace as a reflink where the filesystem shares extents (btrfs,
+/// XFS, bcachefs), so multi-GB datasets stage without copying data; a plain copy otherwise.
+/// Inputs are never hardlinked, not even read-only ones: the guest can write to /work/in as root,
+/// which ignores the mode bits (and can chmod the file), and a shared inode would carry those
+/// writes, and `run_as`'s chown, back to the caller's file.
+fn stage_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let source = fs::File::open(src)?;
+        let target = fs::File::create(dst)?;
+        // SAFETY: FICLONE takes the source fd as its argument and touches no memory of ours; both
+        // fds stay open for the whole call, since `source` and `target` live until the block ends
+        if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == 0 {
+            return target.set_permissions(source.metadata()?.permissions());
+        }
+    }
+    // Uses copy_file_range on Linux, which still avoids a trip through userspace; on macOS it
+    // clones where APFS can
+    fs::copy(src, dst).map(|_| ())
+}
+
+/// The JSON the code left in `VALUE_FILE`, if it wrote one. Invalid JSON is logged and dropped
+/// rather than failing a run whose output is otherwise intact.
+fn read_value(run_id: &str, output_dir: &Path) -> Option<serde_json::Value> {
+    let data = fs::read(output_dir.join(VALUE_FILE)).ok()?;
+    serde_json::from_slice(&data)
+        .map_err(|e| warn!("[run {}] Ignoring out/{}: {}", run_id, VALUE_FILE, e))
+        .ok()
+}
+
+/// Interleave output records line by line. Each line is stamped with the time its first
+/// chunk arrived, so a traceback line sorts correctly against prints from the other stream.
+fn merge_streams(records: &[OutputChunk]) -> String {
+    // (stream, ts of the first chunk, text) for complete lines
+    let mut lines: Vec<(&str, f64, String)> = vec![];
+    let mut pending: HashMap<&str, (f64, String)> = HashMap::new();
+    for r in records {
+        let mut rest = r.chunk.as_str();
+        while !rest.is_empty() {
+            let entry = pending.entry(r.stream.as_str()).or_insert_with(|| (r.ts, String::new()));
+            match rest.find('\n') {
+                Some(i) => {
+                    entry.1.push_str(&rest[..i]);
+                    let (ts, text) = pending.remove(r.stream.as_str()).unwrap_or_default();
+                    lines.push((r.stream.as_str(), ts, text));
+                    rest = &rest[i + 1..];
+                }
+                None => {
+                    entry.1.push_str(rest);
+                    rest = "";
+                }
+            }
+        }
+    }
+    lines.extend(pending.into_iter().map(|(stream, (ts, text))| (stream, ts, text)));
+    lines.sort_by(|a, b| a.1.total_cmp(&b.1));
+    lines.iter().map(|(stream, ts, text)| format!("[+{:.3}s {}] {}\n", ts, stream, text)).collect()
+}
+
 #[derive(Debug)]
 struct VMExecutionResult {
     stdout: String,
     stderr: String,
     exit_code: i32,
-    success: bool,
+    vm_kept: bool,
+    attempts: u32,
+    /// VM may be returned to the warm pool
+    reusable: bool,
+    /// User code ran and finished on its own (not timed out or killed)
+    finished: bool,
+    stage_time: Duration,
+    create_time: Duration,
+    boot_time: Duration,
+    exec_time: Duration,
+    /// Set when the guest stopped sending heartbeats: the last one, and how far into the run it came
+    last_heartbeat: Option<(SystemTime, Duration)>,
 }
 
 #[derive(Debug)]
@@ -451,4 +1592,6 @@ struct Captured {
     success: bool,
     exit_code: Option<i32>,
     timed_out: bool,
+    /// Set when the watchdog killed the guest: the last heartbeat it sent
+    last_heartbeat: Option<SystemTime>,
 }
@@ -485,3 +485,61 @@ class TestImageManagement:
                 # May fail due to network or system configuration
                 # This is acceptable for testing
                 assert isinstance(e, Exception)
+
+
+class TestConcurrentExecution:
+    """Stress parallel executions from many threads."""
+
+    @pytest.mark.integration
+    @pytest.mark.requires_vm
+    @pytest.mark.slow
+    def test_dozens_of_parallel_runs(self, vm_ready):
+        """Each thread gets its own VM, run ID and output."""
+        import flashvm as rip
+        from concurrent.futures import ThreadPoolExecutor
+
+        rip.set_max_concurrent_vms(8)
+
+        def one(i):
+            return i, rip.run(f"print({i} * 2)", timeout_seconds=120)
+
+        with ThreadPoolExecutor(max_workers=32) as pool:
+            results = list(pool.map(one, range(32)))
+
+        run_ids = set()
+        for i, res in results:
+            assert res["exit_code"] == 0, res["stderr"]
+            assert res["stdout"].strip() == str(i * 2)
+            run_ids.add(res["run_id"])
+        assert len(run_ids) == len(results)
+
+    @pytest.mark.integration
+    @pytest.mark.requires_vm
+    def test_scheduler_queues_and_turns_away_excess_runs(self, vm_ready):
+        """Runs beyond the limits wait in line; ones that can't wait are turned away."""
+        import flashvm as rip
+        from concurrent.futures import ThreadPoolExecutor
+
+        previous = rip.scheduler_stats()
+        rip.configure_scheduler(max_vms=1, max_memory_mb=0, max_queue=1)
+        try:
+            def one(i):
+                try:
+                    return rip.run("import time; time.sleep(2)", timeout_seconds=60, queue_timeout_seconds=30)
+                except rip.FlashVMOverloaded as e:
+                    return e
+
+            with ThreadPoolExecutor(max_workers=3) as pool:
+                results = list(pool.map(one, range(3)))
+            stats = rip.scheduler_stats()
+        finally:
+            rip.configure_scheduler(
+                max_vms=previous["max_vms"],
+                max_memory_mb=previous["max_memory_mb"],
+                max_queue=previous["max_queue"],
+            )
+
+        overloaded = [r for r in results if isinstance(r, rip.FlashVMOverloaded)]
+        assert len(overloaded) == 1 and overloaded[0].code == "OVERLOADED"
+        assert all(r["exit_code"] == 0 for r in results if isinstance(r, dict))
+        assert stats["rejected_total"] >= 1 and stats["running"] == 0 and stats["queued"] == 0
@@ -0,0 +1,54 @@
+import io
+
+import pytest
+
+
+@pytest.mark.unit
+def test_stage_arrays_writes_npy_files(check_rip_available, tmp_path):
+    np = pytest.importorskip("numpy")
+    import flashvm as rip
+    from flashvm.arrays import stage_arrays
+
+    matrix = np.arange(12, dtype=np.float32).reshape(3, 4)
+    files_in = stage_arrays({"matrix": matrix, "labels": np.array([1, 2, 3])}, directory=str(tmp_path))
+    assert files_in == [(str(tmp_path / "matrix.npy"), "matrix.npy"), (str(tmp_path / "labels.npy"), "labels.npy")]
+    assert np.array_equal(np.load(files_in[0][0], mmap_mode="r"), matrix)
+
+    with pytest.raises(rip.FlashVMValidationError):
+        stage_arrays({"../escape": matrix}, directory=str(tmp_path))
+    with pytest.raises(rip.FlashVMValidationError):
+        stage_arrays({"objects": np.array([{}, []], dtype=object)}, directory=str(tmp_path))
+
+
+@pytest.mark.unit
+def test_load_arrays_views_inline_artifacts(check_rip_available, tmp_path):
+    np = pytest.importorskip("numpy")
+    import flashvm as rip
+    from flashvm.arrays import load_arrays
+
+    def npy(array):
+        buf = io.BytesIO()
+        np.save(buf, array)
+        return buf.getvalue()
+
+    mean = np.linspace(0, 1, 5)
+    fortran = np.asfortranarray(np.arange(6).reshape(2, 3))
+    content = npy(mean)
+    result = {
+        "artifacts": [
+            {"guest_path": "out/stats/mean.npy", "host_path": "/nonexistent", "size_bytes": len(content), "content": content},
+            {"guest_path": "out/f.npy", "host_path": "/nonexistent", "size_bytes": 0, "content": npy(fortran)},
+            {"guest_path": "out/log.txt", "host_path": "/nonexistent", "size_bytes": 2, "content": b"ok"},
+        ]
+    }
+    arrays = load_arrays(result)
+    assert sorted(arrays) == ["f", "stats/mean"]
+    assert np.array_equal(arrays["stats/mean"], mean)
+    assert np.shares_memory(arrays["stats/mean"], np.frombuffer(content, dtype=np.uint8))
+    assert not arrays["stats/mean"].flags.writeable
+    assert np.array_equal(arrays["f"], fortran)
+
+    big = {"guest_path": "out/big.npy", "host_path": str(tmp_path / "gone.npy"), "size_bytes": 10**9}
+    with pytest.raises(rip.FlashVMValidationError) as exc:
+        load_arrays({"artifacts": [big]})
+    assert "max_bytes_inline" in str(exc.value)
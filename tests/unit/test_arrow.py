@@ -0,0 +1,54 @@
+import pytest
+
+
+@pytest.mark.unit
+def test_stage_tables_writes_ipc_files(check_rip_available, tmp_path):
+    pa = pytest.importorskip("pyarrow")
+    import flashvm as rip
+    from flashvm.arrow import stage_tables
+
+    table = pa.table({"id": [1, 2, 3], "name": ["a", "b", "c"]})
+    batch = pa.RecordBatch.from_pydict({"x": [0.5]})
+    files_in = stage_tables({"people": table, "batch": batch, "plain": {"y": [1]}}, directory=str(tmp_path))
+    assert [guest for _, guest in files_in] == ["people.arrow", "batch.arrow", "plain.arrow"]
+    with pa.memory_map(files_in[0][0], "r") as source:
+        assert pa.ipc.open_file(source).read_all().equals(table)
+
+    with pytest.raises(rip.FlashVMValidationError):
+        stage_tables({"a/b": table}, directory=str(tmp_path))
+
+
+@pytest.mark.unit
+def test_load_tables_opens_arrow_and_feather_artifacts(check_rip_available, tmp_path):
+    pa = pytest.importorskip("pyarrow")
+    import pyarrow.feather
+    import flashvm as rip
+    from flashvm.arrow import load_tables
+
+    table = pa.table({"day": [1, 2], "total": [10.0, 12.5]})
+    sink = pa.BufferOutputStream()
+    with pa.ipc.new_file(sink, table.schema) as writer:
+        writer.write_table(table)
+    inline = sink.getvalue().to_pybytes()
+    on_disk = tmp_path / "daily.feather"
+    pyarrow.feather.write_feather(table, str(on_disk))
+
+    result = {
+        "artifacts": [
+            {"guest_path": "out/stats/daily.arrow", "host_path": "/nonexistent", "size_bytes": len(inline), "content": inline},
+            {"guest_path": "out/daily.feather", "host_path": str(on_disk), "size_bytes": on_disk.stat().st_size},
+            {"guest_path": "out/log.txt", "host_path": "/nonexistent", "size_bytes": 2, "content": b"ok"},
+        ]
+    }
+    tables = load_tables(result)
+    assert sorted(tables) == ["daily", "stats/daily"]
+    assert tables["stats/daily"].equals(table)
+    assert tables["daily"].equals(table)
+
+    gone = {"guest_path": "out/big.arrow", "host_path": str(tmp_path / "gone.arrow"), "size_bytes": 10**9}
+    with pytest.raises(rip.FlashVMValidationError) as exc:
+        load_tables({"artifacts": [gone]})
+    assert "max_bytes_inline" in str(exc.value)
+    garbage = {"guest_path": "out/bad.arrow", "host_path": "/nonexistent", "size_bytes": 3, "content": b"abc"}
+    with pytest.raises(rip.FlashVMValidationError):
+        load_tables({"artifacts": [garbage]})
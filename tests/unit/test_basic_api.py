@@ -37,7 +37,132 @@ class TestBasicAPI:
         assert isinstance(result['kvm'], bool)
         assert isinstance(result['offline_mode'], bool)
         assert isinstance(result['ready'], bool)
+        assert result['storage_tool'] in ('buildah', 'podman')
+
+    def test_doctor_report_checks(self, check_rip_available):
+        """Test doctor() reports each check with a hint when it fails."""
+        import flashvm as rip
+
+        result = rip.doctor()
+        names = {c['name'] for c in result['checks']}
+        assert {'krunvm', 'buildah', 'kvm', 'user_namespaces', 'cache_dir'} <= names
+        for check in result['checks']:
+            assert isinstance(check['ok'], bool)
+            assert isinstance(check['detail'], str)
+            if not check['ok']:
+                assert check['hint']
+        required_ok = all(c['ok'] for c in result['checks'] if c['required'])
+        assert result['ready'] == required_ok
     
+    def test_remove_untracked_cached_image(self, check_rip_available):
+        """Test remove_cached_image() never touches images flashvm didn't create."""
+        import flashvm as rip
+
+        assert rip.remove_cached_image("docker.io/library/not-flashvm:latest") is False
+
+    def test_list_cached_images_structure(self, vm_ready):
+        """Test list_cached_images() reports tracked images with usage metadata."""
+        import flashvm as rip
+
+        rip.prepare_image()
+        images = rip.list_cached_images()
+        embedded = [img for img in images if img['kind'] == 'embedded']
+        assert embedded and embedded[0]['name'] == 'localhost/flashvm:python-basic'
+        for img in images:
+            assert {'name', 'kind', 'size_bytes', 'created_at', 'last_used'} <= set(img)
+
+    def test_evict_cache_within_budget(self, vm_ready):
+        """Test evict_cache() keeps everything when images fit the TTL and size budget."""
+        import flashvm as rip
+
+        rip.prepare_image()
+        rip.configure_cache(ttl_seconds=0, max_size_mb=0)
+        try:
+            assert rip.evict_cache() == []
+            assert any(img['kind'] == 'embedded' for img in rip.list_cached_images())
+        finally:
+            rip.configure_cache(ttl_seconds=24 * 3600, max_size_mb=1024)
+
+    def test_gc_images_keeps_embedded(self, vm_ready):
+        """Test gc_images() never collects the embedded image and validates max_age."""
+        import flashvm as rip
+
+        rip.prepare_image()
+        assert 'localhost/flashvm:python-basic' not in rip.gc_images(max_total_mb=1, max_age=1)
+        assert any(img['kind'] == 'embedded' for img in rip.list_cached_images())
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.gc_images(max_age=-1)
+
+    def test_image_namespace(self, vm_ready):
+        """Test configure_cache(image_namespace=...) moves flashvm's images to another repository."""
+        import flashvm as rip
+
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.configure_cache(image_namespace='Localhost/Bad:tag')
+        rip.configure_cache(image_namespace='localhost/flashvm-pytest')
+        try:
+            rip.prepare_image()
+            assert rip.resolve_base() == 'containers-storage:localhost/flashvm-pytest:python-basic'
+            rip.remove_cached_image('localhost/flashvm-pytest:python-basic')
+        finally:
+            rip.configure_cache(image_namespace='localhost/flashvm')
+
+    def test_inspect_embedded_image(self, vm_ready):
+        """Test inspect_image() describes the embedded image, packages included."""
+        import flashvm as rip
+
+        info = rip.inspect_image(packages=True)
+        assert info['name'] == 'localhost/flashvm:python-basic'
+        assert info['layers'] and all(d.startswith('sha256:') for d in info['layers'])
+        assert info['python_version'].startswith('3.')
+        assert isinstance(info['labels'], dict)
+        assert any(p['name'].lower() == 'pip' for p in info['packages'])
+        assert rip.inspect_image()['packages'] is None
+
+    def test_reproducible_build_pins_timestamps(self, vm_ready, tmp_path):
+        """Test set_reproducible() pins build timestamps and names images after their inputs."""
+        import flashvm as rip
+
+        rip.prepare_image()
+        containerfile = tmp_path / "Containerfile"
+        containerfile.write_text("FROM localhost/flashvm:python-basic\nRUN echo hi > /hello\n")
+        rip.set_reproducible(True, 1700000000)
+        try:
+            assert rip.source_date_epoch() == 1700000000
+            image = rip.build_from_containerfile(str(containerfile))
+            assert rip.inspect_image(image)["created"].startswith("2023-11-14")
+            moved = tmp_path / "moved"
+            moved.mkdir()
+            (moved / "Containerfile").write_text(containerfile.read_text())
+            assert rip.build_from_containerfile(str(moved / "Containerfile")) == image
+        finally:
+            rip.set_reproducible(False)
+        assert rip.source_date_epoch() is None
+
+    def test_resolve_base(self, vm_ready):
+        """Test resolve_base() maps 3.12 to the embedded image and rejects unknown versions."""
+        import flashvm as rip
+
+        assert rip.resolve_base("3.12") == 'containers-storage:localhost/flashvm:python-basic'
+        assert rip.resolve_base() == rip.resolve_base("3.12")
+        with pytest.raises(rip.FlashVMConfigError) as exc:
+            rip.resolve_base("2.7")
+        assert "3.12" in str(exc.value)
+
+    def test_pre_pull_images_reports_progress(self, vm_ready):
+        """Test pre_pull_images() warms local images and reports each one's progress."""
+        import flashvm as rip
+
+        rip.prepare_image()
+        events = []
+        images = ['containers-storage:localhost/flashvm:python-basic', 'oci:/nonexistent/layout:latest']
+        result = rip.pre_pull_images(images, progress=events.append)
+        assert result[images[0]] is None
+        assert result[images[1]]
+        assert {(e['image'], e['event']) for e in events} >= {
+            (images[0], 'started'), (images[0], 'done'), (images[1], 'failed'),
+        }
+
     def test_run_function_signature(self, check_rip_available):
         """Test that run() function has correct signature."""
         import flashvm as rip
@@ -0,0 +1,42 @@
+import pytest
+
+
+@pytest.mark.unit
+def test_conda_prepare_image_requires_packages(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.conda_prepare_image([])
+    assert "empty" in str(exc.value).lower()
+
+
+@pytest.mark.unit
+def test_conda_prepare_image_missing_env_file(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.conda_prepare_image(env_file=str(tmp_path / "environment.yml"))
+    assert "environment.yml" in str(exc.value)
+
+
+@pytest.mark.unit
+@pytest.mark.slow
+def test_conda_prepare_image_and_run(check_rip_available, doctor_check, vm_ready):
+    import flashvm as rip
+    import uuid
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    tag = f"pytest-conda-{uuid.uuid4().hex[:8]}"
+    try:
+        image_ref = rip.conda_prepare_image(["numpy"], tag=tag)
+    except rip.FlashVMExecutionError as e:
+        pytest.skip(f"conda_prepare_image failed (network/containers): {e}")
+
+    code = "import sys, numpy\nprint(sys.executable)\nprint(int(numpy.arange(4).sum()))\n"
+    result = rip.run(code, image=image_ref, memory_mb=1024, timeout_seconds=120)
+    assert result["exit_code"] == 0
+    lines = result["stdout"].splitlines()
+    assert lines[0].startswith("/opt/conda/")
+    assert lines[-1] == "6"
@@ -0,0 +1,53 @@
+import pytest
+
+
+@pytest.mark.unit
+def test_build_from_missing_containerfile(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.build_from_containerfile(str(tmp_path / "Containerfile"))
+
+
+@pytest.mark.unit
+def test_build_from_containerfile_rejects_bad_tag(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    containerfile = tmp_path / "Containerfile"
+    containerfile.write_text("FROM scratch\n")
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.build_from_containerfile(str(containerfile), tag="other/namespace:tag")
+
+
+@pytest.mark.unit
+@pytest.mark.slow
+def test_build_from_containerfile_and_run(check_rip_available, doctor_check, vm_ready, tmp_path):
+    import flashvm as rip
+    import uuid
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    (tmp_path / "greeting.txt").write_text("hello from the context\n")
+    containerfile = tmp_path / "Containerfile"
+    containerfile.write_text(
+        "FROM containers-storage:localhost/flashvm:python-basic\n"
+        "ARG GREETING_PATH=/opt/greeting.txt\n"
+        "COPY greeting.txt ${GREETING_PATH}\n"
+    )
+    rip.prepare_image()
+    tag = f"pytest-cf-{uuid.uuid4().hex[:8]}"
+    image_ref = rip.build_from_containerfile(str(containerfile), tag=tag, build_args={"GREETING_PATH": "/opt/hi.txt"})
+    assert image_ref == f"containers-storage:localhost/flashvm:{tag}"
+    assert any(img["name"] == f"localhost/flashvm:{tag}" for img in rip.list_cached_images())
+
+    result = rip.run("print(open('/opt/hi.txt').read().strip())", image=image_ref)
+    assert result["stdout"].strip() == "hello from the context"
+
+
+@pytest.mark.unit
+def test_push_image_requires_complete_auth(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.push_image("localhost/flashvm:python-basic", "registry.example.com/team/sandbox:1", auth={"username": "me"})
@@ -0,0 +1,131 @@
+import json
+import socket
+import threading
+
+import pytest
+
+
+@pytest.mark.unit
+def test_client_without_daemon_raises(check_rip_available, tmp_path):
+    import flashvm as rip
+    from flashvm.daemon import DaemonClient
+
+    with pytest.raises(rip.FlashVMError) as exc:
+        DaemonClient(str(tmp_path / "missing.sock"))
+    assert "flashvmd" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_client_maps_error_codes(check_rip_available, tmp_path):
+    import flashvm as rip
+    from flashvm.daemon import DaemonClient
+
+    path = str(tmp_path / "fake.sock")
+    server = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    server.bind(path)
+    server.listen(1)
+
+    def answer():
+        conn, _ = server.accept()
+        with conn, conn.makefile("rw") as f:
+            request = json.loads(f.readline())
+            error = {
+                "code": -32000,
+                "message": "Timeout: run exceeded 1s",
+                "data": {"code": "TIMEOUT", "image": "python:3.12", "command": None, "stderr": None},
+            }
+            f.write(json.dumps({"jsonrpc": "2.0", "id": request["id"], "error": error}) + "\n")
+            f.flush()
+
+    thread = threading.Thread(target=answer)
+    thread.start()
+    try:
+        with DaemonClient(path, timeout=5) as client:
+            with pytest.raises(rip.FlashVMTimeout) as exc:
+                client.run("while True: pass")
+        assert exc.value.code == "TIMEOUT"
+        assert exc.value.image == "python:3.12"
+    finally:
+        thread.join()
+        server.close()
+
+
+@pytest.mark.unit
+def test_client_reports_retry_after(check_rip_available, tmp_path):
+    import flashvm as rip
+    from flashvm.daemon import DaemonClient
+
+    path = str(tmp_path / "fake.sock")
+    server = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    server.bind(path)
+    server.listen(1)
+
+    def answer():
+        conn, _ = server.accept()
+        with conn, conn.makefile("rw") as f:
+            request = json.loads(f.readline())
+            error = {
+                "code": -32000,
+                "message": "Rate limited: no runs left for unauthenticated callers (limit: 60 per minute); retry after 2.5s",
+                "data": {"code": "RATE_LIMITED", "image": None, "command": None, "stderr": None, "retry_after": 2.5},
+            }
+            f.write(json.dumps({"jsonrpc": "2.0", "id": request["id"], "error": error}) + "\n")
+            f.flush()
+
+    thread = threading.Thread(target=answer)
+    thread.start()
+    try:
+        with DaemonClient(path, timeout=5) as client:
+            with pytest.raises(rip.FlashVMRateLimited) as exc:
+                client.run("print(1)")
+        assert exc.value.code == "RATE_LIMITED"
+        assert exc.value.retry_after == 2.5
+    finally:
+        thread.join()
+        server.close()
+
+
+@pytest.mark.unit
+def test_ssh_client_ships_inputs_and_artifacts(check_rip_available, tmp_path):
+    import sys
+    from flashvm.daemon import DaemonClient
+
+    # Stands in for `ssh HOST flashvmd --stdio`: answers one run from its stdin
+    fake_ssh = tmp_path / "fake_ssh.py"
+    fake_ssh.write_text(
+        "import base64, json, sys\n"
+        "assert sys.argv[1:] == ['lab', 'flashvmd', '--stdio'], sys.argv\n"
+        "request = json.loads(sys.stdin.readline())\n"
+        "params = request['params']\n"
+        "assert 'out_dir' not in params\n"
+        "data = base64.b64decode(params['files'][0]['content'])\n"
+        "artifact = {'guest_path': 'out/sub/echo.bin', 'content': base64.b64encode(data[::-1]).decode(),\n"
+        "            'size_bytes': len(data)}\n"
+        "result = {'stdout': '', 'stderr': '', 'exit_code': 0, 'artifacts': [artifact]}\n"
+        "print(json.dumps({'jsonrpc': '2.0', 'id': request['id'], 'result': result}), flush=True)\n"
+    )
+    source = tmp_path / "input.bin"
+    source.write_bytes(b"\x00abc")
+    out_dir = tmp_path / "results"
+
+    with DaemonClient.over_ssh("lab", ssh=(sys.executable, str(fake_ssh))) as client:
+        result = client.run("pass", files_in=[(str(source), "in/input.bin")], expect=["out/**"], out_dir=str(out_dir))
+    artifact = result["artifacts"][0]
+    assert artifact["path"] == str(out_dir / "sub" / "echo.bin")
+    assert (out_dir / "sub" / "echo.bin").read_bytes() == b"cba\x00"
+    assert "content" not in artifact
+
+
+@pytest.mark.integration
+@pytest.mark.requires_vm
+def test_daemon_runs_code(check_rip_available, vm_ready):
+    from flashvm.daemon import DaemonClient
+
+    try:
+        client = DaemonClient(timeout=120)
+    except RuntimeError:
+        pytest.skip("flashvmd is not running")
+    with client:
+        result = client.run("print(6 * 7)")
+    assert result["exit_code"] == 0
+    assert result["stdout"].strip() == "42"
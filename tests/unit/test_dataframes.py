@@ -0,0 +1,59 @@
+import io
+import os
+
+import pytest
+
+
+@pytest.mark.unit
+def test_run_dataframe_rejects_bad_names(check_rip_available):
+    pd = pytest.importorskip("pandas")
+    import flashvm as rip
+    from flashvm.dataframes import run_dataframe
+
+    df = pd.DataFrame({"a": [1]})
+    for inputs, outputs in [({"not valid": df}, ()), ({"df": df}, ("class",)), ({"_hidden": df}, ())]:
+        with pytest.raises(rip.FlashVMValidationError):
+            run_dataframe("python:3.12-slim", "pass", inputs=inputs, outputs=outputs)
+
+
+@pytest.mark.unit
+def test_run_dataframe_stages_inputs_and_reads_outputs(check_rip_available, monkeypatch):
+    pd = pytest.importorskip("pandas")
+    pytest.importorskip("pyarrow")
+    import flashvm as rip
+    from flashvm import _core
+    from flashvm.dataframes import run_dataframe
+
+    sales = pd.DataFrame({"day": [1, 1, 2], "total": [3.0, 4.0, 5.0]})
+    daily = sales.groupby("day", as_index=False).sum()
+    calls = []
+
+    def fake_run(code, image=None, files_in=None, expect=None, **options):
+        (path, guest), = files_in
+        assert guest == "sales.parquet"
+        assert pd.read_parquet(path).equals(sales)
+        calls.append((code, image, expect, options, path))
+        buf = io.BytesIO()
+        daily.to_parquet(buf)
+        content = buf.getvalue()
+        artifact = {"guest_path": "out/daily.parquet", "host_path": "/nonexistent", "size_bytes": len(content), "content": content}
+        return {"exit_code": 0, "stdout": "", "stderr": "", "artifacts": [artifact]}
+
+    monkeypatch.setattr(_core, "run", fake_run)
+    code = "daily = sales.groupby('day', as_index=False).sum()"
+    result = run_dataframe("python:3.12-slim", code, inputs={"sales": sales}, outputs=["daily"], timeout_seconds=30)
+    assert result["dataframes"]["daily"].equals(daily)
+    wrapped, image, expect, options, staged = calls[0]
+    assert image == "python:3.12-slim"
+    assert "sales = _pd.read_parquet('/work/in/sales.parquet')" in wrapped
+    assert repr(code) in wrapped
+    assert expect == ["out/daily.parquet"]
+    assert options == {"timeout_seconds": 30, "max_bytes_inline": 256 << 20}
+    # The staged inputs are removed once the run is over
+    assert not os.path.exists(os.path.dirname(staged))
+
+    monkeypatch.setattr(_core, "run", lambda code, **kw: {"exit_code": 1, "stdout": "", "stderr": "Traceback\nKeyError: 'x'\n", "artifacts": []})
+    with pytest.raises(rip.FlashVMExecutionError) as exc:
+        run_dataframe("python:3.12-slim", "sales['x']", inputs={"sales": sales})
+    assert "KeyError: 'x'" in str(exc.value)
+    assert exc.value.stderr.startswith("Traceback")
@@ -411,3 +411,1024 @@ class TestImageHandling:
             except Exception:
                 # May fail due to image availability or network issues
                 pass
+
+
+@pytest.mark.unit
+def test_retry_policy_requires_an_attempt(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(RuntimeError) as exc:
+        rip.run("print(1)", retry_policy={"max_attempts": 0})
+    assert "max_attempts" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_retry_policy_permanent_errors_are_strings(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.run("print(1)", retry_policy={"permanent_errors": "not found"})
+    assert "permanent_errors" in str(exc.value)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_env_values_with_quotes_roundtrip(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    tricky = "it's \"quoted\" with ''' and \"\"\" and \\ backslashes"
+    result = rip.run("import os; print(os.environ['TRICKY'])", env={"TRICKY": tricky})
+    assert result["exit_code"] == 0
+    assert result["stdout"].strip() == tricky
+
+
+@pytest.mark.unit
+def test_requirements_need_network_or_wheel_dir(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(RuntimeError) as exc:
+        rip.run("print(1)", requirements=["six"], network=False)
+    assert "wheel_dir" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_pip_cache_needs_network(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.run("print(1)", pip_cache=True)
+    assert "pip_cache requires network=True" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_proxy_requires_scheme(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", network=True, proxy={"https": "proxy.corp:3128"})
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_proxy_exported_to_guest(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    result = rip.run(
+        "import os; print(os.environ['HTTPS_PROXY'], os.environ['no_proxy'])",
+        network=True,
+        proxy={"https": "http://proxy.corp:3128", "no_proxy": ["localhost", ".corp"]},
+    )
+    assert result["exit_code"] == 0
+    assert result["stdout"].strip() == "http://proxy.corp:3128 localhost,.corp"
+
+
+@pytest.mark.unit
+def test_allow_egress_ports_rejects_port_zero(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.run("print(1)", network=True, allow_egress_ports=[0])
+    assert "allow_egress_ports" in str(exc.value)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_allow_egress_ports_blocks_other_ports(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = """
+import socket
+try:
+    socket.create_connection(("1.1.1.1", 80), timeout=5)
+    print("connected")
+except OSError as e:
+    print("blocked", e.errno)
+"""
+    result = rip.run(code, network=True, allow_egress_ports=[443])
+    if "kernel lacks Landlock" in result["stderr"]:
+        pytest.skip("kernel without Landlock network support")
+    assert result["stdout"].startswith("blocked")
+
+
+@pytest.mark.unit
+class TestPortValidation:
+    """Port mappings are checked before any VM is created."""
+
+    def test_ports_require_network(self, check_rip_available):
+        import flashvm as rip
+
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.run("print(1)", ports=[(18080, 8080)])
+
+    def test_duplicate_host_port(self, check_rip_available):
+        import flashvm as rip
+
+        with pytest.raises(rip.FlashVMConfigError) as exc:
+            rip.run("print(1)", network=True, ports=[(18080, 8080), (18080, 8081)])
+        assert "more than once" in str(exc.value)
+
+    def test_privileged_port_needs_opt_in(self, check_rip_available):
+        import flashvm as rip
+
+        with pytest.raises(rip.FlashVMConfigError) as exc:
+            rip.run("print(1)", network=True, ports=[(80, 8080)])
+        assert "allow_privileged_ports" in str(exc.value)
+
+    def test_port_in_use(self, check_rip_available):
+        import socket
+        import flashvm as rip
+
+        with socket.socket() as s:
+            s.bind(("0.0.0.0", 0))
+            s.listen()
+            busy = s.getsockname()[1]
+            with pytest.raises(rip.FlashVMConfigError) as exc:
+                rip.run("print(1)", network=True, ports=[(busy, 8080)])
+            assert "already in use" in str(exc.value)
+
+
+@pytest.mark.unit
+@pytest.mark.parametrize("guest_path", ["../../etc/passwd", "/etc/passwd", "data/../../x", "/work/in/../out/x"])
+def test_files_in_rejects_escaping_guest_path(check_rip_available, tmp_path, guest_path):
+    import flashvm as rip
+
+    src = tmp_path / "input.txt"
+    src.write_text("data")
+    with pytest.raises(rip.FlashVMValidationError) as exc:
+        rip.run("print(1)", files_in=[(str(src), guest_path)])
+    assert exc.value.code == "VALIDATION"
+
+
+@pytest.mark.unit
+def test_capture_rejects_unknown_mode(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", capture="xml")
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_capture_json_lines_separates_streams(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = "import sys; print('to out'); print('to err', file=sys.stderr); sys.exit(2)"
+    result = rip.run(code, capture="json_lines")
+    assert result["exit_code"] == 2
+    assert result["stdout"] == "to out\n"
+    assert result["stderr"].startswith("to err\n")
+    streams = [r["stream"] for r in result["output"]]
+    assert "stdout" in streams and "stderr" in streams
+    ts = [r["ts"] for r in result["output"]]
+    assert ts == sorted(ts)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_merge_streams_orders_lines(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = (
+        "import sys, time\n"
+        "print('first')\n"
+        "time.sleep(0.05)\n"
+        "print('second', file=sys.stderr)\n"
+        "time.sleep(0.05)\n"
+        "print('third')\n"
+    )
+    result = rip.run(code, merge_streams=True)
+    lines = result["merged_output"].splitlines()
+    assert [line.split("] ", 1)[1] for line in lines] == ["first", "second", "third"]
+    assert "stderr]" in lines[1]
+    assert result["output"] is not None
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_guest_oom_raises_out_of_memory(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = "blocks = []\nwhile True:\n    blocks.append(bytearray(16 * 1024 * 1024))\n"
+    with pytest.raises(rip.FlashVMOutOfMemory) as exc:
+        rip.run(code, memory_mb=256, timeout_seconds=60)
+    assert exc.value.code == "OUT_OF_MEMORY"
+    assert "memory_mb=256" in str(exc.value)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_guest_oom_keeps_partial_result(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    # Nothing here can catch the kill, so the run always ends in an OOM
+    code = "print('allocating', flush=True)\nblocks = []\nwhile True:\n    blocks.append(bytearray(16 * 1024 * 1024))\n"
+    with pytest.raises(rip.FlashVMOutOfMemory) as exc:
+        rip.run(code, memory_mb=256, timeout_seconds=60, expect=["out/*"])
+    result = exc.value.result
+    assert isinstance(result, dict)
+    assert len(result["run_id"]) == 36
+    assert result["exit_code"] != 0
+    assert "allocating" in result["stdout"]
+    assert isinstance(result["artifacts"], list)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_enospc_text_alone_is_not_disk_full(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    # /work has plenty of room: only the guest's own statvfs decides DISK_FULL
+    code = "import errno, os\nraise OSError(errno.ENOSPC, os.strerror(errno.ENOSPC))\n"
+    result = rip.run(code)
+    assert result["exit_code"] != 0
+    assert "No space left on device" in result["stderr"]
+
+
+@pytest.mark.unit
+def test_run_as_rejects_root(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", run_as=(0, 0))
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_run_as_unprivileged_user(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = (
+        "import os\n"
+        "open('/work/out/owned.txt', 'w').write('x')\n"
+        "print(os.getuid(), os.getgid())\n"
+    )
+    result = rip.run(code, run_as=(1000, 1000), expect=["owned.txt"])
+    assert result["exit_code"] == 0
+    assert result["stdout"].strip() == "1000 1000"
+    assert len(result["artifacts"]) == 1
+
+
+@pytest.mark.unit
+def test_secrets_reject_path_like_names(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", secrets={"../token": "x"})
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_secrets_mounted_as_files(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = (
+        "import os\n"
+        "print(open('/run/secrets/API_TOKEN').read())\n"
+        "print('API_TOKEN' in os.environ, os.listdir('/work/scripts'))\n"
+    )
+    result = rip.run(code, secrets={"API_TOKEN": "s3cr3t"})
+    assert result["exit_code"] == 0
+    lines = result["stdout"].splitlines()
+    assert lines[0] == "s3cr3t"
+    assert lines[1].startswith("False")
+    assert "s3cr3t" not in result["stderr"]
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_malformed_image_digest_rejected(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMImageError) as exc:
+        rip.run("print(1)", image="docker://python@sha256:1234")
+    assert exc.value.code == "IMAGE_RESOLUTION"
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_oci_layout_for_other_arch_rejected(check_rip_available, vm_ready, tmp_path):
+    import json
+    import platform
+
+    import flashvm as rip
+
+    other = "s390x" if platform.machine() != "s390x" else "amd64"
+    (tmp_path / "blobs" / "sha256").mkdir(parents=True)
+    (tmp_path / "oci-layout").write_text('{"imageLayoutVersion": "1.0.0"}')
+    index = {
+        "schemaVersion": 2,
+        "manifests": [{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "digest": "sha256:" + "0" * 64,
+            "size": 0,
+            "platform": {"os": "linux", "architecture": other},
+        }],
+    }
+    (tmp_path / "index.json").write_text(json.dumps(index))
+    with pytest.raises(rip.FlashVMImageError) as exc:
+        rip.run("print(1)", image="oci:%s" % tmp_path)
+    assert other in str(exc.value)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_concurrent_prepare_image_processes(check_rip_available, vm_ready):
+    import subprocess
+    import sys
+
+    procs = [
+        subprocess.Popen([sys.executable, "-c", "import flashvm; flashvm.prepare_image()"], stderr=subprocess.PIPE)
+        for _ in range(3)
+    ]
+    for p in procs:
+        _, err = p.communicate(timeout=600)
+        assert p.returncode == 0, err.decode(errors="replace")
+
+
+@pytest.mark.unit
+def test_offline_mode_rejects_registry_image(check_rip_available):
+    import flashvm as rip
+
+    rip.set_offline(True)
+    try:
+        assert rip.is_offline()
+        with pytest.raises(rip.FlashVMConfigError) as exc:
+            rip.run("print(1)", image="docker://docker.io/library/python:3.12-slim")
+        assert "offline mode" in str(exc.value)
+    finally:
+        rip.set_offline(False)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_image_without_python_is_rejected(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMImageError) as exc:
+        rip.run("print(1)", image="docker.io/library/busybox:latest")
+    assert "python3" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_configure_scan_validates_policy(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.configure_scan(["trivy"], fail_on="severe")
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.configure_scan(fail_on="high")
+    rip.configure_scan()
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_scan_policy_refuses_vulnerable_image(vm_ready, tmp_path):
+    import flashvm as rip
+
+    # A stand-in scanner printing trivy-style JSON with one critical finding
+    scanner = tmp_path / "fake-trivy"
+    scanner.write_text(
+        "#!/bin/sh\n"
+        "echo '{\"SchemaVersion\": 2, \"Results\": [{\"Vulnerabilities\": ["
+        "{\"VulnerabilityID\": \"CVE-2099-0001\", \"PkgName\": \"openssl\", "
+        "\"InstalledVersion\": \"1.0\", \"Severity\": \"CRITICAL\"}]}]}'\n"
+    )
+    scanner.chmod(0o755)
+    rip.prepare_image()
+    rip.configure_scan([str(scanner), "{layout}"], fail_on="high")
+    try:
+        report = rip.scan_image("localhost/flashvm:python-basic")
+        assert report["worst"] == "critical"
+        assert report["counts"] == {"critical": 1}
+        with pytest.raises(rip.FlashVMImageError) as exc:
+            rip.run("print(1)")
+        assert "CVE-2099-0001" in str(exc.value)
+    finally:
+        rip.configure_scan()
+
+
+@pytest.mark.unit
+def test_audit_log_records_every_run(check_rip_available, tmp_path):
+    import hashlib
+    import json
+
+    import flashvm as rip
+
+    log = tmp_path / "audit.jsonl"
+    rip.configure_audit(str(log))
+    try:
+        try:
+            result = rip.run("print(1)", labels={"team": "eval"})
+        except rip.FlashVMError as e:
+            # Failed runs are recorded too, with the error code
+            result, code = None, e.code
+    finally:
+        rip.configure_audit()
+    record = json.loads(log.read_text().splitlines()[-1])
+    assert record["labels"] == {"team": "eval"}
+    assert record["code_sha256"] == hashlib.sha256(b"print(1)").hexdigest()
+    assert record["limits"]["memory_mb"] == 512
+    assert record["timeout_extensions"] == []
+    if result is None:
+        assert record["error"] == code and record["exit_code"] is None
+    else:
+        assert record["run_id"] == result["run_id"]
+        assert record["exit_code"] == result["exit_code"]
+
+
+@pytest.mark.unit
+def test_result_cache_settings(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    previous = rip.effective_config()
+    rip.configure_cache(cache_dir=str(tmp_path), result_ttl_seconds=60)
+    try:
+        assert rip.effective_config()["cache"]["result_ttl_seconds"] == 60
+        assert rip.clear_result_cache() == 0
+        (tmp_path / "results" / "stale").mkdir(parents=True)
+        assert rip.clear_result_cache() == 1
+        assert not any((tmp_path / "results").iterdir())
+    finally:
+        rip.configure_cache(
+            cache_dir=previous["cache_dir"], result_ttl_seconds=previous["cache"]["result_ttl_seconds"]
+        )
+
+
+@pytest.mark.unit
+def test_scheduler_settings(check_rip_available):
+    import flashvm as rip
+
+    previous = rip.scheduler_stats()
+    assert previous["max_vms"] >= 1 and previous["running"] == 0
+    rip.configure_scheduler(max_memory_mb=2048, max_queue=4)
+    try:
+        stats = rip.scheduler_stats()
+        assert (stats["max_vms"], stats["max_memory_mb"], stats["max_queue"]) == (previous["max_vms"], 2048, 4)
+        assert rip.effective_config()["scheduler"]["max_queue"] == 4
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.configure_scheduler(max_vms=0)
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.run("print(1)", queue_timeout_seconds=-1)
+    finally:
+        rip.configure_scheduler(max_memory_mb=previous["max_memory_mb"], max_queue=previous["max_queue"])
+
+
+@pytest.mark.unit
+def test_tenants_get_their_own_namespace_and_cache(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    host = rip.effective_config()
+    rip.register_tenant("team-a", {"max_vms": 1, "max_storage_mb": 512})
+    rip.register_tenant("team-b", {"image_namespace": "localhost/team-b", "cache_dir": str(tmp_path)})
+    tenants = rip.list_tenants()
+    assert tenants["team-a"]["image_namespace"] == host["image_namespace"] + "/team-a"
+    assert tenants["team-a"]["cache_dir"] == host["cache_dir"].rstrip("/") + "/tenants/team-a"
+    assert tenants["team-a"]["max_storage_mb"] == 512
+    assert tenants["team-a"]["scheduler"]["max_vms"] == 1
+    assert tenants["team-b"]["cache_dir"] == str(tmp_path)
+    assert rip.scheduler_stats(tenant="team-b")["running"] == 0
+
+    for name, settings in [("Team A", None), ("team-c", {"max_vms": 0}), ("team-c", {"quota": 1})]:
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.register_tenant(name, settings)
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", tenant="nobody")
+
+
+@pytest.mark.unit
+def test_policy_rejects_runs_before_they_start(check_rip_available):
+    import flashvm as rip
+
+    rip.configure_policy({
+        "max_cpus": 2,
+        "allow_network": False,
+        "allowed_registries": ["docker.io"],
+        "require_digest": True,
+        "allowed_packages": ["Requests"],
+    })
+    try:
+        with pytest.raises(rip.FlashVMPolicyViolation) as exc:
+            rip.run("print(1)", cpus=4, network=True)
+        assert exc.value.code == "POLICY_VIOLATION"
+        assert "max_cpus" in str(exc.value) and "network" in str(exc.value)
+        for kwargs in [
+            {"image": "ghcr.io/acme/python:3.11"},
+            {"image": "python:3.11"},
+            {"image": "oci:/nonexistent/layout"},
+            {"requirements": ["requests>=2", "numpy"], "wheel_dir": "/nonexistent"},
+        ]:
+            with pytest.raises(rip.FlashVMConfigError):
+                rip.run("print(1)", **kwargs)
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.configure_policy({"max_cpu": 2})
+    finally:
+        rip.configure_policy()
+    assert rip.effective_config()["policy"]["max_cpus"] is None
+
+
+@pytest.mark.unit
+def test_hooks_can_veto_and_rewrite_runs(check_rip_available):
+    import flashvm as rip
+
+    def quota(ctx):
+        if ctx["labels"].get("team") == "over-budget":
+            raise RuntimeError("team is over budget")
+        return {"cpus": 4}
+
+    rip.register_hook("quota", before=quota)
+    rip.configure_policy({"max_cpus": 2})
+    try:
+        assert rip.list_hooks() == ["quota"]
+        with pytest.raises(rip.FlashVMPolicyViolation) as exc:
+            rip.run("print(1)", labels={"team": "over-budget"})
+        assert "quota" in str(exc.value) and "over budget" in str(exc.value)
+        # The policy sees the settings the hook returned
+        with pytest.raises(rip.FlashVMPolicyViolation) as exc:
+            rip.run("print(1)", cpus=1)
+        assert "cpus=4" in str(exc.value)
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.register_hook("quota")
+    finally:
+        rip.configure_policy()
+        assert rip.unregister_hook("quota")
+    assert not rip.unregister_hook("quota")
+    assert rip.list_hooks() == []
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_after_hooks_post_process_results(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    seen = []
+
+    def redact(ctx, result):
+        seen.append((ctx["run_id"], result["exit_code"]))
+        return {"stdout": result["stdout"].replace("hunter2", "***")}
+
+    rip.register_hook("redact", before=lambda ctx: {"env": {"PASSWORD": "hunter2"}}, after=redact)
+    try:
+        result = rip.run("import os; print(os.environ['PASSWORD'])")
+    finally:
+        rip.unregister_hook("redact")
+    assert result["stdout"].strip() == "***"
+    assert seen == [(result["run_id"], 0)]
+
+
+@pytest.mark.unit
+def test_tenant_rate_limit_turns_away_bursts(check_rip_available):
+    import flashvm as rip
+
+    rip.register_tenant("rate-limited", {"rate_limit": {"runs_per_minute": 1, "burst": 2}})
+    assert rip.list_tenants()["rate-limited"]["rate_limit"] == {"runs_per_minute": 1, "burst": 2}
+    for _ in range(2):
+        try:
+            rip.run("print(1)", tenant="rate-limited")
+        except rip.FlashVMRateLimited:
+            raise
+        except rip.FlashVMError:
+            pass  # no VM support on this host; the run was still charged
+    with pytest.raises(rip.FlashVMRateLimited) as exc:
+        rip.run("print(1)", tenant="rate-limited")
+    assert exc.value.code == "RATE_LIMITED"
+    assert 0 < exc.value.retry_after <= 60
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.configure_rate_limit(burst=0)
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_result_cache_replays_identical_runs(check_rip_available, vm_ready, tmp_path):
+    import flashvm as rip
+
+    code = "import uuid; print(uuid.uuid4())"
+    first = rip.run(code, result_cache=True, env={"CACHE_TEST": str(tmp_path)})
+    second = rip.run(code, result_cache=True, env={"CACHE_TEST": str(tmp_path)})
+    assert not first["cached"] and second["cached"]
+    assert second["stdout"] == first["stdout"]
+    assert second["run_id"] != first["run_id"]
+    other = rip.run(code, result_cache=True, env={"CACHE_TEST": "other" + str(tmp_path)})
+    assert not other["cached"]
+
+
+@pytest.mark.unit
+def test_config_file_rejects_unknown_keys(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    config = tmp_path / "sandbox.toml"
+    config.write_text("cpus = 2\nmemroy_mb = 1024\n")
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.run("print(1)", config_file=str(config))
+    assert "memroy_mb" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_config_file_is_overridden_by_arguments(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    # The file turns egress rules on without network; network=True from the call fixes it
+    config = tmp_path / "sandbox.yaml"
+    config.write_text("allow_egress_ports: [443]\nworkdir: /nested/dir\n")
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.run("print(1)", config_file=str(config))
+    assert "workdir" in str(exc.value)
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.run("print(1)", config_file=str(config), workdir="/work")
+    assert "allow_egress_ports requires network=True" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_env_log_level_read_at_import(check_rip_available):
+    import os
+    import subprocess
+    import sys
+
+    env = dict(os.environ, FLASHVM_LOG_LEVEL="chatty")
+    out = subprocess.run([sys.executable, "-c", "import flashvm"], env=env, capture_output=True, text=True)
+    assert out.returncode == 0
+    assert "ignoring FLASHVM_LOG_LEVEL" in out.stderr
+
+
+@pytest.mark.unit
+def test_profiles_register_and_validate(check_rip_available):
+    import flashvm as rip
+
+    profiles = rip.list_profiles()
+    assert profiles["ml"]["memory_mb"] == 4096
+    assert profiles["network-enabled"] == {"network": True}
+
+    rip.register_profile("pytest-team", {"cpus": 2, "timeout_seconds": 5})
+    assert rip.list_profiles()["pytest-team"] == {"cpus": 2, "timeout_seconds": 5}
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.register_profile("pytest-bad", {"cpu": 2})
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.register_profile("pytest-bad", {"keep_vm": "sometimes"})
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.run("print(1)", profile="no-such-profile")
+    assert "pytest-team" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_global_config_file_read_at_import(check_rip_available, tmp_path):
+    import json
+    import os
+    import subprocess
+    import sys
+
+    config = tmp_path / "config.toml"
+    config.write_text(
+        'image_namespace = "localhost/pytest-global"\n'
+        "[pool]\nmax_idle_per_key = 5\n"
+        "[run]\nmemory_mb = 777\n"
+        "[profiles.pytest-global]\ncpus = 3\n"
+    )
+    script = "import json, flashvm; print(json.dumps(flashvm.effective_config()))"
+    env = {k: v for k, v in os.environ.items() if k != "FLASHVM_IMAGE_NAMESPACE"}
+    env["FLASHVM_CONFIG"] = str(config)
+    out = subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True)
+    assert out.returncode == 0, out.stderr
+    effective = json.loads(out.stdout)
+    assert str(config) in effective["config_files"]
+    assert effective["image_namespace"] == "localhost/pytest-global"
+    assert effective["pool"]["max_idle_per_key"] == 5
+    assert effective["run"]["memory_mb"] == 777
+    assert "pytest-global" in effective["profiles"]
+
+    config.write_text("no_such_key = 1\n")
+    out = subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True)
+    assert out.returncode == 0
+    assert "ignoring" in out.stderr
+    assert json.loads(out.stdout)["errors"]
+
+
+@pytest.mark.unit
+def test_cache_dir_override(check_rip_available, tmp_path):
+    import json
+    import os
+    import subprocess
+    import sys
+
+    import flashvm as rip
+
+    previous = rip.effective_config()["cache_dir"]
+    rip.configure_cache(cache_dir=str(tmp_path / "cache"))
+    try:
+        assert rip.effective_config()["cache_dir"] == str(tmp_path / "cache")
+        (tmp_path / "file").write_text("")
+        with pytest.raises(rip.FlashVMConfigError):
+            rip.configure_cache(cache_dir=str(tmp_path / "file"))
+    finally:
+        rip.configure_cache(cache_dir=previous)
+
+    env = {k: v for k, v in os.environ.items() if k != "FLASHVM_CACHE_DIR"}
+    env["XDG_CACHE_HOME"] = str(tmp_path / "xdg")
+    env["FLASHVM_CONFIG"] = str(tmp_path / "no-config.toml")
+    script = "import json, flashvm; print(json.dumps(flashvm.effective_config()))"
+    out = subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True)
+    assert out.returncode == 0, out.stderr
+    assert json.loads(out.stdout)["cache_dir"] == str(tmp_path / "xdg" / "flashvm")
+
+
+@pytest.mark.unit
+def test_rlimits_validation(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", rlimits={"stack": 1024})
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", rlimits={"nofile": 0})
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_rlimits_applied_in_guest(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = (
+        "import resource\n"
+        "print(resource.getrlimit(resource.RLIMIT_NOFILE)[0], resource.getrlimit(resource.RLIMIT_CORE)[0])\n"
+        "try:\n"
+        "    open('/work/tmp/big', 'wb').write(b'x' * 8192)\n"
+        "except OSError as e:\n"
+        "    print('fsize', e.errno)\n"
+    )
+    result = rip.run(code, rlimits={"nofile": 64, "core": 0, "fsize": 4096}, run_as=(1000, 1000))
+    assert result["exit_code"] == 0, result["stderr"]
+    lines = result["stdout"].split()
+    assert lines[:2] == ["64", "0"]
+    assert "fsize" in result["stdout"]
+
+
+@pytest.mark.unit
+def test_seccomp_profile_validation(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", seccomp="strict")
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", seccomp={"deny": ["not_a_syscall"]})
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.run("print(1)", seccomp={"deny": ["ptrace"], "allow": ["mount"]})
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_seccomp_default_profile_in_guest(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = (
+        "import ctypes, socket\n"
+        "libc = ctypes.CDLL(None, use_errno=True)\n"
+        "print(libc.ptrace(0, 0, 0, 0), ctypes.get_errno())\n"
+        "try:\n"
+        "    socket.socket(socket.AF_INET, socket.SOCK_RAW, socket.IPPROTO_ICMP)\n"
+        "except PermissionError:\n"
+        "    print('raw denied')\n"
+        "socket.socket().close()\n"
+        "print('tcp ok')\n"
+    )
+    result = rip.run(code, seccomp="default")
+    assert result["exit_code"] == 0, result["stderr"]
+    assert result["stdout"].split("\n")[:3] == ["-1 1", "raw denied", "tcp ok"]
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_coverage_reports_missing_lines(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = "def used():\n    return 1\n\ndef unused():\n    return 2\n\nprint(used())\n"
+    result = rip.run(code, coverage=True, requirements=["coverage"], network=True, timeout_seconds=120)
+    assert result["exit_code"] == 0, result["stderr"]
+    report = result["coverage"]
+    assert report["files"]["main.py"]["missing_lines"] == [5]
+    assert 0 < report["percent_covered"] < 100
+    assert report["data_file"] in [a["guest_path"] for a in result["artifacts"]]
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_cprofile_summarizes_hot_functions(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = "import sys\ndef busy():\n    return sum(i * i for i in range(200000))\nbusy()\nsys.exit(3)\n"
+    result = rip.run(code, cprofile=True)
+    assert result["exit_code"] == 3
+    report = result["cprofile"]
+    assert ("main.py", "busy") in [(e["file"], e["function"]) for e in report["top"]]
+    assert report["total_time"] > 0
+    assert report["stats_file"] in [a["guest_path"] for a in result["artifacts"]]
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_peak_memory_is_reported(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    small = rip.run("print(1)")
+    big = rip.run("block = bytearray(128 * 1024 * 1024)\nprint(len(block))", memory_mb=512)
+    assert 0 < small["peak_memory_bytes"] < big["peak_memory_bytes"]
+    assert big["peak_memory_bytes"] >= 128 * 1024 * 1024
+
+
+@pytest.mark.unit
+def test_trace_context_is_validated(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError, match="invalid traceparent"):
+        rip.run("print(1)", trace={"traceparent": "00-abc-def-01"})
+    with pytest.raises(rip.FlashVMConfigError, match="invalid traceparent"):
+        rip.run("print(1)", trace={"traceparent": "00-" + "0" * 32 + "-00f067aa0ba902b7-01"})
+    with pytest.raises(rip.FlashVMConfigError, match="invalid trace key"):
+        rip.run("print(1)", trace={"span_id": "x"})
+    with pytest.raises(rip.FlashVMConfigError, match="correlation_id"):
+        rip.run("print(1)", trace={"correlation_id": "line\nbreak"})
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_trace_context_reaches_the_guest(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+    code = "import os\nprint(os.environ['TRACEPARENT'].split('-')[1], os.environ['FLASHVM_CORRELATION_ID'])"
+    result = rip.run(code, trace={"traceparent": traceparent, "correlation_id": "req-42"})
+    assert result["stdout"].split() == ["4bf92f3577b34da6a3ce929d0e0e4736", "req-42"]
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_display_captures_rich_reprs(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = (
+        "class Table:\n"
+        "    def _repr_html_(self):\n"
+        "        return '<table></table>'\n"
+        "display(Table())\n"
+        "6 * 7\n"
+    )
+    result = rip.run(code, display=True)
+    assert result["exit_code"] == 0, result["stderr"]
+    assert [(d["type"], d["text"]) for d in result["display"]][1] == ("text/plain", "42")
+    html = result["display"][0]
+    assert html["type"] == "text/html"
+    artifact = next(a for a in result["artifacts"] if a["guest_path"] == html["path"])
+    assert open(artifact["host_path"]).read() == "<table></table>"
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_result_json_becomes_value(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = "import json\njson.dump({'answer': 42, 'items': [1, None]}, open('/work/out/flashvm_result.json', 'w'))"
+    assert rip.run(code)["value"] == {"answer": 42, "items": [1, None]}
+    assert rip.run("print('no value')")["value"] is None
+    assert rip.run("open('/work/out/flashvm_result.json', 'w').write('{not json')")["value"] is None
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_many_artifacts_keep_glob_order(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = "for i in range(200):\n    open('/work/out/part-%03d.txt' % i, 'w').write(str(i))"
+    result = rip.run(code, expect=["out/part-*.txt"])
+    paths = [a["guest_path"] for a in result["artifacts"]]
+    assert paths == ["out/part-%03d.txt" % i for i in range(200)]
+    assert [open(a["host_path"]).read() for a in result["artifacts"][:3]] == ["0", "1", "2"]
+
+
+@pytest.mark.unit
+def test_preimport_can_be_turned_off(check_rip_available, tmp_path):
+    import json
+    import os
+    import subprocess
+    import sys
+
+    script = "import json, flashvm; print(json.dumps(flashvm.effective_config()['preimport']))"
+    env = {k: v for k, v in os.environ.items() if k != "FLASHVM_PREIMPORT"}
+    env["FLASHVM_CONFIG"] = str(tmp_path / "config.toml")
+    (tmp_path / "config.toml").write_text("preimport = false\n")
+    out = subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True)
+    assert out.returncode == 0, out.stderr
+    assert json.loads(out.stdout) is False
+
+    (tmp_path / "config.toml").write_text("")
+    assert json.loads(subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True).stdout)
+    env["FLASHVM_PREIMPORT"] = "0"
+    assert not json.loads(subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True).stdout)
+
+
+@pytest.mark.unit
+def test_bench_needs_a_run(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.bench(runs=0)
+
+
+@pytest.mark.unit
+def test_timezone_and_locale_are_validated(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError, match="invalid timezone"):
+        rip.run("print(1)", timezone="../../etc/passwd")
+    with pytest.raises(rip.FlashVMConfigError, match="invalid timezone"):
+        rip.run("print(1)", timezone="Europe/")
+    with pytest.raises(rip.FlashVMConfigError, match="invalid locale"):
+        rip.run("print(1)", locale="de_DE.UTF-8; rm")
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_guest_runs_in_the_requested_timezone(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    code = "import os, time\nprint(time.strftime('%z', time.localtime(1719835200)), os.environ['LANG'])"
+    result = rip.run(code, timezone="Asia/Kolkata", locale="C.UTF-8")
+    assert result["exit_code"] == 0, result["stderr"]
+    assert result["stdout"].split() == ["+0530", "C.UTF-8"]
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_bench_reports_phase_percentiles(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    report = rip.bench(runs=3)
+    assert report["backend"] == "krunvm"
+    assert report["runs"] == 3
+    assert set(report["phases"]) == {"resolve", "stage", "create", "boot", "exec", "collect"}
+    for p in list(report["phases"].values()) + [report["total"]]:
+        assert 0 <= p["p50_ms"] <= p["p95_ms"]
+    assert report["phases"]["boot"]["p50_ms"] > 0
+
+
+@pytest.mark.unit
+@pytest.mark.requires_vm
+def test_results_break_down_execution_time(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    result = rip.run("import time\ntime.sleep(0.5)")
+    timings = result["timings"]
+    assert set(timings) == {"resolve_ms", "stage_ms", "create_ms", "boot_ms", "exec_ms", "collect_ms"}
+    assert timings["exec_ms"] >= 500
+    assert timings["boot_ms"] > 0
+    assert sum(timings.values()) <= result["execution_time_ms"]
+
+
+@pytest.mark.unit
+def test_doctor_reports_host_arch(check_rip_available):
+    import platform
+
+    import flashvm as rip
+
+    expected = {"x86_64": "amd64", "aarch64": "arm64", "arm64": "arm64"}.get(platform.machine(), platform.machine())
+    assert rip.doctor()["arch"] == expected
+
+
+@pytest.mark.unit
+def test_pull_timeout_setting(check_rip_available, tmp_path):
+    import json
+    import os
+    import subprocess
+    import sys
+
+    import flashvm as rip
+
+    assert issubclass(rip.FlashVMPullTimeout, rip.FlashVMTimeout)
+    script = "import json, flashvm; print(json.dumps(flashvm.effective_config()['pull_timeout_seconds']))"
+    env = {k: v for k, v in os.environ.items() if k != "FLASHVM_PULL_TIMEOUT"}
+    env["FLASHVM_CONFIG"] = str(tmp_path / "config.toml")
+    (tmp_path / "config.toml").write_text("")
+    run = lambda: json.loads(subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True).stdout)
+    assert run() == 300
+    (tmp_path / "config.toml").write_text("pull_timeout_seconds = 60\n")
+    assert run() == 60
+    env["FLASHVM_PULL_TIMEOUT"] = "15"
+    assert run() == 15
+
+
+@pytest.mark.unit
+def test_heartbeat_timeout_setting(check_rip_available, tmp_path):
+    import json
+    import os
+    import subprocess
+    import sys
+
+    import flashvm as rip
+
+    assert issubclass(rip.FlashVMGuestUnresponsive, rip.FlashVMTimeout)
+    script = "import json, flashvm; print(json.dumps(flashvm.effective_config()['heartbeat_timeout_seconds']))"
+    env = {k: v for k, v in os.environ.items() if k != "FLASHVM_HEARTBEAT_TIMEOUT"}
+    env["FLASHVM_CONFIG"] = str(tmp_path / "config.toml")
+    (tmp_path / "config.toml").write_text("")
+    run = lambda: json.loads(subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True).stdout)
+    assert run() == 30
+    (tmp_path / "config.toml").write_text("heartbeat_timeout_seconds = 0\n")
+    assert run() == 0
+    env["FLASHVM_HEARTBEAT_TIMEOUT"] = "90"
+    assert run() == 90
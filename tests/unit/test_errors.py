@@ -0,0 +1,50 @@
+"""
+Unit tests for flashvm exception classes and error codes.
+"""
+
+import pytest
+
+
+@pytest.mark.unit
+class TestErrorClasses:
+    """Exceptions are typed and carry machine-readable fields."""
+
+    def test_exception_hierarchy(self, check_rip_available):
+        import flashvm as rip
+
+        assert issubclass(rip.FlashVMError, RuntimeError)
+        for name in [
+            'FlashVMImageError',
+            'FlashVMConfigError',
+            'FlashVMExecutionError',
+            'FlashVMTimeout',
+            'FlashVMMissingDependency',
+            'FlashVMCacheError',
+            'FlashVMValidationError',
+            'FlashVMOutOfMemory',
+            'FlashVMDiskFull',
+            'FlashVMOverloaded',
+            'FlashVMPolicyViolation',
+            'FlashVMRateLimited',
+            'FlashVMKilled',
+        ]:
+            assert issubclass(getattr(rip, name), rip.FlashVMError)
+
+    def test_config_error_has_code(self, check_rip_available):
+        import flashvm as rip
+
+        with pytest.raises(rip.FlashVMConfigError) as exc:
+            rip.set_max_concurrent_vms(0)
+        assert exc.value.code == 'VM_CONFIGURATION'
+        assert exc.value.image is None
+        assert exc.value.command is None
+
+    def test_invalid_image_is_image_error(self, check_rip_available, vm_ready):
+        import flashvm as rip
+
+        with pytest.raises(rip.FlashVMError) as exc:
+            rip.run("print(1)", image="oci:/nonexistent/flashvm-layout")
+        assert exc.value.code in ('IMAGE_RESOLUTION', 'MISSING_DEPENDENCY')
+        if exc.value.code == 'IMAGE_RESOLUTION':
+            assert isinstance(exc.value, rip.FlashVMImageError)
+            assert exc.value.image
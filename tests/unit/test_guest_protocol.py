@@ -0,0 +1,73 @@
+import importlib.util
+import pathlib
+import struct
+import time
+
+import pytest
+
+RUNNER = pathlib.Path(__file__).resolve().parents[2] / "src" / "guest" / "runner.py"
+
+
+@pytest.fixture(scope="module")
+def runner():
+    spec = importlib.util.spec_from_file_location("flashvm_guest_runner", RUNNER)
+    module = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(module)
+    return module
+
+
+@pytest.mark.unit
+def test_codec_matches_messagepack_encodings(runner):
+    # Byte strings as any MessagePack implementation (rmp-serde on the host) writes them
+    cases = [
+        (None, b"\xc0"),
+        (True, b"\xc3"),
+        (7, b"\x07"),
+        (-3, b"\xfd"),
+        (1.5, b"\xcb" + struct.pack(">d", 1.5)),
+        ("run", b"\xa3run"),
+        ([1000, 1000], b"\x92\xcd\x03\xe8\xcd\x03\xe8"),
+        ({"oom": False}, b"\x81\xa3oom\xc2"),
+        (b"\x00\x01", b"\xc4\x02\x00\x01"),
+    ]
+    for value, encoded in cases:
+        assert runner.unpack(encoded) == value
+    value = {"env": {"K": "v" * 40}, "deny": [101, 2**40, -200], "big": 2**64 - 1, "blob": b"x" * 70000}
+    assert runner.unpack(runner.pack(value)) == value
+
+    with pytest.raises(runner.ProtocolError):
+        runner.unpack(b"\x92\x01")
+    with pytest.raises(runner.ProtocolError):
+        runner.unpack(b"\x01\x02")
+
+
+@pytest.mark.unit
+def test_frames_are_versioned(runner, tmp_path):
+    path = str(tmp_path / "status.msgpack")
+    runner.write_frame(path, "status", {"returncode": 0, "oom": False})
+    assert runner.read_frame(path, "status") == {"returncode": 0, "oom": False}
+    with pytest.raises(runner.ProtocolError):
+        runner.read_frame(path, "spec")
+
+    payload = runner.pack({"version": runner.PROTOCOL_VERSION + 1, "kind": "spec", "body": {}})
+    with open(path, "wb") as f:
+        f.write(struct.pack(">I", len(payload)) + payload)
+    with pytest.raises(runner.ProtocolError):
+        runner.read_frame(path, "spec")
+    with open(path, "wb") as f:
+        f.write(struct.pack(">I", len(payload) + 1) + payload)
+    with pytest.raises(runner.ProtocolError):
+        runner.read_frame(path, "spec")
+
+
+@pytest.mark.unit
+def test_heartbeat_keeps_beating(runner, tmp_path):
+    path = tmp_path / "heartbeat"
+    runner.start_heartbeat({"path": str(path), "interval_seconds": 0.05})
+    deadline = time.monotonic() + 5
+    while not path.exists() and time.monotonic() < deadline:
+        time.sleep(0.01)
+    first = path.read_text()
+    while path.read_text() == first and time.monotonic() < deadline:
+        time.sleep(0.01)
+    assert float(path.read_text()) > float(first)
@@ -0,0 +1,34 @@
+import pytest
+
+
+@pytest.mark.unit
+def test_cell_code_compiles(check_rip_available):
+    from flashvm.kernel import cell_code
+
+    code = cell_code("x = 1\nx + 1\n")
+    compile(code, "<cell>", "exec")
+
+
+@pytest.mark.unit
+def test_session_settings_from_environment(check_rip_available, monkeypatch, tmp_path):
+    from flashvm.kernel import Session
+
+    monkeypatch.setenv("FLASHVM_KERNEL_PROFILE", "data")
+    monkeypatch.setenv("FLASHVM_KERNEL_OUT", str(tmp_path))
+    session = Session()
+    assert session.profile == "data"
+    assert session.config_file is None
+    assert session.out_dir == str(tmp_path)
+
+
+@pytest.mark.integration
+@pytest.mark.requires_vm
+def test_session_keeps_state_between_cells(check_rip_available, vm_ready, tmp_path):
+    from flashvm.kernel import Session
+
+    session = Session(out_dir=str(tmp_path))
+    first = session.run_cell("import math\ndef double(v):\n    return 2 * v\nx = double(21)")
+    assert first["report"]["error"] is None
+    second = session.run_cell("math.floor(x + 0.5)")
+    assert second["report"]["error"] is None
+    assert second["report"]["result"]["text/plain"] == "42"
@@ -0,0 +1,38 @@
+import pytest
+
+
+@pytest.mark.unit
+def test_os_prepare_image_requires_packages(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.os_prepare_image([])
+    assert "empty" in str(exc.value).lower()
+
+
+@pytest.mark.unit
+def test_os_prepare_image_rejects_options(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.os_prepare_image(["--allow-untrusted"])
+
+
+@pytest.mark.unit
+@pytest.mark.slow
+def test_os_prepare_image_and_run(check_rip_available, doctor_check, vm_ready):
+    import flashvm as rip
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    try:
+        image_ref = rip.os_prepare_image(["file"])
+    except rip.FlashVMExecutionError as e:
+        pytest.skip(f"os_prepare_image failed (network/containers): {e}")
+    assert image_ref.startswith("containers-storage:localhost/flashvm:python-os-")
+
+    code = "import shutil\nprint(shutil.which('file') is not None)\n"
+    result = rip.run(code, image=image_ref)
+    assert result["exit_code"] == 0
+    assert result["stdout"].strip() == "True"
@@ -62,3 +62,168 @@ def test_pip_prepare_image_with_pandas_and_run(check_rip_available, doctor_check
         pytest.skip(f"VM run failed: {result.get('stderr', '')}")
     assert "pandas " in result.get("stdout", "")
     assert "3" in result.get("stdout", "")
+
+
+@pytest.mark.unit
+def test_pip_prepare_image_missing_requirements_file(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.pip_prepare_image(requirements_file=str(tmp_path / "missing.txt"))
+    assert "missing.txt" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_pip_prepare_image_from_requirements_file(check_rip_available, doctor_check, tmp_path):
+    import flashvm as rip
+    import uuid
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    requirements = tmp_path / "requirements.txt"
+    requirements.write_text("wheel\n")
+    constraints = tmp_path / "constraints.txt"
+    constraints.write_text("wheel<1000\n")
+    tag = f"pytest-reqs-{uuid.uuid4().hex[:8]}"
+    try:
+        image_ref = rip.pip_prepare_image(
+            requirements_file=str(requirements), constraints_file=str(constraints), tag=tag
+        )
+    except rip.FlashVMExecutionError as e:
+        pytest.skip(f"pip_prepare_image failed (network/containers): {e}")
+    assert image_ref == f"containers-storage:localhost/flashvm:{tag}"
+
+
+@pytest.mark.unit
+def test_pip_prepare_image_derived_tag_is_reused(check_rip_available, doctor_check):
+    import flashvm as rip
+    import time
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    try:
+        first = rip.pip_prepare_image(["wheel", "wheel"])
+    except rip.FlashVMExecutionError as e:
+        pytest.skip(f"pip_prepare_image failed (network/containers): {e}")
+    assert first.startswith("containers-storage:localhost/flashvm:python-pip-")
+
+    start = time.monotonic()
+    second = rip.pip_prepare_image([" wheel"])
+    assert second == first
+    assert time.monotonic() - start < 30
+
+
+@pytest.mark.unit
+def test_pip_prepare_image_records_freeze(check_rip_available, doctor_check):
+    import flashvm as rip
+    import uuid
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    tag = f"pytest-freeze-{uuid.uuid4().hex[:8]}"
+    try:
+        image_ref = rip.pip_prepare_image(["wheel"], tag=tag)
+    except rip.FlashVMExecutionError as e:
+        pytest.skip(f"pip_prepare_image failed (network/containers): {e}")
+
+    label = rip.inspect_image(image_ref)["labels"]["io.flashvm.pip-freeze"]
+    assert any(line.lower().startswith("wheel==") for line in label.splitlines())
+    cached = [img for img in rip.list_cached_images() if img["name"] == f"localhost/flashvm:{tag}"]
+    assert cached and cached[0]["pip_freeze"] == label.splitlines()
+
+
+@pytest.mark.unit
+def test_pip_prepare_image_offline_requires_wheel_dir(check_rip_available):
+    import flashvm as rip
+
+    rip.set_offline(True)
+    try:
+        with pytest.raises(rip.FlashVMConfigError) as exc:
+            rip.pip_prepare_image(["requests"])
+        assert "offline mode" in str(exc.value)
+    finally:
+        rip.set_offline(False)
+
+
+@pytest.mark.unit
+def test_pip_prepare_image_progress_and_error_tail(check_rip_available, doctor_check, tmp_path):
+    import flashvm as rip
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    # No index and no matching wheel: pip fails quickly, without network access
+    lines = []
+    with pytest.raises(rip.FlashVMExecutionError) as exc:
+        rip.pip_prepare_image(["flashvm-no-such-package"], wheel_dir=str(tmp_path), progress=lines.append)
+    assert "flashvm: running pip install" in lines
+    assert any("flashvm-no-such-package" in line for line in lines)
+    assert "flashvm-no-such-package" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_pip_prepare_image_use_uv_falls_back_to_pip(check_rip_available, doctor_check, tmp_path):
+    import flashvm as rip
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    # The embedded image has no uv, so the build announces uv and then runs pip
+    lines = []
+    with pytest.raises(rip.FlashVMExecutionError):
+        rip.pip_prepare_image(
+            ["flashvm-no-such-package"], wheel_dir=str(tmp_path), use_uv=True, progress=lines.append
+        )
+    assert "flashvm: running uv pip install" in lines
+    assert any("uv not found in image, using pip" in line for line in lines)
+
+
+@pytest.mark.unit
+def test_build_from_project_needs_lockfile(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    (tmp_path / "pyproject.toml").write_text('[project]\nname = "demo"\nversion = "0.1.0"\n')
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.build_from_project(str(tmp_path))
+    assert "no lockfile" in str(exc.value)
+    assert "pyproject.toml" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_build_from_project_rejects_multi_version_lock(check_rip_available, tmp_path):
+    import flashvm as rip
+
+    (tmp_path / "uv.lock").write_text(
+        "version = 1\n\n"
+        '[[package]]\nname = "numpy"\nversion = "1.26.4"\nsource = { registry = "https://pypi.org/simple" }\n\n'
+        '[[package]]\nname = "numpy"\nversion = "2.1.0"\nsource = { registry = "https://pypi.org/simple" }\n'
+    )
+    with pytest.raises(rip.FlashVMConfigError) as exc:
+        rip.build_from_project(str(tmp_path))
+    assert "numpy" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_build_from_project_offline_with_wheel_dir(check_rip_available, doctor_check, tmp_path):
+    import flashvm as rip
+
+    if not doctor_check.get("buildah", False):
+        pytest.skip("buildah not available")
+
+    project = tmp_path / "project"
+    project.mkdir()
+    (project / "requirements.txt").write_text("flashvm-no-such-package==1.0\n")
+    wheels = tmp_path / "wheels"
+    wheels.mkdir()
+
+    # Offline with a wheel_dir gets as far as pip, which finds no matching wheel
+    rip.set_offline(True)
+    try:
+        with pytest.raises(rip.FlashVMExecutionError) as exc:
+            rip.build_from_project(str(project), wheel_dir=str(wheels))
+        assert "flashvm-no-such-package" in str(exc.value)
+    finally:
+        rip.set_offline(False)
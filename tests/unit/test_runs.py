@@ -0,0 +1,163 @@
+import pytest
+
+
+@pytest.mark.unit
+def test_list_runs_returns_list(check_rip_available):
+    import flashvm as rip
+
+    runs = rip.list_runs()
+    assert isinstance(runs, list)
+    for r in runs:
+        assert {"run_id", "vm_name", "owner_pid", "image", "started_at"} <= set(r)
+
+
+@pytest.mark.unit
+def test_kill_unknown_run_returns_false(check_rip_available):
+    import flashvm as rip
+    import uuid
+
+    assert rip.kill(str(uuid.uuid4())) is False
+
+
+@pytest.mark.unit
+def test_pause_and_resume_unknown_run_return_false(check_rip_available):
+    import flashvm as rip
+    import uuid
+
+    run_id = str(uuid.uuid4())
+    assert rip.pause(run_id) is False
+    assert rip.resume(run_id) is False
+
+
+@pytest.mark.unit
+def test_paused_time_does_not_count_toward_timeout(check_rip_available, vm_ready):
+    import threading
+    import time
+
+    import flashvm as rip
+
+    code = "import time; time.sleep(4); print('done')"
+    box = {}
+    worker = threading.Thread(target=lambda: box.update(result=rip.run(code, timeout_seconds=8)))
+    worker.start()
+    deadline = time.monotonic() + 60
+    while not any(not r["kept"] for r in rip.list_runs()) and time.monotonic() < deadline:
+        time.sleep(0.2)
+    time.sleep(2)
+    run = next(r for r in rip.list_runs() if not r["kept"])
+    assert rip.pause(run["run_id"]) is True
+    assert next(r for r in rip.list_runs() if r["run_id"] == run["run_id"])["paused"]
+    time.sleep(8)
+    assert rip.resume(run["run_id"]) is True
+    worker.join()
+    assert box["result"]["exit_code"] == 0
+    assert box["result"]["stdout"].strip() == "done"
+
+
+@pytest.mark.unit
+def test_result_carries_run_id(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    result = rip.run("print('hi')")
+    assert isinstance(result.get("run_id"), str)
+    assert len(result["run_id"]) == 36
+
+
+@pytest.mark.unit
+def test_guest_sees_run_id(check_rip_available, vm_ready):
+    import flashvm as rip
+
+    result = rip.run("import os; print(os.environ['FLASHVM_RUN_ID'])")
+    assert result["stdout"].strip() == result["run_id"]
+
+
+@pytest.mark.unit
+def test_keep_vm_rejects_unknown_mode(check_rip_available):
+    import flashvm as rip
+
+    with pytest.raises(RuntimeError) as exc:
+        rip.run("print(1)", keep_vm="sometimes")
+    assert "keep_vm" in str(exc.value)
+
+
+@pytest.mark.unit
+def test_attach_unknown_run_raises(check_rip_available):
+    import flashvm as rip
+    import uuid
+
+    with pytest.raises(RuntimeError) as exc:
+        rip.attach(str(uuid.uuid4()))
+    assert "no run registered" in str(exc.value).lower()
+
+
+@pytest.mark.unit
+def test_extend_timeout(check_rip_available):
+    import json
+    import os
+    import pathlib
+    import uuid
+
+    import flashvm as rip
+
+    assert rip.extend_timeout(str(uuid.uuid4()), 30) is False
+    with pytest.raises(rip.FlashVMConfigError):
+        rip.extend_timeout(str(uuid.uuid4()), 0)
+
+    # A run of this process that hasn't started its VM yet
+    run_id = str(uuid.uuid4())
+    runs_dir = pathlib.Path(rip.effective_config()["cache_dir"]) / "runs"
+    runs_dir.mkdir(parents=True, exist_ok=True)
+    record = runs_dir / f"{run_id}.json"
+    record.write_text(json.dumps({
+        "run_id": run_id, "vm_name": f"flashvm-{run_id}", "owner_pid": os.getpid(), "pgid": 0,
+        "image": "python:3.12-alpine", "started_at": "2026-01-01T00:00:00Z", "timeout_ms": 60000,
+    }))
+    try:
+        assert rip.extend_timeout(run_id, 30) is True
+        run = next(r for r in rip.list_runs() if r["run_id"] == run_id)
+        assert run["timeout_seconds"] == 90
+        extension = json.loads(record.read_text())["timeout_extensions"][0]
+        assert extension["seconds"] == 30 and extension["pid"] == os.getpid()
+
+        rip.configure_policy({"max_timeout_seconds": 100})
+        with pytest.raises(rip.FlashVMPolicyViolation):
+            rip.extend_timeout(run_id, 30)
+    finally:
+        rip.configure_policy()
+        record.unlink()
+
+
+@pytest.mark.unit
+def test_kill_while_queued_never_boots(check_rip_available, vm_ready):
+    import threading
+    import time
+
+    import flashvm as rip
+
+    previous = rip.scheduler_stats()["max_vms"]
+    rip.set_max_concurrent_vms(1)
+    try:
+        first = threading.Thread(target=lambda: rip.run("import time; time.sleep(5)"))
+        first.start()
+        deadline = time.monotonic() + 60
+        while not rip.list_runs() and time.monotonic() < deadline:
+            time.sleep(0.1)
+        box = {}
+
+        def queued():
+            try:
+                rip.run("print('booted')")
+            except rip.FlashVMKilled as e:
+                box["error"] = e
+
+        second = threading.Thread(target=queued)
+        second.start()
+        while len(rip.list_runs()) < 2 and time.monotonic() < deadline:
+            time.sleep(0.1)
+        waiting = max(rip.list_runs(), key=lambda r: r["started_at"])
+        assert rip.kill(waiting["run_id"]) is True
+        second.join()
+        first.join()
+        assert box["error"].code == "KILLED"
+    finally:
+        rip.set_max_concurrent_vms(previous)
@@ -0,0 +1,76 @@
+import pytest
+
+JUNIT = b"""<?xml version="1.0" encoding="utf-8"?>
+<testsuites><testsuite name="pytest" errors="1" failures="1" skipped="1" tests="4" time="0.12">
+<testcase classname="test_grade" name="test_adds" file="test_grade.py" line="3" time="0.001" />
+<testcase classname="test_grade" name="test_divides" file="test_grade.py" line="7" time="0.002">
+<failure message="assert 1 == 2">def test_divides():
+&gt;       assert 1 == 2
+E       assert 1 == 2</failure></testcase>
+<testcase classname="test_grade" name="test_later" time="0.000"><skipped type="pytest.skip" message="not yet">test_grade.py:11: not yet</skipped></testcase>
+<testcase classname="" name="test_broken" time="0.000"><error message="collection failure">ImportError: no module named grade</error></testcase>
+</testsuite></testsuites>"""
+
+
+@pytest.mark.unit
+def test_parse_junit_counts_and_cases(check_rip_available):
+    import flashvm as rip
+    from flashvm.suites import parse_junit
+
+    report = parse_junit(JUNIT)
+    assert (report["passed"], report["failed"], report["errors"], report["skipped"], report["total"]) == (1, 1, 1, 1, 4)
+    first, failed, skipped, broken = report["cases"]
+    assert first == {
+        "name": "test_adds",
+        "classname": "test_grade",
+        "file": "test_grade.py",
+        "line": 3,
+        "time": 0.001,
+        "outcome": "passed",
+        "message": None,
+        "details": None,
+    }
+    assert failed["outcome"] == "failed" and failed["message"] == "assert 1 == 2"
+    assert "E       assert 1 == 2" in failed["details"]
+    assert skipped["outcome"] == "skipped" and skipped["message"] == "not yet" and skipped["line"] is None
+    assert broken["outcome"] == "error"
+
+    with pytest.raises(rip.FlashVMValidationError):
+        parse_junit(b"<testsuite>")
+
+
+@pytest.mark.unit
+def test_run_pytest_stages_the_suite(check_rip_available, monkeypatch, tmp_path):
+    import flashvm as rip
+    from flashvm import _core
+    from flashvm.suites import REPORT, run_pytest
+
+    (tmp_path / "pkg" / "__pycache__").mkdir(parents=True)
+    (tmp_path / "test_top.py").write_text("def test_a(): pass\n")
+    (tmp_path / "pkg" / "test_nested.py").write_text("def test_b(): pass\n")
+    (tmp_path / "pkg" / "__pycache__" / "test_nested.cpython-312.pyc").write_bytes(b"")
+    calls = []
+
+    def fake_run(code, files_in=None, expect=None, **options):
+        calls.append((code, sorted(guest for _, guest in files_in), expect, options))
+        artifact = {"guest_path": REPORT, "host_path": "/nonexistent", "size_bytes": len(JUNIT), "content": JUNIT}
+        return {"exit_code": 1, "stdout": "", "stderr": "", "artifacts": [artifact]}
+
+    monkeypatch.setattr(_core, "run", fake_run)
+    result = run_pytest(str(tmp_path), pytest_args=["-x", "-k", "adds"], requirements=["pytest"])
+    assert result["tests"]["failed"] == 1
+    code, staged, expect, options = calls[0]
+    assert staged == ["tests/pkg/test_nested.py", "tests/test_top.py"]
+    assert expect == [REPORT]
+    assert "['-x', '-k', 'adds']" in code
+    assert options == {"requirements": ["pytest"], "max_bytes_inline": 64 << 20}
+
+    run_pytest(str(tmp_path / "test_top.py"))
+    assert calls[1][1] == ["tests/test_top.py"]
+
+    monkeypatch.setattr(_core, "run", lambda code, **kw: {"exit_code": 1, "stdout": "", "stderr": "ModuleNotFoundError: No module named 'pytest'\n", "artifacts": []})
+    with pytest.raises(rip.FlashVMExecutionError) as exc:
+        run_pytest(str(tmp_path))
+    assert "No module named 'pytest'" in str(exc.value)
+    with pytest.raises(rip.FlashVMValidationError):
+        run_pytest(str(tmp_path / "missing"))